@@ -0,0 +1,425 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use phoenix_types::enums::Side;
+use solana_program::pubkey::Pubkey;
+
+use super::maker_stats::percentile;
+use crate::{
+    market_event::{Fill, MarketEventDetails, PhoenixEvent},
+    sdk_client_core::MarketMetadata,
+};
+
+/// Number of crossing intervals kept per market and side for the distribution
+const MAX_INTERVAL_SAMPLES: usize = 1000;
+
+/// Thresholds of `AdverseSelectionMonitor`. Times are in milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdverseSelectionConfig {
+    /// A fill that lands within this long of the reference price crossing the quote counts as
+    /// picked off
+    pub pick_off_threshold_ms: i64,
+    /// The rolling window the picked-off fraction is computed over
+    pub window_ms: i64,
+    /// Fewer fills than this in the window never raise an alert
+    pub min_fills: usize,
+    /// An alert is raised when the picked-off fraction of the window rises above this
+    pub alert_fraction: f64,
+    /// How much reference price history is kept per market. A crossing older than this is
+    /// measured from the oldest price kept.
+    pub reference_history_ms: i64,
+}
+
+impl Default for AdverseSelectionConfig {
+    fn default() -> Self {
+        AdverseSelectionConfig {
+            pick_off_threshold_ms: 500,
+            window_ms: 300_000,
+            min_fills: 10,
+            alert_fraction: 0.3,
+            reference_history_ms: 60_000,
+        }
+    }
+}
+
+/// The picked-off fraction of one market and side rose above
+/// `AdverseSelectionConfig::alert_fraction`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdverseSelectionAlert {
+    pub market: Pubkey,
+    /// The side of the trader's orders that were picked off
+    pub side: Side,
+    pub picked_off_fraction: f64,
+    pub fills_in_window: usize,
+    pub window_ms: i64,
+}
+
+/// Fill timing of one market and side, see `AdverseSelectionMonitor::report`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdverseSelectionStats {
+    pub market: Pubkey,
+    pub side: Side,
+    pub num_fills: u64,
+    /// Fills that happened while the reference price was through the quote
+    pub num_crossed_fills: u64,
+    pub num_picked_off: u64,
+    /// Over the most recent crossed fills, the time from the reference crossing the quote to
+    /// the fill
+    pub median_interval_ms: Option<i64>,
+    pub p90_interval_ms: Option<i64>,
+    /// The picked-off fraction of the fills in the rolling window, None if there are none
+    pub window_picked_off_fraction: Option<f64>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct SideStats {
+    num_fills: u64,
+    num_crossed_fills: u64,
+    num_picked_off: u64,
+    intervals: VecDeque<i64>,
+    /// Fill time and whether the fill was picked off, oldest first
+    window: VecDeque<(i64, bool)>,
+    alerting: bool,
+}
+
+/// Correlates a trader's maker fills with a timestamped reference price to detect adverse
+/// selection. For each fill it measures how long before the fill the reference price crossed
+/// the filled quote: a bid is crossed once the reference is below its price, an ask once it
+/// is above. Fills within `pick_off_threshold_ms` of the crossing count as picked off, i.e.
+/// the quote was not pulled in time.
+///
+/// The reference prices and fills must be fed in time order. Fill times default to the event
+/// timestamp, which has a resolution of one second; use `process_event_at` with the time the
+/// fill was observed for finer measurements.
+#[derive(Clone, Debug)]
+pub struct AdverseSelectionMonitor {
+    pub trader: Pubkey,
+    pub config: AdverseSelectionConfig,
+    markets: HashMap<Pubkey, MarketMetadata>,
+    reference_prices: HashMap<Pubkey, VecDeque<(i64, f64)>>,
+    stats: BTreeMap<(Pubkey, bool), SideStats>,
+}
+
+fn side_key(side: Side) -> bool {
+    matches!(side, Side::Bid)
+}
+
+impl AdverseSelectionMonitor {
+    pub fn new(trader: Pubkey, config: AdverseSelectionConfig) -> Self {
+        AdverseSelectionMonitor {
+            trader,
+            config,
+            markets: HashMap::new(),
+            reference_prices: HashMap::new(),
+            stats: BTreeMap::new(),
+        }
+    }
+
+    /// Fills on `market` are only measured once its metadata is added, which converts their
+    /// prices from ticks.
+    pub fn add_market(&mut self, market: Pubkey, metadata: MarketMetadata) {
+        self.markets.insert(market, metadata);
+    }
+
+    /// Records the reference price of `market` at `timestamp_ms`.
+    pub fn record_reference_price(&mut self, market: Pubkey, timestamp_ms: i64, price: f64) {
+        let history = self.reference_prices.entry(market).or_default();
+        history.push_back((timestamp_ms, price));
+        while history
+            .front()
+            .map(|(ts, _)| timestamp_ms - ts > self.config.reference_history_ms)
+            .unwrap_or(false)
+        {
+            history.pop_front();
+        }
+    }
+
+    /// Processes `event` with its own timestamp as the fill time, see `process_event_at`.
+    pub fn process_event(&mut self, event: &PhoenixEvent) -> Option<AdverseSelectionAlert> {
+        self.process_event_at(event, event.timestamp * 1000)
+    }
+
+    /// Measures a fill of one of the trader's orders that happened at `fill_time_ms`. Returns
+    /// an alert if the fill pushes the picked-off fraction of its market and side above
+    /// `alert_fraction`; the alert is not raised again until the fraction has dropped back
+    /// below. Other events are ignored.
+    pub fn process_event_at(
+        &mut self,
+        event: &PhoenixEvent,
+        fill_time_ms: i64,
+    ) -> Option<AdverseSelectionAlert> {
+        let (price_in_ticks, side) = match event.details {
+            MarketEventDetails::Fill(Fill {
+                maker,
+                price_in_ticks,
+                side_filled,
+                ..
+            }) if maker == self.trader => (price_in_ticks, side_filled),
+            _ => return None,
+        };
+        let quote_price = self
+            .markets
+            .get(&event.market)?
+            .ticks_to_float_price(price_in_ticks);
+        let interval = self.crossing_interval(&event.market, side, quote_price, fill_time_ms);
+        let picked_off = interval
+            .map(|interval| interval < self.config.pick_off_threshold_ms)
+            .unwrap_or(false);
+
+        let config = self.config;
+        let stats = self
+            .stats
+            .entry((event.market, side_key(side)))
+            .or_default();
+        stats.num_fills += 1;
+        if let Some(interval) = interval {
+            stats.num_crossed_fills += 1;
+            stats.intervals.push_back(interval);
+            if stats.intervals.len() > MAX_INTERVAL_SAMPLES {
+                stats.intervals.pop_front();
+            }
+        }
+        if picked_off {
+            stats.num_picked_off += 1;
+        }
+        stats.window.push_back((fill_time_ms, picked_off));
+        while stats
+            .window
+            .front()
+            .map(|(ts, _)| fill_time_ms - ts > config.window_ms)
+            .unwrap_or(false)
+        {
+            stats.window.pop_front();
+        }
+
+        let fills_in_window = stats.window.len();
+        let fraction =
+            stats.window.iter().filter(|(_, p)| *p).count() as f64 / fills_in_window as f64;
+        let above = fills_in_window >= config.min_fills && fraction > config.alert_fraction;
+        let newly_above = above && !stats.alerting;
+        stats.alerting = above;
+        newly_above.then(|| AdverseSelectionAlert {
+            market: event.market,
+            side,
+            picked_off_fraction: fraction,
+            fills_in_window,
+            window_ms: config.window_ms,
+        })
+    }
+
+    /// How long before `fill_time_ms` the reference price of `market` crossed a quote at
+    /// `quote_price` on `side` and stayed through it, or None if it was not through the quote
+    /// at the time of the fill.
+    fn crossing_interval(
+        &self,
+        market: &Pubkey,
+        side: Side,
+        quote_price: f64,
+        fill_time_ms: i64,
+    ) -> Option<i64> {
+        let is_through = |price: f64| match side {
+            Side::Bid => price < quote_price,
+            Side::Ask => price > quote_price,
+        };
+        let mut crossed_at = None;
+        for &(ts, price) in self
+            .reference_prices
+            .get(market)?
+            .iter()
+            .rev()
+            .skip_while(|(ts, _)| *ts > fill_time_ms)
+        {
+            if !is_through(price) {
+                break;
+            }
+            crossed_at = Some(ts);
+        }
+        crossed_at.map(|ts| fill_time_ms - ts)
+    }
+
+    /// The fill timing statistics of every market and side with fills, ordered by market with
+    /// asks before bids.
+    pub fn report(&self) -> Vec<AdverseSelectionStats> {
+        self.stats
+            .iter()
+            .map(|(&(market, is_bid), stats)| {
+                let mut intervals = stats.intervals.iter().copied().collect::<Vec<_>>();
+                intervals.sort_unstable();
+                let (median, p90) = if intervals.is_empty() {
+                    (None, None)
+                } else {
+                    (
+                        Some(percentile(&intervals, 0.5)),
+                        Some(percentile(&intervals, 0.9)),
+                    )
+                };
+                AdverseSelectionStats {
+                    market,
+                    side: if is_bid { Side::Bid } else { Side::Ask },
+                    num_fills: stats.num_fills,
+                    num_crossed_fills: stats.num_crossed_fills,
+                    num_picked_off: stats.num_picked_off,
+                    median_interval_ms: median,
+                    p90_interval_ms: p90,
+                    window_picked_off_fraction: (!stats.window.is_empty()).then(|| {
+                        stats.window.iter().filter(|(_, p)| *p).count() as f64
+                            / stats.window.len() as f64
+                    }),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Signature;
+
+    /// Ticks of 0.001 quote units
+    fn metadata() -> MarketMetadata {
+        MarketMetadata {
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            base_decimals: 9,
+            quote_decimals: 6,
+            base_multiplier: 1_000_000_000,
+            quote_multiplier: 1_000_000,
+            quote_lot_size: 1,
+            base_lot_size: 1_000_000,
+            tick_size_in_quote_atoms_per_base_unit: 1_000,
+            num_base_lots_per_base_unit: 1_000,
+        }
+    }
+
+    fn fill(market: Pubkey, maker: Pubkey, side_filled: Side, price: f64) -> PhoenixEvent {
+        PhoenixEvent {
+            market,
+            sequence_number: 0,
+            slot: 0,
+            timestamp: 0,
+            signature: Signature::default(),
+            signer: Pubkey::new_unique(),
+            event_index: 0,
+            details: MarketEventDetails::Fill(Fill {
+                order_sequence_number: 0,
+                maker,
+                taker: Pubkey::new_unique(),
+                price_in_ticks: (price * 1_000.0) as u64,
+                base_lots_filled: 1,
+                base_lots_remaining: 0,
+                side_filled,
+                is_full_fill: true,
+            }),
+        }
+    }
+
+    fn monitor(config: AdverseSelectionConfig) -> (AdverseSelectionMonitor, Pubkey) {
+        let market = Pubkey::new_unique();
+        let mut monitor = AdverseSelectionMonitor::new(Pubkey::new_unique(), config);
+        monitor.add_market(market, metadata());
+        (monitor, market)
+    }
+
+    #[test]
+    fn measures_the_time_since_the_reference_crossed_the_quote() {
+        let (mut monitor, market) = monitor(AdverseSelectionConfig::default());
+        let trader = monitor.trader;
+        monitor.record_reference_price(market, 0, 100.5);
+        monitor.record_reference_price(market, 1_000, 99.5);
+        monitor.record_reference_price(market, 1_100, 99.0);
+        // Crossed at 1000, filled 200ms later
+        monitor.process_event_at(&fill(market, trader, Side::Bid, 100.0), 1_200);
+        // The reference is below the ask, so the ask was not crossed
+        monitor.process_event_at(&fill(market, trader, Side::Ask, 101.0), 1_300);
+        monitor.record_reference_price(market, 3_000, 98.0);
+        // Still through the bid since 1000
+        monitor.process_event_at(&fill(market, trader, Side::Bid, 100.0), 3_000);
+
+        let report = monitor.report();
+        assert_eq!(report.len(), 2);
+        let asks = &report[0];
+        assert_eq!(asks.side, Side::Ask);
+        assert_eq!((asks.num_fills, asks.num_crossed_fills), (1, 0));
+        assert_eq!(asks.median_interval_ms, None);
+        assert_eq!(asks.window_picked_off_fraction, Some(0.0));
+        let bids = &report[1];
+        assert_eq!(bids.side, Side::Bid);
+        assert_eq!(
+            (bids.num_fills, bids.num_crossed_fills, bids.num_picked_off),
+            (2, 2, 1)
+        );
+        assert_eq!(bids.median_interval_ms, Some(200));
+        assert_eq!(bids.p90_interval_ms, Some(2_000));
+        assert_eq!(bids.window_picked_off_fraction, Some(0.5));
+    }
+
+    #[test]
+    fn ignores_other_makers_and_unknown_markets() {
+        let (mut monitor, market) = monitor(AdverseSelectionConfig::default());
+        let trader = monitor.trader;
+        monitor.record_reference_price(market, 0, 99.0);
+        let other_maker = fill(market, Pubkey::new_unique(), Side::Bid, 100.0);
+        let unknown_market = fill(Pubkey::new_unique(), trader, Side::Bid, 100.0);
+        assert_eq!(monitor.process_event_at(&other_maker, 100), None);
+        assert_eq!(monitor.process_event_at(&unknown_market, 100), None);
+        assert!(monitor.report().is_empty());
+    }
+
+    #[test]
+    fn alerts_once_per_excursion_above_the_threshold() {
+        let config = AdverseSelectionConfig {
+            pick_off_threshold_ms: 500,
+            window_ms: 10_000,
+            min_fills: 2,
+            alert_fraction: 0.5,
+            reference_history_ms: 60_000,
+        };
+        let (mut monitor, market) = monitor(config);
+        let trader = monitor.trader;
+        let bid = fill(market, trader, Side::Bid, 100.0);
+        // The reference crosses the bid 100ms before each picked off fill
+        let picked_off = |monitor: &mut AdverseSelectionMonitor, now: i64| {
+            monitor.record_reference_price(market, now - 200, 101.0);
+            monitor.record_reference_price(market, now - 100, 99.0);
+            monitor.process_event_at(&bid, now)
+        };
+        let clean = |monitor: &mut AdverseSelectionMonitor, now: i64| {
+            monitor.record_reference_price(market, now - 100, 101.0);
+            monitor.process_event_at(&bid, now)
+        };
+
+        // One fill is below min_fills
+        assert_eq!(picked_off(&mut monitor, 1_000), None);
+        let alert = picked_off(&mut monitor, 2_000).unwrap();
+        assert_eq!(alert.market, market);
+        assert_eq!(alert.side, Side::Bid);
+        assert_eq!(alert.fills_in_window, 2);
+        assert_eq!(alert.picked_off_fraction, 1.0);
+        // Still above, so not raised again
+        assert_eq!(picked_off(&mut monitor, 3_000), None);
+        // 3 of 5 picked off is above, then 3 of 6 is not
+        assert_eq!(clean(&mut monitor, 4_000), None);
+        assert_eq!(clean(&mut monitor, 5_000), None);
+        assert_eq!(clean(&mut monitor, 6_000), None);
+        assert!(picked_off(&mut monitor, 7_000).is_some());
+    }
+
+    #[test]
+    fn old_fills_leave_the_window() {
+        let config = AdverseSelectionConfig {
+            window_ms: 1_000,
+            min_fills: 1,
+            ..AdverseSelectionConfig::default()
+        };
+        let (mut monitor, market) = monitor(config);
+        let bid = fill(market, monitor.trader, Side::Bid, 100.0);
+        monitor.record_reference_price(market, 0, 99.0);
+        assert!(monitor.process_event_at(&bid, 100).is_some());
+        monitor.record_reference_price(market, 1_900, 101.0);
+        monitor.process_event_at(&bid, 2_000);
+        let report = monitor.report();
+        assert_eq!(report[0].num_fills, 2);
+        assert_eq!(report[0].num_picked_off, 1);
+        assert_eq!(report[0].window_picked_off_fraction, Some(0.0));
+    }
+}
@@ -0,0 +1,122 @@
+use phoenix_types::market::{FIFOOrderId, TraderState};
+use solana_program::pubkey::Pubkey;
+
+use crate::{
+    orderbook::Orderbook,
+    sdk_client_core::{MarketMetadata, PhoenixOrder},
+};
+
+/// The outcome of comparing a trader's locked lots against their resting orders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockedFundsStatus {
+    /// The locked lots match the resting orders
+    Consistent,
+    /// The snapshot shows a discrepancy that may still settle. Re-check it after a few slots
+    /// to classify it.
+    Unconfirmed,
+    /// A discrepancy seen earlier was gone on the re-check, e.g. a cancel that was still
+    /// being processed
+    PendingSettlement,
+    /// The discrepancy was still there on the re-check
+    Mismatch,
+}
+
+/// Locked lots compared to the lots the trader's resting orders should lock, from a single
+/// market snapshot. Discrepancies are locked minus expected, so a positive value is a phantom
+/// lock that reduces the trader's quoting capacity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LockedFundsAudit {
+    pub market: Pubkey,
+    pub trader: Pubkey,
+    /// The slot of the snapshot
+    pub slot: u64,
+    pub base_lots_locked: u64,
+    pub quote_lots_locked: u64,
+    /// Base lots locked by the trader's asks
+    pub expected_base_lots_locked: u64,
+    /// Quote lots locked by the trader's bids
+    pub expected_quote_lots_locked: u64,
+    pub base_lots_discrepancy: i128,
+    pub quote_lots_discrepancy: i128,
+    pub base_units_discrepancy: f64,
+    pub quote_units_discrepancy: f64,
+    pub status: LockedFundsStatus,
+}
+
+impl LockedFundsAudit {
+    /// Compares `trader_state` against the trader's orders in `orderbook`. The status is
+    /// `Consistent` or `Unconfirmed`; use `reclassify` with a later audit to tell a pending
+    /// settlement from a genuine mismatch.
+    #[allow(clippy::useless_conversion)]
+    pub fn from_snapshot(
+        metadata: &MarketMetadata,
+        market: Pubkey,
+        trader: Pubkey,
+        slot: u64,
+        orderbook: &Orderbook<FIFOOrderId, PhoenixOrder>,
+        trader_state: &TraderState,
+    ) -> Self {
+        let tick_size_in_quote_lots_per_base_unit =
+            (metadata.tick_size_in_quote_atoms_per_base_unit / metadata.quote_lot_size) as u128;
+        let expected_base_lots_locked = orderbook
+            .asks
+            .values()
+            .filter(|order| order.maker_id == trader)
+            .map(|order| order.num_base_lots)
+            .sum::<u64>();
+        // A bid locks its price times its size in quote lots, as the program computes it
+        let expected_quote_lots_locked = orderbook
+            .bids
+            .iter()
+            .filter(|(_, order)| order.maker_id == trader)
+            .map(|(order_id, order)| {
+                let price_in_ticks: u64 = order_id.price_in_ticks.into();
+                (price_in_ticks as u128
+                    * tick_size_in_quote_lots_per_base_unit
+                    * order.num_base_lots as u128
+                    / metadata.num_base_lots_per_base_unit as u128) as u64
+            })
+            .sum::<u64>();
+        let base_lots_locked: u64 = trader_state.base_lots_locked.into();
+        let quote_lots_locked: u64 = trader_state.quote_lots_locked.into();
+        let base_lots_discrepancy = base_lots_locked as i128 - expected_base_lots_locked as i128;
+        let quote_lots_discrepancy = quote_lots_locked as i128 - expected_quote_lots_locked as i128;
+        let status = if base_lots_discrepancy == 0 && quote_lots_discrepancy == 0 {
+            LockedFundsStatus::Consistent
+        } else {
+            LockedFundsStatus::Unconfirmed
+        };
+        LockedFundsAudit {
+            market,
+            trader,
+            slot,
+            base_lots_locked,
+            quote_lots_locked,
+            expected_base_lots_locked,
+            expected_quote_lots_locked,
+            base_lots_discrepancy,
+            quote_lots_discrepancy,
+            base_units_discrepancy: base_lots_discrepancy as f64 * metadata.base_lot_size as f64
+                / metadata.base_multiplier as f64,
+            quote_units_discrepancy: quote_lots_discrepancy as f64 * metadata.quote_lot_size as f64
+                / metadata.quote_multiplier as f64,
+            status,
+        }
+    }
+
+    pub fn has_discrepancy(&self) -> bool {
+        self.base_lots_discrepancy != 0 || self.quote_lots_discrepancy != 0
+    }
+
+    /// Classifies this audit, taken after `previous`: a discrepancy of `previous` that is gone
+    /// now was `PendingSettlement`, one that remains is a `Mismatch`.
+    pub fn reclassify(mut self, previous: &LockedFundsAudit) -> Self {
+        self.status = match (previous.has_discrepancy(), self.has_discrepancy()) {
+            (true, true) => LockedFundsStatus::Mismatch,
+            (true, false) => LockedFundsStatus::PendingSettlement,
+            (false, true) => LockedFundsStatus::Unconfirmed,
+            (false, false) => LockedFundsStatus::Consistent,
+        };
+        self
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, HashMap};
+
+use solana_program::pubkey::Pubkey;
+
+use crate::market_event::{Evict, Fill, MarketEventDetails, PhoenixEvent, Place, Reduce};
+
+const SECONDS_PER_HOUR: i64 = 3600;
+
+/// Maker activity for one market over one hour. Events are bucketed by the hour of their own
+/// timestamp, and quote lifetimes are recorded in the hour the order left the book.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HourlyMakerStats {
+    pub market: Pubkey,
+    /// Unix timestamp of the start of the hour
+    pub hour_start: i64,
+    pub orders_placed: u64,
+    pub orders_cancelled: u64,
+    pub orders_fully_filled: u64,
+    /// Orders that received their first (but not final) fill in this hour
+    pub orders_partially_filled: u64,
+    /// Number of fill events against the trader's orders
+    pub num_fills: u64,
+    pub median_quote_lifetime_secs: Option<i64>,
+    pub p90_quote_lifetime_secs: Option<i64>,
+    /// orders_placed / num_fills, or None if there were no fills
+    pub order_to_trade_ratio: Option<f64>,
+}
+
+/// An order that was still resting when the report was generated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OpenOrderAge {
+    pub market: Pubkey,
+    pub order_sequence_number: u64,
+    pub placed_at: i64,
+    pub age_secs: i64,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct OpenOrder {
+    placed_at: i64,
+    partially_filled: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Bucket {
+    orders_placed: u64,
+    orders_cancelled: u64,
+    orders_fully_filled: u64,
+    orders_partially_filled: u64,
+    num_fills: u64,
+    lifetimes: Vec<i64>,
+}
+
+/// Computes per market, per hour maker statistics for a single trader purely from the event
+/// stream. Events for other makers are ignored.
+#[derive(Clone, Debug)]
+pub struct MakerStats {
+    pub trader: Pubkey,
+    open_orders: HashMap<(Pubkey, u64), OpenOrder>,
+    buckets: BTreeMap<(Pubkey, i64), Bucket>,
+}
+
+fn hour_start(timestamp: i64) -> i64 {
+    timestamp.div_euclid(SECONDS_PER_HOUR) * SECONDS_PER_HOUR
+}
+
+/// Nearest-rank percentile of a sorted, non-empty slice
+pub(crate) fn percentile(sorted: &[i64], p: f64) -> i64 {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+impl MakerStats {
+    pub fn new(trader: Pubkey) -> Self {
+        MakerStats {
+            trader,
+            open_orders: HashMap::new(),
+            buckets: BTreeMap::new(),
+        }
+    }
+
+    pub fn process_events(&mut self, events: &[PhoenixEvent]) {
+        for event in events {
+            self.process_event(event);
+        }
+    }
+
+    pub fn process_event(&mut self, event: &PhoenixEvent) {
+        let key = (event.market, hour_start(event.timestamp));
+        match event.details {
+            MarketEventDetails::Place(Place {
+                order_sequence_number,
+                maker,
+                ..
+            }) if maker == self.trader => {
+                self.open_orders.insert(
+                    (event.market, order_sequence_number),
+                    OpenOrder {
+                        placed_at: event.timestamp,
+                        partially_filled: false,
+                    },
+                );
+                self.buckets.entry(key).or_default().orders_placed += 1;
+            }
+            MarketEventDetails::Fill(Fill {
+                order_sequence_number,
+                maker,
+                is_full_fill,
+                ..
+            }) if maker == self.trader => {
+                let order_key = (event.market, order_sequence_number);
+                let bucket = self.buckets.entry(key).or_default();
+                bucket.num_fills += 1;
+                if is_full_fill {
+                    bucket.orders_fully_filled += 1;
+                    if let Some(order) = self.open_orders.remove(&order_key) {
+                        bucket.lifetimes.push(event.timestamp - order.placed_at);
+                    }
+                } else if let Some(order) = self.open_orders.get_mut(&order_key) {
+                    if !order.partially_filled {
+                        order.partially_filled = true;
+                        bucket.orders_partially_filled += 1;
+                    }
+                }
+            }
+            MarketEventDetails::Reduce(Reduce {
+                order_sequence_number,
+                maker,
+                is_full_cancel,
+                ..
+            }) if maker == self.trader && is_full_cancel => {
+                let bucket = self.buckets.entry(key).or_default();
+                bucket.orders_cancelled += 1;
+                if let Some(order) = self
+                    .open_orders
+                    .remove(&(event.market, order_sequence_number))
+                {
+                    bucket.lifetimes.push(event.timestamp - order.placed_at);
+                }
+            }
+            MarketEventDetails::Evict(Evict {
+                order_sequence_number,
+                maker,
+                ..
+            }) if maker == self.trader => {
+                if let Some(order) = self
+                    .open_orders
+                    .remove(&(event.market, order_sequence_number))
+                {
+                    self.buckets
+                        .entry(key)
+                        .or_default()
+                        .lifetimes
+                        .push(event.timestamp - order.placed_at);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the statistics for every (market, hour) bucket, ordered by market then hour.
+    /// Orders that are still open are not included in the lifetime statistics, see
+    /// `open_orders`.
+    pub fn report(&self) -> Vec<HourlyMakerStats> {
+        self.buckets
+            .iter()
+            .map(|(&(market, hour_start), bucket)| {
+                let mut lifetimes = bucket.lifetimes.clone();
+                lifetimes.sort_unstable();
+                let (median, p90) = if lifetimes.is_empty() {
+                    (None, None)
+                } else {
+                    (
+                        Some(percentile(&lifetimes, 0.5)),
+                        Some(percentile(&lifetimes, 0.9)),
+                    )
+                };
+                HourlyMakerStats {
+                    market,
+                    hour_start,
+                    orders_placed: bucket.orders_placed,
+                    orders_cancelled: bucket.orders_cancelled,
+                    orders_fully_filled: bucket.orders_fully_filled,
+                    orders_partially_filled: bucket.orders_partially_filled,
+                    num_fills: bucket.num_fills,
+                    median_quote_lifetime_secs: median,
+                    p90_quote_lifetime_secs: p90,
+                    order_to_trade_ratio: if bucket.num_fills == 0 {
+                        None
+                    } else {
+                        Some(bucket.orders_placed as f64 / bucket.num_fills as f64)
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Orders placed by the trader that have not been fully filled, cancelled, or evicted,
+    /// with their age at `now`.
+    pub fn open_orders(&self, now: i64) -> Vec<OpenOrderAge> {
+        let mut open_orders = self
+            .open_orders
+            .iter()
+            .map(|(&(market, order_sequence_number), order)| OpenOrderAge {
+                market,
+                order_sequence_number,
+                placed_at: order.placed_at,
+                age_secs: now - order.placed_at,
+            })
+            .collect::<Vec<_>>();
+        open_orders.sort_by_key(|order| (order.market, order.order_sequence_number));
+        open_orders
+    }
+}
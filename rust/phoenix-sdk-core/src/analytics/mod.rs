@@ -0,0 +1,3 @@
+pub mod adverse_selection;
+pub mod locked_funds;
+pub mod maker_stats;
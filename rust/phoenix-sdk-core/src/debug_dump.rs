@@ -0,0 +1,180 @@
+//! A registry of state dumps for post-mortem debugging, enabled with the `serde` feature.
+
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::RecvTimeoutError,
+        Arc, Mutex,
+    },
+    thread::{Builder, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Map, Value};
+
+use crate::kill_switch::KillSwitch;
+
+type DumpFn = dyn Fn() -> Value + Send + Sync;
+
+fn wall_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Long-lived components (caches, trackers, executors, pollers) register a closure that
+/// returns their state as JSON, and `collect` merges every registered dump into one document.
+/// Clones share the same registry.
+///
+/// A dump runs on the thread that collects and must not block: a component that is being
+/// updated should report what it can, e.g. with `try_lock`. A dump that panics is recorded as
+/// an error in the document instead of propagating.
+#[derive(Clone, Default)]
+pub struct DebugStateDump {
+    components: Arc<Mutex<Vec<(String, Arc<DumpFn>)>>>,
+}
+
+impl std::fmt::Debug for DebugStateDump {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DebugStateDump")
+            .field("components", &self.component_names())
+            .finish()
+    }
+}
+
+impl DebugStateDump {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `dump` under `name`, replacing any dump already registered under that name.
+    pub fn register(&self, name: &str, dump: impl Fn() -> Value + Send + Sync + 'static) {
+        let mut components = self.components.lock().unwrap_or_else(|e| e.into_inner());
+        components.retain(|(component, _)| component != name);
+        components.push((name.to_string(), Arc::new(dump)));
+    }
+
+    pub fn unregister(&self, name: &str) {
+        self.components
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(component, _)| component != name);
+    }
+
+    pub fn component_names(&self) -> Vec<String> {
+        self.components
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Runs every registered dump and returns them as one document:
+    /// `{"wall_time_ms", "reason", "components": {name: {"dumped_at_ms", "state" | "error"}}}`.
+    /// `extra` components are added after the registered ones.
+    pub fn collect(&self, reason: Option<&str>, extra: Vec<(String, Value)>) -> Value {
+        // Run the dumps outside the lock, so a dump can register or unregister components
+        let registered = self
+            .components
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let mut components = Map::new();
+        for (name, dump) in registered {
+            let entry = match catch_unwind(AssertUnwindSafe(|| dump())) {
+                Ok(state) => json!({ "dumped_at_ms": wall_time_ms(), "state": state }),
+                Err(_) => json!({ "dumped_at_ms": wall_time_ms(), "error": "dump panicked" }),
+            };
+            components.insert(name, entry);
+        }
+        for (name, state) in extra {
+            components.insert(
+                name,
+                json!({ "dumped_at_ms": wall_time_ms(), "state": state }),
+            );
+        }
+        json!({
+            "wall_time_ms": wall_time_ms(),
+            "reason": reason,
+            "components": components,
+        })
+    }
+
+    /// Writes `document` to `path` as pretty-printed JSON. The document is written to a
+    /// temporary file next to `path` first and then renamed, so a crash mid-write never
+    /// leaves a truncated dump behind.
+    pub fn write_to_file(document: &Value, path: &Path) -> anyhow::Result<()> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let file = File::create(&tmp_path)?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, document)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Collects the registered dumps and writes them to `path`, see `collect`.
+    pub fn dump_to_file(&self, path: &Path, reason: Option<&str>) -> anyhow::Result<()> {
+        Self::write_to_file(&self.collect(reason, vec![]), path)
+    }
+
+    /// Writes a dump to `<dir>/phoenix-dump-<unix millis>.json` on a background thread every
+    /// time `kill_switch` is tripped, with the trip reason. Errors writing the dump are
+    /// printed and do not stop the watcher.
+    pub fn dump_on_kill_switch(
+        &self,
+        kill_switch: &KillSwitch,
+        dir: impl AsRef<Path>,
+    ) -> DebugDumpWatcher {
+        let trips = kill_switch.subscribe();
+        let dir = dir.as_ref().to_path_buf();
+        let dump = self.clone();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let worker_shutdown = shutdown.clone();
+        let worker = Builder::new()
+            .name("debug-dump-watcher".to_string())
+            .spawn(move || {
+                while !worker_shutdown.load(Ordering::Relaxed) {
+                    let reason = match trips.recv_timeout(Duration::from_millis(500)) {
+                        Ok(reason) => reason,
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    };
+                    let path = dir.join(format!("phoenix-dump-{}.json", wall_time_ms()));
+                    let reason = format!("kill switch tripped: {}", reason);
+                    match dump.dump_to_file(&path, Some(&reason)) {
+                        Ok(()) => println!("Wrote debug dump to {}", path.display()),
+                        Err(e) => println!("Error writing debug dump: {}", e),
+                    }
+                }
+            })
+            .unwrap();
+
+        DebugDumpWatcher { worker, shutdown }
+    }
+}
+
+/// The background thread started by `DebugStateDump::dump_on_kill_switch`.
+pub struct DebugDumpWatcher {
+    pub worker: JoinHandle<()>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl DebugDumpWatcher {
+    /// Asks the watcher to stop. Use `join` to wait for it.
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+    }
+
+    pub fn join(self) {
+        self.worker.join().unwrap()
+    }
+}
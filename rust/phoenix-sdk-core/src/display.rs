@@ -0,0 +1,122 @@
+use std::fmt;
+
+use phoenix_types::market::{Ladder, LadderOrder, TraderState};
+
+use crate::sdk_client_core::MarketMetadata;
+
+/// Formats `value` with at most `precision` decimals, dropping trailing zeros, e.g. 1.5 rather
+/// than 1.500000.
+pub fn format_amount(value: f64, precision: usize) -> String {
+    let formatted = format!("{:.*}", precision, value);
+    if !formatted.contains('.') {
+        return formatted;
+    }
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "-0" => "0".to_string(),
+        _ => trimmed.to_string(),
+    }
+}
+
+/// A value formatted in the units of a market, see `DisplayWithMetadata`.
+pub struct MetadataDisplay<'a, T: ?Sized> {
+    value: &'a T,
+    metadata: &'a MarketMetadata,
+    precision: Option<usize>,
+}
+
+impl<T: ?Sized> MetadataDisplay<'_, T> {
+    /// Prints every amount and price with at most `precision` decimals instead of the decimals
+    /// of its token.
+    pub fn with_precision(mut self, precision: usize) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    fn base(&self, units: f64) -> String {
+        format_amount(
+            units,
+            self.precision
+                .unwrap_or(self.metadata.base_decimals as usize),
+        )
+    }
+
+    fn quote(&self, units: f64) -> String {
+        format_amount(
+            units,
+            self.precision
+                .unwrap_or(self.metadata.quote_decimals as usize),
+        )
+    }
+}
+
+/// Formats raw market values, which count lots and ticks, in base and quote units for logs,
+/// e.g. `println!("{}", ladder.display(&metadata))`.
+pub trait DisplayWithMetadata {
+    fn display<'a>(&'a self, metadata: &'a MarketMetadata) -> MetadataDisplay<'a, Self> {
+        MetadataDisplay {
+            value: self,
+            metadata,
+            precision: None,
+        }
+    }
+}
+
+impl DisplayWithMetadata for TraderState {}
+
+impl DisplayWithMetadata for Ladder {}
+
+/// The free and locked balances in units, like `TraderBalances`
+impl fmt::Display for MetadataDisplay<'_, TraderState> {
+    #[allow(clippy::useless_conversion)]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let metadata = self.metadata;
+        let base_units = |lots: u64| {
+            self.base(
+                metadata.base_lots_to_base_amount(lots) as f64 / metadata.base_multiplier as f64,
+            )
+        };
+        let quote_units = |lots: u64| {
+            self.quote(
+                metadata.quote_lots_to_quote_amount(lots) as f64 / metadata.quote_multiplier as f64,
+            )
+        };
+        write!(
+            f,
+            "base free {} locked {}, quote free {} locked {}",
+            base_units(self.value.base_lots_free.into()),
+            base_units(self.value.base_lots_locked.into()),
+            quote_units(self.value.quote_lots_free.into()),
+            quote_units(self.value.quote_lots_locked.into()),
+        )
+    }
+}
+
+/// One level per line, asks from the highest price down, then the bids from the best down,
+/// as price and size in base units. Sizes are converted like the sizes of `UiLadder`.
+impl fmt::Display for MetadataDisplay<'_, Ladder> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let metadata = self.metadata;
+        let level = |f: &mut fmt::Formatter<'_>, side: &str, order: &LadderOrder| {
+            writeln!(
+                f,
+                "{} {} @ {}",
+                side,
+                self.base(
+                    order.size_in_base_lots as f64 * metadata.base_lots_to_base_units_multiplier()
+                ),
+                self.quote(metadata.ticks_to_float_price(order.price_in_ticks)),
+            )
+        };
+        if self.value.bids.is_empty() && self.value.asks.is_empty() {
+            return writeln!(f, "empty ladder");
+        }
+        for order in self.value.asks.iter().rev() {
+            level(f, "ask", order)?;
+        }
+        for order in self.value.bids.iter() {
+            level(f, "bid", order)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,149 @@
+use std::fmt::Display;
+
+use solana_program::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::{instruction_audit::UnexpectedAccount, order_packet_template::OrderValidationError};
+
+/// Errors returned when building, sending, or parsing Phoenix transactions.
+///
+/// The error implements `std::error::Error`, so functions returning `anyhow::Result` can use
+/// `?` on it, and callers can recover it with `anyhow::Error::downcast_ref::<PhoenixSdkError>`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PhoenixSdkError {
+    /// The market is not in the client's market cache.
+    MarketNotLoaded { market: Pubkey },
+    /// Account data could not be decoded as the expected Phoenix account.
+    DeserializationFailed(String),
+    /// The market's lot or tick sizes cannot be used for unit conversions, e.g. a lot size of 0.
+    InvalidLotSize(String),
+    /// The market's base and quote mints are the same token.
+    IdenticalMints { mint: Pubkey },
+    /// A mint of the market has more than `MAX_MINT_DECIMALS` decimals.
+    InvalidDecimals {
+        base_decimals: u32,
+        quote_decimals: u32,
+    },
+    /// The arguments do not describe a valid instruction (e.g. reducing an order by 0 lots).
+    InvalidArgument(String),
+    /// An order template does not describe an order the market can place.
+    InvalidOrder(OrderValidationError),
+    /// The RPC request failed before the transaction could be simulated or executed.
+    RpcError(String),
+    /// The transaction failed simulation or execution. `logs` holds the program logs if the
+    /// RPC node returned them.
+    TransactionFailed { logs: Vec<String> },
+    /// The transaction landed but its Phoenix events could not be fetched or decoded.
+    EventParseFailed { signature: Signature },
+    /// The kill switch is tripped and the instruction would increase exposure.
+    TradingHalted { reason: String },
+    /// The client was created read-only and cannot sign or send transactions.
+    NoSignerConfigured,
+    /// The book cannot absorb the order. `fillable_lots` is how much of `requested_lots` the
+    /// book could fill.
+    InsufficientLiquidity {
+        requested_lots: u64,
+        fillable_lots: u64,
+    },
+    /// The transaction missed its `SendDeadline` and its blockhash expired before it landed.
+    SendDeadlineExpired { signature: Signature },
+    /// Only the market's fee recipient can collect its fees.
+    NotFeeRecipient {
+        market: Pubkey,
+        fee_recipient: Pubkey,
+        signer: Pubkey,
+    },
+    /// The instruction audit found accounts outside the expected set of the instructions'
+    /// market and trader, see `SDKClientCore::audit_phoenix_instructions`.
+    UnexpectedAccounts { accounts: Vec<UnexpectedAccount> },
+}
+
+impl Display for PhoenixSdkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PhoenixSdkError::MarketNotLoaded { market } => {
+                write!(f, "Market {} is not loaded", market)
+            }
+            PhoenixSdkError::DeserializationFailed(msg) => {
+                write!(f, "Deserialization failed: {}", msg)
+            }
+            PhoenixSdkError::InvalidLotSize(msg) => write!(f, "Invalid lot size: {}", msg),
+            PhoenixSdkError::IdenticalMints { mint } => {
+                write!(f, "The market's base and quote mints are both {}", mint)
+            }
+            PhoenixSdkError::InvalidDecimals {
+                base_decimals,
+                quote_decimals,
+            } => write!(
+                f,
+                "Unsupported mint decimals: base {}, quote {}",
+                base_decimals, quote_decimals
+            ),
+            PhoenixSdkError::InvalidArgument(msg) => write!(f, "Invalid argument: {}", msg),
+            PhoenixSdkError::InvalidOrder(e) => write!(f, "Invalid order: {}", e),
+            PhoenixSdkError::RpcError(msg) => write!(f, "RPC error: {}", msg),
+            PhoenixSdkError::TransactionFailed { logs } => {
+                write!(f, "Transaction failed")?;
+                for log in logs {
+                    write!(f, "\n  {}", log)?;
+                }
+                Ok(())
+            }
+            PhoenixSdkError::EventParseFailed { signature } => {
+                write!(f, "Failed to parse events for transaction {}", signature)
+            }
+            PhoenixSdkError::TradingHalted { reason } => write!(f, "Trading halted: {}", reason),
+            PhoenixSdkError::NoSignerConfigured => {
+                write!(f, "The client is read-only and has no signer configured")
+            }
+            PhoenixSdkError::InsufficientLiquidity {
+                requested_lots,
+                fillable_lots,
+            } => write!(
+                f,
+                "Insufficient liquidity: only {} of {} lots can be filled",
+                fillable_lots, requested_lots
+            ),
+            PhoenixSdkError::SendDeadlineExpired { signature } => write!(
+                f,
+                "Transaction {} missed its send deadline and expired without landing",
+                signature
+            ),
+            PhoenixSdkError::NotFeeRecipient {
+                market,
+                fee_recipient,
+                signer,
+            } => write!(
+                f,
+                "{} is not the fee recipient of market {}, {} is",
+                signer, market, fee_recipient
+            ),
+            PhoenixSdkError::UnexpectedAccounts { accounts } => {
+                write!(f, "Instructions reference unexpected accounts")?;
+                for account in accounts {
+                    match account.account_index {
+                        Some(account_index) => write!(
+                            f,
+                            "\n  {} (instruction {}, account {})",
+                            account.pubkey, account.instruction_index, account_index
+                        )?,
+                        None => write!(
+                            f,
+                            "\n  {} (instruction {}, program id)",
+                            account.pubkey, account.instruction_index
+                        )?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PhoenixSdkError {}
+
+impl From<OrderValidationError> for PhoenixSdkError {
+    fn from(e: OrderValidationError) -> Self {
+        PhoenixSdkError::InvalidOrder(e)
+    }
+}
@@ -0,0 +1,62 @@
+use borsh::BorshDeserialize;
+use phoenix_types::events::MarketEvent;
+
+/// Decodes the `MarketEvent`s that follow the header of a Phoenix log instruction one at a time,
+/// directly from the payload without copying it.
+///
+/// This yields the same events, in the same order, as Borsh-decoding the payload as a
+/// `Vec<MarketEvent>` of length `total_events`. Like `try_from_slice`, it yields an error if
+/// bytes are left over after the last event.
+pub struct MarketEventIter<'a> {
+    bytes: &'a [u8],
+    events_left: u32,
+    done: bool,
+}
+
+impl<'a> MarketEventIter<'a> {
+    /// `bytes` is the log payload after the header and `total_events` is the event count
+    /// from the header.
+    pub fn new(bytes: &'a [u8], total_events: u32) -> Self {
+        MarketEventIter {
+            bytes,
+            events_left: total_events,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for MarketEventIter<'_> {
+    type Item = std::io::Result<MarketEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if self.events_left == 0 {
+            self.done = true;
+            if self.bytes.is_empty() {
+                return None;
+            }
+            return Some(Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Not all bytes read",
+            )));
+        }
+        self.events_left -= 1;
+        match MarketEvent::deserialize(&mut self.bytes) {
+            Ok(event) => Some(Ok(event)),
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            (0, Some(0))
+        } else {
+            (0, Some(self.events_left as usize + 1))
+        }
+    }
+}
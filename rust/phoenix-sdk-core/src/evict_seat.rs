@@ -0,0 +1,105 @@
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::phoenix_instruction::KnownPhoenixInstruction;
+
+/// Evicts `trader` from `market`: the trader's orders are canceled, their free funds are sent
+/// to their base and quote token accounts, and their seat is removed. `authority` signs the
+/// instruction and must be the market authority or its successor.
+pub fn create_evict_seat_instruction(
+    market: &Pubkey,
+    authority: &Pubkey,
+    trader: &Pubkey,
+    base_mint: &Pubkey,
+    quote_mint: &Pubkey,
+) -> Instruction {
+    create_evict_seat_instruction_with_program_id(
+        market,
+        authority,
+        trader,
+        base_mint,
+        quote_mint,
+        &phoenix_types::id(),
+    )
+}
+
+pub fn create_evict_seat_instruction_with_program_id(
+    market: &Pubkey,
+    authority: &Pubkey,
+    trader: &Pubkey,
+    base_mint: &Pubkey,
+    quote_mint: &Pubkey,
+    program_id: &Pubkey,
+) -> Instruction {
+    let (log_authority, _) = Pubkey::find_program_address(&[b"log"], program_id);
+    let (seat, _) =
+        Pubkey::find_program_address(&[b"seat", market.as_ref(), trader.as_ref()], program_id);
+    let (base_vault, _) =
+        Pubkey::find_program_address(&[b"vault", market.as_ref(), base_mint.as_ref()], program_id);
+    let (quote_vault, _) = Pubkey::find_program_address(
+        &[b"vault", market.as_ref(), quote_mint.as_ref()],
+        program_id,
+    );
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*program_id, false),
+            AccountMeta::new_readonly(log_authority, false),
+            AccountMeta::new(*market, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new_readonly(*trader, false),
+            AccountMeta::new_readonly(seat, false),
+            AccountMeta::new(get_associated_token_address(trader, base_mint), false),
+            AccountMeta::new(get_associated_token_address(trader, quote_mint), false),
+            AccountMeta::new(base_vault, false),
+            AccountMeta::new(quote_vault, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: vec![KnownPhoenixInstruction::EvictSeat.into()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evict_seat_instruction_tag_and_accounts() {
+        let market = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let trader = Pubkey::new_unique();
+        let base_mint = Pubkey::new_unique();
+        let quote_mint = Pubkey::new_unique();
+        let program_id = phoenix_types::id();
+
+        let ix =
+            create_evict_seat_instruction(&market, &authority, &trader, &base_mint, &quote_mint);
+
+        assert_eq!(ix.program_id, program_id);
+        assert_eq!(ix.data, vec![106]);
+        let (seat, _) =
+            Pubkey::find_program_address(&[b"seat", market.as_ref(), trader.as_ref()], &program_id);
+        let keys = ix
+            .accounts
+            .iter()
+            .map(|meta| meta.pubkey)
+            .collect::<Vec<_>>();
+        assert_eq!(keys[2], market);
+        assert_eq!(keys[3], authority);
+        assert_eq!(keys[4], trader);
+        assert_eq!(keys[5], seat);
+        assert_eq!(keys[6], get_associated_token_address(&trader, &base_mint));
+        assert_eq!(keys[7], get_associated_token_address(&trader, &quote_mint));
+        assert_eq!(keys[10], spl_token::id());
+        let signers = ix
+            .accounts
+            .iter()
+            .filter(|meta| meta.is_signer)
+            .map(|meta| meta.pubkey)
+            .collect::<Vec<_>>();
+        assert_eq!(signers, vec![authority]);
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// Settings of a `FairValueAggregator`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FairValueConfig {
+    /// Prices older than this are left out of the estimate
+    pub max_source_age: Duration,
+    /// The estimates of this past window are used to measure volatility
+    pub volatility_window: Duration,
+    /// Fewer fresh sources than this give no estimate
+    pub min_sources: usize,
+}
+
+impl Default for FairValueConfig {
+    fn default() -> Self {
+        FairValueConfig {
+            max_source_age: Duration::from_secs(5),
+            volatility_window: Duration::from_secs(60),
+            min_sources: 1,
+        }
+    }
+}
+
+/// A fair price combined from several feeds, with how far it can be trusted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FairValueEstimate {
+    /// The median of the fresh sources
+    pub price: f64,
+    /// How uncertain `price` is, in basis points of it: half of `max_disagreement_bps` plus
+    /// `volatility_bps`. 0 when every source agrees and the price has not moved.
+    pub confidence_bps: f64,
+    pub sources_used: usize,
+    /// The spread between the highest and lowest fresh source, in basis points of `price`
+    pub max_disagreement_bps: f64,
+    /// The range of the estimates over the volatility window, in basis points of `price`
+    pub volatility_bps: f64,
+    /// The age of the oldest fresh source
+    pub stalest_source_age: Duration,
+}
+
+/// Combines the prices of several fair price feeds, e.g. Binance and Coinbase, into a
+/// `FairValueEstimate`. Each source keeps its latest price; `estimate` takes the median of the
+/// ones updated within `max_source_age` and scores the disagreement among them and the recent
+/// volatility of the estimate. Times are passed in, so the aggregator can be driven by
+/// recorded feeds.
+#[derive(Clone, Debug)]
+pub struct FairValueAggregator {
+    pub config: FairValueConfig,
+    sources: BTreeMap<String, (f64, Instant)>,
+    /// Past estimated prices, oldest first
+    history: VecDeque<(Instant, f64)>,
+}
+
+impl FairValueAggregator {
+    pub fn new(config: FairValueConfig) -> Self {
+        FairValueAggregator {
+            config,
+            sources: BTreeMap::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Records the latest price of `source`, received at `now`. Non-positive and non-finite
+    /// prices are ignored.
+    pub fn record(&mut self, source: &str, price: f64, now: Instant) {
+        if !price.is_finite() || price <= 0.0 {
+            return;
+        }
+        self.sources.insert(source.to_string(), (price, now));
+    }
+
+    /// The estimate at `now`, None if fewer than `min_sources` sources are fresh. Each
+    /// estimate is remembered for the volatility of later ones, so call this once per update.
+    pub fn estimate(&mut self, now: Instant) -> Option<FairValueEstimate> {
+        let mut prices = vec![];
+        let mut stalest_source_age = Duration::ZERO;
+        for &(price, updated_at) in self.sources.values() {
+            let age = now.saturating_duration_since(updated_at);
+            if age > self.config.max_source_age {
+                continue;
+            }
+            prices.push(price);
+            stalest_source_age = stalest_source_age.max(age);
+        }
+        if prices.is_empty() || prices.len() < self.config.min_sources {
+            return None;
+        }
+        prices.sort_by(f64::total_cmp);
+        let mid = prices.len() / 2;
+        let price = if prices.len() % 2 == 0 {
+            (prices[mid - 1] + prices[mid]) / 2.0
+        } else {
+            prices[mid]
+        };
+        let to_bps = |spread: f64| spread / price * 10_000.0;
+        let max_disagreement_bps = to_bps(prices[prices.len() - 1] - prices[0]);
+
+        self.history.push_back((now, price));
+        while self
+            .history
+            .front()
+            .map(|(ts, _)| now.saturating_duration_since(*ts) > self.config.volatility_window)
+            .unwrap_or(false)
+        {
+            self.history.pop_front();
+        }
+        let (low, high) = self
+            .history
+            .iter()
+            .fold((price, price), |(low, high), &(_, p)| {
+                (low.min(p), high.max(p))
+            });
+        let volatility_bps = to_bps(high - low);
+
+        Some(FairValueEstimate {
+            price,
+            confidence_bps: max_disagreement_bps / 2.0 + volatility_bps,
+            sources_used: prices.len(),
+            max_disagreement_bps,
+            volatility_bps,
+            stalest_source_age,
+        })
+    }
+}
+
+/// How a quoting strategy widens its edge as a `FairValueEstimate` gets less certain.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConfidenceWidening {
+    /// Edge added per basis point of `confidence_bps`. 1.0 widens by half the disagreement
+    /// between the feeds plus the volatility.
+    pub edge_per_confidence_bps: f64,
+    /// Above this `confidence_bps`, the strategy should not quote at all
+    pub max_confidence_bps: f64,
+}
+
+impl Default for ConfidenceWidening {
+    fn default() -> Self {
+        ConfidenceWidening {
+            edge_per_confidence_bps: 1.0,
+            max_confidence_bps: 100.0,
+        }
+    }
+}
+
+impl ConfidenceWidening {
+    /// The edge, in basis points, to quote at around `estimate.price` given the strategy's
+    /// `base_edge_bps`, or None if the estimate is too uncertain to quote.
+    pub fn edge_bps(&self, base_edge_bps: f64, estimate: &FairValueEstimate) -> Option<f64> {
+        if estimate.confidence_bps > self.max_confidence_bps {
+            return None;
+        }
+        Some(base_edge_bps + estimate.confidence_bps * self.edge_per_confidence_bps)
+    }
+
+    /// `edge_bps` as a multiple of `base_edge_bps`. None if the estimate is too uncertain or
+    /// `base_edge_bps` is not positive.
+    pub fn edge_multiplier(&self, base_edge_bps: f64, estimate: &FairValueEstimate) -> Option<f64> {
+        if base_edge_bps <= 0.0 {
+            return None;
+        }
+        self.edge_bps(base_edge_bps, estimate)
+            .map(|edge| edge / base_edge_bps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    fn estimate(confidence_bps: f64) -> FairValueEstimate {
+        FairValueEstimate {
+            price: 100.0,
+            confidence_bps,
+            sources_used: 2,
+            max_disagreement_bps: confidence_bps * 2.0,
+            volatility_bps: 0.0,
+            stalest_source_age: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn estimate_is_the_median_and_scores_the_disagreement() {
+        let now = Instant::now();
+        let mut aggregator = FairValueAggregator::new(FairValueConfig::default());
+        aggregator.record("binance", 100.0, now);
+        aggregator.record("coinbase", 102.0, now);
+        aggregator.record("phoenix", 101.5, now);
+
+        let estimate = aggregator.estimate(now).unwrap();
+        assert_close(estimate.price, 101.5);
+        assert_eq!(estimate.sources_used, 3);
+        assert_close(estimate.max_disagreement_bps, 2.0 / 101.5 * 10_000.0);
+        assert_close(estimate.volatility_bps, 0.0);
+        assert_close(estimate.confidence_bps, estimate.max_disagreement_bps / 2.0);
+
+        // With an even number of sources the median is the mean of the middle two
+        aggregator.record("kraken", 100.5, now);
+        assert_close(aggregator.estimate(now).unwrap().price, 101.0);
+    }
+
+    #[test]
+    fn sources_that_all_agree_have_no_uncertainty() {
+        let now = Instant::now();
+        let mut aggregator = FairValueAggregator::new(FairValueConfig::default());
+        aggregator.record("binance", 20.0, now);
+        aggregator.record("coinbase", 20.0, now);
+        assert_close(aggregator.estimate(now).unwrap().confidence_bps, 0.0);
+    }
+
+    #[test]
+    fn stale_sources_are_left_out() {
+        let start = Instant::now();
+        let mut aggregator = FairValueAggregator::new(FairValueConfig {
+            max_source_age: Duration::from_secs(5),
+            min_sources: 2,
+            ..FairValueConfig::default()
+        });
+        aggregator.record("binance", 100.0, start);
+        aggregator.record("coinbase", 110.0, start + Duration::from_secs(4));
+
+        let estimate = aggregator.estimate(start + Duration::from_secs(5)).unwrap();
+        assert_eq!(estimate.sources_used, 2);
+        assert_eq!(estimate.stalest_source_age, Duration::from_secs(5));
+
+        // Binance is now older than max_source_age, leaving fewer than min_sources
+        assert!(aggregator
+            .estimate(start + Duration::from_secs(6))
+            .is_none());
+
+        aggregator.config.min_sources = 1;
+        let estimate = aggregator.estimate(start + Duration::from_secs(6)).unwrap();
+        assert_close(estimate.price, 110.0);
+        assert_eq!(estimate.sources_used, 1);
+        assert_eq!(estimate.stalest_source_age, Duration::from_secs(2));
+    }
+
+    #[test]
+    fn volatility_covers_the_estimates_of_the_window() {
+        let start = Instant::now();
+        let mut aggregator = FairValueAggregator::new(FairValueConfig {
+            max_source_age: Duration::from_secs(120),
+            volatility_window: Duration::from_secs(60),
+            min_sources: 1,
+        });
+        aggregator.record("binance", 100.0, start);
+        aggregator.estimate(start).unwrap();
+
+        let later = start + Duration::from_secs(30);
+        aggregator.record("binance", 101.0, later);
+        let estimate = aggregator.estimate(later).unwrap();
+        assert_close(estimate.volatility_bps, 1.0 / 101.0 * 10_000.0);
+        assert_close(estimate.confidence_bps, estimate.volatility_bps);
+
+        // The estimate at `start` has left the window
+        let estimate = aggregator
+            .estimate(start + Duration::from_secs(61))
+            .unwrap();
+        assert_close(estimate.volatility_bps, 0.0);
+    }
+
+    #[test]
+    fn invalid_prices_are_ignored() {
+        let now = Instant::now();
+        let mut aggregator = FairValueAggregator::new(FairValueConfig::default());
+        aggregator.record("binance", f64::NAN, now);
+        aggregator.record("coinbase", -1.0, now);
+        aggregator.record("phoenix", f64::INFINITY, now);
+        assert!(aggregator.estimate(now).is_none());
+
+        aggregator.record("binance", 50.0, now);
+        aggregator.record("binance", f64::NAN, now);
+        assert_close(aggregator.estimate(now).unwrap().price, 50.0);
+    }
+
+    #[test]
+    fn edge_widens_with_the_confidence() {
+        let widening = ConfidenceWidening {
+            edge_per_confidence_bps: 0.5,
+            max_confidence_bps: 40.0,
+        };
+        assert_close(widening.edge_bps(10.0, &estimate(0.0)).unwrap(), 10.0);
+        assert_close(widening.edge_bps(10.0, &estimate(20.0)).unwrap(), 20.0);
+        assert_close(
+            widening.edge_multiplier(10.0, &estimate(20.0)).unwrap(),
+            2.0,
+        );
+    }
+
+    #[test]
+    fn quoting_stops_above_the_max_confidence() {
+        let widening = ConfidenceWidening::default();
+        assert_close(widening.edge_bps(5.0, &estimate(100.0)).unwrap(), 105.0);
+        assert!(widening.edge_bps(5.0, &estimate(100.1)).is_none());
+        assert!(widening.edge_multiplier(5.0, &estimate(100.1)).is_none());
+    }
+
+    #[test]
+    fn edge_multiplier_needs_a_positive_base_edge() {
+        let widening = ConfidenceWidening::default();
+        assert!(widening.edge_multiplier(0.0, &estimate(1.0)).is_none());
+        assert!(widening.edge_multiplier(-1.0, &estimate(1.0)).is_none());
+    }
+}
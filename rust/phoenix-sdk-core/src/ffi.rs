@@ -0,0 +1,157 @@
+//! A C ABI over the event parsing and unit conversion logic, enabled with the `ffi` feature.
+//!
+//! Build a library for C or C++ with
+//! `cargo rustc -p phoenix-sdk-core --release --features ffi --crate-type staticlib` (or
+//! `cdylib`), and generate the header with `cbindgen --config cbindgen.toml --output
+//! phoenix_sdk_core.h` from the `phoenix-sdk-core` directory.
+//!
+//! Ownership rules:
+//! - A `MarketMetadata` handle returned by `phoenix_metadata_from_market_account` is owned by
+//!   the caller and must be released exactly once with `phoenix_metadata_destroy`.
+//! - A string returned by `phoenix_parse_events_json` is owned by the caller and must be
+//!   released exactly once with `phoenix_string_destroy`. It must not be freed with `free`.
+//! - Input buffers are borrowed for the duration of the call only.
+//!
+//! No function panics across the boundary. Failures are reported as a null pointer or, for the
+//! conversions, as 0.
+
+use std::{
+    collections::BTreeMap,
+    ffi::CString,
+    os::raw::c_char,
+    panic::{catch_unwind, AssertUnwindSafe},
+    slice,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use rand::{rngs::StdRng, SeedableRng};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+
+use crate::sdk_client_core::{MarketMetadata, SDKClientCore};
+
+/// Borrows `len` bytes at `ptr`, treating a null pointer as an empty slice.
+///
+/// # Safety
+/// `ptr` must be null or valid for reads of `len` bytes.
+unsafe fn bytes<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    if ptr.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr, len)
+    }
+}
+
+/// Derives a metadata handle from the full data of a market account and the decimals of its
+/// base and quote mints. Returns null if the data is not a valid market account.
+///
+/// # Safety
+/// `data` must be valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn phoenix_metadata_from_market_account(
+    data: *const u8,
+    len: usize,
+    base_decimals: u32,
+    quote_decimals: u32,
+) -> *mut MarketMetadata {
+    catch_unwind(AssertUnwindSafe(|| {
+        MarketMetadata::from_market_account_data(bytes(data, len), base_decimals, quote_decimals)
+            .map(|metadata| Box::into_raw(Box::new(metadata)))
+            .unwrap_or(std::ptr::null_mut())
+    }))
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Releases a handle returned by `phoenix_metadata_from_market_account`. Null is ignored.
+///
+/// # Safety
+/// `metadata` must be null or a handle that has not been destroyed yet.
+#[no_mangle]
+pub unsafe extern "C" fn phoenix_metadata_destroy(metadata: *mut MarketMetadata) {
+    if !metadata.is_null() {
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(metadata))));
+    }
+}
+
+/// Converts a floating point price to ticks, rounded down. Returns 0 if `metadata` is null.
+///
+/// # Safety
+/// `metadata` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn phoenix_float_price_to_ticks(
+    metadata: *const MarketMetadata,
+    price: f64,
+) -> u64 {
+    match metadata.as_ref() {
+        Some(metadata) => {
+            catch_unwind(AssertUnwindSafe(|| metadata.float_price_to_ticks(price))).unwrap_or(0)
+        }
+        None => 0,
+    }
+}
+
+/// Converts ticks to a floating point price. Returns 0 if `metadata` is null.
+///
+/// # Safety
+/// `metadata` must be null or a live handle.
+#[no_mangle]
+pub unsafe extern "C" fn phoenix_ticks_to_float_price(
+    metadata: *const MarketMetadata,
+    ticks: u64,
+) -> f64 {
+    match metadata.as_ref() {
+        Some(metadata) => {
+            catch_unwind(AssertUnwindSafe(|| metadata.ticks_to_float_price(ticks))).unwrap_or(0.0)
+        }
+        None => 0.0,
+    }
+}
+
+/// Parses one Phoenix log instruction payload (header included) of the transaction with the
+/// 64 byte `signature`, emitted by the market whose 32 byte key is `market`. Returns the events
+/// as a JSON array, in the format of the `serde` feature, or null if the payload cannot be
+/// parsed.
+///
+/// # Safety
+/// `metadata` must be a live handle, `market` must be valid for reads of 32 bytes, `signature`
+/// for reads of 64 bytes, and `payload` for reads of `payload_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn phoenix_parse_events_json(
+    metadata: *const MarketMetadata,
+    market: *const u8,
+    signature: *const u8,
+    payload: *const u8,
+    payload_len: usize,
+) -> *mut c_char {
+    catch_unwind(AssertUnwindSafe(|| {
+        let metadata = metadata.as_ref()?;
+        let market = Pubkey::try_from(bytes(market, 32)).ok()?;
+        let signature = Signature::new(bytes(signature, 64));
+        let core = SDKClientCore {
+            markets: RwLock::new(BTreeMap::from([(market, *metadata)])),
+            rng: Arc::new(Mutex::new(StdRng::from_entropy())),
+            active_market_key: market,
+            trader: Pubkey::default(),
+            program_id: phoenix_types::id(),
+            order_audit_log: None,
+        };
+        let events =
+            core.parse_phoenix_events(&signature, vec![bytes(payload, payload_len).to_vec()])?;
+        let json = serde_json::to_string(&events).ok()?;
+        CString::new(json).ok().map(CString::into_raw)
+    }))
+    .ok()
+    .flatten()
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Releases a string returned by `phoenix_parse_events_json`. Null is ignored.
+///
+/// # Safety
+/// `s` must be null or a string that has not been destroyed yet.
+#[no_mangle]
+pub unsafe extern "C" fn phoenix_string_destroy(s: *mut c_char) {
+    if !s.is_null() {
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(CString::from_raw(s))));
+    }
+}
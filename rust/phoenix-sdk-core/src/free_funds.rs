@@ -0,0 +1,114 @@
+use borsh::BorshSerialize;
+use phoenix_types::{instructions::CancelUpToParams, order_packet::OrderPacket};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+use crate::phoenix_instruction::KnownPhoenixInstruction;
+
+// The `*WithFreeFunds` instructions only move the trader's deposited funds, so they reference
+// neither the trader's token accounts nor the vaults.
+
+fn free_funds_accounts(market: &Pubkey, trader: &Pubkey, program_id: &Pubkey) -> Vec<AccountMeta> {
+    let (log_authority, _) = Pubkey::find_program_address(&[b"log"], program_id);
+    vec![
+        AccountMeta::new_readonly(*program_id, false),
+        AccountMeta::new_readonly(log_authority, false),
+        AccountMeta::new(*market, false),
+        AccountMeta::new_readonly(*trader, true),
+    ]
+}
+
+/// Places `order_packet` using only the trader's deposited funds. Immediate-or-cancel packets
+/// are sent as `SwapWithFreeFunds`, every other packet as `PlaceLimitOrderWithFreeFunds`. The
+/// proceeds of the order stay deposited on the market.
+pub fn create_new_order_with_free_funds_instruction(
+    market: &Pubkey,
+    trader: &Pubkey,
+    order_packet: &OrderPacket,
+) -> Instruction {
+    create_new_order_with_free_funds_instruction_with_program_id(
+        market,
+        trader,
+        order_packet,
+        &phoenix_types::id(),
+    )
+}
+
+pub fn create_new_order_with_free_funds_instruction_with_program_id(
+    market: &Pubkey,
+    trader: &Pubkey,
+    order_packet: &OrderPacket,
+    program_id: &Pubkey,
+) -> Instruction {
+    let kind = match order_packet {
+        OrderPacket::ImmediateOrCancel { .. } => KnownPhoenixInstruction::SwapWithFreeFunds,
+        _ => KnownPhoenixInstruction::PlaceLimitOrderWithFreeFunds,
+    };
+    let (seat, _) =
+        Pubkey::find_program_address(&[b"seat", market.as_ref(), trader.as_ref()], program_id);
+    let mut accounts = free_funds_accounts(market, trader, program_id);
+    accounts.push(AccountMeta::new_readonly(seat, false));
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: [vec![kind.into()], order_packet.try_to_vec().unwrap()].concat(),
+    }
+}
+
+/// Cancels all of the trader's orders on `market`, leaving the released funds deposited.
+pub fn create_cancel_all_orders_with_free_funds_instruction(
+    market: &Pubkey,
+    trader: &Pubkey,
+) -> Instruction {
+    create_cancel_all_orders_with_free_funds_instruction_with_program_id(
+        market,
+        trader,
+        &phoenix_types::id(),
+    )
+}
+
+pub fn create_cancel_all_orders_with_free_funds_instruction_with_program_id(
+    market: &Pubkey,
+    trader: &Pubkey,
+    program_id: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: free_funds_accounts(market, trader, program_id),
+        data: vec![KnownPhoenixInstruction::CancelAllOrdersWithFreeFunds.into()],
+    }
+}
+
+/// Cancels the trader's orders on `market` selected by `params`, leaving the released funds
+/// deposited.
+pub fn create_cancel_up_to_with_free_funds_instruction(
+    market: &Pubkey,
+    trader: &Pubkey,
+    params: &CancelUpToParams,
+) -> Instruction {
+    create_cancel_up_to_with_free_funds_instruction_with_program_id(
+        market,
+        trader,
+        params,
+        &phoenix_types::id(),
+    )
+}
+
+pub fn create_cancel_up_to_with_free_funds_instruction_with_program_id(
+    market: &Pubkey,
+    trader: &Pubkey,
+    params: &CancelUpToParams,
+    program_id: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: free_funds_accounts(market, trader, program_id),
+        data: [
+            vec![KnownPhoenixInstruction::CancelUpToWithFreeFunds.into()],
+            params.try_to_vec().unwrap(),
+        ]
+        .concat(),
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::BTreeSet;
+
+use solana_program::{instruction::Instruction, pubkey::Pubkey, system_program};
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::sdk_client_core::MarketMetadata;
+
+/// The set of accounts that Phoenix instructions for a given market and trader are allowed to
+/// reference.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExpectedAccounts {
+    pub accounts: BTreeSet<Pubkey>,
+}
+
+/// An account referenced by an instruction that is not in the expected set.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnexpectedAccount {
+    /// Index of the offending instruction in the audited list (0 for a single instruction).
+    pub instruction_index: usize,
+    /// Index into the instruction's account metas, or None if the program id is unexpected.
+    pub account_index: Option<usize>,
+    pub pubkey: Pubkey,
+}
+
+impl ExpectedAccounts {
+    pub fn for_market_and_trader(
+        metadata: &MarketMetadata,
+        market: &Pubkey,
+        trader: &Pubkey,
+    ) -> Self {
+        Self::for_market_and_trader_with_program_id(metadata, market, trader, &phoenix_types::id())
+    }
+
+    /// Derives the program, log authority, market, trader, seat, vaults, trader ATAs, and the
+    /// token and system programs.
+    pub fn for_market_and_trader_with_program_id(
+        metadata: &MarketMetadata,
+        market: &Pubkey,
+        trader: &Pubkey,
+        program_id: &Pubkey,
+    ) -> Self {
+        let (log_authority, _) = Pubkey::find_program_address(&[b"log"], program_id);
+        let (seat, _) =
+            Pubkey::find_program_address(&[b"seat", market.as_ref(), trader.as_ref()], program_id);
+        let (base_vault, _) = Pubkey::find_program_address(
+            &[b"vault", market.as_ref(), metadata.base_mint.as_ref()],
+            program_id,
+        );
+        let (quote_vault, _) = Pubkey::find_program_address(
+            &[b"vault", market.as_ref(), metadata.quote_mint.as_ref()],
+            program_id,
+        );
+        let accounts = [
+            *program_id,
+            log_authority,
+            *market,
+            *trader,
+            seat,
+            base_vault,
+            quote_vault,
+            get_associated_token_address(trader, &metadata.base_mint),
+            get_associated_token_address(trader, &metadata.quote_mint),
+            spl_token::id(),
+            system_program::id(),
+        ]
+        .into_iter()
+        .collect();
+        ExpectedAccounts { accounts }
+    }
+
+    pub fn insert(&mut self, account: Pubkey) -> bool {
+        self.accounts.insert(account)
+    }
+
+    pub fn contains(&self, account: &Pubkey) -> bool {
+        self.accounts.contains(account)
+    }
+}
+
+fn unexpected_accounts(
+    instruction_index: usize,
+    instruction: &Instruction,
+    expected: &ExpectedAccounts,
+) -> Vec<UnexpectedAccount> {
+    let mut unexpected = vec![];
+    if !expected.contains(&instruction.program_id) {
+        unexpected.push(UnexpectedAccount {
+            instruction_index,
+            account_index: None,
+            pubkey: instruction.program_id,
+        });
+    }
+    for (account_index, meta) in instruction.accounts.iter().enumerate() {
+        if !expected.contains(&meta.pubkey) {
+            unexpected.push(UnexpectedAccount {
+                instruction_index,
+                account_index: Some(account_index),
+                pubkey: meta.pubkey,
+            });
+        }
+    }
+    unexpected
+}
+
+/// Checks that the instruction's program id and every account it references are expected.
+pub fn audit_instruction(
+    instruction: &Instruction,
+    expected: &ExpectedAccounts,
+) -> Result<(), Vec<UnexpectedAccount>> {
+    audit_instructions(std::slice::from_ref(instruction), expected)
+}
+
+/// Audits every instruction of a transaction, reporting all unexpected accounts at once.
+pub fn audit_instructions(
+    instructions: &[Instruction],
+    expected: &ExpectedAccounts,
+) -> Result<(), Vec<UnexpectedAccount>> {
+    let unexpected = instructions
+        .iter()
+        .enumerate()
+        .flat_map(|(i, instruction)| unexpected_accounts(i, instruction, expected))
+        .collect::<Vec<_>>();
+    if unexpected.is_empty() {
+        Ok(())
+    } else {
+        Err(unexpected)
+    }
+}
@@ -0,0 +1,115 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::{channel, Receiver, Sender},
+    Arc, Mutex,
+};
+
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+
+use crate::{error::PhoenixSdkError, phoenix_instruction::KnownPhoenixInstruction};
+
+/// A process-wide trading halt. Clones share the same state, so one handle can be given to
+/// the SDK client and another to whatever trips it (an operator command, a risk monitor).
+///
+/// While tripped, instructions that increase exposure (new orders, swaps, deposits) are
+/// rejected with `PhoenixSdkError::TradingHalted`. Cancels, reduces, and withdrawals are
+/// still allowed.
+#[derive(Clone, Debug, Default)]
+pub struct KillSwitch {
+    tripped: Arc<AtomicBool>,
+    reason: Arc<Mutex<String>>,
+    subscribers: Arc<Mutex<Vec<Sender<String>>>>,
+}
+
+impl KillSwitch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Halts trading and notifies every subscriber with `reason`. Tripping an already tripped
+    /// switch keeps the original reason.
+    pub fn trip(&self, reason: &str) {
+        let mut current_reason = self.reason.lock().unwrap();
+        if self.tripped.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        *current_reason = reason.to_string();
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(reason.to_string()).is_ok());
+    }
+
+    pub fn reset(&self) {
+        let mut reason = self.reason.lock().unwrap();
+        self.tripped.store(false, Ordering::SeqCst);
+        reason.clear();
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        self.tripped.load(Ordering::SeqCst)
+    }
+
+    /// The reason the switch was tripped, or None if trading is allowed
+    pub fn reason(&self) -> Option<String> {
+        if self.is_tripped() {
+            Some(self.reason.lock().unwrap().clone())
+        } else {
+            None
+        }
+    }
+
+    /// Returns a receiver that gets the reason every time the switch is tripped, e.g. so a
+    /// quoting loop can cancel all of its orders immediately.
+    pub fn subscribe(&self) -> Receiver<String> {
+        let (sender, receiver) = channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn check(&self) -> Result<(), PhoenixSdkError> {
+        match self.reason() {
+            Some(reason) => Err(PhoenixSdkError::TradingHalted { reason }),
+            None => Ok(()),
+        }
+    }
+
+    /// Fails with `TradingHalted` if the switch is tripped and any of `instructions` is a
+    /// risk-increasing Phoenix instruction for `program_id`.
+    pub fn check_instructions(
+        &self,
+        instructions: &[Instruction],
+        program_id: &Pubkey,
+    ) -> Result<(), PhoenixSdkError> {
+        if !self.is_tripped() {
+            return Ok(());
+        }
+        if instructions
+            .iter()
+            .any(|ix| is_risk_increasing(ix, program_id))
+        {
+            return self.check();
+        }
+        Ok(())
+    }
+}
+
+/// True if `instruction` is a Phoenix instruction that can add exposure: a swap, a new order,
+/// or a deposit.
+pub fn is_risk_increasing(instruction: &Instruction, program_id: &Pubkey) -> bool {
+    if instruction.program_id != *program_id {
+        return false;
+    }
+    matches!(
+        KnownPhoenixInstruction::from_instruction_data(&instruction.data),
+        Some(
+            KnownPhoenixInstruction::Swap
+                | KnownPhoenixInstruction::SwapWithFreeFunds
+                | KnownPhoenixInstruction::PlaceLimitOrder
+                | KnownPhoenixInstruction::PlaceLimitOrderWithFreeFunds
+                | KnownPhoenixInstruction::PlaceMultiplePostOnlyOrders
+                | KnownPhoenixInstruction::PlaceMultiplePostOnlyOrdersWithFreeFunds
+                | KnownPhoenixInstruction::DepositFunds
+        )
+    )
+}
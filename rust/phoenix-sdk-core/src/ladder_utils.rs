@@ -0,0 +1,452 @@
+use phoenix_types::{
+    enums::Side,
+    market::{Ladder, LadderOrder},
+};
+
+use crate::{error::PhoenixSdkError, sdk_client_core::MarketMetadata};
+
+/// The result of simulating a market order against a ladder. Fees are not included.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SimulationSummaryInLots {
+    pub base_lots_filled: u64,
+    pub quote_lots_filled: u64,
+}
+
+/// A simulation result in floating point units. Fees are not included.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SimulationSummary {
+    pub base_units_filled: f64,
+    pub quote_units_filled: f64,
+    /// Quote units per base unit, 0 if nothing filled
+    pub average_price: f64,
+    /// How much worse the average price is than the top of book, 0 if nothing filled
+    pub price_impact_bps: f64,
+}
+
+pub trait MarketSimulator {
+    /// Simulates selling `num_quote_lots` quote lots into the asks, i.e. a market buy with a
+    /// quote lot budget.
+    fn sell_quote(&self, num_quote_lots: u64) -> SimulationSummaryInLots;
+
+    /// Simulates selling `num_base_lots` base lots into the bids, i.e. a market sell.
+    fn sell_base(&self, num_base_lots: u64) -> SimulationSummaryInLots;
+
+    /// Simulates a market buy that spends up to `quote_lots_budget` quote lots on the asks.
+    /// Same as `sell_quote`.
+    fn buy_base_with_quote(&self, quote_lots_budget: u64) -> SimulationSummaryInLots {
+        self.sell_quote(quote_lots_budget)
+    }
+
+    /// Simulates a market sell of `num_base_lots` base lots into the bids, receiving quote.
+    /// Same as `sell_base`.
+    fn buy_quote_with_base(&self, num_base_lots: u64) -> SimulationSummaryInLots {
+        self.sell_base(num_base_lots)
+    }
+}
+
+/// A ladder together with the metadata needed to convert between ticks, base lots, and quote lots.
+pub struct LadderSimulator<'a> {
+    pub ladder: &'a Ladder,
+    pub metadata: &'a MarketMetadata,
+}
+
+impl<'a> LadderSimulator<'a> {
+    pub fn new(ladder: &'a Ladder, metadata: &'a MarketMetadata) -> Self {
+        LadderSimulator { ladder, metadata }
+    }
+
+    /// Quote lots per (base lot * tick), expressed as a fraction `(numerator, denominator)`
+    fn quote_lots_per_base_lot_tick(&self) -> (u128, u128) {
+        (
+            self.metadata.tick_size_in_quote_atoms_per_base_unit as u128,
+            self.metadata.num_base_lots_per_base_unit as u128
+                * self.metadata.quote_lot_size as u128,
+        )
+    }
+
+    fn level_quote_lots(&self, order: &LadderOrder) -> u64 {
+        let (num, denom) = self.quote_lots_per_base_lot_tick();
+        (order.size_in_base_lots as u128 * order.price_in_ticks as u128 * num / denom) as u64
+    }
+
+    /// The total size a taker on `side` can fill while every level stays within
+    /// `max_slippage_bps` of the best level. A `Side::Bid` walks the asks and a `Side::Ask`
+    /// walks the bids. The boundary is inclusive: a level exactly `max_slippage_bps` away
+    /// from the best level is filled.
+    pub fn max_size_within_slippage(
+        &self,
+        side: Side,
+        max_slippage_bps: u64,
+    ) -> SimulationSummaryInLots {
+        let levels = match side {
+            Side::Bid => &self.ladder.asks,
+            Side::Ask => &self.ladder.bids,
+        };
+        let best_price = match levels.first() {
+            Some(order) => order.price_in_ticks as u128,
+            None => return SimulationSummaryInLots::default(),
+        };
+        let mut summary = SimulationSummaryInLots::default();
+        for order in levels.iter() {
+            let deviation = (order.price_in_ticks as u128).abs_diff(best_price);
+            if deviation * 10_000 > max_slippage_bps as u128 * best_price {
+                break;
+            }
+            summary.base_lots_filled += order.size_in_base_lots;
+            summary.quote_lots_filled += self.level_quote_lots(order);
+        }
+        summary
+    }
+
+    /// Simulates an immediate-or-cancel order. A `Side::Bid` walks the asks and a `Side::Ask`
+    /// walks the bids. Matching stops at whichever limit is hit first: `size_in_base_lots`
+    /// base lots traded or `quote_lot_budget` quote lots traded. A limit of None is unbounded,
+    /// so with both None the whole side of the book is consumed.
+    pub fn simulate_taker_order(
+        &self,
+        side: Side,
+        size_in_base_lots: Option<u64>,
+        quote_lot_budget: Option<u64>,
+    ) -> SimulationSummaryInLots {
+        let (num, denom) = self.quote_lots_per_base_lot_tick();
+        let levels = match side {
+            Side::Bid => &self.ladder.asks,
+            Side::Ask => &self.ladder.bids,
+        };
+        let mut summary = SimulationSummaryInLots::default();
+        let mut remaining_base_lots = size_in_base_lots.unwrap_or(u64::MAX);
+        let mut remaining_quote_lots = quote_lot_budget.unwrap_or(u64::MAX);
+        for order in levels.iter() {
+            if remaining_base_lots == 0 || remaining_quote_lots == 0 {
+                break;
+            }
+            let price = order.price_in_ticks as u128 * num;
+            let mut base_lots = order.size_in_base_lots.min(remaining_base_lots);
+            let mut quote_lots = (base_lots as u128 * price / denom) as u64;
+            if quote_lots > remaining_quote_lots {
+                base_lots = (remaining_quote_lots as u128 * denom / price) as u64;
+                quote_lots = (base_lots as u128 * price / denom) as u64;
+                remaining_quote_lots = 0;
+            } else {
+                remaining_quote_lots -= quote_lots;
+            }
+            remaining_base_lots -= base_lots;
+            summary.base_lots_filled += base_lots;
+            summary.quote_lots_filled += quote_lots;
+        }
+        summary
+    }
+}
+
+impl MarketSimulator for LadderSimulator<'_> {
+    fn sell_quote(&self, num_quote_lots: u64) -> SimulationSummaryInLots {
+        let (num, denom) = self.quote_lots_per_base_lot_tick();
+        let mut summary = SimulationSummaryInLots::default();
+        let mut remaining_quote_lots = num_quote_lots;
+        for order in self.ladder.asks.iter() {
+            if remaining_quote_lots == 0 {
+                break;
+            }
+            let level_quote_lots = self.level_quote_lots(order);
+            if level_quote_lots <= remaining_quote_lots {
+                summary.base_lots_filled += order.size_in_base_lots;
+                summary.quote_lots_filled += level_quote_lots;
+                remaining_quote_lots -= level_quote_lots;
+            } else {
+                // Partially fill the level with whatever the remaining budget can afford
+                let base_lots = (remaining_quote_lots as u128 * denom
+                    / (order.price_in_ticks as u128 * num)) as u64;
+                let quote_lots =
+                    (base_lots as u128 * order.price_in_ticks as u128 * num / denom) as u64;
+                summary.base_lots_filled += base_lots;
+                summary.quote_lots_filled += quote_lots;
+                break;
+            }
+        }
+        summary
+    }
+
+    fn sell_base(&self, num_base_lots: u64) -> SimulationSummaryInLots {
+        let (num, denom) = self.quote_lots_per_base_lot_tick();
+        let mut summary = SimulationSummaryInLots::default();
+        let mut remaining_base_lots = num_base_lots;
+        for order in self.ladder.bids.iter() {
+            if remaining_base_lots == 0 {
+                break;
+            }
+            let base_lots = order.size_in_base_lots.min(remaining_base_lots);
+            summary.base_lots_filled += base_lots;
+            summary.quote_lots_filled +=
+                (base_lots as u128 * order.price_in_ticks as u128 * num / denom) as u64;
+            remaining_base_lots -= base_lots;
+        }
+        summary
+    }
+}
+
+/// How the minimum output of an IOC order with slippage is chosen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlippageMode {
+    /// Use this many lots as the minimum output
+    Static(u64),
+    /// Simulate the order against a fresh ladder and accept up to `max_bps` basis points less
+    /// than the simulated output
+    Relative { max_bps: u64 },
+}
+
+impl SlippageMode {
+    /// The minimum output for an IOC order of `lots_in` lots on `side`, in the units of
+    /// `SDKClientCore::get_ioc_with_slippage_ix`: a `Side::Bid` spends `lots_in` quote lots
+    /// and receives base lots, a `Side::Ask` sells `lots_in` base lots and receives quote lots.
+    ///
+    /// In `Relative` mode this returns `InsufficientLiquidity` if the ladder cannot absorb all
+    /// of `lots_in`.
+    pub fn min_lots_out(
+        &self,
+        ladder: &Ladder,
+        metadata: &MarketMetadata,
+        side: Side,
+        lots_in: u64,
+    ) -> Result<u64, PhoenixSdkError> {
+        let max_bps = match *self {
+            SlippageMode::Static(min_lots_out) => return Ok(min_lots_out),
+            SlippageMode::Relative { max_bps } => max_bps.min(10_000),
+        };
+        let simulator = LadderSimulator::new(ladder, metadata);
+        let (max_fillable, lots_out) = match side {
+            Side::Bid => (
+                simulator.sell_quote(u64::MAX).quote_lots_filled,
+                simulator.sell_quote(lots_in).base_lots_filled,
+            ),
+            Side::Ask => (
+                simulator.sell_base(u64::MAX).base_lots_filled,
+                simulator.sell_base(lots_in).quote_lots_filled,
+            ),
+        };
+        if lots_in > max_fillable {
+            return Err(PhoenixSdkError::InsufficientLiquidity {
+                requested_lots: lots_in,
+                fillable_lots: max_fillable,
+            });
+        }
+        Ok((lots_out as u128 * (10_000 - max_bps) as u128 / 10_000) as u64)
+    }
+}
+
+/// Simulates a market order in units. For a `Side::Bid` `size_in_units` is the number of quote
+/// units to spend, for a `Side::Ask` it is the number of base units to sell, matching
+/// `SDKClient::get_swap_ix_with_slippage`.
+pub fn simulate_in_units(
+    ladder: &Ladder,
+    metadata: &MarketMetadata,
+    side: Side,
+    size_in_units: f64,
+) -> SimulationSummary {
+    let simulator = LadderSimulator::new(ladder, metadata);
+    let (summary, top_of_book) = match side {
+        Side::Bid => {
+            let quote_lots = metadata.quote_units_to_quote_lots(size_in_units);
+            (
+                simulator.buy_base_with_quote(quote_lots),
+                ladder.asks.first(),
+            )
+        }
+        Side::Ask => {
+            let base_lots = metadata.base_units_to_base_lots(size_in_units);
+            (
+                simulator.buy_quote_with_base(base_lots),
+                ladder.bids.first(),
+            )
+        }
+    };
+    let base_units_filled = summary.base_lots_filled as f64 * metadata.base_lot_size as f64
+        / metadata.base_multiplier as f64;
+    let quote_units_filled = summary.quote_lots_filled as f64 * metadata.quote_lot_size as f64
+        / metadata.quote_multiplier as f64;
+    if summary.base_lots_filled == 0 {
+        return SimulationSummary::default();
+    }
+    let average_price = quote_units_filled / base_units_filled;
+    let price_impact_bps = top_of_book
+        .map(|order| {
+            let top_price = metadata.ticks_to_float_price(order.price_in_ticks);
+            match side {
+                Side::Bid => (average_price - top_price) / top_price * 10_000.0,
+                Side::Ask => (top_price - average_price) / top_price * 10_000.0,
+            }
+        })
+        .unwrap_or(0.0);
+    SimulationSummary {
+        base_units_filled,
+        quote_units_filled,
+        average_price,
+        price_impact_bps,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::pubkey::Pubkey;
+
+    /// 1000 base lots per base unit and 1000 quote atoms per tick, so a base lot costs its
+    /// price in ticks in quote lots
+    fn metadata() -> MarketMetadata {
+        MarketMetadata {
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            base_decimals: 9,
+            quote_decimals: 6,
+            base_multiplier: 1_000_000_000,
+            quote_multiplier: 1_000_000,
+            quote_lot_size: 1,
+            base_lot_size: 1_000_000,
+            tick_size_in_quote_atoms_per_base_unit: 1_000,
+            num_base_lots_per_base_unit: 1_000,
+        }
+    }
+
+    fn order(price_in_ticks: u64, size_in_base_lots: u64) -> LadderOrder {
+        LadderOrder {
+            price_in_ticks,
+            size_in_base_lots,
+        }
+    }
+
+    /// Asks of 10 lots at 1.00, 20 at 1.01 and 30 at 1.10, bids of 10 lots at 0.99, 20 at
+    /// 0.98 and 30 at 0.90
+    fn ladder() -> Ladder {
+        Ladder {
+            bids: vec![order(990, 10), order(980, 20), order(900, 30)],
+            asks: vec![order(1_000, 10), order(1_010, 20), order(1_100, 30)],
+        }
+    }
+
+    fn summary(base_lots_filled: u64, quote_lots_filled: u64) -> SimulationSummaryInLots {
+        SimulationSummaryInLots {
+            base_lots_filled,
+            quote_lots_filled,
+        }
+    }
+
+    #[test]
+    fn buy_base_with_quote_walks_the_asks() {
+        let (ladder, metadata) = (ladder(), metadata());
+        let simulator = LadderSimulator::new(&ladder, &metadata);
+        // The first level costs 10_000 quote lots, and the remaining 5_000 buy 4 lots at 1_010
+        assert_eq!(simulator.buy_base_with_quote(15_000), summary(14, 14_040));
+        assert_eq!(
+            simulator.buy_base_with_quote(15_000),
+            simulator.sell_quote(15_000)
+        );
+        assert_eq!(simulator.buy_base_with_quote(0), summary(0, 0));
+    }
+
+    #[test]
+    fn buy_quote_with_base_walks_the_bids() {
+        let (ladder, metadata) = (ladder(), metadata());
+        let simulator = LadderSimulator::new(&ladder, &metadata);
+        assert_eq!(simulator.buy_quote_with_base(15), summary(15, 14_800));
+        assert_eq!(simulator.buy_quote_with_base(15), simulator.sell_base(15));
+        assert_eq!(simulator.buy_quote_with_base(u64::MAX), summary(60, 56_500));
+    }
+
+    #[test]
+    fn taker_order_limited_by_base_lots() {
+        let (ladder, metadata) = (ladder(), metadata());
+        let simulator = LadderSimulator::new(&ladder, &metadata);
+        assert_eq!(
+            simulator.simulate_taker_order(Side::Bid, Some(15), None),
+            summary(15, 15_050)
+        );
+        assert_eq!(
+            simulator.simulate_taker_order(Side::Ask, Some(15), None),
+            simulator.buy_quote_with_base(15)
+        );
+    }
+
+    #[test]
+    fn taker_order_limited_by_quote_lots() {
+        let (ladder, metadata) = (ladder(), metadata());
+        let simulator = LadderSimulator::new(&ladder, &metadata);
+        assert_eq!(
+            simulator.simulate_taker_order(Side::Bid, None, Some(15_000)),
+            simulator.buy_base_with_quote(15_000)
+        );
+        // 10 lots at 0.99 and 10 at 0.98 trade 19_700 quote lots
+        assert_eq!(
+            simulator.simulate_taker_order(Side::Ask, None, Some(19_700)),
+            summary(20, 19_700)
+        );
+    }
+
+    #[test]
+    fn taker_order_stops_at_the_first_limit_hit() {
+        let (ladder, metadata) = (ladder(), metadata());
+        let simulator = LadderSimulator::new(&ladder, &metadata);
+        assert_eq!(
+            simulator.simulate_taker_order(Side::Bid, Some(12), Some(15_000)),
+            summary(12, 12_020)
+        );
+        assert_eq!(
+            simulator.simulate_taker_order(Side::Bid, Some(50), Some(15_000)),
+            summary(14, 14_040)
+        );
+        assert_eq!(
+            simulator.simulate_taker_order(Side::Bid, None, None),
+            summary(60, 63_200)
+        );
+    }
+
+    #[test]
+    fn max_size_within_slippage_includes_a_level_on_the_boundary() {
+        let (ladder, metadata) = (ladder(), metadata());
+        let simulator = LadderSimulator::new(&ladder, &metadata);
+        // The second ask, 1.01, is exactly 100 bps above the best ask of 1.00
+        assert_eq!(
+            simulator.max_size_within_slippage(Side::Bid, 100),
+            summary(30, 30_200)
+        );
+        assert_eq!(
+            simulator.max_size_within_slippage(Side::Bid, 99),
+            summary(10, 10_000)
+        );
+        assert_eq!(
+            simulator.max_size_within_slippage(Side::Bid, 1_000),
+            summary(60, 63_200)
+        );
+    }
+
+    #[test]
+    fn max_size_within_slippage_of_the_bids() {
+        let (ladder, metadata) = (ladder(), metadata());
+        let simulator = LadderSimulator::new(&ladder, &metadata);
+        assert_eq!(
+            simulator.max_size_within_slippage(Side::Ask, 0),
+            summary(10, 9_900)
+        );
+        // 0.98 is about 101.01 bps below the best bid of 0.99
+        assert_eq!(
+            simulator.max_size_within_slippage(Side::Ask, 101),
+            summary(10, 9_900)
+        );
+        assert_eq!(
+            simulator.max_size_within_slippage(Side::Ask, 102),
+            summary(30, 29_500)
+        );
+    }
+
+    #[test]
+    fn max_size_within_slippage_of_an_empty_side() {
+        let metadata = metadata();
+        let ladder = Ladder {
+            bids: vec![],
+            asks: vec![],
+        };
+        let simulator = LadderSimulator::new(&ladder, &metadata);
+        assert_eq!(
+            simulator.max_size_within_slippage(Side::Bid, 10_000),
+            SimulationSummaryInLots::default()
+        );
+    }
+}
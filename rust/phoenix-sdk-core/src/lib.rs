@@ -1,3 +1,50 @@
+// Keep the crate buildable on stable Rust
+#![deny(unstable_features)]
+
+pub mod analytics;
+#[cfg(feature = "serde")]
+pub mod debug_dump;
+pub mod display;
+pub mod error;
+pub mod event_decoder;
+pub mod evict_seat;
+pub mod fair_value;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod free_funds;
+pub mod instruction_audit;
+pub mod kill_switch;
+pub mod ladder_utils;
 pub mod market_event;
+pub mod market_fees;
+pub mod multiple_order_packet;
+pub mod order_audit;
+pub mod order_packet_template;
 pub mod orderbook;
+pub mod phoenix_instruction;
 pub mod sdk_client_core;
+#[cfg(feature = "serde")]
+pub mod serde_utils;
+pub mod signature_lru;
+
+// Compile-time check that the public types keep their Debug, Clone, and PartialEq derives
+const _: fn() = || {
+    fn assert_debug_clone<T: std::fmt::Debug + Clone>() {}
+    fn assert_debug_clone_eq<T: std::fmt::Debug + Clone + PartialEq>() {}
+    assert_debug_clone_eq::<error::PhoenixSdkError>();
+    assert_debug_clone::<sdk_client_core::MarketState>();
+    assert_debug_clone_eq::<sdk_client_core::MarketMetadata>();
+    assert_debug_clone_eq::<sdk_client_core::PhoenixOrder>();
+    assert_debug_clone_eq::<order_packet_template::LimitOrderTemplate>();
+    assert_debug_clone_eq::<order_packet_template::PostOnlyOrderTemplate>();
+    assert_debug_clone_eq::<order_packet_template::ImmediateOrCancelOrderTemplate>();
+    assert_debug_clone_eq::<order_packet_template::ReplaceOrderTemplate>();
+    assert_debug_clone_eq::<order_packet_template::OrderValidationError>();
+    assert_debug_clone_eq::<market_event::PhoenixEvent>();
+    assert_debug_clone_eq::<market_event::MarketEventDetails>();
+    assert_debug_clone_eq::<order_audit::OrderAuditRecord>();
+    assert_debug_clone_eq::<market_fees::MarketFeesInfo>();
+    assert_debug_clone_eq::<
+        orderbook::Orderbook<phoenix_types::market::FIFOOrderId, sdk_client_core::PhoenixOrder>,
+    >();
+};
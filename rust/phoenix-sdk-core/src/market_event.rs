@@ -1,14 +1,17 @@
 use phoenix_types::enums::Side;
-use solana_program::pubkey::Pubkey;
+use solana_program::{hash::hashv, pubkey::Pubkey};
 use solana_sdk::signature::Signature;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Fill {
     /// The sequence number of the order that was filled.
     pub order_sequence_number: u64,
     /// The pubkey of the maker.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::pubkey_string"))]
     pub maker: Pubkey,
     /// The pubkey of the taker.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::pubkey_string"))]
     pub taker: Pubkey,
     /// The quote ticks per base unit of the order.
     pub price_in_ticks: u64,
@@ -17,14 +20,17 @@ pub struct Fill {
     /// The number of lots that remain in the order.
     pub base_lots_remaining: u64,
     /// The side of the order that was filled.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::side_string"))]
     pub side_filled: Side,
     /// Whether the order was fully filled.
     pub is_full_fill: bool,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PhoenixEvent {
     /// The pubkey of the market the trade occurred in
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::pubkey_string"))]
     pub market: Pubkey,
     /// The sequence number of the trade event.
     pub sequence_number: u64,
@@ -33,8 +39,13 @@ pub struct PhoenixEvent {
     /// The timestamp of the trade event.
     pub timestamp: i64,
     /// The signature of the transaction that contains this event.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::serde_utils::signature_string")
+    )]
     pub signature: Signature,
     /// The signer of the transaction that contains this event.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::pubkey_string"))]
     pub signer: Pubkey,
     /// The index of the trade in the list of trade_events.
     pub event_index: u64,
@@ -42,11 +53,56 @@ pub struct PhoenixEvent {
     pub details: MarketEventDetails,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// A key that identifies one Phoenix event, see `PhoenixEvent::unique_id`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EventId {
+    pub signature: Signature,
+    pub market: Pubkey,
+    /// The market sequence number of the instruction that emitted the event
+    pub sequence_number: u64,
+    pub event_index: u64,
+}
+
+impl EventId {
+    /// A SHA-256 digest of the fields, for stores that need a fixed size key. The fields are
+    /// hashed in declaration order, each at its fixed width with integers little endian.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        hashv(&[
+            self.signature.as_ref(),
+            self.market.as_ref(),
+            &self.sequence_number.to_le_bytes(),
+            &self.event_index.to_le_bytes(),
+        ])
+        .to_bytes()
+    }
+}
+
+impl PhoenixEvent {
+    /// A key that no other event shares, suitable as a primary or idempotency key.
+    ///
+    /// `event_index` restarts with every Phoenix instruction, so `(signature, event_index)`
+    /// collides when a transaction has several Phoenix instructions on the same market. Each
+    /// such instruction increments the market's sequence number, which the program writes to
+    /// the header of the events it emits, so `(market, sequence_number, event_index)` is unique
+    /// per market. The signature is included so that an event replayed on a minority fork does
+    /// not take the key of the event that was finalized instead.
+    pub fn unique_id(&self) -> EventId {
+        EventId {
+            signature: self.signature,
+            market: self.market,
+            sequence_number: self.sequence_number,
+            event_index: self.event_index,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Reduce {
     /// The sequence number of the order that was reduced.
     pub order_sequence_number: u64,
     /// The pubkey of the maker.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::pubkey_string"))]
     pub maker: Pubkey,
     /// The quote ticks per base unit of the order.
     pub price_in_ticks: u64,
@@ -58,11 +114,13 @@ pub struct Reduce {
     pub is_full_cancel: bool,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Evict {
     /// The sequence number of the order that was evicted.
     pub order_sequence_number: u64,
     /// The pubkey of the maker whose order was evicted.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::pubkey_string"))]
     pub maker: Pubkey,
     /// The price of the order, in quote ticks per base unit
     pub price_in_ticks: u64,
@@ -70,13 +128,15 @@ pub struct Evict {
     pub base_lots_evicted: u64,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Place {
     /// The sequence number of the order that was placed.
     pub order_sequence_number: u64,
     /// The client_order_id of the order that was placed.
     pub client_order_id: u128,
     /// The pubkey of the maker.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::pubkey_string"))]
     pub maker: Pubkey,
     /// The quote ticks per base unit of the order.
     pub price_in_ticks: u64,
@@ -84,7 +144,8 @@ pub struct Place {
     pub base_lots_placed: u64,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FillSummary {
     /// The client_order_id of the order that was filled.
     pub client_order_id: u128,
@@ -98,7 +159,14 @@ pub struct FillSummary {
     pub trade_direction: i8,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// The payload of a Phoenix event.
+///
+/// New event kinds may be added in minor releases, so the enum is `#[non_exhaustive]` and
+/// matches outside this crate need a wildcard arm. Code that only cares about one kind of event
+/// can use the `as_*` accessors instead of matching.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum MarketEventDetails {
     Fill(Fill),
     Place(Place),
@@ -107,3 +175,127 @@ pub enum MarketEventDetails {
     FillSummary(FillSummary),
     Fee(u64),
 }
+
+impl MarketEventDetails {
+    pub fn as_fill(&self) -> Option<&Fill> {
+        match self {
+            MarketEventDetails::Fill(fill) => Some(fill),
+            _ => None,
+        }
+    }
+
+    pub fn as_place(&self) -> Option<&Place> {
+        match self {
+            MarketEventDetails::Place(place) => Some(place),
+            _ => None,
+        }
+    }
+
+    pub fn as_evict(&self) -> Option<&Evict> {
+        match self {
+            MarketEventDetails::Evict(evict) => Some(evict),
+            _ => None,
+        }
+    }
+
+    pub fn as_reduce(&self) -> Option<&Reduce> {
+        match self {
+            MarketEventDetails::Reduce(reduce) => Some(reduce),
+            _ => None,
+        }
+    }
+
+    pub fn as_fill_summary(&self) -> Option<&FillSummary> {
+        match self {
+            MarketEventDetails::FillSummary(fill_summary) => Some(fill_summary),
+            _ => None,
+        }
+    }
+
+    /// The fees collected, in quote lots
+    pub fn as_fee(&self) -> Option<u64> {
+        match self {
+            MarketEventDetails::Fee(fees_collected_in_quote_lots) => {
+                Some(*fees_collected_in_quote_lots)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill() -> MarketEventDetails {
+        MarketEventDetails::Fill(Fill {
+            order_sequence_number: 7,
+            maker: Pubkey::new_unique(),
+            taker: Pubkey::new_unique(),
+            price_in_ticks: 1_000,
+            base_lots_filled: 5,
+            base_lots_remaining: 0,
+            side_filled: Side::Ask,
+            is_full_fill: true,
+        })
+    }
+
+    fn place() -> MarketEventDetails {
+        MarketEventDetails::Place(Place {
+            order_sequence_number: 8,
+            client_order_id: 42,
+            maker: Pubkey::new_unique(),
+            price_in_ticks: 999,
+            base_lots_placed: 10,
+        })
+    }
+
+    fn fill_summary() -> MarketEventDetails {
+        MarketEventDetails::FillSummary(FillSummary {
+            client_order_id: 42,
+            total_base_filled: 5_000_000,
+            total_quote_filled_including_fees: 5_005,
+            total_quote_fees: 5,
+            trade_direction: 1,
+        })
+    }
+
+    #[test]
+    fn as_fill_returns_only_fills() {
+        let details = fill();
+        let fill = details.as_fill().unwrap();
+        assert_eq!(fill.order_sequence_number, 7);
+        assert_eq!(fill.base_lots_filled, 5);
+        assert!(fill.is_full_fill);
+        assert!(place().as_fill().is_none());
+        assert!(fill_summary().as_fill().is_none());
+    }
+
+    #[test]
+    fn as_place_returns_only_places() {
+        let details = place();
+        let place = details.as_place().unwrap();
+        assert_eq!(place.client_order_id, 42);
+        assert_eq!(place.base_lots_placed, 10);
+        assert!(fill().as_place().is_none());
+        assert!(MarketEventDetails::Fee(3).as_place().is_none());
+    }
+
+    #[test]
+    fn as_fill_summary_returns_only_fill_summaries() {
+        let details = fill_summary();
+        let summary = details.as_fill_summary().unwrap();
+        assert_eq!(summary.total_quote_fees, 5);
+        assert_eq!(summary.trade_direction, 1);
+        assert!(fill().as_fill_summary().is_none());
+        assert!(place().as_fill_summary().is_none());
+    }
+
+    #[test]
+    fn accessors_of_other_kinds() {
+        assert_eq!(MarketEventDetails::Fee(3).as_fee(), Some(3));
+        assert!(fill().as_fee().is_none());
+        assert!(fill().as_evict().is_none());
+        assert!(fill().as_reduce().is_none());
+    }
+}
@@ -0,0 +1,278 @@
+use std::mem::size_of;
+
+use borsh::BorshDeserialize;
+use phoenix_types::{dispatch::load_with_dispatch_mut, market::MarketHeader};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::{
+    error::PhoenixSdkError, phoenix_instruction::KnownPhoenixInstruction,
+    sdk_client_core::MarketMetadata,
+};
+
+/// The fee recipient of a market and the fees the market has accrued that were not collected
+/// yet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MarketFeesInfo {
+    pub market: Pubkey,
+    /// Collected fees go to the quote token account of this key
+    pub fee_recipient: Pubkey,
+    pub unclaimed_fees_in_quote_lots: u64,
+    pub unclaimed_fees_in_quote_atoms: u64,
+    pub unclaimed_fees_in_quote_units: f64,
+}
+
+impl MarketFeesInfo {
+    /// Reads the fee recipient from the market header and the unclaimed fees from the market.
+    #[allow(clippy::useless_conversion)]
+    pub fn from_market_account_data(
+        market: Pubkey,
+        market_account_data: &[u8],
+        metadata: &MarketMetadata,
+    ) -> Result<Self, PhoenixSdkError> {
+        if market_account_data.len() < size_of::<MarketHeader>() {
+            return Err(PhoenixSdkError::DeserializationFailed(
+                "Market account data is too short".to_string(),
+            ));
+        }
+        let mut market_account_data = market_account_data.to_vec();
+        let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
+        let header = MarketHeader::try_from_slice(header_bytes)
+            .map_err(|e| PhoenixSdkError::DeserializationFailed(e.to_string()))?;
+        let market_state = load_with_dispatch_mut(&header.market_size_params, bytes)
+            .ok_or_else(|| {
+                PhoenixSdkError::DeserializationFailed(
+                    "Failed to load the market from its account data".to_string(),
+                )
+            })?
+            .inner;
+        let unclaimed_fees_in_quote_lots: u64 = market_state.get_uncollected_fee_amount().into();
+        let unclaimed_fees_in_quote_atoms = unclaimed_fees_in_quote_lots * metadata.quote_lot_size;
+        Ok(MarketFeesInfo {
+            market,
+            fee_recipient: header.fee_recipient,
+            unclaimed_fees_in_quote_lots,
+            unclaimed_fees_in_quote_atoms,
+            unclaimed_fees_in_quote_units: unclaimed_fees_in_quote_atoms as f64
+                / metadata.quote_multiplier as f64,
+        })
+    }
+
+    /// Builds the instruction that collects these fees, signed by `sweeper`. Fails with
+    /// `PhoenixSdkError::NotFeeRecipient` if `sweeper` is not the fee recipient.
+    pub fn collect_fees_instruction(
+        &self,
+        sweeper: &Pubkey,
+        quote_mint: &Pubkey,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        if self.fee_recipient != *sweeper {
+            return Err(PhoenixSdkError::NotFeeRecipient {
+                market: self.market,
+                fee_recipient: self.fee_recipient,
+                signer: *sweeper,
+            });
+        }
+        Ok(create_collect_fees_instruction(
+            &self.market,
+            sweeper,
+            &self.fee_recipient,
+            quote_mint,
+        ))
+    }
+}
+
+/// Sweeps the unclaimed fees of `market` to the quote token account of `fee_recipient`.
+/// `sweeper` signs the instruction.
+pub fn create_collect_fees_instruction(
+    market: &Pubkey,
+    sweeper: &Pubkey,
+    fee_recipient: &Pubkey,
+    quote_mint: &Pubkey,
+) -> Instruction {
+    create_collect_fees_instruction_with_program_id(
+        market,
+        sweeper,
+        fee_recipient,
+        quote_mint,
+        &phoenix_types::id(),
+    )
+}
+
+pub fn create_collect_fees_instruction_with_program_id(
+    market: &Pubkey,
+    sweeper: &Pubkey,
+    fee_recipient: &Pubkey,
+    quote_mint: &Pubkey,
+    program_id: &Pubkey,
+) -> Instruction {
+    let (log_authority, _) = Pubkey::find_program_address(&[b"log"], program_id);
+    let (quote_vault, _) = Pubkey::find_program_address(
+        &[b"vault", market.as_ref(), quote_mint.as_ref()],
+        program_id,
+    );
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*program_id, false),
+            AccountMeta::new_readonly(log_authority, false),
+            AccountMeta::new(*market, false),
+            AccountMeta::new_readonly(*sweeper, true),
+            AccountMeta::new(
+                get_associated_token_address(fee_recipient, quote_mint),
+                false,
+            ),
+            AccountMeta::new(quote_vault, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+        data: vec![KnownPhoenixInstruction::CollectFees.into()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshSerialize;
+    use phoenix_types::market::FIFOMarket;
+
+    fn metadata() -> MarketMetadata {
+        MarketMetadata {
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            base_decimals: 9,
+            quote_decimals: 6,
+            base_multiplier: 1_000_000_000,
+            quote_multiplier: 1_000_000,
+            quote_lot_size: 1,
+            base_lot_size: 1_000_000,
+            tick_size_in_quote_atoms_per_base_unit: 1_000,
+            num_base_lots_per_base_unit: 1_000,
+        }
+    }
+
+    /// The account of an empty 512 bid, 512 ask, 128 seat market with `fee_recipient` and
+    /// `unclaimed_fees` in the unclaimed fees field. The market state starts with
+    /// base_lots_per_base_unit, quote_lots_per_tick, the sequence number, the taker fee, and the
+    /// collected and unclaimed fees, each a u64.
+    fn market_account_data(fee_recipient: Pubkey, unclaimed_fees: u64) -> (MarketHeader, Vec<u8>) {
+        let mut header = MarketHeader::try_from_slice(&[0u8; size_of::<MarketHeader>()]).unwrap();
+        header.market_size_params.bids_size = 512;
+        header.market_size_params.asks_size = 512;
+        header.market_size_params.num_seats = 128;
+        header.fee_recipient = fee_recipient;
+        let mut state = vec![0u8; size_of::<FIFOMarket<Pubkey, 512, 512, 128>>()];
+        state[..8].copy_from_slice(&1_000u64.to_le_bytes());
+        state[40..48].copy_from_slice(&unclaimed_fees.to_le_bytes());
+        let mut data = header.try_to_vec().unwrap();
+        data.extend(state);
+        (header, data)
+    }
+
+    #[test]
+    #[allow(clippy::useless_conversion)]
+    fn fees_info_is_read_from_the_market_account() {
+        let market = Pubkey::new_unique();
+        let fee_recipient = Pubkey::new_unique();
+        let (header, data) = market_account_data(fee_recipient, 5_000_000);
+        let metadata = metadata();
+
+        let info = MarketFeesInfo::from_market_account_data(market, &data, &metadata).unwrap();
+
+        let mut state = data[size_of::<MarketHeader>()..].to_vec();
+        let expected_lots: u64 = load_with_dispatch_mut(&header.market_size_params, &mut state)
+            .unwrap()
+            .inner
+            .get_uncollected_fee_amount()
+            .into();
+        assert_ne!(expected_lots, 0);
+        assert_eq!(info.market, market);
+        assert_eq!(info.fee_recipient, fee_recipient);
+        assert_eq!(info.unclaimed_fees_in_quote_lots, expected_lots);
+        assert_eq!(
+            info.unclaimed_fees_in_quote_atoms,
+            expected_lots * metadata.quote_lot_size
+        );
+        assert_eq!(
+            info.unclaimed_fees_in_quote_units,
+            info.unclaimed_fees_in_quote_atoms as f64 / 1e6
+        );
+    }
+
+    #[test]
+    fn fees_info_rejects_short_data() {
+        assert!(matches!(
+            MarketFeesInfo::from_market_account_data(Pubkey::new_unique(), &[0u8; 10], &metadata()),
+            Err(PhoenixSdkError::DeserializationFailed(_))
+        ));
+    }
+
+    #[test]
+    fn collect_fees_instruction_tag_and_accounts() {
+        let market = Pubkey::new_unique();
+        let sweeper = Pubkey::new_unique();
+        let fee_recipient = Pubkey::new_unique();
+        let quote_mint = Pubkey::new_unique();
+        let program_id = phoenix_types::id();
+
+        let ix = create_collect_fees_instruction(&market, &sweeper, &fee_recipient, &quote_mint);
+
+        assert_eq!(ix.program_id, program_id);
+        assert_eq!(ix.data, vec![108]);
+        let (log_authority, _) = Pubkey::find_program_address(&[b"log"], &program_id);
+        let (quote_vault, _) = Pubkey::find_program_address(
+            &[b"vault", market.as_ref(), quote_mint.as_ref()],
+            &program_id,
+        );
+        let expected = vec![
+            AccountMeta::new_readonly(program_id, false),
+            AccountMeta::new_readonly(log_authority, false),
+            AccountMeta::new(market, false),
+            AccountMeta::new_readonly(sweeper, true),
+            AccountMeta::new(
+                get_associated_token_address(&fee_recipient, &quote_mint),
+                false,
+            ),
+            AccountMeta::new(quote_vault, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ];
+        assert_eq!(ix.accounts, expected);
+    }
+
+    #[test]
+    fn only_the_fee_recipient_can_collect_fees() {
+        let fee_recipient = Pubkey::new_unique();
+        let quote_mint = Pubkey::new_unique();
+        let info = MarketFeesInfo {
+            market: Pubkey::new_unique(),
+            fee_recipient,
+            unclaimed_fees_in_quote_lots: 10,
+            unclaimed_fees_in_quote_atoms: 10,
+            unclaimed_fees_in_quote_units: 0.00001,
+        };
+
+        let ix = info
+            .collect_fees_instruction(&fee_recipient, &quote_mint)
+            .unwrap();
+        assert_eq!(
+            ix,
+            create_collect_fees_instruction(
+                &info.market,
+                &fee_recipient,
+                &fee_recipient,
+                &quote_mint
+            )
+        );
+
+        let other = Pubkey::new_unique();
+        assert_eq!(
+            info.collect_fees_instruction(&other, &quote_mint),
+            Err(PhoenixSdkError::NotFeeRecipient {
+                market: info.market,
+                fee_recipient,
+                signer: other,
+            })
+        );
+    }
+}
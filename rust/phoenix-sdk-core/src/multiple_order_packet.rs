@@ -0,0 +1,174 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::{error::PhoenixSdkError, phoenix_instruction::KnownPhoenixInstruction};
+
+/// Most orders per side of a `MultipleOrderPacket`. A packet with this many bids and asks only
+/// just fits in a legacy transaction that holds nothing but the instruction and is paid by the
+/// trader: each order takes 16 bytes. A compute budget instruction or a separate payer leaves
+/// room for fewer, see `SDKClient::max_post_only_orders_per_transaction`.
+pub const MAX_POST_ONLY_ORDERS_PER_SIDE: usize = 24;
+
+/// A post-only order of a `MultipleOrderPacket`.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CondensedOrder {
+    pub price_in_ticks: u64,
+    pub size_in_base_lots: u64,
+}
+
+/// The argument of `PlaceMultiplePostOnlyOrders`, several post-only orders placed by one
+/// instruction. Every order gets `client_order_id`, 0 if None.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MultipleOrderPacket {
+    pub bids: Vec<CondensedOrder>,
+    pub asks: Vec<CondensedOrder>,
+    pub client_order_id: Option<u128>,
+    /// If true, the whole instruction fails when an order would cross the book. If false,
+    /// crossing orders are amended to the best price that does not cross.
+    pub reject_post_only: bool,
+}
+
+impl MultipleOrderPacket {
+    /// Checks that the packet places at least one order, that no side has more than
+    /// `MAX_POST_ONLY_ORDERS_PER_SIDE` orders, that every order has a price and a size, and
+    /// that the bids are all below the asks.
+    pub fn validate(&self) -> Result<(), PhoenixSdkError> {
+        if self.bids.is_empty() && self.asks.is_empty() {
+            return Err(PhoenixSdkError::InvalidArgument(
+                "Multiple order packet has no orders".to_string(),
+            ));
+        }
+        for (name, orders) in [("bids", &self.bids), ("asks", &self.asks)] {
+            if orders.len() > MAX_POST_ONLY_ORDERS_PER_SIDE {
+                return Err(PhoenixSdkError::InvalidArgument(format!(
+                    "Multiple order packet has {} {}, at most {} fit in one instruction",
+                    orders.len(),
+                    name,
+                    MAX_POST_ONLY_ORDERS_PER_SIDE
+                )));
+            }
+            if let Some(order) = orders
+                .iter()
+                .find(|order| order.price_in_ticks == 0 || order.size_in_base_lots == 0)
+            {
+                return Err(PhoenixSdkError::InvalidArgument(format!(
+                    "Multiple order packet has an order of {} base lots at {} ticks in its {}",
+                    order.size_in_base_lots, order.price_in_ticks, name
+                )));
+            }
+        }
+        let best_bid = self.bids.iter().map(|order| order.price_in_ticks).max();
+        let best_ask = self.asks.iter().map(|order| order.price_in_ticks).min();
+        if let (Some(best_bid), Some(best_ask)) = (best_bid, best_ask) {
+            if best_bid >= best_ask {
+                return Err(PhoenixSdkError::InvalidArgument(format!(
+                    "Multiple order packet crosses itself: bid at {} ticks, ask at {} ticks",
+                    best_bid, best_ask
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn create_place_multiple_post_only_orders_instruction(
+    market: &Pubkey,
+    trader: &Pubkey,
+    base_mint: &Pubkey,
+    quote_mint: &Pubkey,
+    packet: &MultipleOrderPacket,
+) -> Instruction {
+    create_place_multiple_post_only_orders_instruction_with_program_id(
+        market,
+        trader,
+        base_mint,
+        quote_mint,
+        packet,
+        &phoenix_types::id(),
+    )
+}
+
+pub fn create_place_multiple_post_only_orders_instruction_with_program_id(
+    market: &Pubkey,
+    trader: &Pubkey,
+    base_mint: &Pubkey,
+    quote_mint: &Pubkey,
+    packet: &MultipleOrderPacket,
+    program_id: &Pubkey,
+) -> Instruction {
+    let (base_vault, _) =
+        Pubkey::find_program_address(&[b"vault", market.as_ref(), base_mint.as_ref()], program_id);
+    let (quote_vault, _) = Pubkey::find_program_address(
+        &[b"vault", market.as_ref(), quote_mint.as_ref()],
+        program_id,
+    );
+    let mut accounts = trader_seat_accounts(market, trader, program_id);
+    accounts.extend([
+        AccountMeta::new(get_associated_token_address(trader, base_mint), false),
+        AccountMeta::new(get_associated_token_address(trader, quote_mint), false),
+        AccountMeta::new(base_vault, false),
+        AccountMeta::new(quote_vault, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+    ]);
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: [
+            vec![KnownPhoenixInstruction::PlaceMultiplePostOnlyOrders.into()],
+            packet.try_to_vec().unwrap(),
+        ]
+        .concat(),
+    }
+}
+
+/// Like `create_place_multiple_post_only_orders_instruction`, funding the orders only from the
+/// trader's deposited funds, so the trader's token accounts and the vaults are not referenced.
+pub fn create_place_multiple_post_only_orders_with_free_funds_instruction(
+    market: &Pubkey,
+    trader: &Pubkey,
+    packet: &MultipleOrderPacket,
+) -> Instruction {
+    create_place_multiple_post_only_orders_with_free_funds_instruction_with_program_id(
+        market,
+        trader,
+        packet,
+        &phoenix_types::id(),
+    )
+}
+
+pub fn create_place_multiple_post_only_orders_with_free_funds_instruction_with_program_id(
+    market: &Pubkey,
+    trader: &Pubkey,
+    packet: &MultipleOrderPacket,
+    program_id: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: trader_seat_accounts(market, trader, program_id),
+        data: [
+            vec![KnownPhoenixInstruction::PlaceMultiplePostOnlyOrdersWithFreeFunds.into()],
+            packet.try_to_vec().unwrap(),
+        ]
+        .concat(),
+    }
+}
+
+/// The program, log authority, market, trader, and seat
+fn trader_seat_accounts(market: &Pubkey, trader: &Pubkey, program_id: &Pubkey) -> Vec<AccountMeta> {
+    let (log_authority, _) = Pubkey::find_program_address(&[b"log"], program_id);
+    let (seat, _) =
+        Pubkey::find_program_address(&[b"seat", market.as_ref(), trader.as_ref()], program_id);
+    vec![
+        AccountMeta::new_readonly(*program_id, false),
+        AccountMeta::new_readonly(log_authority, false),
+        AccountMeta::new(*market, false),
+        AccountMeta::new_readonly(*trader, true),
+        AccountMeta::new_readonly(seat, false),
+    ]
+}
@@ -0,0 +1,514 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "serde")]
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use borsh::BorshDeserialize;
+use phoenix_types::{
+    enums::{SelfTradeBehavior, Side},
+    order_packet::OrderPacket,
+};
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+
+use crate::{
+    multiple_order_packet::MultipleOrderPacket,
+    order_packet_template::{
+        ImmediateOrCancelOrderTemplate, LimitOrderTemplate, PostOnlyOrderTemplate,
+    },
+    phoenix_instruction::KnownPhoenixInstruction,
+};
+
+/// Phoenix instructions lay out the program, the log authority, the market, and the trader
+/// first
+pub(crate) const MARKET_ACCOUNT_INDEX: usize = 2;
+const TRADER_ACCOUNT_INDEX: usize = 3;
+
+/// When an `OrderAuditRecord` was made.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OrderAuditStage {
+    /// An instruction builder of `SDKClientCore` returned the instruction
+    Built,
+    /// A send helper of `SDKClient` is about to send the instruction
+    Sending,
+}
+
+/// The decoded argument of a swap or place instruction. Lots and ticks are plain integers,
+/// and fields the SDK never sets, like the expiry of an order, are left out.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AuditedOrderPacket {
+    PostOnly {
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::side_string"))]
+        side: Side,
+        price_in_ticks: u64,
+        num_base_lots: u64,
+        client_order_id: u128,
+        reject_post_only: bool,
+        use_only_deposited_funds: bool,
+    },
+    Limit {
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::side_string"))]
+        side: Side,
+        price_in_ticks: u64,
+        num_base_lots: u64,
+        #[cfg_attr(
+            feature = "serde",
+            serde(with = "crate::serde_utils::self_trade_behavior_string")
+        )]
+        self_trade_behavior: SelfTradeBehavior,
+        match_limit: Option<u64>,
+        client_order_id: u128,
+        use_only_deposited_funds: bool,
+    },
+    ImmediateOrCancel {
+        #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::side_string"))]
+        side: Side,
+        /// None for a swap without a limit price, e.g. `get_ioc_with_slippage_ix`
+        price_in_ticks: Option<u64>,
+        num_base_lots: u64,
+        num_quote_lots: u64,
+        min_base_lots_to_fill: u64,
+        min_quote_lots_to_fill: u64,
+        #[cfg_attr(
+            feature = "serde",
+            serde(with = "crate::serde_utils::self_trade_behavior_string")
+        )]
+        self_trade_behavior: SelfTradeBehavior,
+        match_limit: Option<u64>,
+        client_order_id: u128,
+        use_only_deposited_funds: bool,
+    },
+    /// The argument of `PlaceMultiplePostOnlyOrders` and its free funds variant
+    MultiplePostOnly(MultipleOrderPacket),
+}
+
+impl From<&OrderPacket> for AuditedOrderPacket {
+    #[allow(clippy::useless_conversion)]
+    fn from(order_packet: &OrderPacket) -> Self {
+        match *order_packet {
+            OrderPacket::PostOnly {
+                side,
+                price_in_ticks,
+                num_base_lots,
+                client_order_id,
+                reject_post_only,
+                use_only_deposited_funds,
+                ..
+            } => AuditedOrderPacket::PostOnly {
+                side,
+                price_in_ticks: u64::from(price_in_ticks),
+                num_base_lots: u64::from(num_base_lots),
+                client_order_id,
+                reject_post_only,
+                use_only_deposited_funds,
+            },
+            OrderPacket::Limit {
+                side,
+                price_in_ticks,
+                num_base_lots,
+                self_trade_behavior,
+                match_limit,
+                client_order_id,
+                use_only_deposited_funds,
+                ..
+            } => AuditedOrderPacket::Limit {
+                side,
+                price_in_ticks: u64::from(price_in_ticks),
+                num_base_lots: u64::from(num_base_lots),
+                self_trade_behavior,
+                match_limit,
+                client_order_id,
+                use_only_deposited_funds,
+            },
+            OrderPacket::ImmediateOrCancel {
+                side,
+                price_in_ticks,
+                num_base_lots,
+                num_quote_lots,
+                min_base_lots_to_fill,
+                min_quote_lots_to_fill,
+                self_trade_behavior,
+                match_limit,
+                client_order_id,
+                use_only_deposited_funds,
+                ..
+            } => AuditedOrderPacket::ImmediateOrCancel {
+                side,
+                price_in_ticks: price_in_ticks.map(u64::from),
+                num_base_lots: u64::from(num_base_lots),
+                num_quote_lots: u64::from(num_quote_lots),
+                min_base_lots_to_fill: u64::from(min_base_lots_to_fill),
+                min_quote_lots_to_fill: u64::from(min_quote_lots_to_fill),
+                self_trade_behavior,
+                match_limit,
+                client_order_id,
+                use_only_deposited_funds,
+            },
+        }
+    }
+}
+
+impl AuditedOrderPacket {
+    /// Decodes the argument of a swap or place instruction from its data, or returns None for
+    /// other instructions
+    pub fn from_instruction_data(data: &[u8]) -> Option<Self> {
+        match KnownPhoenixInstruction::from_instruction_data(data)? {
+            KnownPhoenixInstruction::Swap
+            | KnownPhoenixInstruction::SwapWithFreeFunds
+            | KnownPhoenixInstruction::PlaceLimitOrder
+            | KnownPhoenixInstruction::PlaceLimitOrderWithFreeFunds => {
+                OrderPacket::try_from_slice(&data[1..])
+                    .ok()
+                    .map(|packet| AuditedOrderPacket::from(&packet))
+            }
+            KnownPhoenixInstruction::PlaceMultiplePostOnlyOrders
+            | KnownPhoenixInstruction::PlaceMultiplePostOnlyOrdersWithFreeFunds => {
+                MultipleOrderPacket::try_from_slice(&data[1..])
+                    .ok()
+                    .map(AuditedOrderPacket::MultiplePostOnly)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The template an order instruction was built from, see `OrderAuditRecord::template`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AuditedTemplate {
+    Limit(LimitOrderTemplate),
+    PostOnly(PostOnlyOrderTemplate),
+    ImmediateOrCancel(ImmediateOrCancelOrderTemplate),
+    /// The templates of one `PlaceMultiplePostOnlyOrders` instruction
+    PostOnlyBatch(Vec<PostOnlyOrderTemplate>),
+}
+
+/// A record of one Phoenix instruction the SDK built or is about to send, see
+/// `OrderAuditLog`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrderAuditRecord {
+    /// Milliseconds since the Unix epoch when the record was made
+    pub wall_time_ms: u64,
+    pub stage: OrderAuditStage,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::pubkey_string"))]
+    pub market: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::pubkey_string"))]
+    pub trader: Pubkey,
+    /// The name of the instruction, e.g. `PlaceLimitOrder`
+    pub instruction: String,
+    /// The decoded order packet of swap and place instructions
+    pub order_packet: Option<AuditedOrderPacket>,
+    /// The template the instruction was built from, if it was built by a `*_from_template`
+    /// builder
+    pub template: Option<AuditedTemplate>,
+    /// The instruction data as sent, so the record can be decoded again independently
+    pub data: Vec<u8>,
+}
+
+/// Receives a record of every Phoenix instruction the SDK builds and, again, before the SDK
+/// sends it, e.g. to keep an append-only trail for compliance. Instructions built by the SDK
+/// and sent with its send helpers are recorded twice, once per `OrderAuditStage`.
+///
+/// A failing hook never blocks order flow: errors are logged by the caller and the
+/// instruction is sent regardless. `record` runs on the sending thread, so expensive work
+/// should be handed off.
+pub trait OrderAuditLog: Send + Sync {
+    fn record(&self, record: &OrderAuditRecord) -> anyhow::Result<()>;
+}
+
+impl OrderAuditRecord {
+    /// The record of `instruction`, a Phoenix instruction
+    pub fn new(
+        instruction: &Instruction,
+        stage: OrderAuditStage,
+        template: Option<AuditedTemplate>,
+    ) -> Self {
+        let wall_time_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        let account = |index: usize| {
+            instruction
+                .accounts
+                .get(index)
+                .map(|meta| meta.pubkey)
+                .unwrap_or_default()
+        };
+        OrderAuditRecord {
+            wall_time_ms,
+            stage,
+            market: account(MARKET_ACCOUNT_INDEX),
+            trader: account(TRADER_ACCOUNT_INDEX),
+            instruction: KnownPhoenixInstruction::from_instruction_data(&instruction.data)
+                .map(|kind| kind.name())
+                .unwrap_or("Unknown")
+                .to_string(),
+            order_packet: AuditedOrderPacket::from_instruction_data(&instruction.data),
+            template,
+            data: instruction.data.clone(),
+        }
+    }
+}
+
+/// Returns a `Sending` record for each instruction of `instructions` that targets
+/// `program_id`. Other instructions, like compute budget instructions, are skipped.
+pub fn order_audit_records(
+    instructions: &[Instruction],
+    program_id: &Pubkey,
+) -> Vec<OrderAuditRecord> {
+    instructions
+        .iter()
+        .filter(|ix| ix.program_id == *program_id)
+        .map(|ix| OrderAuditRecord::new(ix, OrderAuditStage::Sending, None))
+        .collect()
+}
+
+/// An `OrderAuditLog` that appends each record as a line of JSON to a file and syncs it to
+/// disk before returning. Once the file reaches `max_file_bytes`, it is renamed to
+/// `<path>.<unix millis>` and a new file is started.
+#[cfg(feature = "serde")]
+pub struct JsonlOrderAuditLog {
+    path: PathBuf,
+    max_file_bytes: u64,
+    /// The open file and its current size
+    file: Mutex<(File, u64)>,
+}
+
+#[cfg(feature = "serde")]
+impl JsonlOrderAuditLog {
+    /// Opens `path` for appending, creating it if needed.
+    pub fn new(path: impl Into<PathBuf>, max_file_bytes: u64) -> anyhow::Result<Self> {
+        let path = path.into();
+        let file = Self::open(&path)?;
+        let len = file.metadata()?.len();
+        Ok(JsonlOrderAuditLog {
+            path,
+            max_file_bytes,
+            file: Mutex::new((file, len)),
+        })
+    }
+
+    fn open(path: &Path) -> anyhow::Result<File> {
+        Ok(OpenOptions::new().create(true).append(true).open(path)?)
+    }
+
+    fn rotate(&self, file: &mut (File, u64), wall_time_ms: u64) -> anyhow::Result<()> {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(format!(".{}", wall_time_ms));
+        std::fs::rename(&self.path, rotated)?;
+        *file = (Self::open(&self.path)?, 0);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl OrderAuditLog for JsonlOrderAuditLog {
+    fn record(&self, record: &OrderAuditRecord) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().unwrap();
+        if file.1 > 0 && file.1 + line.len() as u64 > self.max_file_bytes {
+            self.rotate(&mut file, record.wall_time_ms)?;
+        }
+        file.0.write_all(&line)?;
+        file.0.sync_data()?;
+        file.1 += line.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sdk_client_core::{MarketMetadata, SDKClientCore};
+    use phoenix_types::market::FIFOOrderId;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::{
+        collections::BTreeMap,
+        sync::{Arc, Mutex, RwLock},
+    };
+
+    #[derive(Default)]
+    struct RecordingLog(Mutex<Vec<OrderAuditRecord>>);
+
+    impl OrderAuditLog for RecordingLog {
+        fn record(&self, record: &OrderAuditRecord) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(record.clone());
+            Ok(())
+        }
+    }
+
+    struct FailingLog;
+
+    impl OrderAuditLog for FailingLog {
+        fn record(&self, _record: &OrderAuditRecord) -> anyhow::Result<()> {
+            Err(anyhow::anyhow!("disk full"))
+        }
+    }
+
+    fn metadata() -> MarketMetadata {
+        MarketMetadata {
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            base_decimals: 9,
+            quote_decimals: 6,
+            base_multiplier: 1_000_000_000,
+            quote_multiplier: 1_000_000,
+            quote_lot_size: 1,
+            base_lot_size: 1_000_000,
+            tick_size_in_quote_atoms_per_base_unit: 1_000,
+            num_base_lots_per_base_unit: 1_000,
+        }
+    }
+
+    fn core(order_audit_log: Arc<dyn OrderAuditLog>) -> SDKClientCore {
+        let market = Pubkey::new_unique();
+        SDKClientCore {
+            markets: RwLock::new(BTreeMap::from([(market, metadata())])),
+            rng: Arc::new(Mutex::new(StdRng::seed_from_u64(0))),
+            active_market_key: market,
+            trader: Pubkey::new_unique(),
+            program_id: phoenix_types::id(),
+            order_audit_log: Some(order_audit_log),
+        }
+    }
+
+    #[test]
+    #[allow(clippy::useless_conversion)]
+    fn every_builder_records_the_instruction_it_returns() {
+        let log = Arc::new(RecordingLog::default());
+        let core = core(log.clone());
+        let order_id = FIFOOrderId {
+            price_in_ticks: 1_000u64.into(),
+            order_sequence_number: 7,
+        };
+        let post_only = PostOnlyOrderTemplate::new(Side::Bid, 1.0, 2.0);
+        let instructions = vec![
+            core.get_ioc_ix(1_000_000, Side::Bid, 10).unwrap(),
+            core.get_fok_sell_ix(1_000_000, 1_000_000_000).unwrap(),
+            core.get_ioc_with_slippage_ix(10, 1, Side::Ask).unwrap(),
+            core.get_ioc_from_tick_price_ix(1_000, Side::Ask, 10)
+                .unwrap(),
+            core.get_post_only_ix(1_000_000, Side::Bid, 10).unwrap(),
+            core.get_post_only_ix_from_tick_price(1_000, Side::Ask, 10, 1, true)
+                .unwrap(),
+            core.get_post_only_ix_from_template(&post_only).unwrap(),
+            core.get_limit_order_ix(1_000_000, Side::Ask, 10).unwrap(),
+            core.get_limit_order_ix_from_tick_price(1_000, Side::Bid, 10, 1)
+                .unwrap(),
+            core.get_limit_order_ix_from_template(&LimitOrderTemplate::new(Side::Ask, 1.0, 2.0))
+                .unwrap(),
+            core.get_ioc_ix_from_template(&ImmediateOrCancelOrderTemplate::new(
+                Side::Bid,
+                1.0,
+                2.0,
+            ))
+            .unwrap(),
+            core.get_place_multiple_post_only_orders_ix(
+                &core.active_market_key,
+                &[(1_000, 10)],
+                &[(2_000, 10)],
+                None,
+                None,
+                Some(true),
+            )
+            .unwrap(),
+            core.get_place_multiple_post_only_orders_ix_from_templates(
+                &core.active_market_key,
+                &[post_only],
+            )
+            .unwrap(),
+            core.get_cancel_ids_ix(vec![order_id]).unwrap(),
+            core.get_reduce_order_ix(order_id, 1).unwrap(),
+            core.get_cancel_up_to_ix(None, Side::Bid).unwrap(),
+            core.get_cancel_all_ix().unwrap(),
+            core.get_cancel_up_to_with_free_funds_ix(None, Side::Ask)
+                .unwrap(),
+            core.get_cancel_all_with_free_funds_ix().unwrap(),
+        ];
+
+        let records = log.0.lock().unwrap();
+        assert_eq!(records.len(), instructions.len());
+        for (record, instruction) in records.iter().zip(&instructions) {
+            assert_eq!(record.stage, OrderAuditStage::Built);
+            assert_eq!(record.market, core.active_market_key);
+            assert_eq!(record.trader, core.trader);
+            assert_eq!(record.data, instruction.data);
+            assert_eq!(
+                record.order_packet,
+                AuditedOrderPacket::from_instruction_data(&instruction.data)
+            );
+        }
+        assert_eq!(
+            records[6].template,
+            Some(AuditedTemplate::PostOnly(post_only))
+        );
+        assert_eq!(
+            records[12].template,
+            Some(AuditedTemplate::PostOnlyBatch(vec![post_only]))
+        );
+        assert!(records[13..]
+            .iter()
+            .all(|record| record.order_packet.is_none()));
+    }
+
+    #[test]
+    fn decoded_packet_matches_the_built_order() {
+        let log = Arc::new(RecordingLog::default());
+        let core = core(log.clone());
+        core.get_post_only_generic_ix(1_000_000, Side::Ask, 25, Some(9), Some(true), None)
+            .unwrap();
+        core.get_ioc_with_min_fill_ix(
+            1_000_000,
+            Side::Bid,
+            10,
+            Some(4),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+        let records = log.0.lock().unwrap();
+        assert_eq!(
+            records[0].order_packet,
+            Some(AuditedOrderPacket::PostOnly {
+                side: Side::Ask,
+                price_in_ticks: metadata().quote_atoms_per_base_unit_to_ticks(1_000_000),
+                num_base_lots: 25,
+                client_order_id: 9,
+                reject_post_only: true,
+                use_only_deposited_funds: false,
+            })
+        );
+        match records[1].order_packet {
+            Some(AuditedOrderPacket::ImmediateOrCancel {
+                side,
+                num_base_lots,
+                min_base_lots_to_fill,
+                min_quote_lots_to_fill,
+                ..
+            }) => {
+                assert_eq!(side, Side::Bid);
+                assert_eq!(num_base_lots, 10);
+                assert_eq!(min_base_lots_to_fill, 4);
+                assert_eq!(min_quote_lots_to_fill, 0);
+            }
+            ref other => panic!("Expected an immediate-or-cancel packet, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn failing_log_does_not_fail_the_builder() {
+        let core = core(Arc::new(FailingLog));
+        assert!(core.get_cancel_all_ix().is_ok());
+    }
+}
@@ -0,0 +1,432 @@
+use std::fmt::Display;
+
+use phoenix_types::{
+    enums::{SelfTradeBehavior, Side},
+    market::FIFOOrderId,
+};
+
+use crate::sdk_client_core::MarketMetadata;
+
+/// Why an order template cannot be turned into a valid order on a market. Returned by the
+/// templates' `validate` and, wrapped in `PhoenixSdkError::InvalidOrder`, by the
+/// `*_from_template` builders.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrderValidationError {
+    /// The price is NaN, infinite, zero, or negative.
+    InvalidPrice { price: f64 },
+    /// The size is NaN, infinite, zero, or negative.
+    InvalidSize { size_in_base_units: f64 },
+    /// The size is smaller than one base lot, so the order would be for 0 lots.
+    SizeBelowOneLot {
+        size_in_base_units: f64,
+        base_lot_in_base_units: f64,
+    },
+    /// The price does not land on a tick the market can represent: it is not a whole number of
+    /// ticks, rounds down to 0 ticks, or overflows. `rounded_price` is the price the order would
+    /// be sent at.
+    PriceOutsideTickGrid { price: f64, rounded_price: f64 },
+    /// A minimum fill of an immediate-or-cancel order is NaN, infinite, or negative, or the
+    /// base minimum is larger than the order.
+    InvalidMinimumFill {
+        min_base_units_to_fill: f64,
+        min_quote_units_to_fill: f64,
+    },
+}
+
+impl Display for OrderValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderValidationError::InvalidPrice { price } => {
+                write!(f, "Price {} is not a positive number", price)
+            }
+            OrderValidationError::InvalidSize { size_in_base_units } => {
+                write!(f, "Size {} is not a positive number", size_in_base_units)
+            }
+            OrderValidationError::SizeBelowOneLot {
+                size_in_base_units,
+                base_lot_in_base_units,
+            } => write!(
+                f,
+                "Size {} is smaller than one base lot of {} base units",
+                size_in_base_units, base_lot_in_base_units
+            ),
+            OrderValidationError::PriceOutsideTickGrid {
+                price,
+                rounded_price,
+            } => write!(
+                f,
+                "Price {} is outside the market's tick grid, it would be sent as {}",
+                price, rounded_price
+            ),
+            OrderValidationError::InvalidMinimumFill {
+                min_base_units_to_fill,
+                min_quote_units_to_fill,
+            } => write!(
+                f,
+                "Invalid minimum fill of {} base units and {} quote units",
+                min_base_units_to_fill, min_quote_units_to_fill
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OrderValidationError {}
+
+/// How far, relative to the price, a template's price may be from the tick it is sent at. This
+/// only absorbs floating point error: a price between two ticks is rejected.
+const TICK_GRID_RELATIVE_TOLERANCE: f64 = 1e-9;
+
+/// Checks the price and size shared by every template, as they are converted by
+/// `MarketMetadata::float_price_to_ticks` and `MarketMetadata::base_units_to_base_lots`.
+fn validate_price_and_size(
+    metadata: &MarketMetadata,
+    price: f64,
+    size_in_base_units: f64,
+) -> Result<(), OrderValidationError> {
+    if !price.is_finite() || price <= 0.0 {
+        return Err(OrderValidationError::InvalidPrice { price });
+    }
+    if !size_in_base_units.is_finite() || size_in_base_units <= 0.0 {
+        return Err(OrderValidationError::InvalidSize { size_in_base_units });
+    }
+    let price_in_ticks = metadata.float_price_to_ticks(price);
+    let rounded_price = metadata.ticks_to_float_price(price_in_ticks);
+    if price_in_ticks == 0
+        || price_in_ticks == u64::MAX
+        || (rounded_price - price).abs() > price * TICK_GRID_RELATIVE_TOLERANCE
+    {
+        return Err(OrderValidationError::PriceOutsideTickGrid {
+            price,
+            rounded_price,
+        });
+    }
+    if metadata.base_units_to_base_lots(size_in_base_units) == 0 {
+        return Err(OrderValidationError::SizeBelowOneLot {
+            size_in_base_units,
+            base_lot_in_base_units: metadata.base_lots_to_base_units_multiplier(),
+        });
+    }
+    Ok(())
+}
+
+/// Describes a limit order in human readable units. The price is a floating point number of
+/// quote units per base unit and the size is a floating point number of base units. These are
+/// converted to ticks and base lots with the market's metadata when the instruction is built.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LimitOrderTemplate {
+    /// The side of the order (Bid or Ask).
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::side_string"))]
+    pub side: Side,
+    /// The price of the order, in quote units per base unit.
+    pub price_as_float: f64,
+    /// The size of the order, in base units.
+    pub size_in_base_units: f64,
+    /// What should happen if the order crosses one of the trader's own resting orders.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::serde_utils::self_trade_behavior_string")
+    )]
+    pub self_trade_behavior: SelfTradeBehavior,
+    /// The maximum number of orders to match against, if any.
+    pub match_limit: Option<u64>,
+    /// The client_order_id that will be attached to the order.
+    pub client_order_id: u128,
+    /// If true, the order is only funded from the trader's deposited funds.
+    pub use_only_deposited_funds: bool,
+}
+
+/// Describes a post-only order in human readable units, see `LimitOrderTemplate`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PostOnlyOrderTemplate {
+    /// The side of the order (Bid or Ask).
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::side_string"))]
+    pub side: Side,
+    /// The price of the order, in quote units per base unit.
+    pub price_as_float: f64,
+    /// The size of the order, in base units.
+    pub size_in_base_units: f64,
+    /// The client_order_id that will be attached to the order.
+    pub client_order_id: u128,
+    /// If true, an order that would cross the book fails. If false, the program slides it to
+    /// the best price that does not cross, see `post_only_price_in_ticks`.
+    pub reject_post_only: bool,
+    /// If true, the order is only funded from the trader's deposited funds.
+    pub use_only_deposited_funds: bool,
+}
+
+/// Describes an immediate-or-cancel order in human readable units, see `LimitOrderTemplate`.
+/// The order fails unless at least `min_base_units_to_fill` base units and
+/// `min_quote_units_to_fill` quote units are filled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImmediateOrCancelOrderTemplate {
+    /// The side of the order (Bid or Ask).
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::side_string"))]
+    pub side: Side,
+    /// The worst price the order fills at, in quote units per base unit.
+    pub price_as_float: f64,
+    /// The size of the order, in base units.
+    pub size_in_base_units: f64,
+    /// The least the order must fill, in base units, 0 for no minimum.
+    pub min_base_units_to_fill: f64,
+    /// The least the order must fill, in quote units, 0 for no minimum.
+    pub min_quote_units_to_fill: f64,
+    /// What should happen if the order crosses one of the trader's own resting orders.
+    #[cfg_attr(
+        feature = "serde",
+        serde(with = "crate::serde_utils::self_trade_behavior_string")
+    )]
+    pub self_trade_behavior: SelfTradeBehavior,
+    /// The maximum number of orders to match against, if any.
+    pub match_limit: Option<u64>,
+    /// The client_order_id that will be attached to the order.
+    pub client_order_id: u128,
+    /// If true, the order is only funded from the trader's deposited funds.
+    pub use_only_deposited_funds: bool,
+}
+
+impl LimitOrderTemplate {
+    /// A limit order with the defaults of `SDKClientCore::get_limit_order_generic_ix`:
+    /// `DecrementTake` self trades, no match limit, client order id 0, and funded from the
+    /// trader's token accounts when needed.
+    pub fn new(side: Side, price_as_float: f64, size_in_base_units: f64) -> Self {
+        LimitOrderTemplate {
+            side,
+            price_as_float,
+            size_in_base_units,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            match_limit: None,
+            client_order_id: 0,
+            use_only_deposited_funds: false,
+        }
+    }
+
+    pub fn self_trade_behavior(mut self, self_trade_behavior: SelfTradeBehavior) -> Self {
+        self.self_trade_behavior = self_trade_behavior;
+        self
+    }
+
+    pub fn match_limit(mut self, match_limit: u64) -> Self {
+        self.match_limit = Some(match_limit);
+        self
+    }
+
+    pub fn client_order_id(mut self, client_order_id: u128) -> Self {
+        self.client_order_id = client_order_id;
+        self
+    }
+
+    pub fn use_only_deposited_funds(mut self, use_only_deposited_funds: bool) -> Self {
+        self.use_only_deposited_funds = use_only_deposited_funds;
+        self
+    }
+
+    /// Checks that the template describes an order `metadata`'s market can place: a positive
+    /// price on the market's tick grid and a size of at least one base lot.
+    pub fn validate(&self, metadata: &MarketMetadata) -> Result<(), OrderValidationError> {
+        validate_price_and_size(metadata, self.price_as_float, self.size_in_base_units)
+    }
+}
+
+impl PostOnlyOrderTemplate {
+    /// A post-only order with the program's defaults: rejected if it would cross, client order
+    /// id 0, and funded from the trader's token accounts when needed.
+    pub fn new(side: Side, price_as_float: f64, size_in_base_units: f64) -> Self {
+        PostOnlyOrderTemplate {
+            side,
+            price_as_float,
+            size_in_base_units,
+            client_order_id: 0,
+            reject_post_only: true,
+            use_only_deposited_funds: false,
+        }
+    }
+
+    pub fn client_order_id(mut self, client_order_id: u128) -> Self {
+        self.client_order_id = client_order_id;
+        self
+    }
+
+    pub fn reject_post_only(mut self, reject_post_only: bool) -> Self {
+        self.reject_post_only = reject_post_only;
+        self
+    }
+
+    pub fn use_only_deposited_funds(mut self, use_only_deposited_funds: bool) -> Self {
+        self.use_only_deposited_funds = use_only_deposited_funds;
+        self
+    }
+
+    /// See `LimitOrderTemplate::validate`.
+    pub fn validate(&self, metadata: &MarketMetadata) -> Result<(), OrderValidationError> {
+        validate_price_and_size(metadata, self.price_as_float, self.size_in_base_units)
+    }
+}
+
+impl ImmediateOrCancelOrderTemplate {
+    /// An immediate-or-cancel order with no minimum fill, `CancelProvide` self trades, no match
+    /// limit, client order id 0, and funded from the trader's token accounts when needed.
+    pub fn new(side: Side, price_as_float: f64, size_in_base_units: f64) -> Self {
+        ImmediateOrCancelOrderTemplate {
+            side,
+            price_as_float,
+            size_in_base_units,
+            min_base_units_to_fill: 0.0,
+            min_quote_units_to_fill: 0.0,
+            self_trade_behavior: SelfTradeBehavior::CancelProvide,
+            match_limit: None,
+            client_order_id: 0,
+            use_only_deposited_funds: false,
+        }
+    }
+
+    pub fn min_base_units_to_fill(mut self, min_base_units_to_fill: f64) -> Self {
+        self.min_base_units_to_fill = min_base_units_to_fill;
+        self
+    }
+
+    pub fn min_quote_units_to_fill(mut self, min_quote_units_to_fill: f64) -> Self {
+        self.min_quote_units_to_fill = min_quote_units_to_fill;
+        self
+    }
+
+    pub fn self_trade_behavior(mut self, self_trade_behavior: SelfTradeBehavior) -> Self {
+        self.self_trade_behavior = self_trade_behavior;
+        self
+    }
+
+    pub fn match_limit(mut self, match_limit: u64) -> Self {
+        self.match_limit = Some(match_limit);
+        self
+    }
+
+    pub fn client_order_id(mut self, client_order_id: u128) -> Self {
+        self.client_order_id = client_order_id;
+        self
+    }
+
+    pub fn use_only_deposited_funds(mut self, use_only_deposited_funds: bool) -> Self {
+        self.use_only_deposited_funds = use_only_deposited_funds;
+        self
+    }
+
+    /// See `LimitOrderTemplate::validate`. Also checks that the minimum fills are not negative
+    /// and that the base minimum does not exceed the size.
+    pub fn validate(&self, metadata: &MarketMetadata) -> Result<(), OrderValidationError> {
+        validate_price_and_size(metadata, self.price_as_float, self.size_in_base_units)?;
+        let invalid = |min: f64| !min.is_finite() || min < 0.0;
+        if invalid(self.min_base_units_to_fill)
+            || invalid(self.min_quote_units_to_fill)
+            || self.min_base_units_to_fill > self.size_in_base_units
+        {
+            return Err(OrderValidationError::InvalidMinimumFill {
+                min_base_units_to_fill: self.min_base_units_to_fill,
+                min_quote_units_to_fill: self.min_quote_units_to_fill,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Moves a resting order: cancels `order_to_cancel` and places `new_order` in the same
+/// transaction, see `SDKClient::get_replace_order_ixs`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReplaceOrderTemplate {
+    pub order_to_cancel: FIFOOrderId,
+    pub new_order: LimitOrderTemplate,
+}
+
+/// The price, in ticks, at which the program places a post-only order for `price_in_ticks`
+/// given the best price on the opposite side of the book. This mirrors the program's rule: a
+/// bid at or above the best ask is moved to one tick below it, and an ask at or below the best
+/// bid to one tick above it. Returns None if the order would be rejected instead, i.e. it
+/// crosses and `reject_post_only` is set, or a bid would have to move to 0 ticks.
+///
+/// The program skips expired orders when it looks up the best opposite price, so
+/// `best_opposite_price_in_ticks` should exclude them.
+pub fn post_only_price_in_ticks(
+    side: Side,
+    price_in_ticks: u64,
+    best_opposite_price_in_ticks: Option<u64>,
+    reject_post_only: bool,
+) -> Option<u64> {
+    let best = match best_opposite_price_in_ticks {
+        Some(best) => best,
+        None => return Some(price_in_ticks),
+    };
+    let crosses = match side {
+        Side::Bid => price_in_ticks >= best,
+        Side::Ask => price_in_ticks <= best,
+    };
+    if !crosses {
+        return Some(price_in_ticks);
+    }
+    if reject_post_only {
+        return None;
+    }
+    match side {
+        Side::Bid => best.checked_sub(1).filter(|&ticks| ticks > 0),
+        Side::Ask => best.checked_add(1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn post_only_keeps_its_price_on_an_empty_or_uncrossed_book() {
+        assert_eq!(
+            post_only_price_in_ticks(Side::Bid, 100, None, true),
+            Some(100)
+        );
+        assert_eq!(
+            post_only_price_in_ticks(Side::Bid, 99, Some(100), true),
+            Some(99)
+        );
+        assert_eq!(
+            post_only_price_in_ticks(Side::Ask, 101, Some(100), true),
+            Some(101)
+        );
+    }
+
+    #[test]
+    fn crossing_post_only_slides_one_tick_off_the_best_price() {
+        assert_eq!(
+            post_only_price_in_ticks(Side::Bid, 100, Some(100), false),
+            Some(99)
+        );
+        assert_eq!(
+            post_only_price_in_ticks(Side::Bid, 150, Some(100), false),
+            Some(99)
+        );
+        assert_eq!(
+            post_only_price_in_ticks(Side::Ask, 100, Some(100), false),
+            Some(101)
+        );
+        assert_eq!(
+            post_only_price_in_ticks(Side::Ask, 50, Some(100), false),
+            Some(101)
+        );
+    }
+
+    #[test]
+    fn crossing_post_only_is_rejected() {
+        assert_eq!(
+            post_only_price_in_ticks(Side::Bid, 100, Some(100), true),
+            None
+        );
+        assert_eq!(
+            post_only_price_in_ticks(Side::Ask, 100, Some(100), true),
+            None
+        );
+        // A bid cannot slide below the best ask of 1 tick
+        assert_eq!(post_only_price_in_ticks(Side::Bid, 5, Some(1), false), None);
+        assert_eq!(
+            post_only_price_in_ticks(Side::Ask, 5, Some(u64::MAX), false),
+            None
+        );
+    }
+}
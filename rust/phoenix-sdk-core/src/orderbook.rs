@@ -3,10 +3,13 @@ use std::collections::BTreeMap;
 use itertools::Itertools;
 use num_traits::ToPrimitive;
 use phoenix_types::enums::Side;
-use phoenix_types::market::{FIFOOrderId, FIFORestingOrder, Market};
+use phoenix_types::market::{FIFOOrderId, FIFORestingOrder, Market, MarketHeader};
 use rust_decimal::Decimal;
 
-use crate::sdk_client_core::PhoenixOrder;
+use crate::{
+    market_event::{Evict, Fill, MarketEventDetails, PhoenixEvent, Place, Reduce},
+    sdk_client_core::PhoenixOrder,
+};
 
 pub trait OrderbookKey {
     fn price(&self) -> f64;
@@ -64,15 +67,39 @@ impl OrderbookValue for Decimal {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+/// A price level of a `UiLadder`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UiLadderLevel {
+    /// Price in quote units per base unit
+    pub price: f64,
+    pub size_in_base_units: f64,
+}
+
+/// An L2 view of an `Orderbook`. Both sides are ordered best price first.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UiLadder {
+    pub bids: Vec<UiLadderLevel>,
+    pub asks: Vec<UiLadderLevel>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Orderbook<K: Ord + OrderbookKey + Copy, V: OrderbookValue + Copy> {
     pub size_mult: f64,
     pub price_mult: f64,
     pub bids: BTreeMap<K, V>,
     pub asks: BTreeMap<K, V>,
+    /// `apply_event` ignores events with a lower market sequence number than this. It is the
+    /// sequence number of the last applied event, or the market header's sequence number for a
+    /// book loaded with `from_market_and_header`.
+    pub sequence_number: u64,
 }
 
 impl Orderbook<FIFOOrderId, PhoenixOrder> {
+    /// Loads the book from a market snapshot. The sequence number starts at 0, so `apply_event`
+    /// applies every event, including ones the snapshot already reflects. Use
+    /// `from_market_and_header` to apply events on top of the snapshot.
     pub fn from_market(market: &dyn Market, size_mult: f64, price_mult: f64) -> Self {
         let traders = market
             .get_registered_traders()
@@ -86,12 +113,7 @@ impl Orderbook<FIFOOrderId, PhoenixOrder> {
             index_to_trader.insert(index as u64, *trader);
         }
 
-        let mut orderbook = Orderbook {
-            size_mult,
-            price_mult,
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
-        };
+        let mut orderbook = Orderbook::new(size_mult, price_mult);
         for side in [Side::Bid, Side::Ask].iter() {
             orderbook.update_orders(
                 *side,
@@ -120,9 +142,112 @@ impl Orderbook<FIFOOrderId, PhoenixOrder> {
         }
         orderbook
     }
+
+    /// Loads the book from a market snapshot and seeds its sequence number from the header read
+    /// with it. The program bumps the header's sequence number after each instruction, so
+    /// `apply_event` skips the events the snapshot already reflects.
+    pub fn from_market_and_header(
+        header: &MarketHeader,
+        market: &dyn Market,
+        size_mult: f64,
+        price_mult: f64,
+    ) -> Self {
+        let mut orderbook = Self::from_market(market, size_mult, price_mult);
+        orderbook.sequence_number = header.market_sequence_number;
+        orderbook
+    }
+
+    /// Updates the book with a Place, Fill, Reduce, or Evict event. Other events are ignored,
+    /// as are events with a lower sequence number than the last applied event. Returns an
+    /// error if a Fill or Reduce leaves lots on an order that is not in the book.
+    pub fn apply_event(&mut self, event: &PhoenixEvent) -> anyhow::Result<()> {
+        if event.sequence_number < self.sequence_number {
+            return Ok(());
+        }
+        let (order_sequence_number, price_in_ticks, remaining) = match event.details {
+            MarketEventDetails::Place(Place {
+                order_sequence_number,
+                price_in_ticks,
+                base_lots_placed,
+                maker,
+                ..
+            }) => {
+                let book = match Side::from_order_sequence_number(order_sequence_number) {
+                    Side::Bid => &mut self.bids,
+                    Side::Ask => &mut self.asks,
+                };
+                book.insert(
+                    FIFOOrderId {
+                        price_in_ticks,
+                        order_sequence_number,
+                    },
+                    PhoenixOrder {
+                        num_base_lots: base_lots_placed,
+                        maker_id: maker,
+                    },
+                );
+                self.sequence_number = event.sequence_number;
+                return Ok(());
+            }
+            MarketEventDetails::Fill(Fill {
+                order_sequence_number,
+                price_in_ticks,
+                base_lots_remaining,
+                ..
+            })
+            | MarketEventDetails::Reduce(Reduce {
+                order_sequence_number,
+                price_in_ticks,
+                base_lots_remaining,
+                ..
+            }) => (order_sequence_number, price_in_ticks, base_lots_remaining),
+            MarketEventDetails::Evict(Evict {
+                order_sequence_number,
+                price_in_ticks,
+                ..
+            }) => (order_sequence_number, price_in_ticks, 0),
+            _ => return Ok(()),
+        };
+
+        let order_id = FIFOOrderId {
+            price_in_ticks,
+            order_sequence_number,
+        };
+        let book = match Side::from_order_sequence_number(order_sequence_number) {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+        if remaining == 0 {
+            book.remove(&order_id);
+        } else {
+            match book.get_mut(&order_id) {
+                Some(order) => order.num_base_lots = remaining,
+                None => {
+                    return Err(anyhow::anyhow!(
+                        "Order {} at {} ticks is not in the book",
+                        order_sequence_number,
+                        price_in_ticks
+                    ))
+                }
+            }
+        }
+        self.sequence_number = event.sequence_number;
+        Ok(())
+    }
 }
 
 impl<K: Ord + OrderbookKey + Copy, V: OrderbookValue + Copy> Orderbook<K, V> {
+    /// An empty book with a sequence number of 0
+    pub fn new(size_mult: f64, price_mult: f64) -> Self {
+        Orderbook {
+            size_mult,
+            price_mult,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+            sequence_number: 0,
+        }
+    }
+
     pub fn get_bids(&self) -> Vec<(K, V)> {
         self.bids
             .iter()
@@ -138,43 +263,111 @@ impl<K: Ord + OrderbookKey + Copy, V: OrderbookValue + Copy> Orderbook<K, V> {
             .collect::<Vec<_>>()
     }
 
-    pub fn print_ladder(&self, levels: usize, precision: usize) {
-        #[allow(clippy::needless_collect)]
-        let asks = self
-            .get_asks()
+    /// The highest bid, or None if there are no bids
+    pub fn best_bid(&self) -> Option<(K, V)> {
+        self.bids
             .iter()
-            .group_by(|(price, _)| price.price() * self.price_mult)
-            .into_iter()
-            .map(|(price, group)| {
-                let size = group.map(|(_, size)| size.size()).sum::<f64>() * self.size_mult;
-                (price, size)
-            })
-            .take(levels)
-            .collect::<Vec<_>>();
-        let bids = self
-            .get_bids()
+            .next_back()
+            .map(|(&price, &size)| (price, size))
+    }
+
+    /// The lowest ask, or None if there are no asks
+    pub fn best_ask(&self) -> Option<(K, V)> {
+        self.asks.iter().next().map(|(&price, &size)| (price, size))
+    }
+
+    /// The midpoint of the best bid and ask, in quote units per base unit. None if either side
+    /// is empty.
+    pub fn mid_price_in_units(&self) -> Option<f64> {
+        let (bid, _) = self.best_bid()?;
+        let (ask, _) = self.best_ask()?;
+        Some((bid.price() + ask.price()) / 2.0 * self.price_mult)
+    }
+
+    /// The best ask minus the best bid, in quote units per base unit. None if either side is
+    /// empty.
+    pub fn spread_in_units(&self) -> Option<f64> {
+        let (bid, _) = self.best_bid()?;
+        let (ask, _) = self.best_ask()?;
+        Some((ask.price() - bid.price()) * self.price_mult)
+    }
+
+    /// The resting size, in base units, on `side` priced within `bps` basis points of the mid.
+    /// Returns 0 if either side of the book is empty.
+    pub fn liquidity_within_bps(&self, side: Side, bps: u64) -> f64 {
+        let mid = match self.mid_price_in_units() {
+            Some(mid) => mid,
+            None => return 0.0,
+        };
+        let band = mid * bps as f64 / 10_000.0;
+        let (book, in_band): (_, Box<dyn Fn(f64) -> bool>) = match side {
+            Side::Bid => (&self.bids, Box::new(|price: f64| price >= mid - band)),
+            Side::Ask => (&self.asks, Box::new(|price: f64| price <= mid + band)),
+        };
+        book.iter()
+            .filter(|(price, _)| in_band(price.price() * self.price_mult))
+            .map(|(_, size)| size.size())
+            .sum::<f64>()
+            * self.size_mult
+    }
+
+    /// (bid size - ask size) / (bid size + ask size) over the top `levels` price levels of each
+    /// side, in [-1, 1]. Returns 0 if the book is empty.
+    pub fn imbalance(&self, levels: usize) -> f64 {
+        let UiLadder { bids, asks } = self.to_ui_ladder(levels);
+        let bid_size = bids
             .iter()
-            .rev()
-            .group_by(|(price, _)| price.price() * self.price_mult)
-            .into_iter()
-            .map(|(price, group)| {
-                let size = group.map(|(_, size)| size.size()).sum::<f64>() * self.size_mult;
-                (price, size)
-            })
-            .take(levels)
-            .collect::<Vec<_>>();
+            .map(|level| level.size_in_base_units)
+            .sum::<f64>();
+        let ask_size = asks
+            .iter()
+            .map(|level| level.size_in_base_units)
+            .sum::<f64>();
+        let total = bid_size + ask_size;
+        if total > 0.0 {
+            (bid_size - ask_size) / total
+        } else {
+            0.0
+        }
+    }
+
+    /// Aggregates the orders at each price into levels, best price first, converting prices and
+    /// sizes with the book's multipliers.
+    pub fn to_ui_ladder(&self, levels: usize) -> UiLadder {
+        let aggregate = |orders: Vec<(K, V)>| {
+            let ladder_levels = orders
+                .iter()
+                .group_by(|(price, _)| price.price() * self.price_mult)
+                .into_iter()
+                .map(|(price, group)| UiLadderLevel {
+                    price,
+                    size_in_base_units: group.map(|(_, size)| size.size()).sum::<f64>()
+                        * self.size_mult,
+                })
+                .take(levels)
+                .collect::<Vec<_>>();
+            ladder_levels
+        };
+        UiLadder {
+            bids: aggregate(self.get_bids()),
+            asks: aggregate(self.get_asks()),
+        }
+    }
+
+    pub fn print_ladder(&self, levels: usize, precision: usize) {
+        let UiLadder { bids, asks } = self.to_ui_ladder(levels);
 
         let width: usize = 10;
 
-        for (ask_price, ask_size) in asks.into_iter().rev() {
-            let p = format!("{:.1$}", ask_price, precision);
-            let s = format!("{:.1$}", ask_size, precision);
+        for level in asks.into_iter().rev() {
+            let p = format!("{:.1$}", level.price, precision);
+            let s = format!("{:.1$}", level.size_in_base_units, precision);
             let str = format!("{:width$} {:^width$} {:<width$}", "", p, s);
             println!("{}", str);
         }
-        for (bid_price, bid_size) in bids {
-            let p = format!("{:.1$}", bid_price, precision);
-            let s = format!("{:.1$}", bid_size, precision);
+        for level in bids {
+            let p = format!("{:.1$}", level.price, precision);
+            let s = format!("{:.1$}", level.size_in_base_units, precision);
             let str = format!("{:>width$} {:^width$} {:width$}", s, p, "");
             println!("{}", str);
         }
@@ -221,28 +414,191 @@ impl<K: Ord + OrderbookKey + Copy, V: OrderbookValue + Copy> Orderbook<K, V> {
         self.update_orders(side, vec![(price, lots_remaining)]);
     }
 
-    pub fn vwap(&self, levels: usize) -> f64 {
-        let bids: Vec<_> = self.get_bids();
-        let asks: Vec<_> = self.get_asks();
-        let denom = bids
-            .iter()
-            .take(levels)
-            .zip(asks.iter().take(levels))
-            .map(|((_, bid_resting_order), (_, ask_resting_order))| {
-                ask_resting_order.size() + bid_resting_order.size()
-            })
-            .sum::<f64>();
-        let num = bids
-            .iter()
-            .take(levels)
-            .zip(asks.iter().take(levels))
-            .map(
-                |((bid_order_id, bid_resting_order), (ask_order_id, ask_resting_order))| {
-                    (ask_resting_order.size() * bid_order_id.price())
-                        + (bid_resting_order.size() * ask_order_id.price())
-                },
-            )
-            .sum::<f64>();
-        num / (denom * self.price_mult)
+    /// A size weighted mid over the top `levels` orders of each side: the volume weighted
+    /// average bid and ask, each weighted by the size on the opposite side. If either side has
+    /// fewer than `levels` orders it falls back to the midpoint of the best bid and ask.
+    /// Returns None if either side is empty.
+    pub fn vwap(&self, levels: usize) -> Option<f64> {
+        let bids = self.get_bids();
+        let asks = self.get_asks();
+        if bids.len() < levels || asks.len() < levels {
+            return self.mid_price_in_units();
+        }
+        let weighted = |orders: &[(K, V)]| {
+            orders
+                .iter()
+                .take(levels)
+                .fold((0.0, 0.0), |(size, notional), (price, order)| {
+                    (size + order.size(), notional + order.size() * price.price())
+                })
+        };
+        let (bid_size, bid_notional) = weighted(&bids);
+        let (ask_size, ask_notional) = weighted(&asks);
+        if bid_size <= 0.0 || ask_size <= 0.0 {
+            return self.mid_price_in_units();
+        }
+        let bid_vwap = bid_notional / bid_size;
+        let ask_vwap = ask_notional / ask_size;
+        Some((ask_size * bid_vwap + bid_size * ask_vwap) / (bid_size + ask_size) * self.price_mult)
+    }
+}
+
+/// Where the bids of one book overlap the asks of another, see `crossing_report`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Crossing {
+    /// The best bid net of fees minus the best ask including fees, in quote units per base unit
+    pub price_overlap: f64,
+    /// Base units that could be bought from the asks and sold to the bids at a profit net of
+    /// fees
+    pub crossable_size_base_units: f64,
+    /// Number of price levels, on both sides together, that take part in the crossing
+    pub levels_involved: usize,
+}
+
+/// Compares the bids of `bids` to the asks of `asks_source`, in human units, and returns how
+/// far they cross after paying `fee_bps` on each leg. Returns None if no bid is above an ask
+/// after fees.
+///
+/// Pass the same book twice with a fee of 0 to detect a self-crossed book, which points to a
+/// bug in how the book was parsed or maintained. Pass a Phoenix book and an external reference
+/// to check for a cross-venue overlap.
+pub fn crossing_report<K1, V1, K2, V2>(
+    bids: &Orderbook<K1, V1>,
+    asks_source: &Orderbook<K2, V2>,
+    fee_bps: u64,
+) -> Option<Crossing>
+where
+    K1: Ord + OrderbookKey + Copy,
+    V1: OrderbookValue + Copy,
+    K2: Ord + OrderbookKey + Copy,
+    V2: OrderbookValue + Copy,
+{
+    let fee = fee_bps as f64 / 10_000.0;
+    let bid_levels = bids.to_ui_ladder(usize::MAX).bids;
+    let ask_levels = asks_source.to_ui_ladder(usize::MAX).asks;
+    let sell_price = |level: &UiLadderLevel| level.price * (1.0 - fee);
+    let buy_price = |level: &UiLadderLevel| level.price * (1.0 + fee);
+
+    let price_overlap = sell_price(bid_levels.first()?) - buy_price(ask_levels.first()?);
+    if price_overlap <= 0.0 {
+        return None;
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut bid_remaining = bid_levels[0].size_in_base_units;
+    let mut ask_remaining = ask_levels[0].size_in_base_units;
+    let mut crossable_size_base_units = 0.0;
+    // Levels are consumed best first, so the levels that take part are the first
+    // `bid_levels_involved` bids and the first `ask_levels_involved` asks
+    let (mut bid_levels_involved, mut ask_levels_involved) = (0, 0);
+    while i < bid_levels.len()
+        && j < ask_levels.len()
+        && sell_price(&bid_levels[i]) > buy_price(&ask_levels[j])
+    {
+        let size = bid_remaining.min(ask_remaining);
+        crossable_size_base_units += size;
+        bid_levels_involved = i + 1;
+        ask_levels_involved = j + 1;
+        bid_remaining -= size;
+        ask_remaining -= size;
+        if bid_remaining <= 0.0 {
+            i += 1;
+            bid_remaining = bid_levels
+                .get(i)
+                .map_or(0.0, |level| level.size_in_base_units);
+        }
+        if ask_remaining <= 0.0 {
+            j += 1;
+            ask_remaining = ask_levels
+                .get(j)
+                .map_or(0.0, |level| level.size_in_base_units);
+        }
+    }
+
+    Some(Crossing {
+        price_overlap,
+        crossable_size_base_units,
+        levels_involved: bid_levels_involved + ask_levels_involved,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A book of `(ticks, lots)` levels where a tick is 0.5 quote units and a lot is 0.25 base
+    /// units, so the conversions are exact
+    fn book(bids: &[(u64, u64)], asks: &[(u64, u64)]) -> Orderbook<u64, u64> {
+        let mut book = Orderbook::new(0.25, 0.5);
+        book.bids.extend(bids.iter().copied());
+        book.asks.extend(asks.iter().copied());
+        book
+    }
+
+    /// Bids of 1 base unit at 98 and 2 at 99, asks of 0.5 at 101 and 3 at 102
+    fn two_level_book() -> Orderbook<u64, u64> {
+        book(&[(196, 4), (198, 8)], &[(202, 2), (204, 12)])
+    }
+
+    #[test]
+    fn best_bid_and_ask_are_the_top_of_each_side() {
+        let book = two_level_book();
+        assert_eq!(book.best_bid(), Some((198, 8)));
+        assert_eq!(book.best_ask(), Some((202, 2)));
+    }
+
+    #[test]
+    fn mid_and_spread_are_in_units() {
+        let book = two_level_book();
+        assert_eq!(book.mid_price_in_units(), Some(100.0));
+        assert_eq!(book.spread_in_units(), Some(2.0));
+    }
+
+    #[test]
+    fn accessors_of_an_empty_side_are_none() {
+        let bids_only = book(&[(198, 8)], &[]);
+        assert_eq!(bids_only.best_bid(), Some((198, 8)));
+        assert_eq!(bids_only.best_ask(), None);
+        assert_eq!(bids_only.mid_price_in_units(), None);
+        assert_eq!(bids_only.spread_in_units(), None);
+
+        let empty = book(&[], &[]);
+        assert_eq!(empty.best_bid(), None);
+        assert_eq!(empty.mid_price_in_units(), None);
+    }
+
+    #[test]
+    fn liquidity_within_bps_of_the_mid() {
+        let book = two_level_book();
+        // 100 bps of the mid of 100 is 1 quote unit, and the band is inclusive
+        assert_eq!(book.liquidity_within_bps(Side::Bid, 100), 2.0);
+        assert_eq!(book.liquidity_within_bps(Side::Ask, 100), 0.5);
+        assert_eq!(book.liquidity_within_bps(Side::Bid, 300), 3.0);
+        assert_eq!(book.liquidity_within_bps(Side::Ask, 300), 3.5);
+        assert_eq!(book.liquidity_within_bps(Side::Bid, 0), 0.0);
+    }
+
+    #[test]
+    fn liquidity_of_a_one_sided_book_is_zero() {
+        let bids_only = book(&[(198, 8)], &[]);
+        assert_eq!(bids_only.liquidity_within_bps(Side::Bid, 10_000), 0.0);
+        assert_eq!(bids_only.liquidity_within_bps(Side::Ask, 10_000), 0.0);
+    }
+
+    #[test]
+    fn imbalance_over_the_top_levels() {
+        let book = two_level_book();
+        assert_eq!(book.imbalance(1), (2.0 - 0.5) / 2.5);
+        assert_eq!(book.imbalance(2), (3.0 - 3.5) / 6.5);
+        assert_eq!(book.imbalance(10), book.imbalance(2));
+        assert_eq!(book.imbalance(0), 0.0);
+    }
+
+    #[test]
+    fn imbalance_of_empty_and_one_sided_books() {
+        assert_eq!(book(&[], &[]).imbalance(5), 0.0);
+        assert!(!book(&[], &[]).imbalance(5).is_nan());
+        assert_eq!(book(&[(198, 8)], &[]).imbalance(5), 1.0);
+        assert_eq!(book(&[], &[(202, 2)]).imbalance(5), -1.0);
     }
 }
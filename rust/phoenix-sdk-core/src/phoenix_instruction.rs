@@ -0,0 +1,252 @@
+use phoenix_types::events::MarketEvent;
+
+/// The instructions of the Phoenix program by their tag, the first byte of the instruction
+/// data. Unlike `phoenix_types::instructions::PhoenixInstruction`, converting from a tag never
+/// fails: tags this version of the SDK does not know are kept as `Unknown`, so the tag always
+/// round trips.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum KnownPhoenixInstruction {
+    Swap,
+    SwapWithFreeFunds,
+    PlaceLimitOrder,
+    PlaceLimitOrderWithFreeFunds,
+    ReduceOrder,
+    ReduceOrderWithFreeFunds,
+    CancelAllOrders,
+    CancelAllOrdersWithFreeFunds,
+    CancelUpTo,
+    CancelUpToWithFreeFunds,
+    CancelMultipleOrdersById,
+    CancelMultipleOrdersByIdWithFreeFunds,
+    WithdrawFunds,
+    DepositFunds,
+    RequestSeat,
+    Log,
+    PlaceMultiplePostOnlyOrders,
+    PlaceMultiplePostOnlyOrdersWithFreeFunds,
+    InitializeMarket,
+    ClaimAuthority,
+    NameSuccessor,
+    ChangeMarketStatus,
+    ChangeSeatStatus,
+    RequestSeatAuthorized,
+    EvictSeat,
+    ForceCancelOrders,
+    CollectFees,
+    ChangeFeeRecipient,
+    Unknown(u8),
+}
+
+impl KnownPhoenixInstruction {
+    /// Every instruction except `Unknown`, in tag order
+    pub const ALL_KNOWN: [KnownPhoenixInstruction; 28] = [
+        KnownPhoenixInstruction::Swap,
+        KnownPhoenixInstruction::SwapWithFreeFunds,
+        KnownPhoenixInstruction::PlaceLimitOrder,
+        KnownPhoenixInstruction::PlaceLimitOrderWithFreeFunds,
+        KnownPhoenixInstruction::ReduceOrder,
+        KnownPhoenixInstruction::ReduceOrderWithFreeFunds,
+        KnownPhoenixInstruction::CancelAllOrders,
+        KnownPhoenixInstruction::CancelAllOrdersWithFreeFunds,
+        KnownPhoenixInstruction::CancelUpTo,
+        KnownPhoenixInstruction::CancelUpToWithFreeFunds,
+        KnownPhoenixInstruction::CancelMultipleOrdersById,
+        KnownPhoenixInstruction::CancelMultipleOrdersByIdWithFreeFunds,
+        KnownPhoenixInstruction::WithdrawFunds,
+        KnownPhoenixInstruction::DepositFunds,
+        KnownPhoenixInstruction::RequestSeat,
+        KnownPhoenixInstruction::Log,
+        KnownPhoenixInstruction::PlaceMultiplePostOnlyOrders,
+        KnownPhoenixInstruction::PlaceMultiplePostOnlyOrdersWithFreeFunds,
+        KnownPhoenixInstruction::InitializeMarket,
+        KnownPhoenixInstruction::ClaimAuthority,
+        KnownPhoenixInstruction::NameSuccessor,
+        KnownPhoenixInstruction::ChangeMarketStatus,
+        KnownPhoenixInstruction::ChangeSeatStatus,
+        KnownPhoenixInstruction::RequestSeatAuthorized,
+        KnownPhoenixInstruction::EvictSeat,
+        KnownPhoenixInstruction::ForceCancelOrders,
+        KnownPhoenixInstruction::CollectFees,
+        KnownPhoenixInstruction::ChangeFeeRecipient,
+    ];
+
+    pub fn tag(&self) -> u8 {
+        match self {
+            KnownPhoenixInstruction::Swap => 0,
+            KnownPhoenixInstruction::SwapWithFreeFunds => 1,
+            KnownPhoenixInstruction::PlaceLimitOrder => 2,
+            KnownPhoenixInstruction::PlaceLimitOrderWithFreeFunds => 3,
+            KnownPhoenixInstruction::ReduceOrder => 4,
+            KnownPhoenixInstruction::ReduceOrderWithFreeFunds => 5,
+            KnownPhoenixInstruction::CancelAllOrders => 6,
+            KnownPhoenixInstruction::CancelAllOrdersWithFreeFunds => 7,
+            KnownPhoenixInstruction::CancelUpTo => 8,
+            KnownPhoenixInstruction::CancelUpToWithFreeFunds => 9,
+            KnownPhoenixInstruction::CancelMultipleOrdersById => 10,
+            KnownPhoenixInstruction::CancelMultipleOrdersByIdWithFreeFunds => 11,
+            KnownPhoenixInstruction::WithdrawFunds => 12,
+            KnownPhoenixInstruction::DepositFunds => 13,
+            KnownPhoenixInstruction::RequestSeat => 14,
+            KnownPhoenixInstruction::Log => 15,
+            KnownPhoenixInstruction::PlaceMultiplePostOnlyOrders => 16,
+            KnownPhoenixInstruction::PlaceMultiplePostOnlyOrdersWithFreeFunds => 17,
+            KnownPhoenixInstruction::InitializeMarket => 100,
+            KnownPhoenixInstruction::ClaimAuthority => 101,
+            KnownPhoenixInstruction::NameSuccessor => 102,
+            KnownPhoenixInstruction::ChangeMarketStatus => 103,
+            KnownPhoenixInstruction::ChangeSeatStatus => 104,
+            KnownPhoenixInstruction::RequestSeatAuthorized => 105,
+            KnownPhoenixInstruction::EvictSeat => 106,
+            KnownPhoenixInstruction::ForceCancelOrders => 107,
+            KnownPhoenixInstruction::CollectFees => 108,
+            KnownPhoenixInstruction::ChangeFeeRecipient => 109,
+            KnownPhoenixInstruction::Unknown(tag) => *tag,
+        }
+    }
+
+    /// The instruction's name as the program spells it, e.g. for logging. `Unknown` tags are
+    /// named "Unknown".
+    pub fn name(&self) -> &'static str {
+        match self {
+            KnownPhoenixInstruction::Swap => "Swap",
+            KnownPhoenixInstruction::SwapWithFreeFunds => "SwapWithFreeFunds",
+            KnownPhoenixInstruction::PlaceLimitOrder => "PlaceLimitOrder",
+            KnownPhoenixInstruction::PlaceLimitOrderWithFreeFunds => "PlaceLimitOrderWithFreeFunds",
+            KnownPhoenixInstruction::ReduceOrder => "ReduceOrder",
+            KnownPhoenixInstruction::ReduceOrderWithFreeFunds => "ReduceOrderWithFreeFunds",
+            KnownPhoenixInstruction::CancelAllOrders => "CancelAllOrders",
+            KnownPhoenixInstruction::CancelAllOrdersWithFreeFunds => "CancelAllOrdersWithFreeFunds",
+            KnownPhoenixInstruction::CancelUpTo => "CancelUpTo",
+            KnownPhoenixInstruction::CancelUpToWithFreeFunds => "CancelUpToWithFreeFunds",
+            KnownPhoenixInstruction::CancelMultipleOrdersById => "CancelMultipleOrdersById",
+            KnownPhoenixInstruction::CancelMultipleOrdersByIdWithFreeFunds => {
+                "CancelMultipleOrdersByIdWithFreeFunds"
+            }
+            KnownPhoenixInstruction::WithdrawFunds => "WithdrawFunds",
+            KnownPhoenixInstruction::DepositFunds => "DepositFunds",
+            KnownPhoenixInstruction::RequestSeat => "RequestSeat",
+            KnownPhoenixInstruction::Log => "Log",
+            KnownPhoenixInstruction::PlaceMultiplePostOnlyOrders => "PlaceMultiplePostOnlyOrders",
+            KnownPhoenixInstruction::PlaceMultiplePostOnlyOrdersWithFreeFunds => {
+                "PlaceMultiplePostOnlyOrdersWithFreeFunds"
+            }
+            KnownPhoenixInstruction::InitializeMarket => "InitializeMarket",
+            KnownPhoenixInstruction::ClaimAuthority => "ClaimAuthority",
+            KnownPhoenixInstruction::NameSuccessor => "NameSuccessor",
+            KnownPhoenixInstruction::ChangeMarketStatus => "ChangeMarketStatus",
+            KnownPhoenixInstruction::ChangeSeatStatus => "ChangeSeatStatus",
+            KnownPhoenixInstruction::RequestSeatAuthorized => "RequestSeatAuthorized",
+            KnownPhoenixInstruction::EvictSeat => "EvictSeat",
+            KnownPhoenixInstruction::ForceCancelOrders => "ForceCancelOrders",
+            KnownPhoenixInstruction::CollectFees => "CollectFees",
+            KnownPhoenixInstruction::ChangeFeeRecipient => "ChangeFeeRecipient",
+            KnownPhoenixInstruction::Unknown(_) => "Unknown",
+        }
+    }
+
+    pub fn is_known(&self) -> bool {
+        !matches!(self, KnownPhoenixInstruction::Unknown(_))
+    }
+
+    /// The kind of the first byte of an instruction's data, None if the data is empty.
+    pub fn from_instruction_data(data: &[u8]) -> Option<Self> {
+        data.first().map(|&tag| tag.into())
+    }
+
+    /// The instruction that emitted the events of a log, read from the `instruction` field of
+    /// its header. None if `event` is not a header.
+    pub fn from_log_header(event: &MarketEvent) -> Option<Self> {
+        match event {
+            MarketEvent::Header { header } => Some(header.instruction.into()),
+            _ => None,
+        }
+    }
+}
+
+impl From<u8> for KnownPhoenixInstruction {
+    fn from(tag: u8) -> Self {
+        KnownPhoenixInstruction::ALL_KNOWN
+            .iter()
+            .find(|instruction| instruction.tag() == tag)
+            .copied()
+            .unwrap_or(KnownPhoenixInstruction::Unknown(tag))
+    }
+}
+
+impl From<KnownPhoenixInstruction> for u8 {
+    fn from(instruction: KnownPhoenixInstruction) -> Self {
+        instruction.tag()
+    }
+}
+
+impl std::fmt::Display for KnownPhoenixInstruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KnownPhoenixInstruction::Unknown(tag) => write!(f, "Unknown({})", tag),
+            _ => write!(f, "{}", self.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn every_tag_round_trips() {
+        for tag in 0..=u8::MAX {
+            let instruction = KnownPhoenixInstruction::from(tag);
+            assert_eq!(u8::from(instruction), tag);
+            assert_eq!(
+                instruction.is_known(),
+                KnownPhoenixInstruction::ALL_KNOWN.contains(&instruction)
+            );
+        }
+    }
+
+    #[test]
+    fn known_instructions_have_distinct_ordered_tags_and_names() {
+        let tags = KnownPhoenixInstruction::ALL_KNOWN
+            .iter()
+            .map(|instruction| instruction.tag())
+            .collect::<Vec<_>>();
+        assert!(tags.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", tags);
+        let names = KnownPhoenixInstruction::ALL_KNOWN
+            .iter()
+            .map(|instruction| instruction.name())
+            .collect::<BTreeSet<_>>();
+        assert_eq!(names.len(), KnownPhoenixInstruction::ALL_KNOWN.len());
+        assert!(!names.contains("Unknown"));
+        for instruction in KnownPhoenixInstruction::ALL_KNOWN {
+            assert!(instruction.is_known());
+            assert_eq!(instruction.to_string(), instruction.name());
+        }
+    }
+
+    #[test]
+    fn unknown_tags_are_kept() {
+        assert_eq!(
+            KnownPhoenixInstruction::from(18),
+            KnownPhoenixInstruction::Unknown(18)
+        );
+        assert_eq!(
+            KnownPhoenixInstruction::Unknown(200).to_string(),
+            "Unknown(200)"
+        );
+        assert_eq!(KnownPhoenixInstruction::Unknown(200).name(), "Unknown");
+    }
+
+    #[test]
+    fn reads_the_tag_from_instruction_data() {
+        assert_eq!(KnownPhoenixInstruction::from_instruction_data(&[]), None);
+        assert_eq!(
+            KnownPhoenixInstruction::from_instruction_data(&[2, 0, 1]),
+            Some(KnownPhoenixInstruction::PlaceLimitOrder)
+        );
+        assert_eq!(
+            KnownPhoenixInstruction::from_instruction_data(&[108]),
+            Some(KnownPhoenixInstruction::CollectFees)
+        );
+    }
+}
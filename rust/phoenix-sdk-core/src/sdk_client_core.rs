@@ -1,34 +1,64 @@
 use borsh::BorshDeserialize;
 use phoenix_types::{
+    dispatch::load_with_dispatch_mut,
     enums::{SelfTradeBehavior, Side},
     events::MarketEvent,
     instructions::{
         create_cancel_all_orders_instruction, create_cancel_multiple_orders_by_id_instruction,
         create_cancel_up_to_instruction, create_new_order_instruction,
-        CancelMultipleOrdersByIdParams, CancelOrderParams, CancelUpToParams,
+        create_reduce_order_instruction, CancelMultipleOrdersByIdParams, CancelOrderParams,
+        CancelUpToParams, ReduceOrderParams,
     },
-    market::{FIFOOrderId, TraderState},
+    market::{FIFOOrderId, MarketHeader, TraderState},
     order_packet::OrderPacket,
 };
 use rand::{rngs::StdRng, Rng};
-use solana_sdk::signature::Signature;
+use solana_sdk::{
+    address_lookup_table_account::AddressLookupTableAccount,
+    hash::Hash,
+    message::{v0, Message, VersionedMessage},
+    packet::PACKET_DATA_SIZE,
+    signature::Signature,
+};
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     fmt::Display,
-    ops::{Deref, Div, Rem},
-    sync::{Arc, Mutex},
+    mem::size_of,
+    ops::{Div, Rem},
+    sync::{Arc, Mutex, RwLock},
 };
 
 use anyhow;
 use solana_program::{instruction::Instruction, pubkey::Pubkey};
 
 use crate::{
+    error::PhoenixSdkError,
+    event_decoder::MarketEventIter,
+    free_funds::{
+        create_cancel_all_orders_with_free_funds_instruction,
+        create_cancel_up_to_with_free_funds_instruction,
+        create_new_order_with_free_funds_instruction,
+    },
+    instruction_audit::{audit_instruction, ExpectedAccounts, UnexpectedAccount},
     market_event::{Evict, Fill, FillSummary, MarketEventDetails, PhoenixEvent, Place, Reduce},
+    multiple_order_packet::{
+        create_place_multiple_post_only_orders_instruction,
+        create_place_multiple_post_only_orders_with_free_funds_instruction, CondensedOrder,
+        MultipleOrderPacket,
+    },
+    order_audit::{
+        AuditedTemplate, OrderAuditLog, OrderAuditRecord, OrderAuditStage, MARKET_ACCOUNT_INDEX,
+    },
+    order_packet_template::{
+        post_only_price_in_ticks, ImmediateOrCancelOrderTemplate, LimitOrderTemplate,
+        PostOnlyOrderTemplate,
+    },
     orderbook::Orderbook,
 };
 
 const AUDIT_LOG_HEADER_LEN: usize = 92;
 
+#[derive(Clone, Debug)]
 pub struct MarketState {
     /// State of the bids and offers in the market.
     pub orderbook: Orderbook<FIFOOrderId, PhoenixOrder>,
@@ -36,9 +66,29 @@ pub struct MarketState {
     pub traders: BTreeMap<Pubkey, TraderState>,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// A value read from the chain with the slot of the RPC context it was read at, so snapshots
+/// from different RPC nodes can be ordered and stale ones detected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot<T> {
+    pub slot: u64,
+    pub value: T,
+}
+
+impl<T> Snapshot<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Snapshot<U> {
+        Snapshot {
+            slot: self.slot,
+            value: f(self.value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PhoenixOrder {
     pub num_base_lots: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::pubkey_string"))]
     pub maker_id: Pubkey,
 }
 
@@ -64,7 +114,69 @@ where
     format!("{}.{}", lhs, rhs)
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Sets the fill minimums of an immediate-or-cancel packet. Other packets are returned as is.
+#[allow(clippy::useless_conversion)]
+fn with_min_fill(
+    mut order_packet: OrderPacket,
+    min_base_lots: u64,
+    min_quote_lots: u64,
+) -> OrderPacket {
+    if let OrderPacket::ImmediateOrCancel {
+        min_base_lots_to_fill,
+        min_quote_lots_to_fill,
+        ..
+    } = &mut order_packet
+    {
+        *min_base_lots_to_fill = min_base_lots.into();
+        *min_quote_lots_to_fill = min_quote_lots.into();
+    }
+    order_packet
+}
+
+/// Called by `parse_phoenix_events` for a decoded event it has no `MarketEventDetails` variant
+/// for, e.g. one added in a newer program version. The event is reported and skipped, so a
+/// program upgrade does not break parsing of the events the SDK understands.
+fn skip_unknown_event(market: &Pubkey) {
+    println!("Skipping an unknown event of market {}", market);
+}
+
+/// Returns the size in bytes of a legacy transaction containing `instructions` with `payer` as
+/// the fee payer. Compare this against `solana_sdk::packet::PACKET_DATA_SIZE` to check whether
+/// the instructions fit in a single transaction.
+pub fn get_transaction_size(instructions: &[Instruction], payer: &Pubkey) -> usize {
+    let message = Message::new(instructions, Some(payer));
+    let num_signatures = message.header.num_required_signatures as usize;
+    // The signature list is prefixed by its length as a compact-u16
+    let signature_len_prefix = if num_signatures < 0x80 { 1 } else { 2 };
+    signature_len_prefix + num_signatures * 64 + message.serialize().len()
+}
+
+/// Like `get_transaction_size`, for a v0 transaction that loads accounts from `lookup_tables`.
+/// Accounts found in a table take one byte instead of 32.
+pub fn get_v0_transaction_size(
+    instructions: &[Instruction],
+    payer: &Pubkey,
+    lookup_tables: &[AddressLookupTableAccount],
+) -> anyhow::Result<usize> {
+    let message = v0::Message::try_compile(payer, instructions, lookup_tables, Hash::default())
+        .map_err(|e| anyhow::anyhow!("Failed to compile v0 message: {:?}", e))?;
+    let num_signatures = message.header.num_required_signatures as usize;
+    let signature_len_prefix = if num_signatures < 0x80 { 1 } else { 2 };
+    Ok(
+        signature_len_prefix
+            + num_signatures * 64
+            + VersionedMessage::V0(message).serialize().len(),
+    )
+}
+
+/// The most decimals a mint of a market can have, as `10^decimals` must fit in a u64.
+pub const MAX_MINT_DECIMALS: u32 = 19;
+
+/// The units of a market. Metadata derived with `from_header` has distinct mints, at most
+/// `MAX_MINT_DECIMALS` decimals per mint, and nonzero lot and tick sizes, so the conversions
+/// cannot divide by zero. The conversions rely on this, so metadata should not be built by
+/// hand from unchecked values.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct MarketMetadata {
     pub base_mint: Pubkey,
     pub quote_mint: Pubkey,
@@ -80,26 +192,100 @@ pub struct MarketMetadata {
     pub num_base_lots_per_base_unit: u64,
 }
 
-pub struct SDKClientCore {
-    pub markets: BTreeMap<Pubkey, MarketMetadata>,
-    pub rng: Arc<Mutex<StdRng>>,
-    pub active_market_key: Pubkey,
-    pub trader: Pubkey,
-    pub program_id: Pubkey,
-}
+/// `MarketMetadata` is the canonical API for converting between units, amounts, lots, and
+/// ticks of a market. The conversion methods on `SDKClientCore` delegate to the metadata of the
+/// active market.
+impl MarketMetadata {
+    /// Derives the metadata of a market from its account data. The token decimals are not
+    /// stored in the market account, so they must be read from the mint accounts.
+    #[allow(clippy::useless_conversion)]
+    pub fn from_market_account_data(
+        market_account_data: &[u8],
+        base_decimals: u32,
+        quote_decimals: u32,
+    ) -> Result<Self, PhoenixSdkError> {
+        if market_account_data.len() < size_of::<MarketHeader>() {
+            return Err(PhoenixSdkError::DeserializationFailed(
+                "Market account data is too short".to_string(),
+            ));
+        }
+        let mut market_account_data = market_account_data.to_vec();
+        let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
+        let header = MarketHeader::try_from_slice(header_bytes)
+            .map_err(|e| PhoenixSdkError::DeserializationFailed(e.to_string()))?;
+        let market = load_with_dispatch_mut(&header.market_size_params, bytes)
+            .ok_or_else(|| {
+                PhoenixSdkError::DeserializationFailed(
+                    "Failed to load the market from its account data".to_string(),
+                )
+            })?
+            .inner;
 
-impl Deref for SDKClientCore {
-    type Target = MarketMetadata;
+        Self::from_header(
+            &header,
+            market.get_base_lots_per_base_unit().into(),
+            base_decimals,
+            quote_decimals,
+        )
+    }
 
-    fn deref(&self) -> &Self::Target {
-        self.markets.get(&self.active_market_key).unwrap()
+    /// Derives the metadata of a market from its header and the market's base lots per base
+    /// unit, which is stored after the header. Used when only part of the market account was
+    /// downloaded.
+    #[allow(clippy::useless_conversion)]
+    pub fn from_header(
+        header: &MarketHeader,
+        base_lots_per_base_unit: u64,
+        base_decimals: u32,
+        quote_decimals: u32,
+    ) -> Result<Self, PhoenixSdkError> {
+        // A buggy or malicious market account can hold any header, so it is checked before
+        // any conversion depends on it
+        if header.base_params.mint_key == header.quote_params.mint_key {
+            return Err(PhoenixSdkError::IdenticalMints {
+                mint: header.base_params.mint_key,
+            });
+        }
+        if base_decimals > MAX_MINT_DECIMALS || quote_decimals > MAX_MINT_DECIMALS {
+            return Err(PhoenixSdkError::InvalidDecimals {
+                base_decimals,
+                quote_decimals,
+            });
+        }
+        let metadata = MarketMetadata {
+            base_mint: header.base_params.mint_key,
+            quote_mint: header.quote_params.mint_key,
+            base_decimals,
+            quote_decimals,
+            base_multiplier: 10u64.pow(base_decimals),
+            quote_multiplier: 10u64.pow(quote_decimals),
+            quote_lot_size: header.get_quote_lot_size().into(),
+            base_lot_size: header.get_base_lot_size().into(),
+            tick_size_in_quote_atoms_per_base_unit: header
+                .get_tick_size_in_quote_atoms_per_base_unit()
+                .into(),
+            num_base_lots_per_base_unit: base_lots_per_base_unit,
+        };
+        // The conversions divide by each of these
+        if metadata.base_lot_size == 0
+            || metadata.quote_lot_size == 0
+            || metadata.tick_size_in_quote_atoms_per_base_unit == 0
+            || metadata.num_base_lots_per_base_unit == 0
+        {
+            return Err(PhoenixSdkError::InvalidLotSize(format!(
+                "base lot size {}, quote lot size {}, tick size {}, base lots per base unit {}",
+                metadata.base_lot_size,
+                metadata.quote_lot_size,
+                metadata.tick_size_in_quote_atoms_per_base_unit,
+                metadata.num_base_lots_per_base_unit
+            )));
+        }
+        Ok(metadata)
     }
-}
 
-impl SDKClientCore {
     /// RECOMMENDED:
     /// Converts base units to base lots. For example if the base currency was a Widget and you wanted to
-    /// convert 3 Widgets to base lots you would call sdk.base_unit_to_base_lots(3.0). This would return
+    /// convert 3 Widgets to base lots you would call metadata.base_unit_to_base_lots(3.0). This would return
     /// the number of base lots that would be equivalent to 3 Widgets.
     pub fn base_units_to_base_lots(&self, base_units: f64) -> u64 {
         (base_units * self.base_multiplier as f64 / self.base_lot_size as f64) as u64
@@ -107,7 +293,7 @@ impl SDKClientCore {
 
     /// RECOMMENDED:
     /// Converts base amount to base lots. For example if the base currency was a Widget with 9 decimals and you wanted to
-    /// convert 3 Widgets to base lots you would call sdk.base_amount_to_base_lots(3_000_000_000). This would return
+    /// convert 3 Widgets to base lots you would call metadata.base_amount_to_base_lots(3_000_000_000). This would return
     /// the number of base lots that would be equivalent to 3 Widgets.
     pub fn base_amount_to_base_lots(&self, base_amount: u64) -> u64 {
         base_amount / self.base_lot_size
@@ -115,7 +301,7 @@ impl SDKClientCore {
 
     /// RECOMMENDED:
     /// Converts base lots to base units. For example if the base currency was a Widget where there are
-    /// 100 base lots per Widget, you would call sdk.base_lots_to_base_units(300) to convert 300 base lots
+    /// 100 base lots per Widget, you would call metadata.base_lots_to_base_units(300) to convert 300 base lots
     /// to 3 Widgets.
     pub fn base_lots_to_base_amount(&self, base_lots: u64) -> u64 {
         base_lots * self.base_lot_size
@@ -123,7 +309,7 @@ impl SDKClientCore {
 
     /// RECOMMENDED:
     /// Converts quote units to quote lots. For example if the quote currency was USDC you wanted to
-    /// convert 3 USDC to quote lots you would call sdk.quote_unit_to_quote_lots(3.0). This would return
+    /// convert 3 USDC to quote lots you would call metadata.quote_unit_to_quote_lots(3.0). This would return
     /// the number of quote lots that would be equivalent to 3 USDC.
     pub fn quote_units_to_quote_lots(&self, quote_units: f64) -> u64 {
         (quote_units * self.quote_multiplier as f64 / self.quote_lot_size as f64) as u64
@@ -131,7 +317,7 @@ impl SDKClientCore {
 
     /// RECOMMENDED:
     /// Converts quote amount to quote lots. For example if the quote currency was USDC with 6 decimals and you wanted to
-    /// convert 3 USDC to quote lots you would call sdk.quote_amount_to_quote_lots(3_000_000). This would return
+    /// convert 3 USDC to quote lots you would call metadata.quote_amount_to_quote_lots(3_000_000). This would return
     /// the number of quote lots that would be equivalent to 3 USDC.
     pub fn quote_amount_to_quote_lots(&self, quote_amount: u64) -> u64 {
         quote_amount / self.quote_lot_size
@@ -139,7 +325,7 @@ impl SDKClientCore {
 
     /// RECOMMENDED:
     /// Converts quote lots to quote units. For example if the quote currency was USDC there are
-    /// 100 quote lots per USDC (each quote lot is worth 0.01 USDC), you would call sdk.quote_lots_to_quote_units(300) to convert 300 quote lots
+    /// 100 quote lots per USDC (each quote lot is worth 0.01 USDC), you would call metadata.quote_lots_to_quote_units(300) to convert 300 quote lots
     /// to an amount equal to 3 USDC (3_000_000).
     pub fn quote_lots_to_quote_amount(&self, quote_lots: u64) -> u64 {
         quote_lots * self.quote_lot_size
@@ -147,7 +333,7 @@ impl SDKClientCore {
 
     /// Converts a base amount to a floating point number of base units. For example if the base currency
     /// is a Widget where the token has 9 decimals and you wanted to convert a base amount of 1000000000 to
-    /// a floating point number of base units you would call sdk.base_amount_to_float(1_000_000_000). This
+    /// a floating point number of base units you would call metadata.base_amount_to_float(1_000_000_000). This
     /// would return 1.0. This is useful for displaying the base amount in a human readable format.
     pub fn base_amount_to_base_unit_as_float(&self, base_amount: u64) -> f64 {
         base_amount as f64 / self.base_multiplier as f64
@@ -155,7 +341,7 @@ impl SDKClientCore {
 
     /// Converts a quote amount to a floating point number of quote units. For example if the quote currency
     /// is USDC the token has 6 decimals and you wanted to convert a quote amount of 1000000 to
-    /// a floating point number of quote units you would call sdk.quote_amount_to_float(1_000_000). This
+    /// a floating point number of quote units you would call metadata.quote_amount_to_float(1_000_000). This
     /// would return 1.0. This is useful for displaying the quote amount in a human readable format.
     pub fn quote_amount_to_quote_unit_as_float(&self, quote_amount: u64) -> f64 {
         quote_amount as f64 / self.quote_multiplier as f64
@@ -187,6 +373,18 @@ impl SDKClientCore {
             / self.num_base_lots_per_base_unit
     }
 
+    /// Takes in information from a fill event and converts it into the equivalent number of
+    /// quote atoms. Same as `fill_event_to_quote_amount`.
+    pub fn fill_event_to_quote_atoms(&self, fill: &Fill) -> u64 {
+        self.fill_event_to_quote_amount(fill)
+    }
+
+    /// Converts a price in quote atoms per base unit to a number of ticks (rounded down). This is
+    /// the conversion the order instruction builders apply to their `price` argument.
+    pub fn quote_atoms_per_base_unit_to_ticks(&self, price: u64) -> u64 {
+        price / self.tick_size_in_quote_atoms_per_base_unit
+    }
+
     /// Takes in a price as a floating point number and converts it to a number of ticks (rounded down)
     pub fn float_price_to_ticks(&self, price: f64) -> u64 {
         ((price * self.quote_multiplier as f64)
@@ -215,24 +413,315 @@ impl SDKClientCore {
     }
 }
 
+/// Returned when freshly derived metadata for a market disagrees with the cached entry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MetadataConflict {
+    pub market: Pubkey,
+    pub cached: MarketMetadata,
+    pub fetched: MarketMetadata,
+}
+
+impl Display for MetadataConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Metadata conflict for market {}: cached {:?}, fetched {:?}",
+            self.market, self.cached, self.fetched
+        )
+    }
+}
+
+impl std::error::Error for MetadataConflict {}
+
+pub struct SDKClientCore {
+    /// Behind a lock so markets can be added through a shared reference, e.g. an
+    /// `Arc<SDKClient>` shared between tasks
+    pub markets: RwLock<BTreeMap<Pubkey, MarketMetadata>>,
+    pub rng: Arc<Mutex<StdRng>>,
+    pub active_market_key: Pubkey,
+    pub trader: Pubkey,
+    pub program_id: Pubkey,
+    /// Receives a record of every order and cancel instruction the builders return, see
+    /// `OrderAuditLog`
+    pub order_audit_log: Option<Arc<dyn OrderAuditLog>>,
+}
+
+/// Conversions with the metadata of the active market. Each fails with `MarketNotLoaded` if the
+/// active market is not cached, e.g. on a read-only client before `change_active_market`.
+impl SDKClientCore {
+    /// See `MarketMetadata::base_units_to_base_lots`. Uses the active market.
+    pub fn base_units_to_base_lots(&self, base_units: f64) -> Result<u64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.base_units_to_base_lots(base_units))
+    }
+
+    /// See `MarketMetadata::base_amount_to_base_lots`. Uses the active market.
+    pub fn base_amount_to_base_lots(&self, base_amount: u64) -> Result<u64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.base_amount_to_base_lots(base_amount))
+    }
+
+    /// See `MarketMetadata::base_lots_to_base_amount`. Uses the active market.
+    pub fn base_lots_to_base_amount(&self, base_lots: u64) -> Result<u64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.base_lots_to_base_amount(base_lots))
+    }
+
+    /// See `MarketMetadata::quote_units_to_quote_lots`. Uses the active market.
+    pub fn quote_units_to_quote_lots(&self, quote_units: f64) -> Result<u64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.quote_units_to_quote_lots(quote_units))
+    }
+
+    /// See `MarketMetadata::quote_amount_to_quote_lots`. Uses the active market.
+    pub fn quote_amount_to_quote_lots(&self, quote_amount: u64) -> Result<u64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.quote_amount_to_quote_lots(quote_amount))
+    }
+
+    /// See `MarketMetadata::quote_lots_to_quote_amount`. Uses the active market.
+    pub fn quote_lots_to_quote_amount(&self, quote_lots: u64) -> Result<u64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.quote_lots_to_quote_amount(quote_lots))
+    }
+
+    /// See `MarketMetadata::base_amount_to_base_unit_as_float`. Uses the active market.
+    pub fn base_amount_to_base_unit_as_float(
+        &self,
+        base_amount: u64,
+    ) -> Result<f64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.base_amount_to_base_unit_as_float(base_amount))
+    }
+
+    /// See `MarketMetadata::quote_amount_to_quote_unit_as_float`. Uses the active market.
+    pub fn quote_amount_to_quote_unit_as_float(
+        &self,
+        quote_amount: u64,
+    ) -> Result<f64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.quote_amount_to_quote_unit_as_float(quote_amount))
+    }
+
+    /// See `MarketMetadata::print_quote_amount`. Uses the active market.
+    pub fn print_quote_amount(&self, quote_amount: u64) -> Result<(), PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.print_quote_amount(quote_amount))
+    }
+
+    /// See `MarketMetadata::print_base_amount`. Uses the active market.
+    pub fn print_base_amount(&self, base_amount: u64) -> Result<(), PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.print_base_amount(base_amount))
+    }
+
+    /// See `MarketMetadata::fill_event_to_quote_amount`. Uses the active market.
+    pub fn fill_event_to_quote_amount(&self, fill: &Fill) -> Result<u64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.fill_event_to_quote_amount(fill))
+    }
+
+    /// See `MarketMetadata::order_to_quote_amount`. Uses the active market.
+    pub fn order_to_quote_amount(
+        &self,
+        base_lots: u64,
+        price_in_ticks: u64,
+    ) -> Result<u64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.order_to_quote_amount(base_lots, price_in_ticks))
+    }
+
+    /// See `MarketMetadata::fill_event_to_quote_atoms`. Uses the active market.
+    pub fn fill_event_to_quote_atoms(&self, fill: &Fill) -> Result<u64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.fill_event_to_quote_atoms(fill))
+    }
+
+    /// See `MarketMetadata::quote_atoms_per_base_unit_to_ticks`. Uses the active market.
+    pub fn quote_atoms_per_base_unit_to_ticks(&self, price: u64) -> Result<u64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.quote_atoms_per_base_unit_to_ticks(price))
+    }
+
+    /// See `MarketMetadata::float_price_to_ticks`. Uses the active market.
+    pub fn float_price_to_ticks(&self, price: f64) -> Result<u64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.float_price_to_ticks(price))
+    }
+
+    /// See `MarketMetadata::float_price_to_ticks_rounded_up`. Uses the active market.
+    pub fn float_price_to_ticks_rounded_up(&self, price: f64) -> Result<u64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.float_price_to_ticks_rounded_up(price))
+    }
+
+    /// See `MarketMetadata::ticks_to_float_price`. Uses the active market.
+    pub fn ticks_to_float_price(&self, ticks: u64) -> Result<f64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.ticks_to_float_price(ticks))
+    }
+
+    /// See `MarketMetadata::base_lots_to_base_units_multiplier`. Uses the active market.
+    pub fn base_lots_to_base_units_multiplier(&self) -> Result<f64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.base_lots_to_base_units_multiplier())
+    }
+
+    /// See `MarketMetadata::ticks_to_float_price_multiplier`. Uses the active market.
+    pub fn ticks_to_float_price_multiplier(&self) -> Result<f64, PhoenixSdkError> {
+        self.active_market_metadata()
+            .map(|metadata| metadata.ticks_to_float_price_multiplier())
+    }
+}
+
 impl SDKClientCore {
     pub fn get_next_client_order_id(&self) -> u128 {
         self.rng.lock().unwrap().gen::<u128>()
     }
 
-    pub fn change_active_market(&mut self, market: &Pubkey) -> anyhow::Result<()> {
-        if self.markets.get(market).is_some() {
-            self.active_market_key = *market;
+    pub fn change_active_market(&mut self, market: &Pubkey) -> Result<(), PhoenixSdkError> {
+        self.market_metadata(market)?;
+        self.active_market_key = *market;
+        Ok(())
+    }
+
+    /// The cached metadata of `market_key`, or `MarketNotLoaded` if it is not cached
+    pub fn market_metadata(&self, market_key: &Pubkey) -> Result<MarketMetadata, PhoenixSdkError> {
+        self.markets.read().unwrap().get(market_key).copied().ok_or(
+            PhoenixSdkError::MarketNotLoaded {
+                market: *market_key,
+            },
+        )
+    }
+
+    /// Caches the metadata of `market_key`, returning the entry it replaced
+    pub fn insert_market_metadata(
+        &self,
+        market_key: Pubkey,
+        metadata: MarketMetadata,
+    ) -> Option<MarketMetadata> {
+        self.markets.write().unwrap().insert(market_key, metadata)
+    }
+
+    /// The keys of the cached markets
+    pub fn market_keys(&self) -> Vec<Pubkey> {
+        self.markets.read().unwrap().keys().copied().collect()
+    }
+
+    /// The cached metadata of the active market. Panics if it is not cached; the instruction
+    /// builders and conversions use `active_market_metadata`, which returns `MarketNotLoaded`
+    /// instead.
+    pub fn get_active_market_metadata(&self) -> MarketMetadata {
+        *self
+            .markets
+            .read()
+            .unwrap()
+            .get(&self.active_market_key)
+            .unwrap()
+    }
+
+    /// The cached metadata of the active market, or `MarketNotLoaded` if it is not cached
+    pub fn active_market_metadata(&self) -> Result<MarketMetadata, PhoenixSdkError> {
+        self.market_metadata(&self.active_market_key)
+    }
+
+    /// Fails with `NoSignerConfigured` if there is no trader to sign orders, i.e. the trader
+    /// is `Pubkey::default()` as on a read-only `SDKClient`
+    pub fn ensure_signer(&self) -> Result<(), PhoenixSdkError> {
+        if self.trader == Pubkey::default() {
+            Err(PhoenixSdkError::NoSignerConfigured)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The metadata of the active market for building one of the trader's instructions. Fails
+    /// with `NoSignerConfigured` on a client without a trader, or `MarketNotLoaded`.
+    fn order_metadata(&self) -> Result<MarketMetadata, PhoenixSdkError> {
+        self.ensure_signer()?;
+        self.active_market_metadata()
+    }
+
+    /// Returns the accounts that instructions built for the active market and trader may
+    /// reference, or `MarketNotLoaded` if the active market is not cached
+    pub fn get_expected_accounts(&self) -> Result<ExpectedAccounts, PhoenixSdkError> {
+        Ok(ExpectedAccounts::for_market_and_trader_with_program_id(
+            &self.active_market_metadata()?,
+            &self.active_market_key,
+            &self.trader,
+            &self.program_id,
+        ))
+    }
+
+    /// Audits each Phoenix instruction in `instructions` against the expected accounts of the
+    /// market it references and the client's trader, see `ExpectedAccounts`. Instructions of
+    /// other programs are not audited. Fails with `UnexpectedAccounts`, indexed into
+    /// `instructions`, or with `MarketNotLoaded` if an instruction's market is not cached.
+    pub fn audit_phoenix_instructions(
+        &self,
+        instructions: &[Instruction],
+    ) -> Result<(), PhoenixSdkError> {
+        let mut expected_by_market: BTreeMap<Pubkey, ExpectedAccounts> = BTreeMap::new();
+        let mut unexpected = vec![];
+        for (instruction_index, instruction) in instructions.iter().enumerate() {
+            if instruction.program_id != self.program_id {
+                continue;
+            }
+            let market_key = instruction
+                .accounts
+                .get(MARKET_ACCOUNT_INDEX)
+                .map(|meta| meta.pubkey)
+                .ok_or_else(|| {
+                    PhoenixSdkError::InvalidArgument(format!(
+                        "Phoenix instruction {} has no market account",
+                        instruction_index
+                    ))
+                })?;
+            if !expected_by_market.contains_key(&market_key) {
+                let expected = ExpectedAccounts::for_market_and_trader_with_program_id(
+                    &self.market_metadata(&market_key)?,
+                    &market_key,
+                    &self.trader,
+                    &self.program_id,
+                );
+                expected_by_market.insert(market_key, expected);
+            }
+            if let Err(accounts) = audit_instruction(instruction, &expected_by_market[&market_key])
+            {
+                unexpected.extend(accounts.into_iter().map(|account| UnexpectedAccount {
+                    instruction_index,
+                    ..account
+                }));
+            }
+        }
+        if unexpected.is_empty() {
             Ok(())
         } else {
-            Err(anyhow::Error::msg("Market not found"))
+            Err(PhoenixSdkError::UnexpectedAccounts {
+                accounts: unexpected,
+            })
         }
     }
 
-    pub fn get_active_market_metadata(&self) -> &MarketMetadata {
-        self.markets.get(&self.active_market_key).unwrap()
+    /// The markets that emitted `events`, Phoenix log instruction payloads as passed to
+    /// `parse_phoenix_events`. Payloads without a valid header are skipped.
+    pub fn event_markets(events: &[Vec<u8>]) -> Vec<Pubkey> {
+        events
+            .iter()
+            .filter_map(|event| {
+                match MarketEvent::try_from_slice(event.get(..AUDIT_LOG_HEADER_LEN)?).ok()? {
+                    MarketEvent::Header { header } => Some(header.market),
+                    _ => None,
+                }
+            })
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
     }
 
+    /// Decodes the Phoenix log instruction payloads of the transaction `sig`. The events of a
+    /// market that is not cached are skipped with a warning; cache the markets returned by
+    /// `event_markets` first to get every event.
     pub fn parse_phoenix_events(
         &self,
         sig: &Signature,
@@ -241,25 +730,42 @@ impl SDKClientCore {
         let mut market_events: Vec<PhoenixEvent> = vec![];
 
         for event in events.iter() {
-            let header_event = MarketEvent::try_from_slice(&event[..AUDIT_LOG_HEADER_LEN]).ok()?;
+            let header_event = event
+                .get(..AUDIT_LOG_HEADER_LEN)
+                .and_then(|header_bytes| MarketEvent::try_from_slice(header_bytes).ok());
             let header = match header_event {
-                MarketEvent::Header { header } => Some(header),
+                Some(MarketEvent::Header { header }) => header,
                 _ => {
-                    panic!("Expected a header event");
+                    println!("Skipping a Phoenix log payload without a header event");
+                    continue;
                 }
-            }?;
-            let offset = AUDIT_LOG_HEADER_LEN;
-            let mut phoenix_event_bytes = (header.total_events as u32).to_le_bytes().to_vec();
-            phoenix_event_bytes.extend_from_slice(&event[offset..]);
-            let phoenix_events = match Vec::<MarketEvent>::try_from_slice(&phoenix_event_bytes) {
-                Ok(v) => v,
-                Err(e) => {
-                    println!("Error parsing events: {:?}", e);
-                    return None;
+            };
+            // Lots are converted with the metadata of the market that emitted the events. The
+            // events of a market that is not cached are skipped, since converting them with
+            // another market's lot sizes would misreport them. Callers cache the markets
+            // returned by `event_markets` first.
+            let metadata = match self.market_metadata(&header.market) {
+                Ok(metadata) => metadata,
+                Err(_) => {
+                    println!(
+                        "Warning: skipping {} events of market {}, which is not cached",
+                        header.total_events, header.market
+                    );
+                    continue;
                 }
             };
+            let phoenix_events =
+                MarketEventIter::new(&event[AUDIT_LOG_HEADER_LEN..], header.total_events as u32);
+            market_events.reserve(header.total_events as usize);
             let mut trade_direction = None;
             for phoenix_event in phoenix_events {
+                let phoenix_event = match phoenix_event {
+                    Ok(e) => e,
+                    Err(e) => {
+                        println!("Error parsing events: {:?}", e);
+                        return None;
+                    }
+                };
                 match phoenix_event {
                     MarketEvent::Fill {
                         index,
@@ -381,10 +887,10 @@ impl SDKClientCore {
                         event_index: index as u64,
                         details: MarketEventDetails::FillSummary(FillSummary {
                             client_order_id,
-                            total_base_filled: total_base_lots_filled * self.base_lot_size,
+                            total_base_filled: total_base_lots_filled * metadata.base_lot_size,
                             total_quote_filled_including_fees: total_quote_lots_filled
-                                * self.quote_lot_size,
-                            total_quote_fees: total_fee_in_quote_lots * self.quote_lot_size,
+                                * metadata.quote_lot_size,
+                            total_quote_fees: total_fee_in_quote_lots * metadata.quote_lot_size,
                             trade_direction: trade_direction.unwrap_or(0),
                         }),
                     }),
@@ -400,19 +906,64 @@ impl SDKClientCore {
                         signer: header.signer,
                         event_index: index as u64,
                         details: MarketEventDetails::Fee(
-                            fees_collected_in_quote_lots * self.quote_lot_size,
+                            fees_collected_in_quote_lots * metadata.quote_lot_size,
                         ),
                     }),
-                    _ => {
-                        panic!("Unexpected Event!");
-                    }
+                    _ => skip_unknown_event(&header.market),
                 }
             }
         }
         Some(market_events)
     }
 
-    pub fn get_ioc_ix(&self, price: u64, side: Side, num_base_lots: u64) -> Instruction {
+    /// Passes a `Built` record of `instruction` to the order audit log, if one is set, and
+    /// returns the instruction. A failing log is reported and otherwise ignored, so it never
+    /// fails the builder.
+    fn audited(&self, instruction: Instruction, template: Option<AuditedTemplate>) -> Instruction {
+        if let Some(order_audit_log) = self.order_audit_log.as_ref() {
+            let record = OrderAuditRecord::new(&instruction, OrderAuditStage::Built, template);
+            if let Err(e) = order_audit_log.record(&record) {
+                println!("Failed to record order audit record {:?}: {}", record, e);
+            }
+        }
+        instruction
+    }
+
+    /// The order instruction for `order_packet` on the active market, built from `template` if
+    /// given. With `use_only_deposited_funds` this is the `*WithFreeFunds` variant, which
+    /// references neither the trader's token accounts nor the vaults, so more orders fit in a
+    /// transaction.
+    fn new_order_ix(
+        &self,
+        order_packet: &OrderPacket,
+        use_only_deposited_funds: bool,
+        template: Option<AuditedTemplate>,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        let metadata = self.order_metadata()?;
+        let instruction = if use_only_deposited_funds {
+            create_new_order_with_free_funds_instruction(
+                &self.active_market_key,
+                &self.trader,
+                order_packet,
+            )
+        } else {
+            create_new_order_instruction(
+                &self.active_market_key.clone(),
+                &self.trader,
+                &metadata.base_mint,
+                &metadata.quote_mint,
+                order_packet,
+            )
+        };
+        Ok(self.audited(instruction, template))
+    }
+
+    pub fn get_ioc_ix(
+        &self,
+        price: u64,
+        side: Side,
+        num_base_lots: u64,
+    ) -> Result<Instruction, PhoenixSdkError> {
         self.get_ioc_generic_ix(price, side, num_base_lots, None, None, None, None)
     }
 
@@ -426,29 +977,102 @@ impl SDKClientCore {
         match_limit: Option<u64>,
         client_order_id: Option<u128>,
         use_only_deposited_funds: Option<bool>,
-    ) -> Instruction {
-        let num_quote_ticks_per_base_unit = price / self.tick_size_in_quote_atoms_per_base_unit;
+    ) -> Result<Instruction, PhoenixSdkError> {
+        self.get_ioc_with_min_fill_ix(
+            price,
+            side,
+            num_base_lots,
+            None,
+            None,
+            self_trade_behavior,
+            match_limit,
+            client_order_id,
+            use_only_deposited_funds,
+        )
+    }
+
+    /// Same as `get_ioc_generic_ix`, but the order fails unless it fills at least
+    /// `min_base_lots_to_fill` base lots and `min_quote_lots_to_fill` quote lots. Both default
+    /// to 0, no minimum.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_ioc_with_min_fill_ix(
+        &self,
+        price: u64,
+        side: Side,
+        num_base_lots: u64,
+        min_base_lots_to_fill: Option<u64>,
+        min_quote_lots_to_fill: Option<u64>,
+        self_trade_behavior: Option<SelfTradeBehavior>,
+        match_limit: Option<u64>,
+        client_order_id: Option<u128>,
+        use_only_deposited_funds: Option<bool>,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        let num_quote_ticks_per_base_unit = self
+            .order_metadata()?
+            .quote_atoms_per_base_unit_to_ticks(price);
         let self_trade_behavior = self_trade_behavior.unwrap_or(SelfTradeBehavior::CancelProvide);
         let client_order_id = client_order_id.unwrap_or(0);
         let use_only_deposited_funds = use_only_deposited_funds.unwrap_or(false);
-        create_new_order_instruction(
-            &self.active_market_key.clone(),
-            &self.trader,
-            &self.base_mint,
-            &self.quote_mint,
-            &OrderPacket::new_ioc_by_lots(
-                side,
-                num_quote_ticks_per_base_unit,
-                num_base_lots,
-                self_trade_behavior,
-                match_limit,
-                client_order_id,
-                use_only_deposited_funds,
+        self.new_order_ix(
+            &with_min_fill(
+                OrderPacket::new_ioc_by_lots(
+                    side,
+                    num_quote_ticks_per_base_unit,
+                    num_base_lots,
+                    self_trade_behavior,
+                    match_limit,
+                    client_order_id,
+                    use_only_deposited_funds,
+                ),
+                min_base_lots_to_fill.unwrap_or(0),
+                min_quote_lots_to_fill.unwrap_or(0),
+            ),
+            use_only_deposited_funds,
+            None,
+        )
+    }
+
+    pub fn get_ioc_ix_from_template(
+        &self,
+        ioc_order_template: &ImmediateOrCancelOrderTemplate,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        let metadata = self.order_metadata()?;
+        ioc_order_template.validate(&metadata)?;
+        let &ImmediateOrCancelOrderTemplate {
+            side,
+            price_as_float,
+            size_in_base_units,
+            min_base_units_to_fill,
+            min_quote_units_to_fill,
+            self_trade_behavior,
+            match_limit,
+            client_order_id,
+            use_only_deposited_funds,
+        } = ioc_order_template;
+        self.new_order_ix(
+            &with_min_fill(
+                OrderPacket::new_ioc_by_lots(
+                    side,
+                    metadata.float_price_to_ticks(price_as_float),
+                    metadata.base_units_to_base_lots(size_in_base_units),
+                    self_trade_behavior,
+                    match_limit,
+                    client_order_id,
+                    use_only_deposited_funds,
+                ),
+                metadata.base_units_to_base_lots(min_base_units_to_fill),
+                metadata.quote_units_to_quote_lots(min_quote_units_to_fill),
             ),
+            use_only_deposited_funds,
+            Some(AuditedTemplate::ImmediateOrCancel(*ioc_order_template)),
         )
     }
 
-    pub fn get_fok_sell_ix(&self, price: u64, size_in_base_lots: u64) -> Instruction {
+    pub fn get_fok_sell_ix(
+        &self,
+        price: u64,
+        size_in_base_lots: u64,
+    ) -> Result<Instruction, PhoenixSdkError> {
         self.get_fok_generic_ix(price, Side::Ask, size_in_base_lots, None, None, None, None)
     }
 
@@ -460,7 +1084,7 @@ impl SDKClientCore {
         match_limit: Option<u64>,
         client_order_id: Option<u128>,
         use_only_deposited_funds: Option<bool>,
-    ) -> Instruction {
+    ) -> Result<Instruction, PhoenixSdkError> {
         self.get_fok_generic_ix(
             price,
             Side::Bid,
@@ -480,7 +1104,7 @@ impl SDKClientCore {
         match_limit: Option<u64>,
         client_order_id: Option<u128>,
         use_only_deposited_funds: Option<bool>,
-    ) -> Instruction {
+    ) -> Result<Instruction, PhoenixSdkError> {
         self.get_fok_generic_ix(
             price,
             Side::Ask,
@@ -502,19 +1126,16 @@ impl SDKClientCore {
         match_limit: Option<u64>,
         client_order_id: Option<u128>,
         use_only_deposited_funds: Option<bool>,
-    ) -> Instruction {
+    ) -> Result<Instruction, PhoenixSdkError> {
         let self_trade_behavior = self_trade_behavior.unwrap_or(SelfTradeBehavior::CancelProvide);
         let client_order_id = client_order_id.unwrap_or(0);
-        let target_price_in_ticks = price / self.tick_size_in_quote_atoms_per_base_unit;
+        let metadata = self.order_metadata()?;
+        let target_price_in_ticks = metadata.quote_atoms_per_base_unit_to_ticks(price);
         let use_only_deposited_funds = use_only_deposited_funds.unwrap_or(false);
         match side {
             Side::Bid => {
-                let quote_lot_budget = size / self.quote_lot_size;
-                create_new_order_instruction(
-                    &self.active_market_key.clone(),
-                    &self.trader,
-                    &self.base_mint,
-                    &self.quote_mint,
+                let quote_lot_budget = metadata.quote_amount_to_quote_lots(size);
+                self.new_order_ix(
                     &OrderPacket::new_fok_buy_with_limit_price(
                         target_price_in_ticks,
                         quote_lot_budget,
@@ -523,15 +1144,13 @@ impl SDKClientCore {
                         client_order_id,
                         use_only_deposited_funds,
                     ),
+                    use_only_deposited_funds,
+                    None,
                 )
             }
             Side::Ask => {
-                let num_base_lots = size / self.base_lot_size;
-                create_new_order_instruction(
-                    &self.active_market_key.clone(),
-                    &self.trader,
-                    &self.base_mint,
-                    &self.quote_mint,
+                let num_base_lots = metadata.base_amount_to_base_lots(size);
+                self.new_order_ix(
                     &OrderPacket::new_fok_sell_with_limit_price(
                         target_price_in_ticks,
                         num_base_lots,
@@ -540,6 +1159,8 @@ impl SDKClientCore {
                         client_order_id,
                         use_only_deposited_funds,
                     ),
+                    use_only_deposited_funds,
+                    None,
                 )
             }
         }
@@ -550,19 +1171,13 @@ impl SDKClientCore {
         lots_in: u64,
         min_lots_out: u64,
         side: Side,
-    ) -> Instruction {
+    ) -> Result<Instruction, PhoenixSdkError> {
         let order_type = match side {
             Side::Bid => OrderPacket::new_ioc_buy_with_slippage(lots_in, min_lots_out),
             Side::Ask => OrderPacket::new_ioc_sell_with_slippage(lots_in, min_lots_out),
         };
 
-        create_new_order_instruction(
-            &self.active_market_key.clone(),
-            &self.trader,
-            &self.base_mint,
-            &self.quote_mint,
-            &order_type,
-        )
+        self.new_order_ix(&order_type, false, None)
     }
 
     pub fn get_ioc_from_tick_price_ix(
@@ -570,12 +1185,8 @@ impl SDKClientCore {
         tick_price: u64,
         side: Side,
         size: u64,
-    ) -> Instruction {
-        create_new_order_instruction(
-            &self.active_market_key.clone(),
-            &self.trader,
-            &self.base_mint,
-            &self.quote_mint,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        self.new_order_ix(
             &OrderPacket::new_ioc_by_lots(
                 side,
                 tick_price,
@@ -585,10 +1196,17 @@ impl SDKClientCore {
                 self.rng.lock().unwrap().gen::<u128>(),
                 false,
             ),
+            false,
+            None,
         )
     }
 
-    pub fn get_post_only_ix(&self, price: u64, side: Side, size: u64) -> Instruction {
+    pub fn get_post_only_ix(
+        &self,
+        price: u64,
+        side: Side,
+        size: u64,
+    ) -> Result<Instruction, PhoenixSdkError> {
         self.get_post_only_generic_ix(price, side, size, None, None, None)
     }
 
@@ -600,16 +1218,14 @@ impl SDKClientCore {
         client_order_id: Option<u128>,
         reject_post_only: Option<bool>,
         use_only_deposited_funds: Option<bool>,
-    ) -> Instruction {
-        let price_in_ticks = price / self.tick_size_in_quote_atoms_per_base_unit;
+    ) -> Result<Instruction, PhoenixSdkError> {
+        let price_in_ticks = self
+            .order_metadata()?
+            .quote_atoms_per_base_unit_to_ticks(price);
         let client_order_id = client_order_id.unwrap_or(0);
         let reject_post_only = reject_post_only.unwrap_or(false);
         let use_only_deposited_funds = use_only_deposited_funds.unwrap_or(false);
-        create_new_order_instruction(
-            &self.active_market_key.clone(),
-            &self.trader,
-            &self.base_mint,
-            &self.quote_mint,
+        self.new_order_ix(
             &OrderPacket::new_post_only(
                 side,
                 price_in_ticks,
@@ -618,6 +1234,8 @@ impl SDKClientCore {
                 reject_post_only,
                 use_only_deposited_funds,
             ),
+            use_only_deposited_funds,
+            None,
         )
     }
 
@@ -628,12 +1246,8 @@ impl SDKClientCore {
         size: u64,
         client_order_id: u128,
         improve_price_on_cross: bool,
-    ) -> Instruction {
-        create_new_order_instruction(
-            &self.active_market_key.clone(),
-            &self.trader,
-            &self.base_mint,
-            &self.quote_mint,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        self.new_order_ix(
             &if improve_price_on_cross {
                 OrderPacket::new_adjustable_post_only_default_with_client_order_id(
                     side,
@@ -649,13 +1263,209 @@ impl SDKClientCore {
                     client_order_id,
                 )
             },
+            false,
+            None,
+        )
+    }
+
+    pub fn get_post_only_ix_from_template(
+        &self,
+        post_only_order_template: &PostOnlyOrderTemplate,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        let metadata = self.order_metadata()?;
+        post_only_order_template.validate(&metadata)?;
+        let &PostOnlyOrderTemplate {
+            side,
+            price_as_float,
+            size_in_base_units,
+            client_order_id,
+            reject_post_only,
+            use_only_deposited_funds,
+        } = post_only_order_template;
+        self.new_order_ix(
+            &OrderPacket::new_post_only(
+                side,
+                metadata.float_price_to_ticks(price_as_float),
+                metadata.base_units_to_base_lots(size_in_base_units),
+                client_order_id,
+                reject_post_only,
+                use_only_deposited_funds,
+            ),
+            use_only_deposited_funds,
+            Some(AuditedTemplate::PostOnly(*post_only_order_template)),
+        )
+    }
+
+    /// Predicts the price, in ticks, at which the program will place
+    /// `post_only_order_template` on the active market given `orderbook`, a snapshot of the
+    /// book taken before the order is sent. Returns None if the order would be rejected. See
+    /// `post_only_price_in_ticks` for the amendment rule.
+    #[allow(clippy::useless_conversion)]
+    pub fn predict_post_only_price_in_ticks(
+        &self,
+        orderbook: &Orderbook<FIFOOrderId, PhoenixOrder>,
+        post_only_order_template: &PostOnlyOrderTemplate,
+    ) -> Result<Option<u64>, PhoenixSdkError> {
+        let side = post_only_order_template.side;
+        let best_opposite = match side {
+            Side::Bid => orderbook.best_ask(),
+            Side::Ask => orderbook.best_bid(),
+        }
+        .map(|(order_id, _)| u64::from(order_id.price_in_ticks));
+        Ok(post_only_price_in_ticks(
+            side,
+            self.float_price_to_ticks(post_only_order_template.price_as_float)?,
+            best_opposite,
+            post_only_order_template.reject_post_only,
+        ))
+    }
+
+    /// Places every order of `bids` and `asks`, given as (price in ticks, size in base lots),
+    /// on `market_key` with one `PlaceMultiplePostOnlyOrders` instruction. Every order gets
+    /// `client_order_id`. Fails if the orders do not pass `MultipleOrderPacket::validate`, e.g.
+    /// if a side has more than `MAX_POST_ONLY_ORDERS_PER_SIDE` orders, or if the instruction
+    /// does not fit in a transaction of its own paid by the trader. A transaction with more
+    /// instructions or another payer may fit fewer orders, see
+    /// `SDKClient::max_post_only_orders_per_transaction`.
+    pub fn get_place_multiple_post_only_orders_ix(
+        &self,
+        market_key: &Pubkey,
+        bids: &[(u64, u64)],
+        asks: &[(u64, u64)],
+        client_order_id: Option<u128>,
+        reject_post_only: Option<bool>,
+        use_only_deposited_funds: Option<bool>,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        self.multiple_post_only_orders_ix(
+            market_key,
+            bids,
+            asks,
+            client_order_id,
+            reject_post_only,
+            use_only_deposited_funds,
+            None,
         )
     }
 
-    pub fn get_limit_order_ix(&self, price: u64, side: Side, size: u64) -> Instruction {
+    /// `get_place_multiple_post_only_orders_ix`, built from `template` if given
+    #[allow(clippy::too_many_arguments)]
+    fn multiple_post_only_orders_ix(
+        &self,
+        market_key: &Pubkey,
+        bids: &[(u64, u64)],
+        asks: &[(u64, u64)],
+        client_order_id: Option<u128>,
+        reject_post_only: Option<bool>,
+        use_only_deposited_funds: Option<bool>,
+        template: Option<AuditedTemplate>,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        self.ensure_signer()?;
+        let metadata = self.market_metadata(market_key)?;
+        let condense = |orders: &[(u64, u64)]| {
+            orders
+                .iter()
+                .map(|&(price_in_ticks, size_in_base_lots)| CondensedOrder {
+                    price_in_ticks,
+                    size_in_base_lots,
+                })
+                .collect::<Vec<_>>()
+        };
+        let packet = MultipleOrderPacket {
+            bids: condense(bids),
+            asks: condense(asks),
+            client_order_id,
+            reject_post_only: reject_post_only.unwrap_or(false),
+        };
+        packet.validate()?;
+        let instruction = if use_only_deposited_funds.unwrap_or(false) {
+            create_place_multiple_post_only_orders_with_free_funds_instruction(
+                market_key,
+                &self.trader,
+                &packet,
+            )
+        } else {
+            create_place_multiple_post_only_orders_instruction(
+                market_key,
+                &self.trader,
+                &metadata.base_mint,
+                &metadata.quote_mint,
+                &packet,
+            )
+        };
+        let size = get_transaction_size(&[instruction.clone()], &self.trader);
+        if size > PACKET_DATA_SIZE {
+            return Err(PhoenixSdkError::InvalidArgument(format!(
+                "{} post-only orders take a transaction of {} bytes, more than the {} that fit",
+                packet.bids.len() + packet.asks.len(),
+                size,
+                PACKET_DATA_SIZE
+            )));
+        }
+        Ok(self.audited(instruction, template))
+    }
+
+    /// Same as `get_place_multiple_post_only_orders_ix`, with the orders given as templates,
+    /// grouped by their side. One instruction takes a single client order id and set of flags,
+    /// so every template must have the same `client_order_id`, `reject_post_only`, and
+    /// `use_only_deposited_funds`.
+    pub fn get_place_multiple_post_only_orders_ix_from_templates(
+        &self,
+        market_key: &Pubkey,
+        templates: &[PostOnlyOrderTemplate],
+    ) -> Result<Instruction, PhoenixSdkError> {
+        let metadata = self.market_metadata(market_key)?;
+        let first = templates.first().ok_or_else(|| {
+            PhoenixSdkError::InvalidArgument("No post-only order templates given".to_string())
+        })?;
+        if templates.iter().any(|template| {
+            template.client_order_id != first.client_order_id
+                || template.reject_post_only != first.reject_post_only
+                || template.use_only_deposited_funds != first.use_only_deposited_funds
+        }) {
+            return Err(PhoenixSdkError::InvalidArgument(
+                "Post-only order templates placed by one instruction must share their client \
+                 order id, reject_post_only, and use_only_deposited_funds"
+                    .to_string(),
+            ));
+        }
+        for template in templates {
+            template.validate(&metadata)?;
+        }
+        let orders_on = |side: Side| {
+            templates
+                .iter()
+                .filter(|template| template.side == side)
+                .map(|template| {
+                    (
+                        metadata.float_price_to_ticks(template.price_as_float),
+                        metadata.base_units_to_base_lots(template.size_in_base_units),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        self.multiple_post_only_orders_ix(
+            market_key,
+            &orders_on(Side::Bid),
+            &orders_on(Side::Ask),
+            Some(first.client_order_id),
+            Some(first.reject_post_only),
+            Some(first.use_only_deposited_funds),
+            Some(AuditedTemplate::PostOnlyBatch(templates.to_vec())),
+        )
+    }
+
+    pub fn get_limit_order_ix(
+        &self,
+        price: u64,
+        side: Side,
+        size: u64,
+    ) -> Result<Instruction, PhoenixSdkError> {
         self.get_limit_order_generic_ix(price, side, size, None, None, None, None)
     }
 
+    /// A limit order at `price` quote atoms per base unit for `size` base lots. Unset options
+    /// default to `DecrementTake` self trades, no match limit, client order id 0, and funding
+    /// from the trader's token accounts, like `LimitOrderTemplate::new`.
     #[allow(clippy::too_many_arguments)]
     pub fn get_limit_order_generic_ix(
         &self,
@@ -666,16 +1476,14 @@ impl SDKClientCore {
         match_limit: Option<u64>,
         client_order_id: Option<u128>,
         use_only_deposited_funds: Option<bool>,
-    ) -> Instruction {
-        let num_quote_ticks_per_base_unit = price / self.tick_size_in_quote_atoms_per_base_unit;
+    ) -> Result<Instruction, PhoenixSdkError> {
+        let num_quote_ticks_per_base_unit = self
+            .order_metadata()?
+            .quote_atoms_per_base_unit_to_ticks(price);
         let self_trade_behavior = self_trade_behavior.unwrap_or(SelfTradeBehavior::DecrementTake);
         let client_order_id = client_order_id.unwrap_or(0);
         let use_only_deposited_funds = use_only_deposited_funds.unwrap_or(false);
-        create_new_order_instruction(
-            &self.active_market_key.clone(),
-            &self.trader,
-            &self.base_mint,
-            &self.quote_mint,
+        self.new_order_ix(
             &OrderPacket::new_limit_order(
                 side,
                 num_quote_ticks_per_base_unit,
@@ -685,6 +1493,8 @@ impl SDKClientCore {
                 client_order_id,
                 use_only_deposited_funds,
             ),
+            use_only_deposited_funds,
+            None,
         )
     }
 
@@ -694,22 +1504,53 @@ impl SDKClientCore {
         side: Side,
         size: u64,
         client_order_id: u128,
-    ) -> Instruction {
-        create_new_order_instruction(
-            &self.active_market_key.clone(),
-            &self.trader,
-            &self.base_mint,
-            &self.quote_mint,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        self.new_order_ix(
             &OrderPacket::new_limit_order_default_with_client_order_id(
                 side,
                 tick_price,
                 size,
                 client_order_id,
             ),
+            false,
+            None,
         )
     }
 
-    pub fn get_cancel_ids_ix(&self, ids: Vec<FIFOOrderId>) -> Instruction {
+    pub fn get_limit_order_ix_from_template(
+        &self,
+        limit_order_template: &LimitOrderTemplate,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        let metadata = self.order_metadata()?;
+        limit_order_template.validate(&metadata)?;
+        let &LimitOrderTemplate {
+            side,
+            price_as_float,
+            size_in_base_units,
+            self_trade_behavior,
+            match_limit,
+            client_order_id,
+            use_only_deposited_funds,
+        } = limit_order_template;
+        self.new_order_ix(
+            &OrderPacket::new_limit_order(
+                side,
+                metadata.float_price_to_ticks(price_as_float),
+                metadata.base_units_to_base_lots(size_in_base_units),
+                self_trade_behavior,
+                match_limit,
+                client_order_id,
+                use_only_deposited_funds,
+            ),
+            use_only_deposited_funds,
+            Some(AuditedTemplate::Limit(*limit_order_template)),
+        )
+    }
+
+    /// A single cancel instruction for every id. About 30 or more ids do not fit in one
+    /// transaction; `SDKClient::get_cancel_ids_ixs_chunked` splits them.
+    pub fn get_cancel_ids_ix(&self, ids: Vec<FIFOOrderId>) -> Result<Instruction, PhoenixSdkError> {
+        let metadata = self.order_metadata()?;
         let mut cancel_orders = vec![];
         for &FIFOOrderId {
             price_in_ticks,
@@ -726,38 +1567,336 @@ impl SDKClientCore {
             orders: cancel_orders,
         };
 
-        create_cancel_multiple_orders_by_id_instruction(
-            &self.active_market_key.clone(),
-            &self.trader,
-            &self.base_mint,
-            &self.quote_mint,
-            &cancel_multiple_orders,
-        )
+        Ok(self.audited(
+            create_cancel_multiple_orders_by_id_instruction(
+                &self.active_market_key.clone(),
+                &self.trader,
+                &metadata.base_mint,
+                &metadata.quote_mint,
+                &cancel_multiple_orders,
+            ),
+            None,
+        ))
     }
 
-    pub fn get_cancel_up_to_ix(&self, tick_limit: Option<u64>, side: Side) -> Instruction {
+    /// Reduces the size of a resting order by `base_lots_to_remove` without cancelling it. If
+    /// `base_lots_to_remove` is greater than or equal to the order's remaining size, the program
+    /// removes the whole order, which is equivalent to cancelling it.
+    pub fn get_reduce_order_ix(
+        &self,
+        order_id: FIFOOrderId,
+        base_lots_to_remove: u64,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        let metadata = self.order_metadata()?;
+        if base_lots_to_remove == 0 {
+            return Err(PhoenixSdkError::InvalidArgument(
+                "Reduce order must remove at least one base lot".to_string(),
+            ));
+        }
+        let FIFOOrderId {
+            price_in_ticks,
+            order_sequence_number,
+        } = order_id;
+        let params = ReduceOrderParams {
+            base_params: CancelOrderParams {
+                side: Side::from_order_sequence_number(order_sequence_number),
+                price_in_ticks,
+                order_sequence_number,
+            },
+            size: base_lots_to_remove,
+        };
+        Ok(self.audited(
+            create_reduce_order_instruction(
+                &self.active_market_key.clone(),
+                &self.trader,
+                &metadata.base_mint,
+                &metadata.quote_mint,
+                &params,
+            ),
+            None,
+        ))
+    }
+
+    /// Same as `get_reduce_order_ix`, with the size to remove given in base units. The size is
+    /// rounded down to a whole number of base lots.
+    pub fn get_reduce_order_ix_in_base_units(
+        &self,
+        order_id: FIFOOrderId,
+        base_units_to_remove: f64,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        let base_lots_to_remove = self
+            .order_metadata()?
+            .base_units_to_base_lots(base_units_to_remove);
+        self.get_reduce_order_ix(order_id, base_lots_to_remove)
+    }
+
+    pub fn get_cancel_up_to_ix(
+        &self,
+        tick_limit: Option<u64>,
+        side: Side,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        self.get_cancel_up_to_generic_ix(side, tick_limit, None, None)
+    }
+
+    /// Cancels the trader's orders on `side`, walking the side from the best price outwards:
+    /// bids from the highest price down, asks from the lowest price up. With `tick_limit`, the
+    /// walk stops at the limit, so only bids at or above it, or asks at or below it, are
+    /// cancelled.
+    ///
+    /// `num_orders_to_search` caps the orders the program looks at, the trader's and others',
+    /// and `num_orders_to_cancel` caps the orders it cancels. Both bound the compute the
+    /// instruction uses. Since each call starts again from the best price, repeating a call
+    /// with only `num_orders_to_cancel` set works through the side in chunks, while a
+    /// `num_orders_to_search` cap stops making progress once the searched orders hold none of
+    /// the trader's.
+    pub fn get_cancel_up_to_generic_ix(
+        &self,
+        side: Side,
+        tick_limit: Option<u64>,
+        num_orders_to_search: Option<u32>,
+        num_orders_to_cancel: Option<u32>,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        let metadata = self.order_metadata()?;
+        let params = CancelUpToParams {
+            side,
+            tick_limit,
+            num_orders_to_search,
+            num_orders_to_cancel,
+        };
+
+        Ok(self.audited(
+            create_cancel_up_to_instruction(
+                &self.active_market_key.clone(),
+                &self.trader,
+                &metadata.base_mint,
+                &metadata.quote_mint,
+                &params,
+            ),
+            None,
+        ))
+    }
+
+    pub fn get_cancel_all_ix(&self) -> Result<Instruction, PhoenixSdkError> {
+        let metadata = self.order_metadata()?;
+        Ok(self.audited(
+            create_cancel_all_orders_instruction(
+                &self.active_market_key.clone(),
+                &self.trader,
+                &metadata.base_mint,
+                &metadata.quote_mint,
+            ),
+            None,
+        ))
+    }
+
+    /// Same as `get_cancel_up_to_ix`, but the released funds stay deposited on the market
+    /// instead of being withdrawn to the trader's token accounts.
+    pub fn get_cancel_up_to_with_free_funds_ix(
+        &self,
+        tick_limit: Option<u64>,
+        side: Side,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        self.order_metadata()?;
         let params = CancelUpToParams {
             side,
             tick_limit,
             num_orders_to_search: None,
             num_orders_to_cancel: None,
         };
+        Ok(self.audited(
+            create_cancel_up_to_with_free_funds_instruction(
+                &self.active_market_key,
+                &self.trader,
+                &params,
+            ),
+            None,
+        ))
+    }
 
-        create_cancel_up_to_instruction(
-            &self.active_market_key.clone(),
-            &self.trader,
-            &self.base_mint,
-            &self.quote_mint,
-            &params,
-        )
+    /// Same as `get_cancel_all_ix`, but the released funds stay deposited on the market
+    /// instead of being withdrawn to the trader's token accounts.
+    pub fn get_cancel_all_with_free_funds_ix(&self) -> Result<Instruction, PhoenixSdkError> {
+        self.order_metadata()?;
+        Ok(self.audited(
+            create_cancel_all_orders_with_free_funds_instruction(
+                &self.active_market_key,
+                &self.trader,
+            ),
+            None,
+        ))
     }
+}
 
-    pub fn get_cancel_all_ix(&self) -> Instruction {
-        create_cancel_all_orders_instruction(
-            &self.active_market_key.clone(),
-            &self.trader,
-            &self.base_mint,
-            &self.quote_mint,
-        )
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{order_audit::AuditedOrderPacket, order_packet_template::OrderValidationError};
+    use rand::SeedableRng;
+
+    /// 1000 base lots per base unit, 1 quote atom per quote lot and ticks of 1000 quote atoms
+    fn metadata() -> MarketMetadata {
+        MarketMetadata {
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            base_decimals: 9,
+            quote_decimals: 6,
+            base_multiplier: 1_000_000_000,
+            quote_multiplier: 1_000_000,
+            quote_lot_size: 1,
+            base_lot_size: 1_000_000,
+            tick_size_in_quote_atoms_per_base_unit: 1_000,
+            num_base_lots_per_base_unit: 1_000,
+        }
+    }
+
+    fn core() -> SDKClientCore {
+        let market = Pubkey::new_unique();
+        SDKClientCore {
+            markets: RwLock::new(BTreeMap::from([(market, metadata())])),
+            rng: Arc::new(Mutex::new(StdRng::seed_from_u64(0))),
+            active_market_key: market,
+            trader: Pubkey::new_unique(),
+            program_id: phoenix_types::id(),
+            order_audit_log: None,
+        }
+    }
+
+    fn packet(ix: &Instruction) -> AuditedOrderPacket {
+        AuditedOrderPacket::from_instruction_data(&ix.data).unwrap()
+    }
+
+    #[test]
+    fn ioc_with_min_fill_sets_the_minimums() {
+        let core = core();
+        let ix = core
+            .get_ioc_with_min_fill_ix(
+                1_000_000,
+                Side::Bid,
+                10,
+                Some(4),
+                Some(3_000),
+                Some(SelfTradeBehavior::Abort),
+                Some(2),
+                Some(7),
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            packet(&ix),
+            AuditedOrderPacket::ImmediateOrCancel {
+                side: Side::Bid,
+                price_in_ticks: Some(1_000),
+                num_base_lots: 10,
+                num_quote_lots: 0,
+                min_base_lots_to_fill: 4,
+                min_quote_lots_to_fill: 3_000,
+                self_trade_behavior: SelfTradeBehavior::Abort,
+                match_limit: Some(2),
+                client_order_id: 7,
+                use_only_deposited_funds: false,
+            }
+        );
+    }
+
+    #[test]
+    fn ioc_minimums_default_to_zero() {
+        let core = core();
+        let with_min_fill = core
+            .get_ioc_with_min_fill_ix(1_000_000, Side::Ask, 10, None, None, None, None, None, None)
+            .unwrap();
+        let plain = core
+            .get_ioc_generic_ix(1_000_000, Side::Ask, 10, None, None, None, None)
+            .unwrap();
+        assert_eq!(with_min_fill.data, plain.data);
+        match packet(&with_min_fill) {
+            AuditedOrderPacket::ImmediateOrCancel {
+                min_base_lots_to_fill,
+                min_quote_lots_to_fill,
+                self_trade_behavior,
+                ..
+            } => {
+                assert_eq!((min_base_lots_to_fill, min_quote_lots_to_fill), (0, 0));
+                assert_eq!(self_trade_behavior, SelfTradeBehavior::CancelProvide);
+            }
+            other => panic!("Expected an immediate-or-cancel packet, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn ioc_template_converts_the_minimums_to_lots() {
+        let core = core();
+        let template = ImmediateOrCancelOrderTemplate::new(Side::Bid, 1.5, 2.0)
+            .min_base_units_to_fill(0.5)
+            .min_quote_units_to_fill(0.25);
+        match packet(&core.get_ioc_ix_from_template(&template).unwrap()) {
+            AuditedOrderPacket::ImmediateOrCancel {
+                price_in_ticks,
+                num_base_lots,
+                min_base_lots_to_fill,
+                min_quote_lots_to_fill,
+                ..
+            } => {
+                assert_eq!(price_in_ticks, Some(1_500));
+                assert_eq!(num_base_lots, 2_000);
+                assert_eq!(min_base_lots_to_fill, 500);
+                assert_eq!(min_quote_lots_to_fill, 250_000);
+            }
+            other => panic!("Expected an immediate-or-cancel packet, got {:?}", other),
+        }
+
+        let too_large = template.min_base_units_to_fill(3.0);
+        assert!(matches!(
+            core.get_ioc_ix_from_template(&too_large),
+            Err(PhoenixSdkError::InvalidOrder(
+                OrderValidationError::InvalidMinimumFill { .. }
+            ))
+        ));
+    }
+
+    /// A header with every field zeroed, as a buggy or malicious market account could hold
+    fn zeroed_header() -> MarketHeader {
+        MarketHeader::try_from_slice(&[0u8; size_of::<MarketHeader>()]).unwrap()
+    }
+
+    #[test]
+    fn from_header_rejects_identical_mints() {
+        let header = zeroed_header();
+        assert!(matches!(
+            MarketMetadata::from_header(&header, 1_000, 9, 6),
+            Err(PhoenixSdkError::IdenticalMints { mint }) if mint == Pubkey::default()
+        ));
+    }
+
+    #[test]
+    fn from_header_rejects_too_many_decimals() {
+        let mut header = zeroed_header();
+        header.base_params.mint_key = Pubkey::new_unique();
+        header.quote_params.mint_key = Pubkey::new_unique();
+        assert!(matches!(
+            MarketMetadata::from_header(&header, 1_000, MAX_MINT_DECIMALS + 1, 6),
+            Err(PhoenixSdkError::InvalidDecimals {
+                base_decimals,
+                quote_decimals: 6,
+            }) if base_decimals == MAX_MINT_DECIMALS + 1
+        ));
+    }
+
+    #[test]
+    fn from_header_rejects_zero_lot_sizes() {
+        let mut header = zeroed_header();
+        header.base_params.mint_key = Pubkey::new_unique();
+        header.quote_params.mint_key = Pubkey::new_unique();
+        assert!(matches!(
+            MarketMetadata::from_header(&header, 1_000, 9, 6),
+            Err(PhoenixSdkError::InvalidLotSize(_))
+        ));
+    }
+
+    #[test]
+    fn from_market_account_data_rejects_short_data() {
+        assert!(matches!(
+            MarketMetadata::from_market_account_data(&[0u8; 10], 9, 6),
+            Err(PhoenixSdkError::DeserializationFailed(_))
+        ));
     }
 }
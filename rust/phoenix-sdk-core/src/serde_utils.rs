@@ -0,0 +1,161 @@
+//! Serde support for the SDK types, enabled with the `serde` feature. Pubkeys and signatures
+//! are serialized as base58 strings, and orderbooks keyed by `FIFOOrderId` are serialized with
+//! each side as a list of orders, since JSON maps need string keys.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use phoenix_types::market::FIFOOrderId;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{orderbook::Orderbook, sdk_client_core::PhoenixOrder};
+
+pub mod pubkey_string {
+    use super::*;
+    use solana_program::pubkey::Pubkey;
+
+    pub fn serialize<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&pubkey.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Pubkey::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+pub mod signature_string {
+    use super::*;
+    use solana_sdk::signature::Signature;
+
+    pub fn serialize<S: Serializer>(
+        signature: &Signature,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&signature.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Signature, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Signature::from_str(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serializes `Side` as "Bid" or "Ask"
+pub mod side_string {
+    use super::*;
+    use phoenix_types::enums::Side;
+
+    pub fn serialize<S: Serializer>(side: &Side, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match side {
+            Side::Bid => "Bid",
+            Side::Ask => "Ask",
+        })
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Side, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "Bid" => Ok(Side::Bid),
+            "Ask" => Ok(Side::Ask),
+            s => Err(serde::de::Error::custom(format!("Invalid side: {}", s))),
+        }
+    }
+}
+
+pub mod self_trade_behavior_string {
+    use super::*;
+    use phoenix_types::enums::SelfTradeBehavior;
+
+    pub fn serialize<S: Serializer>(
+        self_trade_behavior: &SelfTradeBehavior,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match self_trade_behavior {
+            SelfTradeBehavior::Abort => "Abort",
+            SelfTradeBehavior::CancelProvide => "CancelProvide",
+            SelfTradeBehavior::DecrementTake => "DecrementTake",
+        })
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<SelfTradeBehavior, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "Abort" => Ok(SelfTradeBehavior::Abort),
+            "CancelProvide" => Ok(SelfTradeBehavior::CancelProvide),
+            "DecrementTake" => Ok(SelfTradeBehavior::DecrementTake),
+            s => Err(serde::de::Error::custom(format!(
+                "Invalid self trade behavior: {}",
+                s
+            ))),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct OrderRepr {
+    price_in_ticks: u64,
+    order_sequence_number: u64,
+    #[serde(flatten)]
+    order: PhoenixOrder,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OrderbookRepr {
+    size_mult: f64,
+    price_mult: f64,
+    bids: Vec<OrderRepr>,
+    asks: Vec<OrderRepr>,
+    #[serde(default)]
+    sequence_number: u64,
+}
+
+fn to_orders(book: &BTreeMap<FIFOOrderId, PhoenixOrder>) -> Vec<OrderRepr> {
+    book.iter()
+        .map(|(order_id, order)| OrderRepr {
+            price_in_ticks: order_id.price_in_ticks,
+            order_sequence_number: order_id.order_sequence_number,
+            order: *order,
+        })
+        .collect()
+}
+
+fn from_orders(orders: Vec<OrderRepr>) -> BTreeMap<FIFOOrderId, PhoenixOrder> {
+    orders
+        .into_iter()
+        .map(|order| {
+            (
+                FIFOOrderId {
+                    price_in_ticks: order.price_in_ticks,
+                    order_sequence_number: order.order_sequence_number,
+                },
+                order.order,
+            )
+        })
+        .collect()
+}
+
+impl Serialize for Orderbook<FIFOOrderId, PhoenixOrder> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        OrderbookRepr {
+            size_mult: self.size_mult,
+            price_mult: self.price_mult,
+            bids: to_orders(&self.bids),
+            asks: to_orders(&self.asks),
+            sequence_number: self.sequence_number,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Orderbook<FIFOOrderId, PhoenixOrder> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let repr = OrderbookRepr::deserialize(deserializer)?;
+        Ok(Orderbook {
+            size_mult: repr.size_mult,
+            price_mult: repr.price_mult,
+            bids: from_orders(repr.bids),
+            asks: from_orders(repr.asks),
+            sequence_number: repr.sequence_number,
+        })
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use solana_sdk::signature::Signature;
+
+struct Entry<V> {
+    value: V,
+    touched: Instant,
+    /// Matches the stamp of this entry's live position in `SignatureLru::order`
+    stamp: u64,
+}
+
+/// A signature-keyed cache bounded by `capacity` and, optionally, by the age of its entries.
+///
+/// Once full, inserting evicts the least recently used entry. With a `ttl`, an entry that was
+/// not inserted or promoted within `ttl` is treated as absent and dropped on the next write.
+/// `get` promotes an entry, while `peek` and `contains` do not, which suits deduplication:
+/// a duplicate must not extend the lifetime of the original.
+///
+/// Lookups and inserts are O(1) amortized. Promoting an entry leaves a stale position in the
+/// recency queue that is skipped on eviction and compacted once the queue grows past twice the
+/// capacity.
+pub struct SignatureLru<V> {
+    capacity: usize,
+    ttl: Option<Duration>,
+    entries: HashMap<Signature, Entry<V>>,
+    /// Least recently used first
+    order: VecDeque<(Signature, u64)>,
+    next_stamp: u64,
+}
+
+impl<V> SignatureLru<V> {
+    /// Panics if `capacity` is 0.
+    pub fn new(capacity: usize, ttl: Option<Duration>) -> Self {
+        assert!(capacity > 0, "SignatureLru needs a capacity of at least 1");
+        SignatureLru {
+            capacity,
+            ttl,
+            entries: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            next_stamp: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of entries, including expired entries that were not dropped yet.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn is_expired(&self, entry: &Entry<V>, now: Instant) -> bool {
+        self.ttl
+            .map(|ttl| now.duration_since(entry.touched) >= ttl)
+            .unwrap_or(false)
+    }
+
+    fn stamp(&mut self) -> u64 {
+        self.next_stamp += 1;
+        self.next_stamp
+    }
+
+    /// Whether `signature` is cached and not expired. Does not promote it.
+    pub fn contains(&self, signature: &Signature) -> bool {
+        self.peek(signature).is_some()
+    }
+
+    /// The value cached for `signature`, without promoting it.
+    pub fn peek(&self, signature: &Signature) -> Option<&V> {
+        let now = Instant::now();
+        self.entries
+            .get(signature)
+            .filter(|entry| !self.is_expired(entry, now))
+            .map(|entry| &entry.value)
+    }
+
+    /// The value cached for `signature`, promoting it to most recently used and resetting its
+    /// age.
+    pub fn get(&mut self, signature: &Signature) -> Option<&V> {
+        if !self.contains(signature) {
+            return None;
+        }
+        let stamp = self.stamp();
+        let entry = self.entries.get_mut(signature)?;
+        entry.touched = Instant::now();
+        entry.stamp = stamp;
+        self.order.push_back((*signature, stamp));
+        self.compact();
+        self.entries.get(signature).map(|entry| &entry.value)
+    }
+
+    /// Caches `value` for `signature` as the most recently used entry, evicting expired
+    /// entries and then the least recently used ones beyond the capacity. Returns the value
+    /// previously cached for `signature`, if it had not expired.
+    pub fn insert(&mut self, signature: Signature, value: V) -> Option<V> {
+        let now = Instant::now();
+        let stamp = self.stamp();
+        let previous = self
+            .entries
+            .insert(
+                signature,
+                Entry {
+                    value,
+                    touched: now,
+                    stamp,
+                },
+            )
+            .filter(|entry| !self.is_expired(entry, now))
+            .map(|entry| entry.value);
+        self.order.push_back((signature, stamp));
+        self.evict(now);
+        previous
+    }
+
+    /// Inserts `signature` if it is not cached. Returns false if it was, without promoting it.
+    pub fn insert_if_absent(&mut self, signature: Signature, value: V) -> bool {
+        if self.contains(&signature) {
+            return false;
+        }
+        self.insert(signature, value);
+        true
+    }
+
+    pub fn remove(&mut self, signature: &Signature) -> Option<V> {
+        // The stale position in `order` is skipped on eviction
+        self.entries.remove(signature).map(|entry| entry.value)
+    }
+
+    /// Drops expired entries now rather than on the next write
+    pub fn purge_expired(&mut self) {
+        self.evict(Instant::now());
+    }
+
+    fn evict(&mut self, now: Instant) {
+        while let Some(&(signature, stamp)) = self.order.front() {
+            let evict = match self.entries.get(&signature) {
+                Some(entry) if entry.stamp == stamp => {
+                    self.entries.len() > self.capacity || self.is_expired(entry, now)
+                }
+                // A stale position left by a promotion, a removal, or a re-insert
+                _ => {
+                    self.order.pop_front();
+                    continue;
+                }
+            };
+            if !evict {
+                break;
+            }
+            self.order.pop_front();
+            self.entries.remove(&signature);
+        }
+        self.compact();
+    }
+
+    /// Drops the stale positions from `order` once it holds more than twice the capacity
+    fn compact(&mut self) {
+        if self.order.len() > 2 * self.capacity {
+            let entries = &self.entries;
+            self.order.retain(|(signature, stamp)| {
+                entries
+                    .get(signature)
+                    .map(|entry| entry.stamp == *stamp)
+                    .unwrap_or(false)
+            });
+        }
+    }
+}
+
+/// A `SignatureLru` behind a mutex, for caches shared between threads or tasks.
+pub struct SyncSignatureLru<V> {
+    inner: Mutex<SignatureLru<V>>,
+}
+
+impl<V: Clone> SyncSignatureLru<V> {
+    /// Panics if `capacity` is 0.
+    pub fn new(capacity: usize, ttl: Option<Duration>) -> Self {
+        SyncSignatureLru {
+            inner: Mutex::new(SignatureLru::new(capacity, ttl)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().unwrap().is_empty()
+    }
+
+    /// See `SignatureLru::contains`
+    pub fn contains(&self, signature: &Signature) -> bool {
+        self.inner.lock().unwrap().contains(signature)
+    }
+
+    /// See `SignatureLru::peek`
+    pub fn peek(&self, signature: &Signature) -> Option<V> {
+        self.inner.lock().unwrap().peek(signature).cloned()
+    }
+
+    /// See `SignatureLru::get`
+    pub fn get(&self, signature: &Signature) -> Option<V> {
+        self.inner.lock().unwrap().get(signature).cloned()
+    }
+
+    /// See `SignatureLru::insert`
+    pub fn insert(&self, signature: Signature, value: V) -> Option<V> {
+        self.inner.lock().unwrap().insert(signature, value)
+    }
+
+    /// Checks and inserts under one lock, so exactly one of several concurrent callers with the
+    /// same signature gets true. See `SignatureLru::insert_if_absent`.
+    pub fn insert_if_absent(&self, signature: Signature, value: V) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .insert_if_absent(signature, value)
+    }
+
+    pub fn remove(&self, signature: &Signature) -> Option<V> {
+        self.inner.lock().unwrap().remove(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signatures(n: usize) -> Vec<Signature> {
+        (0..n).map(|_| Signature::new_unique()).collect()
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_entry() {
+        let sigs = signatures(4);
+        let mut lru = SignatureLru::new(3, None);
+        for (i, sig) in sigs[..3].iter().enumerate() {
+            lru.insert(*sig, i);
+        }
+        assert_eq!(lru.get(&sigs[0]), Some(&0));
+        lru.insert(sigs[3], 3);
+        assert_eq!(lru.len(), 3);
+        assert!(lru.contains(&sigs[0]));
+        assert!(!lru.contains(&sigs[1]));
+        assert!(lru.contains(&sigs[2]));
+        assert!(lru.contains(&sigs[3]));
+    }
+
+    #[test]
+    fn peek_does_not_promote() {
+        let sigs = signatures(3);
+        let mut lru = SignatureLru::new(2, None);
+        lru.insert(sigs[0], 0);
+        lru.insert(sigs[1], 1);
+        assert_eq!(lru.peek(&sigs[0]), Some(&0));
+        assert!(!lru.insert_if_absent(sigs[0], 10));
+        lru.insert(sigs[2], 2);
+        assert!(!lru.contains(&sigs[0]));
+        assert_eq!(lru.peek(&sigs[1]), Some(&1));
+    }
+
+    #[test]
+    fn insert_returns_the_previous_value() {
+        let sig = Signature::new_unique();
+        let mut lru = SignatureLru::new(2, None);
+        assert_eq!(lru.insert(sig, 1), None);
+        assert_eq!(lru.insert(sig, 2), Some(1));
+        assert_eq!(lru.len(), 1);
+        assert_eq!(lru.remove(&sig), Some(2));
+        assert!(lru.is_empty());
+    }
+
+    #[test]
+    fn expired_entries_are_absent_and_purged() {
+        let sig = Signature::new_unique();
+        let mut lru = SignatureLru::new(2, Some(Duration::from_millis(20)));
+        lru.insert(sig, 1);
+        assert!(lru.contains(&sig));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(!lru.contains(&sig));
+        assert_eq!(lru.get(&sig), None);
+        assert_eq!(lru.len(), 1);
+        lru.purge_expired();
+        assert!(lru.is_empty());
+        assert!(lru.insert_if_absent(sig, 2));
+    }
+
+    #[test]
+    fn stale_positions_do_not_evict_live_entries() {
+        let sigs = signatures(3);
+        let mut lru = SignatureLru::new(2, None);
+        lru.insert(sigs[0], 0);
+        lru.insert(sigs[1], 1);
+        // Leaves many stale positions of sigs[0] in the queue, which are compacted
+        for _ in 0..10 {
+            lru.get(&sigs[0]);
+        }
+        assert!(lru.order.len() <= 2 * lru.capacity());
+        lru.insert(sigs[2], 2);
+        assert!(lru.contains(&sigs[0]));
+        assert!(!lru.contains(&sigs[1]));
+        assert!(lru.contains(&sigs[2]));
+    }
+
+    #[test]
+    fn insert_if_absent_admits_one_concurrent_caller() {
+        let lru = std::sync::Arc::new(SyncSignatureLru::new(16, None));
+        let sig = Signature::new_unique();
+        let admitted = (0..8)
+            .map(|_| {
+                let lru = lru.clone();
+                std::thread::spawn(move || lru.insert_if_absent(sig, ()))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|&admitted| admitted)
+            .count();
+        assert_eq!(admitted, 1);
+    }
+}
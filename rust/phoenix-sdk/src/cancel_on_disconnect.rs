@@ -0,0 +1,258 @@
+use crate::{
+    instruction_queue::InstructionSender, market_event_handler::SDKMarketEvent,
+    sdk_client::SDKClient,
+};
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use std::{
+    collections::BTreeSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::{Builder, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// How often the worker checks for shutdown while no events arrive
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug)]
+struct Outage {
+    since: Instant,
+    /// Whether the quotes were pulled during this outage
+    cancelled: bool,
+}
+
+/// Pulls the trader's quotes when the market data connection drops, since the strategy can no
+/// longer see the market.
+///
+/// The watcher follows the `SourceDisconnected` and `SourceReconnected` events of a data
+/// source such as `LogSubscriber`. An outage starts when any connection drops and ends once
+/// every dropped connection is back. If an outage lasts longer than `grace_period`, a cancel
+/// all batch is queued as urgent for each configured market; shorter blips are ignored. After
+/// an outage that pulled the quotes, `ReadyToRequote` is emitted once every connection is back,
+/// with its missed events backfilled, and a fair price for every configured market has arrived
+/// since. A market is priced by a `MarketFairPriceUpdate` for it. A `FairPriceUpdate` or
+/// `FairValueEstimate`, which names no market, only prices the market of a watcher configured
+/// with a single market.
+#[derive(Clone, Debug)]
+pub struct CancelOnDisconnect {
+    pub grace_period: Duration,
+    cancel_instructions: Vec<(Pubkey, Instruction)>,
+    /// Markets whose connection is down
+    disconnected: BTreeSet<Pubkey>,
+    /// Markets with a fair price since every connection came back
+    priced: BTreeSet<Pubkey>,
+    outage: Option<Outage>,
+}
+
+impl CancelOnDisconnect {
+    /// Cancels all of the trader's orders on each of `market_keys`. The markets must already be
+    /// loaded in `sdk`.
+    pub fn new(
+        sdk: &SDKClient,
+        market_keys: &[Pubkey],
+        grace_period: Duration,
+    ) -> anyhow::Result<Self> {
+        let cancel_instructions = market_keys
+            .iter()
+            .map(|market_key| Ok((*market_key, sdk.market(*market_key).cancel_all_ix()?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self::with_cancel_instructions(
+            cancel_instructions,
+            grace_period,
+        ))
+    }
+
+    /// Like `new`, with the instruction queued for each market given, e.g. a free funds cancel.
+    pub fn with_cancel_instructions(
+        cancel_instructions: Vec<(Pubkey, Instruction)>,
+        grace_period: Duration,
+    ) -> Self {
+        CancelOnDisconnect {
+            grace_period,
+            cancel_instructions,
+            disconnected: BTreeSet::new(),
+            priced: BTreeSet::new(),
+            outage: None,
+        }
+    }
+
+    pub fn markets(&self) -> Vec<Pubkey> {
+        self.cancel_instructions
+            .iter()
+            .map(|(market, _)| *market)
+            .collect()
+    }
+
+    /// Whether the quotes were pulled and `ReadyToRequote` has not been emitted yet. The
+    /// strategy should not quote while this is set.
+    pub fn quotes_pulled(&self) -> bool {
+        self.outage.map(|outage| outage.cancelled).unwrap_or(false)
+    }
+
+    /// Updates the connection state with `event`, received at `now`. Returns `ReadyToRequote`
+    /// if `event` is the last fair price missing to end an outage that pulled the quotes.
+    pub fn observe(&mut self, event: &SDKMarketEvent, now: Instant) -> Option<SDKMarketEvent> {
+        let priced_markets = match event {
+            SDKMarketEvent::MarketFairPriceUpdate { market, .. } => vec![*market],
+            SDKMarketEvent::FairPriceUpdate { .. } | SDKMarketEvent::FairValueEstimate { .. }
+                if self.cancel_instructions.len() == 1 =>
+            {
+                self.markets()
+            }
+            _ => vec![],
+        };
+        match event {
+            SDKMarketEvent::SourceDisconnected { market } => {
+                self.disconnected.insert(*market);
+                self.priced.clear();
+                if self.outage.is_none() {
+                    self.outage = Some(Outage {
+                        since: now,
+                        cancelled: false,
+                    });
+                }
+            }
+            SDKMarketEvent::SourceReconnected { market } => {
+                self.disconnected.remove(market);
+                // Back within the grace period, nothing was pulled
+                if self.disconnected.is_empty() && !self.quotes_pulled() {
+                    self.outage = None;
+                }
+            }
+            _ if !priced_markets.is_empty()
+                && self.disconnected.is_empty()
+                && self.quotes_pulled() =>
+            {
+                self.priced.extend(priced_markets);
+                if self
+                    .markets()
+                    .iter()
+                    .all(|market| self.priced.contains(market))
+                {
+                    self.outage = None;
+                    self.priced.clear();
+                    return Some(SDKMarketEvent::ReadyToRequote {
+                        markets: self.markets(),
+                    });
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// The cancel batches to queue at `now`: one per market, the first time an outage has
+    /// lasted longer than the grace period, and none otherwise.
+    pub fn due_cancels(&mut self, now: Instant) -> Vec<Vec<Instruction>> {
+        let outage = match self.outage.as_mut() {
+            Some(outage) if !outage.cancelled && !self.disconnected.is_empty() => outage,
+            _ => return vec![],
+        };
+        if now.saturating_duration_since(outage.since) < self.grace_period {
+            return vec![];
+        }
+        outage.cancelled = true;
+        self.cancel_instructions
+            .iter()
+            .map(|(_, ix)| vec![ix.clone()])
+            .collect()
+    }
+
+    /// When the current outage runs out of grace, if the quotes are still up.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        match self.outage {
+            Some(outage) if !outage.cancelled && !self.disconnected.is_empty() => {
+                Some(outage.since + self.grace_period)
+            }
+            _ => None,
+        }
+    }
+
+    /// Forwards the events of `event_receiver` to `event_sender` on a background thread,
+    /// queuing the due cancels on `instruction_sender` as urgent and adding `ReadyToRequote` to
+    /// the batch of the fair price that ends an outage.
+    pub fn spawn(
+        self,
+        event_receiver: Receiver<Vec<SDKMarketEvent>>,
+        event_sender: Sender<Vec<SDKMarketEvent>>,
+        instruction_sender: InstructionSender,
+    ) -> CancelOnDisconnectWorker {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let worker_shutdown = shutdown.clone();
+        let worker = Builder::new()
+            .name("cancel-on-disconnect".to_string())
+            .spawn(move || {
+                self.run(
+                    event_receiver,
+                    event_sender,
+                    instruction_sender,
+                    worker_shutdown,
+                )
+            })
+            .unwrap();
+        CancelOnDisconnectWorker { worker, shutdown }
+    }
+
+    fn run(
+        mut self,
+        event_receiver: Receiver<Vec<SDKMarketEvent>>,
+        event_sender: Sender<Vec<SDKMarketEvent>>,
+        instruction_sender: InstructionSender,
+        shutdown: Arc<AtomicBool>,
+    ) {
+        while !shutdown.load(Ordering::Relaxed) {
+            let timeout = self
+                .next_deadline()
+                .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+                .unwrap_or(SHUTDOWN_POLL_INTERVAL)
+                .min(SHUTDOWN_POLL_INTERVAL);
+            match event_receiver.recv_timeout(timeout) {
+                Ok(mut events) => {
+                    let now = Instant::now();
+                    let ready = events
+                        .iter()
+                        .filter_map(|event| self.observe(event, now))
+                        .collect::<Vec<_>>();
+                    events.extend(ready);
+                    if event_sender.send(events).is_err() {
+                        println!("Event receiver disconnected, stopping cancel on disconnect");
+                        return;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            let cancels = self.due_cancels(Instant::now());
+            if !cancels.is_empty() {
+                println!(
+                    "Warning: market data disconnected for more than {:?}, cancelling all orders",
+                    self.grace_period
+                );
+            }
+            for cancel in cancels {
+                if let Err(e) = instruction_sender.send_urgent(cancel) {
+                    println!("Failed to queue cancel on disconnect: {}", e);
+                }
+            }
+        }
+    }
+}
+
+pub struct CancelOnDisconnectWorker {
+    pub worker: JoinHandle<()>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl CancelOnDisconnectWorker {
+    /// Asks the worker to stop. Use `join` to wait for it.
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+    }
+
+    pub fn join(self) {
+        self.worker.join().unwrap()
+    }
+}
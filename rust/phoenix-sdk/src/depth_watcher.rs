@@ -0,0 +1,140 @@
+use crate::{
+    market_event_handler::SDKMarketEvent,
+    orderbook::{crossing_report, Orderbook, OrderbookKey, OrderbookValue},
+    sdk_client::SDKClient,
+};
+use phoenix_types::enums::Side;
+use solana_program::pubkey::Pubkey;
+use std::{
+    collections::HashMap,
+    sync::{mpsc::Sender, Arc},
+    thread::{Builder, JoinHandle},
+    time::Duration,
+};
+
+/// Turns a stream of orderbook snapshots into `SDKMarketEvent::DepthChanged` events.
+///
+/// For each market and side, the watcher tracks the resting size within `bps_window` basis
+/// points of the mid and compares it to the depth it last reported. A change is reported once
+/// it moves more than `threshold` base units away from the last reported depth. A change in the
+/// opposite direction to the previous report must additionally clear `hysteresis` base units,
+/// so a depth oscillating around the trigger level does not flap. The first snapshot of a side
+/// only sets the baseline.
+///
+/// With `alarm_on_self_cross` set, every snapshot is also checked for bids at or above its own
+/// asks, and a `BookCrossed` event is emitted if it is crossed.
+#[derive(Clone, Debug)]
+pub struct DepthWatcher {
+    pub bps_window: u64,
+    pub threshold: f64,
+    pub hysteresis: f64,
+    pub alarm_on_self_cross: bool,
+    /// Last reported depth of the bid and ask side of each market
+    reported: HashMap<Pubkey, [Option<ReportedDepth>; 2]>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ReportedDepth {
+    depth: f64,
+    /// Sign of the last reported change, 0 if only the baseline has been recorded
+    direction: i8,
+}
+
+impl DepthWatcher {
+    pub fn new(bps_window: u64, threshold: f64, hysteresis: f64) -> Self {
+        DepthWatcher {
+            bps_window,
+            threshold,
+            hysteresis,
+            alarm_on_self_cross: false,
+            reported: HashMap::new(),
+        }
+    }
+
+    /// Compares the snapshot to the last reported depth of each side and returns a
+    /// `DepthChanged` event for every side that moved past the threshold, preceded by a
+    /// `BookCrossed` event if the alarm is on and the book is crossed.
+    pub fn observe<K: Ord + OrderbookKey + Copy, V: OrderbookValue + Copy>(
+        &mut self,
+        market: Pubkey,
+        orderbook: &Orderbook<K, V>,
+    ) -> Vec<SDKMarketEvent> {
+        let mut events = vec![];
+        if self.alarm_on_self_cross {
+            if let Some(crossing) = crossing_report(orderbook, orderbook, 0) {
+                events.push(SDKMarketEvent::BookCrossed { market, crossing });
+            }
+        }
+        let reported_sides = self.reported.entry(market).or_default();
+        for (i, side) in [Side::Bid, Side::Ask].into_iter().enumerate() {
+            let new_depth = orderbook.liquidity_within_bps(side, self.bps_window);
+            let reported = match reported_sides[i].as_mut() {
+                Some(reported) => reported,
+                None => {
+                    reported_sides[i] = Some(ReportedDepth {
+                        depth: new_depth,
+                        direction: 0,
+                    });
+                    continue;
+                }
+            };
+            let change = new_depth - reported.depth;
+            let direction = if change > 0.0 { 1 } else { -1 };
+            let required = if reported.direction != 0 && direction != reported.direction {
+                self.threshold + self.hysteresis
+            } else {
+                self.threshold
+            };
+            if change.abs() > required {
+                events.push(SDKMarketEvent::DepthChanged {
+                    market,
+                    side,
+                    bps_window: self.bps_window,
+                    old_depth: reported.depth,
+                    new_depth,
+                });
+                *reported = ReportedDepth {
+                    depth: new_depth,
+                    direction,
+                };
+            }
+        }
+        events
+    }
+
+    /// Fetches the orderbook of each market in `market_keys` every `interval_ms` milliseconds
+    /// on a background thread and sends any depth changes to `event_sender`. The markets must
+    /// already be loaded in `sdk`.
+    pub fn spawn(
+        mut self,
+        sdk: Arc<SDKClient>,
+        market_keys: Vec<Pubkey>,
+        event_sender: Sender<Vec<SDKMarketEvent>>,
+        interval_ms: u64,
+    ) -> JoinHandle<()> {
+        Builder::new()
+            .name("depth-watcher".to_string())
+            .spawn(move || {
+                let rt = tokio::runtime::Runtime::new().unwrap();
+                loop {
+                    for market_key in market_keys.iter() {
+                        let orderbook = match rt.block_on(sdk.get_orderbook_for_market(market_key))
+                        {
+                            Ok(orderbook) => orderbook,
+                            Err(e) => {
+                                println!("Failed to fetch orderbook for {}: {}", market_key, e);
+                                continue;
+                            }
+                        };
+                        let events = self.observe(*market_key, &orderbook);
+                        if !events.is_empty() && event_sender.send(events).is_err() {
+                            println!("Event receiver disconnected, stopping depth watcher");
+                            return;
+                        }
+                    }
+                    std::thread::sleep(Duration::from_millis(interval_ms));
+                }
+            })
+            .unwrap()
+    }
+}
@@ -1,96 +1,617 @@
-use crate::{market_event_handler::SDKMarketEvent, sdk_client::SDKClient};
+use crate::{
+    market_event_handler::{PhoenixEvent, SDKMarketEvent},
+    sdk_client::SDKClient,
+    signature_lru::SignatureLru,
+};
 use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_program::pubkey::Pubkey;
 use solana_sdk::{commitment_config::CommitmentConfig, signature::Signature};
 use std::{
+    collections::{BTreeMap, HashMap, HashSet},
     str::FromStr,
-    sync::{mpsc::Sender, Arc},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
     thread::{Builder, JoinHandle},
     time::Duration,
 };
 
+/// Maximum number of signatures returned by one `getSignaturesForAddress` request
+const SIGNATURE_PAGE_LIMIT: usize = 1000;
+
+/// Number of processed signatures remembered per poller to drop duplicates
+const PROCESSED_SIGNATURES_CAPACITY: usize = 10_000;
+
+/// Maximum number of signatures in one `getSignatureStatuses` request
+const SIGNATURE_STATUS_LIMIT: usize = 256;
+
+/// Number of checks in a row a signature must be unknown to the node before its events are
+/// retracted, so a node that briefly lags does not retract a live transaction
+const MISSING_CHECKS_BEFORE_RETRACTION: u8 = 2;
+
+/// A transaction whose events were sent at confirmed commitment but that is not finalized yet
+#[derive(Clone, Debug)]
+struct UnfinalizedSignature {
+    signature: Signature,
+    /// The sequence numbers of the events sent, by market
+    sequence_numbers: BTreeMap<Pubkey, Vec<u64>>,
+    missing_checks: u8,
+}
+
+/// Follows the transactions whose events were sent until they are finalized, and retracts
+/// the events of the ones a fork dropped.
+#[derive(Debug, Default)]
+struct ForkTracker {
+    unfinalized: Vec<UnfinalizedSignature>,
+}
+
+impl ForkTracker {
+    fn track(&mut self, signature: Signature, events: &[PhoenixEvent]) {
+        if events.is_empty() {
+            return;
+        }
+        let mut sequence_numbers = BTreeMap::<Pubkey, Vec<u64>>::new();
+        for event in events {
+            let market_sequence_numbers = sequence_numbers.entry(event.market).or_default();
+            if !market_sequence_numbers.contains(&event.sequence_number) {
+                market_sequence_numbers.push(event.sequence_number);
+            }
+        }
+        self.unfinalized.push(UnfinalizedSignature {
+            signature,
+            sequence_numbers,
+            missing_checks: 0,
+        });
+    }
+
+    /// Checks the status of every tracked signature. Finalized signatures stop being tracked.
+    /// The ledger history is searched too, so a finalized signature that left the node's
+    /// status cache is still found. Signatures the node no longer knows, because the fork that
+    /// included them was dropped, are returned as one `EventsRetracted` per market. Signatures
+    /// whose status cannot be fetched are checked again next time.
+    fn check(&mut self, sdk: &SDKClient) -> Vec<SDKMarketEvent> {
+        let mut retractions = vec![];
+        let unfinalized = std::mem::take(&mut self.unfinalized);
+        for chunk in unfinalized.chunks(SIGNATURE_STATUS_LIMIT) {
+            let signatures = chunk
+                .iter()
+                .map(|unfinalized| unfinalized.signature)
+                .collect::<Vec<_>>();
+            let statuses = match sdk.client.get_signature_statuses_with_history(&signatures) {
+                Ok(response) => response.value,
+                Err(e) => {
+                    println!("Failed to fetch signature statuses: {}", e);
+                    self.unfinalized.extend_from_slice(chunk);
+                    continue;
+                }
+            };
+            for (unfinalized, status) in chunk.iter().zip(statuses) {
+                let mut unfinalized = unfinalized.clone();
+                match status {
+                    Some(status) if status.satisfies_commitment(CommitmentConfig::finalized()) => {}
+                    Some(_) => {
+                        unfinalized.missing_checks = 0;
+                        self.unfinalized.push(unfinalized);
+                    }
+                    None => {
+                        unfinalized.missing_checks += 1;
+                        if unfinalized.missing_checks < MISSING_CHECKS_BEFORE_RETRACTION {
+                            self.unfinalized.push(unfinalized);
+                            continue;
+                        }
+                        println!(
+                            "Warning: transaction {} was dropped by a fork, retracting its events",
+                            unfinalized.signature
+                        );
+                        retractions.extend(unfinalized.sequence_numbers.into_iter().map(
+                            |(market, sequence_numbers)| SDKMarketEvent::EventsRetracted {
+                                signature: unfinalized.signature,
+                                market,
+                                sequence_numbers,
+                            },
+                        ));
+                    }
+                }
+            }
+        }
+        retractions
+    }
+}
+
+/// The signatures a poller or subscriber has already processed. Checking for a duplicate does
+/// not promote it, so signatures are evicted in the order they were first processed.
+pub(crate) fn processed_signatures() -> SignatureLru<()> {
+    SignatureLru::new(PROCESSED_SIGNATURES_CAPACITY, None)
+}
+
+/// The pagination of `EventPoller::fetch_new_signatures`. `fetch_page(before, limit)` returns
+/// at most `limit` signatures older than `before` and newer than `until`, newest first, each
+/// with whether its transaction succeeded, as `getSignaturesForAddress` does.
+fn collect_new_signatures(
+    until: Option<Signature>,
+    mut fetch_page: impl FnMut(Option<Signature>, usize) -> anyhow::Result<Vec<(String, bool)>>,
+) -> anyhow::Result<Vec<(Signature, bool)>> {
+    let mut signatures = vec![];
+    let mut before = None;
+    loop {
+        let limit = if until.is_none() {
+            1
+        } else {
+            SIGNATURE_PAGE_LIMIT
+        };
+        let page = fetch_page(before, limit)?;
+        let page_len = page.len();
+        let previous_before = before;
+        for (signature, succeeded) in page.iter() {
+            match Signature::from_str(signature) {
+                Ok(signature) => {
+                    before = Some(signature);
+                    signatures.push((signature, *succeeded));
+                }
+                Err(e) => println!("Skipping invalid signature {}: {}", signature, e),
+            }
+        }
+        // A short page means there is nothing older than `before` and newer than `until`
+        if until.is_none() || page_len < limit || before == previous_before {
+            break;
+        }
+    }
+    // The RPC returns signatures newest first
+    signatures.reverse();
+    Ok(signatures)
+}
+
+/// The signatures of `signatures` whose events still need to be sent, in order and each once.
+/// Failed transactions emit no events and processed ones were sent already.
+fn signatures_to_process(
+    signatures: &[(Signature, bool)],
+    processed: &SignatureLru<()>,
+) -> Vec<Signature> {
+    let mut seen = HashSet::new();
+    signatures
+        .iter()
+        .filter(|(signature, succeeded)| {
+            *succeeded && !processed.contains(signature) && seen.insert(*signature)
+        })
+        .map(|(signature, _)| *signature)
+        .collect()
+}
+
 pub struct EventPoller {
     pub worker: JoinHandle<()>,
+    shutdown: Arc<AtomicBool>,
 }
 
 impl EventPoller {
+    /// Polls the transaction history of each market in `market_keys` every `timeout_ms`
+    /// milliseconds and sends the Phoenix events it finds.
     pub fn new(
         sdk: Arc<SDKClient>,
+        market_keys: Vec<Pubkey>,
         event_sender: Sender<Vec<SDKMarketEvent>>,
         timeout_ms: u64,
     ) -> Self {
+        Self::spawn(sdk, market_keys, event_sender, timeout_ms, None, false)
+    }
+
+    /// Like `new`, but also sends an `SDKMarketEvent::Watermark` after each poll in which
+    /// every market's signatures and every transaction were fetched and parsed, and every
+    /// event was sent. A poll with any failure sends no watermark.
+    ///
+    /// The watermark is the slot observed at `watermark_commitment` just before the poll
+    /// started. Once a `Watermark { timestamp, slot }` is sent, no further event with a slot
+    /// at or below `slot`, or with a timestamp at or below `timestamp`, will be sent by this
+    /// poller. Watermarks only increase. Events are fetched at confirmed commitment, so with
+    /// `CommitmentConfig::confirmed()` the guarantee assumes confirmed blocks are not rolled
+    /// back. With `CommitmentConfig::finalized()` it holds unconditionally, at the cost of
+    /// lagging the events by the finalization delay.
+    pub fn new_with_watermarks(
+        sdk: Arc<SDKClient>,
+        market_keys: Vec<Pubkey>,
+        event_sender: Sender<Vec<SDKMarketEvent>>,
+        timeout_ms: u64,
+        watermark_commitment: CommitmentConfig,
+    ) -> Self {
+        Self::spawn(
+            sdk,
+            market_keys,
+            event_sender,
+            timeout_ms,
+            Some(watermark_commitment),
+            false,
+        )
+    }
+
+    /// Like `new_with_watermarks`, but also follows each transaction whose events were sent
+    /// until it is finalized. The node is asked for the status of these transactions after
+    /// every poll. A transaction it stops knowing about was included by a fork that was
+    /// dropped, and the poller sends an `SDKMarketEvent::EventsRetracted` for each of its
+    /// markets. Consumers take the events they received as the baseline and undo the
+    /// retracted ones. If a retracted transaction lands again, its events are sent again.
+    ///
+    /// Retractions can follow a `Watermark` of confirmed commitment that covered the
+    /// retracted events.
+    pub fn new_with_retractions(
+        sdk: Arc<SDKClient>,
+        market_keys: Vec<Pubkey>,
+        event_sender: Sender<Vec<SDKMarketEvent>>,
+        timeout_ms: u64,
+        watermark_commitment: CommitmentConfig,
+    ) -> Self {
+        Self::spawn(
+            sdk,
+            market_keys,
+            event_sender,
+            timeout_ms,
+            Some(watermark_commitment),
+            true,
+        )
+    }
+
+    fn spawn(
+        sdk: Arc<SDKClient>,
+        market_keys: Vec<Pubkey>,
+        event_sender: Sender<Vec<SDKMarketEvent>>,
+        timeout_ms: u64,
+        watermark_commitment: Option<CommitmentConfig>,
+        track_retractions: bool,
+    ) -> Self {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let worker_shutdown = shutdown.clone();
         let worker = Builder::new()
             .name("event-poller".to_string())
-            .spawn(move || Self::run(event_sender, sdk.clone(), timeout_ms))
+            .spawn(move || {
+                Self::poll(
+                    event_sender,
+                    sdk,
+                    market_keys,
+                    timeout_ms,
+                    watermark_commitment,
+                    track_retractions.then(ForkTracker::default),
+                    worker_shutdown,
+                )
+            })
             .unwrap();
 
-        Self { worker }
+        Self { worker, shutdown }
     }
 
     pub fn new_with_default_timeout(
         sdk: Arc<SDKClient>,
+        market_keys: Vec<Pubkey>,
         event_sender: Sender<Vec<SDKMarketEvent>>,
     ) -> Self {
-        Self::new(sdk, event_sender, 1000)
+        Self::new(sdk, market_keys, event_sender, 1000)
+    }
+
+    /// Asks the poller to stop. The poller finishes the poll in progress, sending its events,
+    /// and then exits. Use `join` to wait for it.
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Relaxed);
     }
 
     pub fn join(self) {
         self.worker.join().unwrap()
     }
 
-    pub fn run(event_sender: Sender<Vec<SDKMarketEvent>>, sdk: Arc<SDKClient>, timeout_ms: u64) {
-        let mut until = None;
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        // TODO: keep some state of signatures that have already been processed
-        // TODO: make sure events are processed in order
-        loop {
-            let config = match until {
-                None => GetConfirmedSignaturesForAddress2Config {
-                    before: None,
-                    until: None,
-                    limit: Some(1),
-                    commitment: Some(CommitmentConfig::confirmed()),
-                },
-                Some(until) => GetConfirmedSignaturesForAddress2Config {
-                    before: None,
-                    until: Some(until),
-                    limit: None,
-                    commitment: Some(CommitmentConfig::confirmed()),
-                },
-            };
-
-            // This is not 100% robust, but it's good enough for now.
-            // TODO: join futures and await
-            for (i, signature) in sdk
+    /// Fetches the signatures of the transactions for `market_key` that are newer than `until`,
+    /// oldest first, each paired with whether the transaction succeeded. Without `until`, only
+    /// the most recent transaction is returned. Pages backwards with `before` when more than
+    /// one page of new signatures has arrived.
+    pub(crate) fn fetch_new_signatures(
+        sdk: &SDKClient,
+        market_key: &Pubkey,
+        until: Option<Signature>,
+    ) -> anyhow::Result<Vec<(Signature, bool)>> {
+        collect_new_signatures(until, |before, limit| {
+            Ok(sdk
                 .client
-                .get_signatures_for_address_with_config(&sdk.core.active_market_key, config)
-                .unwrap_or_default()
-                .iter()
-                .map(|tx| Signature::from_str(&tx.signature).unwrap())
-                .enumerate()
-                .rev()
-            {
-                if i == 0 {
-                    until = Some(signature);
+                .get_signatures_for_address_with_config(
+                    market_key,
+                    GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until,
+                        limit: Some(limit),
+                        commitment: Some(CommitmentConfig::confirmed()),
+                    },
+                )?
+                .into_iter()
+                .map(|tx| (tx.signature, tx.err.is_none()))
+                .collect())
+        })
+    }
+
+    /// Polls each market in turn. Signatures are processed strictly oldest to newest and
+    /// signatures that were already processed are skipped. A market's cursor only advances
+    /// once the events of every new signature have been sent, so a failed batch is retried on
+    /// the next poll. See `new_with_watermarks` for the watermark guarantee. Returns once
+    /// `shutdown` is set, after finishing the poll in progress.
+    pub fn run(
+        event_sender: Sender<Vec<SDKMarketEvent>>,
+        sdk: Arc<SDKClient>,
+        market_keys: Vec<Pubkey>,
+        timeout_ms: u64,
+        watermark_commitment: Option<CommitmentConfig>,
+        shutdown: Arc<AtomicBool>,
+    ) {
+        Self::poll(
+            event_sender,
+            sdk,
+            market_keys,
+            timeout_ms,
+            watermark_commitment,
+            None,
+            shutdown,
+        )
+    }
+
+    fn poll(
+        event_sender: Sender<Vec<SDKMarketEvent>>,
+        sdk: Arc<SDKClient>,
+        market_keys: Vec<Pubkey>,
+        timeout_ms: u64,
+        watermark_commitment: Option<CommitmentConfig>,
+        mut fork_tracker: Option<ForkTracker>,
+        shutdown: Arc<AtomicBool>,
+    ) {
+        let mut until: HashMap<Pubkey, Signature> = HashMap::new();
+        let mut processed = processed_signatures();
+        let mut last_watermark_slot = 0;
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        while !shutdown.load(Ordering::Relaxed) {
+            // Every transaction at or below this slot is visible to the fetches below
+            let watermark_slot = watermark_commitment.and_then(|commitment| {
+                sdk.client
+                    .get_slot_with_commitment(commitment)
+                    .map_err(|e| println!("Failed to fetch the watermark slot: {}", e))
+                    .ok()
+            });
+            let mut poll_complete = true;
+            for market_key in market_keys.iter() {
+                let signatures = match Self::fetch_new_signatures(
+                    &sdk,
+                    market_key,
+                    until.get(market_key).copied(),
+                ) {
+                    Ok(signatures) => signatures,
+                    Err(e) => {
+                        println!("Failed to fetch signatures for {}: {}", market_key, e);
+                        poll_complete = false;
+                        continue;
+                    }
+                };
+                let mut all_sent = true;
+                for signature in signatures_to_process(&signatures, &processed) {
+                    // TODO: This currently blocks on every iteration, which is not ideal.
+                    //       We should be able to spin up chunks of requests and join.
+                    let events = match rt.block_on(sdk.parse_events_from_transaction(&signature)) {
+                        Some(events) => events,
+                        None => {
+                            // Later signatures wait too, so events stay in order
+                            println!(
+                                "Failed to parse events of {}, retrying on the next poll",
+                                signature
+                            );
+                            all_sent = false;
+                            poll_complete = false;
+                            break;
+                        }
+                    };
+                    if event_sender
+                        .send(
+                            events
+                                .iter()
+                                .map(|&e| SDKMarketEvent::PhoenixEvent { event: Box::new(e) })
+                                .collect::<Vec<_>>(),
+                        )
+                        .is_err()
+                    {
+                        println!("Event sender disconnected, retrying on the next poll");
+                        all_sent = false;
+                        poll_complete = false;
+                        break;
+                    }
+                    processed.insert(signature, ());
+                    if let Some(fork_tracker) = fork_tracker.as_mut() {
+                        fork_tracker.track(signature, &events);
+                    }
+                }
+                if all_sent {
+                    if let Some(&(latest, _)) = signatures.last() {
+                        until.insert(*market_key, latest);
+                    }
                 }
-                // TODO: This currently blocks on every iteration, which is not ideal.
-                //       We should be able to spin up chunks of requests and join.
-                let events = rt
-                    .block_on(sdk.parse_events_from_transaction(&signature))
-                    .unwrap_or_default();
-                if event_sender
-                    .send(
-                        events
-                            .iter()
-                            .map(|&e| SDKMarketEvent::PhoenixEvent { event: Box::new(e) })
-                            .collect::<Vec<_>>(),
-                    )
-                    .is_err()
-                {
+            }
+            if let Some(fork_tracker) = fork_tracker.as_mut() {
+                let retractions = fork_tracker.check(&sdk);
+                for retraction in retractions.iter() {
+                    if let SDKMarketEvent::EventsRetracted { signature, .. } = retraction {
+                        processed.remove(signature);
+                    }
+                }
+                if !retractions.is_empty() && event_sender.send(retractions).is_err() {
                     println!("Event sender disconnected, continuing");
-                    continue;
                 }
             }
-            std::thread::sleep(Duration::from_millis(timeout_ms));
+            if let Some(slot) = watermark_slot.filter(|&slot| slot > last_watermark_slot) {
+                if poll_complete {
+                    match sdk.client.get_block_time(slot) {
+                        Ok(block_time) => {
+                            last_watermark_slot = slot;
+                            // Later slots can share the block time of `slot`, so the timestamp
+                            // guarantee only holds strictly below it
+                            let watermark = SDKMarketEvent::Watermark {
+                                timestamp: block_time - 1,
+                                slot,
+                            };
+                            if event_sender.send(vec![watermark]).is_err() {
+                                println!("Event sender disconnected, continuing");
+                            }
+                        }
+                        Err(e) => println!("Failed to fetch the block time of {}: {}", slot, e),
+                    }
+                }
+            }
+            if !shutdown.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(timeout_ms));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A market's transaction history, newest first, served like `getSignaturesForAddress`
+    struct MockHistory {
+        signatures: Vec<(Signature, bool)>,
+        /// The `before` and `limit` of every page requested
+        requests: Vec<(Option<Signature>, usize)>,
+    }
+
+    impl MockHistory {
+        /// `len` successful transactions
+        fn new(len: usize) -> Self {
+            MockHistory {
+                signatures: (0..len).map(|_| (Signature::new_unique(), true)).collect(),
+                requests: vec![],
+            }
+        }
+
+        fn position(&self, signature: &Signature) -> usize {
+            self.signatures
+                .iter()
+                .position(|(s, _)| s == signature)
+                .unwrap()
         }
+
+        fn page(
+            &mut self,
+            until: Option<Signature>,
+            before: Option<Signature>,
+            limit: usize,
+        ) -> Vec<(String, bool)> {
+            self.requests.push((before, limit));
+            let start = before.map_or(0, |before| self.position(&before) + 1);
+            let end = until.map_or(self.signatures.len(), |until| self.position(&until));
+            self.signatures[start..end.max(start)]
+                .iter()
+                .take(limit)
+                .map(|(signature, succeeded)| (signature.to_string(), *succeeded))
+                .collect()
+        }
+
+        fn collect(&mut self, until: Option<Signature>) -> Vec<(Signature, bool)> {
+            collect_new_signatures(until, |before, limit| Ok(self.page(until, before, limit)))
+                .unwrap()
+        }
+
+        /// The `n` newest signatures, oldest first
+        fn newest(&self, n: usize) -> Vec<(Signature, bool)> {
+            self.signatures[..n].iter().rev().copied().collect()
+        }
+    }
+
+    #[test]
+    fn without_a_cursor_only_the_latest_signature_is_fetched() {
+        let mut history = MockHistory::new(5);
+        assert_eq!(history.collect(None), history.newest(1));
+        assert_eq!(history.requests, vec![(None, 1)]);
+    }
+
+    #[test]
+    fn new_signatures_are_paged_back_to_the_cursor_and_returned_oldest_first() {
+        let mut history = MockHistory::new(2_600);
+        let until = history.signatures[2_500].0;
+
+        let signatures = history.collect(Some(until));
+
+        assert_eq!(signatures, history.newest(2_500));
+        let befores = history
+            .requests
+            .iter()
+            .map(|&(before, limit)| {
+                assert_eq!(limit, SIGNATURE_PAGE_LIMIT);
+                before
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            befores,
+            vec![
+                None,
+                Some(history.signatures[999].0),
+                Some(history.signatures[1_999].0),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_full_last_page_needs_one_more_request() {
+        let mut history = MockHistory::new(1_500);
+        let until = history.signatures[SIGNATURE_PAGE_LIMIT].0;
+        assert_eq!(
+            history.collect(Some(until)),
+            history.newest(SIGNATURE_PAGE_LIMIT)
+        );
+        assert_eq!(history.requests.len(), 2);
+    }
+
+    #[test]
+    fn nothing_new_since_the_cursor() {
+        let mut history = MockHistory::new(3);
+        let until = history.signatures[0].0;
+        assert!(history.collect(Some(until)).is_empty());
+    }
+
+    #[test]
+    fn invalid_signatures_are_skipped() {
+        let valid = Signature::new_unique();
+        let signatures =
+            collect_new_signatures(None, |_, _| Ok(vec![("not a signature".to_string(), true)]))
+                .unwrap();
+        assert!(signatures.is_empty());
+        let signatures =
+            collect_new_signatures(None, |_, _| Ok(vec![(valid.to_string(), false)])).unwrap();
+        assert_eq!(signatures, vec![(valid, false)]);
+    }
+
+    #[test]
+    fn failed_processed_and_repeated_signatures_are_not_processed() {
+        let [a, b, c, d] = [(); 4].map(|_| Signature::new_unique());
+        let mut processed = processed_signatures();
+        processed.insert(b, ());
+
+        let to_process = signatures_to_process(
+            &[(a, true), (b, true), (c, false), (d, true), (a, true)],
+            &processed,
+        );
+
+        assert_eq!(to_process, vec![a, d]);
+    }
+
+    #[test]
+    fn a_retried_poll_only_processes_the_signatures_not_sent_yet() {
+        let mut history = MockHistory::new(10);
+        let until = history.signatures[9].0;
+        let mut processed = processed_signatures();
+
+        // The first poll sent the events of the 4 oldest new signatures and then failed, so its
+        // cursor did not advance and the next poll fetches the same signatures again
+        let first_poll = history.collect(Some(until));
+        for (signature, _) in first_poll.iter().take(4) {
+            processed.insert(*signature, ());
+        }
+        let second_poll = history.collect(Some(until));
+
+        assert_eq!(
+            signatures_to_process(&second_poll, &processed),
+            first_poll[4..]
+                .iter()
+                .map(|(signature, _)| *signature)
+                .collect::<Vec<_>>()
+        );
     }
 }
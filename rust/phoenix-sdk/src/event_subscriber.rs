@@ -0,0 +1,369 @@
+use crate::{
+    event_poller::{processed_signatures, EventPoller},
+    market_event_handler::SDKMarketEvent,
+    sdk_client::SDKClient,
+    signature_lru::SignatureLru,
+};
+use futures::StreamExt;
+use solana_client::{
+    nonblocking::pubsub_client::PubsubClient,
+    rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signature};
+use std::{
+    str::FromStr,
+    sync::{mpsc::Sender, Arc},
+    thread::{Builder, JoinHandle},
+    time::Duration,
+};
+use tokio::runtime::Handle;
+
+/// Delay before reconnecting after the websocket connection fails or closes
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Number of attempts to fetch a transaction that was just notified, since the RPC node may
+/// not serve it yet
+const FETCH_ATTEMPTS: usize = 3;
+
+/// What became of the events of one signature, see `LogSubscriber::send_events`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Delivery {
+    /// Sent, or already sent before
+    Sent,
+    /// The transaction could not be fetched or parsed, so it is retried later
+    FetchFailed,
+    ReceiverDisconnected,
+}
+
+/// Streams the Phoenix events of a set of markets over the RPC PubSub `logsSubscribe` endpoint,
+/// with lower latency than `EventPoller`.
+///
+/// Log notifications do not carry the Phoenix event data, so each notified transaction is
+/// fetched and parsed with `SDKClient::parse_events_from_transaction`. When the subscription
+/// drops, the subscriber reconnects and backfills the transactions it missed through the same
+/// signature history `EventPoller` uses. Signatures already processed are not sent twice.
+/// A transaction that cannot be fetched is retried after each later notification, and the
+/// backfill cursor stays before it until it is sent, so a reconnect backfills it too.
+///
+/// The blocking RPC calls run on tokio's blocking thread pool, so a slow fetch for one market
+/// does not hold up the others.
+///
+/// Once a market's subscription is up, a `SourceDisconnected` event is sent when it drops and
+/// a `SourceReconnected` event once it is resubscribed and every missed transaction was sent,
+/// see `CancelOnDisconnect`. A backfill that fails is retried on a new subscription first.
+pub struct LogSubscriber {
+    pub worker: JoinHandle<()>,
+}
+
+impl LogSubscriber {
+    /// `ws_url` is the websocket endpoint of the RPC node, e.g. `wss://api.devnet.solana.com`.
+    pub fn new(
+        sdk: Arc<SDKClient>,
+        ws_url: String,
+        market_keys: Vec<Pubkey>,
+        event_sender: Sender<Vec<SDKMarketEvent>>,
+    ) -> Self {
+        let worker = Builder::new()
+            .name("log-subscriber".to_string())
+            .spawn(move || {
+                let rt = tokio::runtime::Runtime::new().unwrap();
+                rt.block_on(futures::future::join_all(market_keys.iter().map(
+                    |market_key| Self::run(&sdk, &ws_url, *market_key, &event_sender),
+                )));
+            })
+            .unwrap();
+
+        Self { worker }
+    }
+
+    pub fn join(self) {
+        self.worker.join().unwrap()
+    }
+
+    /// Subscribes to the logs of `market_key` until the event receiver disconnects.
+    async fn run(
+        sdk: &Arc<SDKClient>,
+        ws_url: &str,
+        market_key: Pubkey,
+        event_sender: &Sender<Vec<SDKMarketEvent>>,
+    ) {
+        // Every signature up to `last_seen` was sent; `pending` failed to fetch since
+        let mut last_seen: Option<Signature> = None;
+        let mut newest: Option<Signature> = None;
+        let mut pending: Vec<Signature> = vec![];
+        let mut processed = processed_signatures();
+        // Whether the subscription was up before, and whether it dropped since
+        let mut subscribed_once = false;
+        let mut disconnected = false;
+        loop {
+            if subscribed_once
+                && !disconnected
+                && !Self::send_connection_event(
+                    SDKMarketEvent::SourceDisconnected { market: market_key },
+                    event_sender,
+                )
+            {
+                return;
+            }
+            disconnected = subscribed_once;
+            let pubsub = match PubsubClient::new(ws_url).await {
+                Ok(pubsub) => pubsub,
+                Err(e) => {
+                    println!("Failed to connect to {}: {}", ws_url, e);
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    continue;
+                }
+            };
+            let (mut notifications, unsubscribe) = match pubsub
+                .logs_subscribe(
+                    RpcTransactionLogsFilter::Mentions(vec![market_key.to_string()]),
+                    RpcTransactionLogsConfig {
+                        commitment: Some(CommitmentConfig::confirmed()),
+                    },
+                )
+                .await
+            {
+                Ok(subscription) => subscription,
+                Err(e) => {
+                    println!("Failed to subscribe to the logs of {}: {}", market_key, e);
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    continue;
+                }
+            };
+
+            // Backfill after subscribing so nothing falls between the gap and the stream.
+            // Overlapping signatures are dropped by `processed`.
+            let mut backfilled = true;
+            if last_seen.is_some() {
+                let backfill = {
+                    let sdk = sdk.clone();
+                    let until = last_seen;
+                    Self::blocking(move || {
+                        EventPoller::fetch_new_signatures(&sdk, &market_key, until)
+                    })
+                    .await
+                };
+                match backfill {
+                    Ok(signatures) => {
+                        // Backfilled signatures that failed before are sent in order here
+                        pending.clear();
+                        for (signature, succeeded) in signatures {
+                            if !succeeded {
+                                continue;
+                            }
+                            let delivery = Self::send_events(
+                                sdk,
+                                &market_key,
+                                &signature,
+                                FETCH_ATTEMPTS,
+                                &mut processed,
+                                event_sender,
+                            )
+                            .await;
+                            if !Self::record_delivery(
+                                delivery,
+                                signature,
+                                &mut newest,
+                                &mut last_seen,
+                                &mut pending,
+                            ) {
+                                return;
+                            }
+                        }
+                        backfilled = pending.is_empty();
+                    }
+                    Err(e) => {
+                        println!("Failed to backfill {}: {}", market_key, e);
+                        backfilled = false;
+                    }
+                }
+            }
+            if !backfilled {
+                // Stay disconnected until the gap is backfilled, so consumers do not resume on
+                // a gap, and resubscribe to backfill again
+                unsubscribe().await;
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+            if disconnected
+                && !Self::send_connection_event(
+                    SDKMarketEvent::SourceReconnected { market: market_key },
+                    event_sender,
+                )
+            {
+                return;
+            }
+            subscribed_once = true;
+            disconnected = false;
+
+            while let Some(notification) = notifications.next().await {
+                if notification.value.err.is_some() {
+                    continue;
+                }
+                let signature = match Signature::from_str(&notification.value.signature) {
+                    Ok(signature) => signature,
+                    Err(e) => {
+                        println!(
+                            "Skipping invalid signature {}: {}",
+                            notification.value.signature, e
+                        );
+                        continue;
+                    }
+                };
+                if !Self::retry_pending(
+                    sdk,
+                    &market_key,
+                    &mut pending,
+                    &mut processed,
+                    event_sender,
+                )
+                .await
+                {
+                    return;
+                }
+                if pending.is_empty() {
+                    last_seen = newest;
+                }
+                let delivery = Self::send_events(
+                    sdk,
+                    &market_key,
+                    &signature,
+                    FETCH_ATTEMPTS,
+                    &mut processed,
+                    event_sender,
+                )
+                .await;
+                if !Self::record_delivery(
+                    delivery,
+                    signature,
+                    &mut newest,
+                    &mut last_seen,
+                    &mut pending,
+                ) {
+                    return;
+                }
+            }
+            unsubscribe().await;
+            println!("Log subscription for {} closed, resubscribing", market_key);
+        }
+    }
+
+    /// Runs `f`, which makes blocking RPC calls, on the blocking thread pool
+    async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+        tokio::task::spawn_blocking(f)
+            .await
+            .expect("Blocking RPC task panicked")
+    }
+
+    /// Updates the cursors after `signature` was handled. The backfill cursor only moves while
+    /// no earlier signature is pending. Returns false if the event receiver disconnected.
+    fn record_delivery(
+        delivery: Delivery,
+        signature: Signature,
+        newest: &mut Option<Signature>,
+        last_seen: &mut Option<Signature>,
+        pending: &mut Vec<Signature>,
+    ) -> bool {
+        match delivery {
+            Delivery::ReceiverDisconnected => return false,
+            Delivery::FetchFailed => pending.push(signature),
+            Delivery::Sent => {}
+        }
+        *newest = Some(signature);
+        if pending.is_empty() {
+            *last_seen = *newest;
+        }
+        true
+    }
+
+    /// Tries once more to send the events of each pending signature, oldest first, keeping
+    /// those that still fail. Returns false if the event receiver disconnected.
+    async fn retry_pending(
+        sdk: &Arc<SDKClient>,
+        market_key: &Pubkey,
+        pending: &mut Vec<Signature>,
+        processed: &mut SignatureLru<()>,
+        event_sender: &Sender<Vec<SDKMarketEvent>>,
+    ) -> bool {
+        let mut still_pending = vec![];
+        for signature in pending.drain(..) {
+            match Self::send_events(sdk, market_key, &signature, 1, processed, event_sender).await {
+                Delivery::Sent => {}
+                Delivery::FetchFailed => still_pending.push(signature),
+                Delivery::ReceiverDisconnected => return false,
+            }
+        }
+        *pending = still_pending;
+        true
+    }
+
+    /// Returns false if the event receiver disconnected.
+    fn send_connection_event(
+        event: SDKMarketEvent,
+        event_sender: &Sender<Vec<SDKMarketEvent>>,
+    ) -> bool {
+        if event_sender.send(vec![event]).is_err() {
+            println!("Event receiver disconnected, stopping log subscriber");
+            return false;
+        }
+        true
+    }
+
+    /// Fetches, parses, and sends the events of `signature` for `market_key` unless it was
+    /// already processed, making up to `attempts` fetches.
+    async fn send_events(
+        sdk: &Arc<SDKClient>,
+        market_key: &Pubkey,
+        signature: &Signature,
+        attempts: usize,
+        processed: &mut SignatureLru<()>,
+        event_sender: &Sender<Vec<SDKMarketEvent>>,
+    ) -> Delivery {
+        if processed.contains(signature) {
+            return Delivery::Sent;
+        }
+        let mut events = None;
+        for attempt in 0..attempts {
+            if attempt > 0 {
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+            let handle = Handle::current();
+            let sdk = sdk.clone();
+            let signature = *signature;
+            events = Self::blocking(move || {
+                handle.block_on(sdk.parse_events_from_transaction(&signature))
+            })
+            .await;
+            if events.is_some() {
+                break;
+            }
+        }
+        let events = match events {
+            Some(events) => events,
+            None => {
+                println!(
+                    "Failed to fetch the events of {}, retrying later",
+                    signature
+                );
+                return Delivery::FetchFailed;
+            }
+        };
+        processed.insert(*signature, ());
+        if event_sender
+            .send(
+                events
+                    .iter()
+                    // A transaction can touch several markets, each with its own subscription
+                    .filter(|e| e.market == *market_key)
+                    .map(|&e| SDKMarketEvent::PhoenixEvent { event: Box::new(e) })
+                    .collect::<Vec<_>>(),
+            )
+            .is_err()
+        {
+            println!("Event receiver disconnected, stopping log subscriber");
+            return Delivery::ReceiverDisconnected;
+        }
+        Delivery::Sent
+    }
+}
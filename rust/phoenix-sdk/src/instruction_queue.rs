@@ -0,0 +1,400 @@
+use crate::kill_switch::is_risk_increasing;
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use std::{
+    collections::VecDeque,
+    sync::{
+        mpsc::{RecvTimeoutError, TryRecvError},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+/// The priority class of an instruction batch in an `instruction_queue`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// Cancels and kill switch actions. Urgent batches are always received before normal ones
+    /// and are never refused for lack of capacity.
+    Urgent,
+    /// Quotes and everything else
+    Normal,
+}
+
+impl Priority {
+    /// `Urgent` if none of `instructions` can add exposure on `program_id` (cancels, reduces,
+    /// withdrawals), `Normal` otherwise. See `is_risk_increasing`.
+    pub fn classify(instructions: &[Instruction], program_id: &Pubkey) -> Self {
+        if instructions
+            .iter()
+            .any(|ix| is_risk_increasing(ix, program_id))
+        {
+            Priority::Normal
+        } else {
+            Priority::Urgent
+        }
+    }
+}
+
+/// Why a batch could not be queued. The batch is handed back.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QueueSendError {
+    /// The queue holds `capacity` normal batches
+    Full(Vec<Instruction>),
+    /// The receiver was dropped
+    Disconnected(Vec<Instruction>),
+}
+
+impl std::fmt::Display for QueueSendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueueSendError::Full(_) => write!(f, "Instruction queue is full"),
+            QueueSendError::Disconnected(_) => write!(f, "Instruction queue receiver disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for QueueSendError {}
+
+struct QueueState {
+    urgent: VecDeque<Vec<Instruction>>,
+    /// Normal batches in send order, with their coalescing key
+    normal: VecDeque<(Option<String>, Vec<Instruction>)>,
+    capacity: usize,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+struct Queue {
+    state: Mutex<QueueState>,
+    /// Signalled when a batch is queued or the last sender is dropped
+    not_empty: Condvar,
+    /// Signalled when a normal batch is received or the receiver is dropped
+    not_full: Condvar,
+}
+
+impl Queue {
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Creates a bounded two-priority instruction queue for a `TransactionExecutor`. At most
+/// `capacity` normal batches are queued at once; urgent batches do not count against it.
+pub fn instruction_queue(capacity: usize) -> (InstructionSender, InstructionReceiver) {
+    let queue = Arc::new(Queue {
+        state: Mutex::new(QueueState {
+            urgent: VecDeque::new(),
+            normal: VecDeque::new(),
+            capacity: capacity.max(1),
+            senders: 1,
+            receiver_alive: true,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+    });
+    (
+        InstructionSender {
+            queue: queue.clone(),
+        },
+        InstructionReceiver { queue },
+    )
+}
+
+/// An instruction queue without a capacity, which behaves like the unbounded channel the
+/// executor used to take, with urgent batches still received first.
+pub fn unbounded_instruction_queue() -> (InstructionSender, InstructionReceiver) {
+    instruction_queue(usize::MAX)
+}
+
+/// The sending half of an `instruction_queue`. Clones send to the same queue.
+pub struct InstructionSender {
+    queue: Arc<Queue>,
+}
+
+impl Clone for InstructionSender {
+    fn clone(&self) -> Self {
+        self.queue.lock().senders += 1;
+        InstructionSender {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl Drop for InstructionSender {
+    fn drop(&mut self) {
+        let mut state = self.queue.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.queue.not_empty.notify_all();
+        }
+    }
+}
+
+impl InstructionSender {
+    /// Queues an urgent batch. It is received before every normal batch, after the urgent
+    /// batches already queued.
+    pub fn send_urgent(&self, instructions: Vec<Instruction>) -> Result<(), QueueSendError> {
+        let mut state = self.queue.lock();
+        if !state.receiver_alive {
+            return Err(QueueSendError::Disconnected(instructions));
+        }
+        state.urgent.push_back(instructions);
+        self.queue.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Queues a normal batch, waiting for space if the queue is full.
+    pub fn send(&self, instructions: Vec<Instruction>) -> Result<(), QueueSendError> {
+        self.push_normal(None, instructions, true)
+    }
+
+    /// Queues a normal batch, failing with `Full` instead of waiting if the queue is full.
+    pub fn try_send(&self, instructions: Vec<Instruction>) -> Result<(), QueueSendError> {
+        self.push_normal(None, instructions, false)
+    }
+
+    /// Queues a normal batch that supersedes any queued batch with the same `key`, e.g. the
+    /// market and "requote". A superseded batch is replaced in its place in the queue, so the
+    /// latest batch keeps the original's turn and needs no extra space. Otherwise waits for
+    /// space like `send`.
+    pub fn send_coalescable(
+        &self,
+        key: &str,
+        instructions: Vec<Instruction>,
+    ) -> Result<(), QueueSendError> {
+        self.push_normal(Some(key.to_string()), instructions, true)
+    }
+
+    fn push_normal(
+        &self,
+        key: Option<String>,
+        instructions: Vec<Instruction>,
+        wait: bool,
+    ) -> Result<(), QueueSendError> {
+        let mut state = self.queue.lock();
+        loop {
+            if !state.receiver_alive {
+                return Err(QueueSendError::Disconnected(instructions));
+            }
+            if let Some(key) = key.as_ref() {
+                if let Some(queued) = state
+                    .normal
+                    .iter_mut()
+                    .find(|(queued_key, _)| queued_key.as_ref() == Some(key))
+                {
+                    queued.1 = instructions;
+                    return Ok(());
+                }
+            }
+            if state.normal.len() < state.capacity {
+                state.normal.push_back((key, instructions));
+                self.queue.not_empty.notify_one();
+                return Ok(());
+            }
+            if !wait {
+                return Err(QueueSendError::Full(instructions));
+            }
+            state = self
+                .queue
+                .not_full
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+/// The receiving half of an `instruction_queue`.
+pub struct InstructionReceiver {
+    queue: Arc<Queue>,
+}
+
+impl Drop for InstructionReceiver {
+    fn drop(&mut self) {
+        self.queue.lock().receiver_alive = false;
+        self.queue.not_full.notify_all();
+    }
+}
+
+impl InstructionReceiver {
+    fn pop(&self, state: &mut QueueState) -> Option<Vec<Instruction>> {
+        if let Some(instructions) = state.urgent.pop_front() {
+            return Some(instructions);
+        }
+        let (_, instructions) = state.normal.pop_front()?;
+        self.queue.not_full.notify_one();
+        Some(instructions)
+    }
+
+    /// The next batch, urgent batches first, without waiting.
+    pub fn try_recv(&self) -> Result<Vec<Instruction>, TryRecvError> {
+        let mut state = self.queue.lock();
+        match self.pop(&mut state) {
+            Some(instructions) => Ok(instructions),
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// The next batch, urgent batches first, waiting up to `timeout` for one. Fails with
+    /// `Disconnected` once every sender is dropped and the queue is empty.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Vec<Instruction>, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.queue.lock();
+        loop {
+            if let Some(instructions) = self.pop(&mut state) {
+                return Ok(instructions);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self
+                .queue
+                .not_empty
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// Number of urgent and normal batches waiting
+    pub fn queued(&self) -> (usize, usize) {
+        let state = self.queue.lock();
+        (state.urgent.len(), state.normal.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phoenix_instruction::KnownPhoenixInstruction;
+
+    /// A one-instruction batch, told apart by `id`
+    fn batch(instruction: KnownPhoenixInstruction, id: u8) -> Vec<Instruction> {
+        vec![Instruction::new_with_bytes(
+            phoenix_types::id(),
+            &[instruction.tag(), id],
+            vec![],
+        )]
+    }
+
+    fn id(batch: &[Instruction]) -> u8 {
+        batch[0].data[1]
+    }
+
+    fn drain(receiver: &InstructionReceiver) -> Vec<u8> {
+        std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|batch| id(&batch))
+            .collect()
+    }
+
+    #[test]
+    fn classifies_cancels_as_urgent() {
+        let program_id = phoenix_types::id();
+        let cancel = batch(KnownPhoenixInstruction::CancelAllOrders, 0);
+        let mut quote = batch(KnownPhoenixInstruction::PlaceMultiplePostOnlyOrders, 0);
+        assert_eq!(Priority::classify(&cancel, &program_id), Priority::Urgent);
+        assert_eq!(Priority::classify(&quote, &program_id), Priority::Normal);
+        quote.extend(cancel);
+        assert_eq!(Priority::classify(&quote, &program_id), Priority::Normal);
+    }
+
+    #[test]
+    fn receives_urgent_batches_first_in_send_order() {
+        let (sender, receiver) = instruction_queue(8);
+        let quote = KnownPhoenixInstruction::PlaceLimitOrder;
+        let cancel = KnownPhoenixInstruction::CancelAllOrders;
+        sender.send(batch(quote, 1)).unwrap();
+        sender.send_urgent(batch(cancel, 2)).unwrap();
+        sender.send(batch(quote, 3)).unwrap();
+        sender.send_urgent(batch(cancel, 4)).unwrap();
+        assert_eq!(receiver.queued(), (2, 2));
+        assert_eq!(drain(&receiver), vec![2, 4, 1, 3]);
+    }
+
+    #[test]
+    fn full_queue_refuses_normal_batches_but_not_urgent_ones() {
+        let (sender, receiver) = instruction_queue(2);
+        let quote = KnownPhoenixInstruction::PlaceLimitOrder;
+        sender.try_send(batch(quote, 1)).unwrap();
+        sender.try_send(batch(quote, 2)).unwrap();
+        assert_eq!(
+            sender.try_send(batch(quote, 3)),
+            Err(QueueSendError::Full(batch(quote, 3)))
+        );
+        sender
+            .send_urgent(batch(KnownPhoenixInstruction::CancelAllOrders, 4))
+            .unwrap();
+        assert_eq!(receiver.queued(), (1, 2));
+        assert_eq!(id(&receiver.try_recv().unwrap()), 4);
+        assert_eq!(id(&receiver.try_recv().unwrap()), 1);
+        sender.try_send(batch(quote, 5)).unwrap();
+        assert_eq!(drain(&receiver), vec![2, 5]);
+    }
+
+    #[test]
+    fn blocked_send_resumes_when_a_batch_is_received() {
+        let (sender, receiver) = instruction_queue(1);
+        let quote = KnownPhoenixInstruction::PlaceLimitOrder;
+        sender.send(batch(quote, 1)).unwrap();
+        let blocked = std::thread::spawn(move || sender.send(batch(quote, 2)));
+        assert_eq!(
+            id(&receiver.recv_timeout(Duration::from_secs(5)).unwrap()),
+            1
+        );
+        blocked.join().unwrap().unwrap();
+        assert_eq!(
+            id(&receiver.recv_timeout(Duration::from_secs(5)).unwrap()),
+            2
+        );
+    }
+
+    #[test]
+    fn coalesced_batch_replaces_the_queued_one_in_place() {
+        let (sender, receiver) = instruction_queue(2);
+        let quote = KnownPhoenixInstruction::PlaceMultiplePostOnlyOrders;
+        sender
+            .send_coalescable("sol-usdc/requote", batch(quote, 1))
+            .unwrap();
+        sender.send(batch(quote, 2)).unwrap();
+        // The queue is full, but superseding needs no space
+        sender
+            .send_coalescable("sol-usdc/requote", batch(quote, 3))
+            .unwrap();
+        assert_eq!(receiver.queued(), (0, 2));
+        assert_eq!(drain(&receiver), vec![3, 2]);
+        // Once received, the key no longer supersedes anything
+        sender
+            .send_coalescable("sol-usdc/requote", batch(quote, 4))
+            .unwrap();
+        sender
+            .send_coalescable("eth-usdc/requote", batch(quote, 5))
+            .unwrap();
+        assert_eq!(drain(&receiver), vec![4, 5]);
+    }
+
+    #[test]
+    fn reports_disconnection() {
+        let (sender, receiver) = instruction_queue(2);
+        let quote = KnownPhoenixInstruction::PlaceLimitOrder;
+        let second_sender = sender.clone();
+        sender.send(batch(quote, 1)).unwrap();
+        drop(sender);
+        assert_eq!(receiver.try_recv().map(|batch| id(&batch)), Ok(1));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+        drop(second_sender);
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+
+        let (sender, receiver) = instruction_queue(2);
+        drop(receiver);
+        assert_eq!(
+            sender.send(batch(quote, 2)),
+            Err(QueueSendError::Disconnected(batch(quote, 2)))
+        );
+    }
+}
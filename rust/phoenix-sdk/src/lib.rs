@@ -1,6 +1,57 @@
+// Keep the crate buildable on stable Rust
+#![deny(unstable_features)]
+
+#[cfg(feature = "analytics")]
+pub use phoenix_sdk_core::analytics;
+#[cfg(feature = "executor")]
+pub mod cancel_on_disconnect;
+#[cfg(feature = "serde")]
+pub use phoenix_sdk_core::debug_dump;
+#[cfg(feature = "rpc-client")]
+pub mod depth_watcher;
+pub use phoenix_sdk_core::display;
+pub use phoenix_sdk_core::error;
+pub use phoenix_sdk_core::event_decoder;
+#[cfg(feature = "rpc-client")]
 pub mod event_poller;
+#[cfg(feature = "rpc-client")]
+pub mod event_subscriber;
+pub use phoenix_sdk_core::fair_value;
+pub use phoenix_sdk_core::instruction_audit;
+#[cfg(feature = "executor")]
+pub mod instruction_queue;
+pub use phoenix_sdk_core::kill_switch;
+pub use phoenix_sdk_core::ladder_utils;
 pub mod market_event_handler;
+#[cfg(feature = "rpc-client")]
+pub mod market_lookup;
+pub use phoenix_sdk_core::multiple_order_packet;
+pub use phoenix_sdk_core::order_audit;
+pub use phoenix_sdk_core::order_packet_template;
 pub use phoenix_sdk_core::orderbook;
+#[cfg(feature = "executor")]
+pub mod payer_pool;
+pub use phoenix_sdk_core::phoenix_instruction;
+#[cfg(feature = "price-listeners")]
 pub mod price_listeners;
+#[cfg(feature = "rpc-client")]
+pub mod rpc_failover;
+#[cfg(feature = "rpc-client")]
+pub mod rpc_health;
+#[cfg(feature = "rpc-client")]
 pub mod sdk_client;
+#[cfg(feature = "rpc-client")]
+pub mod sdk_client_builder;
+pub use phoenix_sdk_core::sdk_client_core;
+pub use phoenix_sdk_core::signature_lru;
+pub mod strategy_runtime;
+#[cfg(feature = "executor")]
 pub mod transaction_executor;
+
+// Compile-time check that the public types keep their Debug, Clone, and PartialEq derives
+const _: fn() = || {
+    fn assert_debug_clone_eq<T: std::fmt::Debug + Clone + PartialEq>() {}
+    assert_debug_clone_eq::<market_event_handler::SDKMarketEvent>();
+    #[cfg(feature = "executor")]
+    assert_debug_clone_eq::<transaction_executor::ExecutionResult>();
+};
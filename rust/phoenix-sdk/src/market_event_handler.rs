@@ -1,12 +1,109 @@
-pub use phoenix_sdk_core::market_event::{Fill, MarketEventDetails, PhoenixEvent};
-use solana_program::instruction::Instruction;
+use phoenix_sdk_core::analytics::adverse_selection::AdverseSelectionAlert;
+use phoenix_sdk_core::fair_value::FairValueEstimate;
+pub use phoenix_sdk_core::market_event::{EventId, Fill, MarketEventDetails, PhoenixEvent};
+use phoenix_sdk_core::orderbook::Crossing;
+use phoenix_types::enums::Side;
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_sdk::signature::Signature;
 use std::sync::mpsc::Sender;
 
-#[derive(Clone, Debug)]
+/// Events delivered to strategies by the pollers, watchers, and price listeners.
+///
+/// Like `MarketEventDetails`, this enum is `#[non_exhaustive]`: new variants may be added in
+/// minor releases, so matches need a wildcard arm.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum SDKMarketEvent {
-    PhoenixEvent { event: Box<PhoenixEvent> },
-    FairPriceUpdate { price: f64 },
+    PhoenixEvent {
+        event: Box<PhoenixEvent>,
+    },
+    FairPriceUpdate {
+        price: f64,
+    },
+    /// A fair price for `market` alone, e.g. derived from its own fills. Emitted by
+    /// `PhoenixTapePriceSource`.
+    MarketFairPriceUpdate {
+        market: Pubkey,
+        price: f64,
+    },
     RefreshEvent,
+    /// A fair price combined from several feeds, with its confidence. Emitted by whatever
+    /// feeds a `FairValueAggregator`.
+    FairValueEstimate {
+        estimate: FairValueEstimate,
+    },
+    /// The resting size on `side` within `bps_window` basis points of the mid moved from
+    /// `old_depth` to `new_depth` base units. Emitted by `DepthWatcher`.
+    DepthChanged {
+        market: Pubkey,
+        side: Side,
+        bps_window: u64,
+        old_depth: f64,
+        new_depth: f64,
+    },
+    /// The bids of `market`'s book overlap its own asks, which points to a bug in how the book
+    /// was parsed or maintained. Emitted by `DepthWatcher` when `alarm_on_self_cross` is set.
+    BookCrossed {
+        market: Pubkey,
+        crossing: Crossing,
+    },
+    /// The trader's fills on one market and side are being picked off too often. Emitted by
+    /// whatever feeds an `AdverseSelectionMonitor`.
+    AdverseSelection {
+        alert: AdverseSelectionAlert,
+    },
+    /// A fee payer of a `PayerPool` dropped below the pool's low balance threshold
+    LowPayerBalance {
+        payer: Pubkey,
+        lamports: u64,
+    },
+    /// The connection `market`'s events were streamed over dropped. Emitted by
+    /// `LogSubscriber` once per outage.
+    SourceDisconnected {
+        market: Pubkey,
+    },
+    /// The connection of `market` is back and the events missed during the outage were
+    /// backfilled. Emitted by `LogSubscriber`.
+    SourceReconnected {
+        market: Pubkey,
+    },
+    /// Every connection is back after an outage that pulled the quotes of `markets`, and a
+    /// fair price for each of them has arrived since. Emitted by `CancelOnDisconnect`.
+    ReadyToRequote {
+        markets: Vec<Pubkey>,
+    },
+    /// No further events at or below `slot`, or with a timestamp at or below `timestamp`, will
+    /// be sent by the source. Emitted by `EventPoller::new_with_watermarks`.
+    Watermark {
+        timestamp: i64,
+        slot: u64,
+    },
+    /// The transaction `signature` was dropped by a fork after its events on `market`, with
+    /// `sequence_numbers`, were sent at confirmed commitment. Consumers should undo those
+    /// events. Emitted by `EventPoller::new_with_retractions`.
+    EventsRetracted {
+        signature: Signature,
+        market: Pubkey,
+        sequence_numbers: Vec<u64>,
+    },
+}
+
+impl SDKMarketEvent {
+    pub fn as_phoenix_event(&self) -> Option<&PhoenixEvent> {
+        match self {
+            SDKMarketEvent::PhoenixEvent { event } => Some(event.as_ref()),
+            _ => None,
+        }
+    }
+
+    /// The price of a `FairPriceUpdate` or `MarketFairPriceUpdate`
+    pub fn as_fair_price_update(&self) -> Option<f64> {
+        match self {
+            SDKMarketEvent::FairPriceUpdate { price }
+            | SDKMarketEvent::MarketFairPriceUpdate { price, .. } => Some(*price),
+            _ => None,
+        }
+    }
 }
 
 pub trait MarketEventHandler<T> {
@@ -34,6 +131,9 @@ pub trait MarketEventHandler<T> {
                 MarketEventDetails::Fee(..) => {
                     // Ignore fee events
                 }
+                _ => {
+                    self.handle_unknown_event(sender, event)?;
+                }
             }
         }
         Ok(())
@@ -52,6 +152,17 @@ pub trait MarketEventHandler<T> {
         sender: &Sender<T>,
         update: &PhoenixEvent,
     ) -> anyhow::Result<()>;
+
+    /// Called for event kinds added to `MarketEventDetails` after this handler was written.
+    /// The default implementation logs a warning and ignores the event.
+    fn handle_unknown_event(
+        &mut self,
+        _sender: &Sender<T>,
+        update: &PhoenixEvent,
+    ) -> anyhow::Result<()> {
+        println!("Warning: ignoring unhandled event {:?}", update);
+        Ok(())
+    }
 }
 
 pub struct LogHandler;
@@ -0,0 +1,252 @@
+use solana_program::pubkey::Pubkey;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Time a market that failed to load is not fetched again, see `MarketLookupCache::new`
+pub const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Counters of `MarketLookupCache::load`, see `MarketLookupCache::stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MarketLookupStats {
+    /// Lookups of markets that were already cached
+    pub hits: u64,
+    /// Lookups skipped because the market recently failed to load
+    pub negative_hits: u64,
+    /// Lookups that waited for a fetch of the same market already in flight
+    pub coalesced: u64,
+    /// Fetches started, one per lookup that found the market neither cached nor in flight
+    pub misses: u64,
+    /// Fetches that failed, each of which puts the market in the negative cache
+    pub failures: u64,
+}
+
+#[derive(Default)]
+struct LookupState {
+    /// Markets being fetched. Lookups of the same market wait on the lock of the fetch.
+    in_flight: HashMap<Pubkey, Arc<tokio::sync::Mutex<()>>>,
+    /// Markets that failed to load, with the time they may be fetched again
+    missing: HashMap<Pubkey, Instant>,
+    stats: MarketLookupStats,
+}
+
+/// Deduplicates the market fetches of the event parsers, e.g.
+/// `SDKClient::parse_events_from_transaction`, which load the markets of the events they find.
+///
+/// The client's market cache is the positive cache. On top of it this keeps a negative cache
+/// of markets that failed to load, which are not fetched again until their retry-after has
+/// passed, and coalesces concurrent lookups of one market into a single fetch.
+pub struct MarketLookupCache {
+    retry_after: Duration,
+    state: Mutex<LookupState>,
+}
+
+impl Default for MarketLookupCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_RETRY_AFTER)
+    }
+}
+
+impl MarketLookupCache {
+    /// `retry_after` is how long a market that failed to load is skipped.
+    pub fn new(retry_after: Duration) -> Self {
+        MarketLookupCache {
+            retry_after,
+            state: Mutex::new(LookupState::default()),
+        }
+    }
+
+    pub fn stats(&self) -> MarketLookupStats {
+        self.state.lock().unwrap().stats
+    }
+
+    /// Returns whether `market` is cached, according to `is_cached`, once the lookup is done.
+    ///
+    /// A market that is not cached is fetched with `fetch`, unless it failed to load less than
+    /// `retry_after` ago. If another lookup of the market is fetching it, this waits for that
+    /// fetch instead of starting one.
+    pub async fn load<F, Fut>(
+        &self,
+        market: &Pubkey,
+        is_cached: impl Fn(&Pubkey) -> bool,
+        fetch: F,
+    ) -> bool
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = anyhow::Result<()>>,
+    {
+        if is_cached(market) {
+            self.state.lock().unwrap().stats.hits += 1;
+            return true;
+        }
+        let lock = {
+            let mut state = self.state.lock().unwrap();
+            if self.is_missing(&mut state, market) {
+                state.stats.negative_hits += 1;
+                return false;
+            }
+            state.in_flight.entry(*market).or_default().clone()
+        };
+        let _guard = lock.lock().await;
+
+        // The lookup that held the lock may have loaded the market, or failed to
+        if is_cached(market) {
+            self.state.lock().unwrap().stats.coalesced += 1;
+            return true;
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            if self.is_missing(&mut state, market) {
+                state.stats.coalesced += 1;
+                return false;
+            }
+            state.stats.misses += 1;
+        }
+        let result = fetch().await;
+
+        let mut state = self.state.lock().unwrap();
+        if let Err(e) = &result {
+            println!("Warning: failed to load market {}: {}", market, e);
+            state.stats.failures += 1;
+            state
+                .missing
+                .insert(*market, Instant::now() + self.retry_after);
+        }
+        if state
+            .in_flight
+            .get(market)
+            .map_or(false, |in_flight| Arc::ptr_eq(in_flight, &lock))
+        {
+            state.in_flight.remove(market);
+        }
+        result.is_ok() && is_cached(market)
+    }
+
+    /// Whether `market` is in the negative cache. Expired entries are removed.
+    fn is_missing(&self, state: &mut LookupState, market: &Pubkey) -> bool {
+        match state.missing.get(market) {
+            Some(&retry_at) if retry_at > Instant::now() => true,
+            Some(_) => {
+                state.missing.remove(market);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashSet, sync::atomic::AtomicUsize, sync::atomic::Ordering};
+
+    /// A market source that counts its fetches and loads the markets of `existing`
+    #[derive(Default)]
+    struct CountingClient {
+        existing: HashSet<Pubkey>,
+        cached: Mutex<HashSet<Pubkey>>,
+        fetches: AtomicUsize,
+    }
+
+    impl CountingClient {
+        fn is_cached(&self, market: &Pubkey) -> bool {
+            self.cached.lock().unwrap().contains(market)
+        }
+
+        async fn fetch(&self, market: Pubkey) -> anyhow::Result<()> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            if !self.existing.contains(&market) {
+                return Err(anyhow::anyhow!("account not found"));
+            }
+            self.cached.lock().unwrap().insert(market);
+            Ok(())
+        }
+
+        async fn load(&self, cache: &MarketLookupCache, market: Pubkey) -> bool {
+            cache
+                .load(&market, |key| self.is_cached(key), || self.fetch(market))
+                .await
+        }
+    }
+
+    #[tokio::test]
+    async fn one_fetch_per_unknown_market_regardless_of_batch_count() {
+        let found = Pubkey::new_unique();
+        let missing = Pubkey::new_unique();
+        let client = CountingClient {
+            existing: HashSet::from([found]),
+            ..CountingClient::default()
+        };
+        let cache = MarketLookupCache::default();
+
+        for _ in 0..50 {
+            assert!(client.load(&cache, found).await);
+            assert!(!client.load(&cache, missing).await);
+        }
+
+        assert_eq!(client.fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            cache.stats(),
+            MarketLookupStats {
+                hits: 49,
+                negative_hits: 49,
+                coalesced: 0,
+                misses: 2,
+                failures: 1,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_lookups_share_one_fetch() {
+        let found = Pubkey::new_unique();
+        let missing = Pubkey::new_unique();
+        let client = CountingClient {
+            existing: HashSet::from([found]),
+            ..CountingClient::default()
+        };
+        let cache = MarketLookupCache::default();
+
+        let loaded = futures::future::join_all(
+            (0..10).flat_map(|_| [client.load(&cache, found), client.load(&cache, missing)]),
+        )
+        .await;
+
+        assert_eq!(loaded.iter().filter(|&&loaded| loaded).count(), 10);
+        assert_eq!(client.fetches.load(Ordering::SeqCst), 2);
+        let stats = cache.stats();
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.coalesced, 18);
+    }
+
+    #[tokio::test]
+    async fn missing_markets_are_fetched_again_after_the_retry_after() {
+        let missing = Pubkey::new_unique();
+        let client = CountingClient::default();
+        let cache = MarketLookupCache::new(Duration::from_millis(20));
+
+        assert!(!client.load(&cache, missing).await);
+        assert!(!client.load(&cache, missing).await);
+        assert_eq!(client.fetches.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(!client.load(&cache, missing).await);
+        assert_eq!(client.fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn cached_markets_are_never_fetched() {
+        let market = Pubkey::new_unique();
+        let client = CountingClient::default();
+        client.cached.lock().unwrap().insert(market);
+        let cache = MarketLookupCache::default();
+
+        assert!(client.load(&cache, market).await);
+        assert_eq!(client.fetches.load(Ordering::SeqCst), 0);
+        assert_eq!(cache.stats().hits, 1);
+    }
+}
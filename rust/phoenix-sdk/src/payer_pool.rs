@@ -0,0 +1,251 @@
+use crate::market_event_handler::SDKMarketEvent;
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_sdk::signature::{Keypair, Signer};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// How `PayerPool` picks the fee payer for a transaction that is not pinned to a payer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PayerSelection {
+    /// Cycle through the payers in order
+    #[default]
+    RoundRobin,
+    /// Pick the payer whose last transaction is the oldest
+    LeastRecentlyUsed,
+}
+
+/// A pool of fee payer keypairs. Transactions signed with different fee payers do not contend
+/// on the payer's write lock, so a burst of transactions can execute in parallel within a slot.
+/// Only the fee payer rotates: the trader still signs every transaction.
+pub struct PayerPool {
+    payers: Vec<Arc<Keypair>>,
+    pub selection: PayerSelection,
+    /// Markets whose transactions always use the payer at the given index, so their
+    /// transactions are ordered by that payer's write lock
+    pinned_markets: HashMap<Pubkey, usize>,
+    next: usize,
+    last_used: Vec<Option<Instant>>,
+    /// A payer below this balance, in lamports, triggers a `LowPayerBalance` event
+    pub low_balance_lamports: u64,
+    /// How often `balances_due` asks for the balances to be refreshed
+    pub balance_check_interval: Duration,
+    last_balance_check: Option<Instant>,
+    /// Whether each payer was below `low_balance_lamports` at the last check, so the warning is
+    /// only emitted once per dip
+    low_balance: Vec<bool>,
+}
+
+impl PayerPool {
+    /// Fails if `payers` is empty.
+    pub fn new(payers: Vec<Keypair>, selection: PayerSelection) -> anyhow::Result<Self> {
+        if payers.is_empty() {
+            return Err(anyhow::anyhow!("PayerPool needs at least one payer"));
+        }
+        let num_payers = payers.len();
+        Ok(PayerPool {
+            payers: payers.into_iter().map(Arc::new).collect(),
+            selection,
+            pinned_markets: HashMap::new(),
+            next: 0,
+            last_used: vec![None; num_payers],
+            low_balance_lamports: 10_000_000,
+            balance_check_interval: Duration::from_secs(60),
+            last_balance_check: None,
+            low_balance: vec![false; num_payers],
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.payers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.payers.is_empty()
+    }
+
+    pub fn payer_keys(&self) -> Vec<Pubkey> {
+        self.payers.iter().map(|payer| payer.pubkey()).collect()
+    }
+
+    /// Always uses the payer at `payer_index` for transactions that reference `market`.
+    pub fn pin_market(&mut self, market: Pubkey, payer_index: usize) -> anyhow::Result<()> {
+        if payer_index >= self.payers.len() {
+            return Err(anyhow::anyhow!(
+                "Payer index {} out of range, the pool has {} payers",
+                payer_index,
+                self.payers.len()
+            ));
+        }
+        self.pinned_markets.insert(market, payer_index);
+        Ok(())
+    }
+
+    pub fn unpin_market(&mut self, market: &Pubkey) {
+        self.pinned_markets.remove(market);
+    }
+
+    /// Returns the index of the fee payer to use for `instructions` and marks it as used. If an
+    /// instruction references a pinned market, that market's payer is used.
+    pub fn select(&mut self, instructions: &[Instruction]) -> usize {
+        let pinned = instructions.iter().find_map(|ix| {
+            ix.accounts
+                .iter()
+                .find_map(|meta| self.pinned_markets.get(&meta.pubkey).copied())
+        });
+        let index = match (pinned, self.selection) {
+            (Some(index), _) => index,
+            (None, PayerSelection::RoundRobin) => {
+                let index = self.next;
+                self.next = (self.next + 1) % self.payers.len();
+                index
+            }
+            // Payers that were never used sort first
+            (None, PayerSelection::LeastRecentlyUsed) => (0..self.payers.len())
+                .min_by_key(|&i| self.last_used[i])
+                .unwrap_or(0),
+        };
+        self.last_used[index] = Some(Instant::now());
+        index
+    }
+
+    pub fn payer(&self, index: usize) -> Arc<Keypair> {
+        self.payers[index].clone()
+    }
+
+    /// Whether `balance_check_interval` has passed since the last call to `update_balances`.
+    pub fn balances_due(&self) -> bool {
+        self.last_balance_check
+            .map(|last| last.elapsed() >= self.balance_check_interval)
+            .unwrap_or(true)
+    }
+
+    /// Records the balance of each payer, in the order of `payer_keys`. Returns a
+    /// `LowPayerBalance` event for every payer that has dropped below `low_balance_lamports`
+    /// since the previous check.
+    pub fn update_balances(&mut self, balances: &[u64]) -> Vec<SDKMarketEvent> {
+        self.last_balance_check = Some(Instant::now());
+        let mut events = vec![];
+        for (i, &lamports) in balances.iter().enumerate().take(self.payers.len()) {
+            let is_low = lamports < self.low_balance_lamports;
+            if is_low && !self.low_balance[i] {
+                events.push(SDKMarketEvent::LowPayerBalance {
+                    payer: self.payers[i].pubkey(),
+                    lamports,
+                });
+            }
+            self.low_balance[i] = is_low;
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::instruction::AccountMeta;
+
+    fn pool(num_payers: usize, selection: PayerSelection) -> PayerPool {
+        PayerPool::new((0..num_payers).map(|_| Keypair::new()).collect(), selection).unwrap()
+    }
+
+    fn ix_on(market: Pubkey) -> Instruction {
+        Instruction {
+            program_id: phoenix_types::id(),
+            accounts: vec![AccountMeta::new(market, false)],
+            data: vec![],
+        }
+    }
+
+    #[test]
+    fn new_rejects_an_empty_pool() {
+        assert!(PayerPool::new(vec![], PayerSelection::RoundRobin).is_err());
+    }
+
+    #[test]
+    fn round_robin_uses_every_payer_equally() {
+        let mut pool = pool(3, PayerSelection::RoundRobin);
+        let picks = (0..9).map(|_| pool.select(&[])).collect::<Vec<_>>();
+        assert_eq!(picks, vec![0, 1, 2, 0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn least_recently_used_picks_the_oldest_payer() {
+        let mut pool = pool(3, PayerSelection::LeastRecentlyUsed);
+        let mut picks = vec![];
+        for _ in 0..3 {
+            picks.push(pool.select(&[]));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        // Payer 1 is used again out of turn, so payer 0 and then payer 2 are now the oldest
+        pool.last_used[1] = Some(Instant::now());
+        picks.push(pool.select(&[]));
+        std::thread::sleep(Duration::from_millis(1));
+        picks.push(pool.select(&[]));
+        assert_eq!(picks, vec![0, 1, 2, 0, 2]);
+    }
+
+    #[test]
+    fn pinned_markets_always_use_their_payer() {
+        let market = Pubkey::new_unique();
+        let mut pool = pool(3, PayerSelection::RoundRobin);
+        pool.pin_market(market, 2).unwrap();
+        assert!(pool.pin_market(Pubkey::new_unique(), 3).is_err());
+
+        assert_eq!(pool.select(&[ix_on(market)]), 2);
+        assert_eq!(pool.select(&[ix_on(Pubkey::new_unique())]), 0);
+        assert_eq!(pool.select(&[ix_on(market)]), 2);
+        assert_eq!(pool.select(&[]), 1);
+
+        pool.unpin_market(&market);
+        assert_eq!(pool.select(&[ix_on(market)]), 2);
+        assert_eq!(pool.select(&[ix_on(market)]), 0);
+    }
+
+    #[test]
+    fn low_balance_is_reported_once_per_dip() {
+        let mut pool = pool(2, PayerSelection::RoundRobin);
+        pool.low_balance_lamports = 1_000;
+        let keys = pool.payer_keys();
+        assert!(pool.balances_due());
+
+        assert_eq!(
+            pool.update_balances(&[500, 2_000]),
+            vec![SDKMarketEvent::LowPayerBalance {
+                payer: keys[0],
+                lamports: 500,
+            }]
+        );
+        assert!(!pool.balances_due());
+        // Still low, so no new event
+        assert!(pool.update_balances(&[400, 2_000]).is_empty());
+        // Payer 0 was topped up and payer 1 dipped
+        assert_eq!(
+            pool.update_balances(&[5_000, 999]),
+            vec![SDKMarketEvent::LowPayerBalance {
+                payer: keys[1],
+                lamports: 999,
+            }]
+        );
+        // Payer 0 dips again
+        assert_eq!(
+            pool.update_balances(&[0, 999]),
+            vec![SDKMarketEvent::LowPayerBalance {
+                payer: keys[0],
+                lamports: 0,
+            }]
+        );
+    }
+
+    #[test]
+    fn balances_are_due_after_the_check_interval() {
+        let mut pool = pool(1, PayerSelection::RoundRobin);
+        pool.balance_check_interval = Duration::from_millis(5);
+        pool.update_balances(&[u64::MAX]);
+        assert!(!pool.balances_due());
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(pool.balances_due());
+    }
+}
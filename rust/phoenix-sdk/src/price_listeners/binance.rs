@@ -3,7 +3,6 @@ use binance::{api::Binance, market::Market, websockets::*};
 use phoenix_types::enums::*;
 use rust_decimal::prelude::*;
 use std::{
-    collections::BTreeMap,
     sync::{atomic::AtomicBool, mpsc::Sender, Arc, RwLock},
     thread,
     thread::JoinHandle,
@@ -15,12 +14,7 @@ pub struct BinancePriceListener {
 
 impl BinancePriceListener {
     pub fn new(market_name: String, sender: Sender<Vec<SDKMarketEvent>>) -> Self {
-        let ladder = Arc::new(RwLock::new(Orderbook {
-            size_mult: 1.0,
-            price_mult: 1.0,
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
-        }));
+        let ladder = Arc::new(RwLock::new(Orderbook::new(1.0, 1.0)));
         let worker = thread::Builder::new()
             .name("binance-ladder".to_string())
             .spawn(move || Self::run(ladder, market_name, sender))
@@ -89,10 +83,15 @@ impl BinancePriceListener {
                         .collect::<Vec<_>>(),
                 );
             }
-            let vwap = ladder
+            let vwap = match ladder
                 .read()
                 .map_err(|e| format!("Error reading from ladder: {e}"))?
-                .vwap(3);
+                .vwap(3)
+            {
+                Some(vwap) => vwap,
+                // One side of the book is empty, wait for more updates
+                None => return Ok(()),
+            };
             match sender.send(vec![SDKMarketEvent::FairPriceUpdate { price: vwap }]) {
                 Ok(_) => {}
                 Err(e) => println!("Error while sending fair price update: {}", e),
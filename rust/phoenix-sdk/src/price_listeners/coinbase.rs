@@ -6,7 +6,6 @@ use futures::StreamExt;
 use phoenix_types::enums::*;
 use rust_decimal::prelude::*;
 use std::{
-    collections::BTreeMap,
     sync::{mpsc::Sender, Arc, RwLock},
     thread,
     thread::JoinHandle,
@@ -18,12 +17,7 @@ pub struct CoinbasePriceListener {
 
 impl CoinbasePriceListener {
     pub fn new(market_name: String, sender: Sender<Vec<SDKMarketEvent>>) -> Self {
-        let ladder = Arc::new(RwLock::new(Orderbook {
-            size_mult: 1.0,
-            price_mult: 1.0,
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
-        }));
+        let ladder = Arc::new(RwLock::new(Orderbook::new(1.0, 1.0)));
         let worker = thread::Builder::new()
             .name("coinbase-ladder".to_string())
             .spawn(move || Self::run(ladder, market_name, sender, false))
@@ -36,12 +30,7 @@ impl CoinbasePriceListener {
         market_name: String,
         sender: Sender<Vec<SDKMarketEvent>>,
     ) -> Self {
-        let ladder = Arc::new(RwLock::new(Orderbook {
-            size_mult: 1.0,
-            price_mult: 1.0,
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
-        }));
+        let ladder = Arc::new(RwLock::new(Orderbook::new(1.0, 1.0)));
         let worker = thread::Builder::new()
             .name("coinbase-ladder".to_string())
             .spawn(move || Self::run(ladder, market_name, sender, true))
@@ -221,8 +210,12 @@ impl CoinbasePriceListener {
                 }
             };
 
-            let vwap = ladder.read().unwrap().vwap(3);
-            if vwap.is_nan() || vwap.is_infinite() || vwap <= 0.0 {
+            let vwap = match ladder.read().unwrap().vwap(3) {
+                Some(vwap) => vwap,
+                // One side of the book is empty, wait for more updates
+                None => continue,
+            };
+            if vwap.is_infinite() || vwap <= 0.0 {
                 println!(
                     "Price is invalid: {}, reconnecting as after 10 seconds",
                     vwap
@@ -1,2 +1,4 @@
 pub mod binance;
 pub mod coinbase;
+pub mod phoenix_tape;
+pub mod quote_conversion;
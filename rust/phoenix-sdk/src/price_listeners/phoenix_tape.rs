@@ -0,0 +1,205 @@
+use crate::{
+    market_event_handler::{MarketEventDetails, PhoenixEvent, SDKMarketEvent},
+    sdk_client_core::MarketMetadata,
+};
+use solana_program::pubkey::Pubkey;
+use std::{
+    collections::VecDeque,
+    sync::mpsc::{Receiver, RecvTimeoutError, Sender},
+    thread,
+    thread::JoinHandle,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// How `PhoenixTapePriceSource` turns the fills of a market into a reference price
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TapeEstimator {
+    /// The price of the most recent fill
+    LastTrade,
+    /// The size weighted price of the last `n` fills
+    VwapLastTrades(usize),
+    /// The size weighted price of the fills within the window of the most recent fill
+    VwapWindow(Duration),
+    /// An exponentially weighted average of the fill prices, each weighted by its size. The
+    /// weight of a fill halves every `half_life` of trading time, so fills with the same
+    /// timestamp combine as a size weighted price.
+    Ewma { half_life: Duration },
+}
+
+/// A reference price for markets without an external feed, derived from the market's own
+/// fills.
+///
+/// Fills can come from any event source, e.g. an `EventPoller` or `EventSubscriber`. Once no
+/// fill happened within `staleness`, no price is reported, so a quoting strategy falls back to
+/// quoting around the mid. Time is taken from the event timestamps, in seconds.
+#[derive(Clone, Debug)]
+pub struct PhoenixTapePriceSource {
+    pub market: Pubkey,
+    pub metadata: MarketMetadata,
+    pub estimator: TapeEstimator,
+    pub staleness: Duration,
+    /// Timestamp, price in quote units per base unit, and size in base units of the fills the
+    /// estimator still needs, oldest first
+    trades: VecDeque<(i64, f64, f64)>,
+    /// The average price of the `Ewma` estimator and the decayed size of the fills behind it
+    ewma: Option<(f64, f64)>,
+}
+
+impl PhoenixTapePriceSource {
+    pub fn new(
+        market: Pubkey,
+        metadata: MarketMetadata,
+        estimator: TapeEstimator,
+        staleness: Duration,
+    ) -> Self {
+        PhoenixTapePriceSource {
+            market,
+            metadata,
+            estimator,
+            staleness,
+            trades: VecDeque::new(),
+            ewma: None,
+        }
+    }
+
+    /// Records `event` if it is a fill on the source's market. Returns whether it was.
+    pub fn observe(&mut self, event: &PhoenixEvent) -> bool {
+        let fill = match &event.details {
+            MarketEventDetails::Fill(fill) if event.market == self.market => fill,
+            _ => return false,
+        };
+        let price = self.metadata.ticks_to_float_price(fill.price_in_ticks);
+        let size =
+            fill.base_lots_filled as f64 * self.metadata.base_lots_to_base_units_multiplier();
+        self.record_trade(event.timestamp, price, size);
+        true
+    }
+
+    /// Records a trade at `timestamp`, in Unix seconds.
+    pub fn record_trade(&mut self, timestamp: i64, price: f64, size: f64) {
+        if let TapeEstimator::Ewma { half_life } = self.estimator {
+            let (ewma, weight) = match (self.ewma, self.trades.back()) {
+                (Some((ewma, weight)), Some(&(last_timestamp, _, _))) => {
+                    let elapsed = (timestamp - last_timestamp).max(0) as f64;
+                    let half_life = half_life.as_secs_f64().max(f64::MIN_POSITIVE);
+                    (ewma, weight * 0.5_f64.powf(elapsed / half_life))
+                }
+                _ => (price, 0.0),
+            };
+            let total_weight = weight + size;
+            self.ewma = Some(if total_weight > 0.0 {
+                ((ewma * weight + price * size) / total_weight, total_weight)
+            } else {
+                (price, 0.0)
+            });
+        }
+        self.trades.push_back((timestamp, price, size));
+        self.prune(timestamp);
+    }
+
+    fn prune(&mut self, latest: i64) {
+        let keep = match self.estimator {
+            TapeEstimator::VwapLastTrades(n) => n.max(1),
+            TapeEstimator::VwapWindow(window) => {
+                let cutoff = latest - window.as_secs() as i64;
+                self.trades
+                    .iter()
+                    .filter(|(timestamp, _, _)| *timestamp >= cutoff)
+                    .count()
+                    .max(1)
+            }
+            TapeEstimator::LastTrade | TapeEstimator::Ewma { .. } => 1,
+        };
+        while self.trades.len() > keep {
+            self.trades.pop_front();
+        }
+    }
+
+    /// The reference price at `now`, in Unix seconds, or None if there was no fill within
+    /// `staleness` of `now`.
+    pub fn fair_price_at(&self, now: i64) -> Option<f64> {
+        let &(last_timestamp, last_price, _) = self.trades.back()?;
+        if now - last_timestamp > self.staleness.as_secs() as i64 {
+            return None;
+        }
+        match self.estimator {
+            TapeEstimator::LastTrade => Some(last_price),
+            TapeEstimator::Ewma { .. } => self.ewma.map(|(ewma, _)| ewma),
+            TapeEstimator::VwapLastTrades(_) | TapeEstimator::VwapWindow(_) => {
+                let (size, notional) = self.trades.iter().fold(
+                    (0.0, 0.0),
+                    |(size, notional), (_, price, trade_size)| {
+                        (size + trade_size, notional + price * trade_size)
+                    },
+                );
+                if size > 0.0 {
+                    Some(notional / size)
+                } else {
+                    Some(last_price)
+                }
+            }
+        }
+    }
+
+    pub fn fair_price(&self) -> Option<f64> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() as i64)
+            .unwrap_or_default();
+        self.fair_price_at(now)
+    }
+
+    /// Reads events from `receiver` on a background thread and sends a `MarketFairPriceUpdate`
+    /// to `sender` after each batch that contains a fill on the market, and after each
+    /// `interval` without any events. Nothing is sent while the price is stale. The thread stops when
+    /// either channel disconnects.
+    pub fn spawn(
+        mut self,
+        receiver: Receiver<Vec<SDKMarketEvent>>,
+        sender: Sender<Vec<SDKMarketEvent>>,
+        interval: Duration,
+    ) -> PhoenixTapePriceListener {
+        let worker = thread::Builder::new()
+            .name("phoenix-tape".to_string())
+            .spawn(move || loop {
+                match receiver.recv_timeout(interval) {
+                    Ok(events) => {
+                        let mut filled = false;
+                        for event in events.iter().filter_map(|e| e.as_phoenix_event()) {
+                            filled |= self.observe(event);
+                        }
+                        if !filled {
+                            continue;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+                if let Some(price) = self.fair_price() {
+                    if sender
+                        .send(vec![SDKMarketEvent::MarketFairPriceUpdate {
+                            market: self.market,
+                            price,
+                        }])
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            })
+            .unwrap();
+
+        PhoenixTapePriceListener { worker }
+    }
+}
+
+/// The background thread started by `PhoenixTapePriceSource::spawn`.
+pub struct PhoenixTapePriceListener {
+    pub worker: JoinHandle<()>,
+}
+
+impl PhoenixTapePriceListener {
+    pub fn join(self) {
+        self.worker.join().unwrap()
+    }
+}
@@ -0,0 +1,123 @@
+use solana_program::pubkey::Pubkey;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Maps the quote mint of a Phoenix market to the external feed that prices it in the
+/// currency of the fair price feed, e.g. the SOL mint to `SOL-USD` when quoting a SOL-quoted
+/// market from a USD feed. Quote mints without an entry are assumed to match the fair price
+/// feed's currency, e.g. USDC for a USD feed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QuoteCurrencyConfig {
+    pub conversion_feeds: HashMap<Pubkey, String>,
+}
+
+impl QuoteCurrencyConfig {
+    /// The conversion feed for markets quoted in `quote_mint`, None if no conversion is needed
+    pub fn conversion_feed(&self, quote_mint: &Pubkey) -> Option<&str> {
+        self.conversion_feeds.get(quote_mint).map(String::as_str)
+    }
+
+    /// A `QuoteConversion` for markets quoted in `quote_mint`
+    pub fn conversion_for(&self, quote_mint: &Pubkey, max_age: Duration) -> QuoteConversion {
+        if self.conversion_feed(quote_mint).is_some() {
+            QuoteConversion::via_rate(max_age)
+        } else {
+            QuoteConversion::identity(max_age)
+        }
+    }
+}
+
+/// Converts an external fair price to the quote currency of a Phoenix market.
+///
+/// The fair price is in the feed's currency per base unit, e.g. USD per SOL, and the conversion
+/// rate is the price of the market's quote asset in that currency, e.g. USD per USDC or USD per
+/// SOL. The converted price is the fair price divided by the rate. With `identity` the fair
+/// price is passed through.
+///
+/// Both prices must have been updated within `max_age`, otherwise `fair_price` fails, so a
+/// stalled conversion feed stops quoting like a stalled fair price feed would.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuoteConversion {
+    pub max_age: Duration,
+    needs_rate: bool,
+    fair_price: Option<(f64, Instant)>,
+    conversion_rate: Option<(f64, Instant)>,
+}
+
+impl QuoteConversion {
+    pub fn identity(max_age: Duration) -> Self {
+        QuoteConversion {
+            max_age,
+            needs_rate: false,
+            fair_price: None,
+            conversion_rate: None,
+        }
+    }
+
+    pub fn via_rate(max_age: Duration) -> Self {
+        QuoteConversion {
+            needs_rate: true,
+            ..Self::identity(max_age)
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        !self.needs_rate
+    }
+
+    /// Records a price from the fair price feed, e.g. a `FairPriceUpdate`
+    pub fn update_fair_price(&mut self, price: f64) {
+        self.update_fair_price_at(price, Instant::now());
+    }
+
+    pub fn update_fair_price_at(&mut self, price: f64, at: Instant) {
+        self.fair_price = Some((price, at));
+    }
+
+    /// Records a price from the conversion feed. Ignored by an identity conversion.
+    pub fn update_conversion_rate(&mut self, rate: f64) {
+        self.update_conversion_rate_at(rate, Instant::now());
+    }
+
+    pub fn update_conversion_rate_at(&mut self, rate: f64, at: Instant) {
+        self.conversion_rate = Some((rate, at));
+    }
+
+    /// The fair price in the market's quote units per base unit
+    pub fn fair_price(&self) -> anyhow::Result<f64> {
+        self.fair_price_at(Instant::now())
+    }
+
+    pub fn fair_price_at(&self, now: Instant) -> anyhow::Result<f64> {
+        let fair_price = self.fresh(self.fair_price, "fair price", now)?;
+        if !self.needs_rate {
+            return Ok(fair_price);
+        }
+        let rate = self.fresh(self.conversion_rate, "conversion rate", now)?;
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(anyhow::anyhow!("Invalid conversion rate {}", rate));
+        }
+        Ok(fair_price / rate)
+    }
+
+    fn fresh(
+        &self,
+        price: Option<(f64, Instant)>,
+        name: &str,
+        now: Instant,
+    ) -> anyhow::Result<f64> {
+        let (price, at) = price.ok_or_else(|| anyhow::anyhow!("No {} received yet", name))?;
+        let age = now.saturating_duration_since(at);
+        if age > self.max_age {
+            return Err(anyhow::anyhow!(
+                "The {} is stale: last updated {:?} ago, the limit is {:?}",
+                name,
+                age,
+                self.max_age
+            ));
+        }
+        Ok(price)
+    }
+}
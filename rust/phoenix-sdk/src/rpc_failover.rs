@@ -0,0 +1,278 @@
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    rpc_client::{RpcClient, SerializableTransaction},
+    rpc_config::RpcSendTransactionConfig,
+    rpc_custom_error::{
+        JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED, JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+    },
+    rpc_request::{RpcError, RpcResponseErrorData},
+};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, signature::Signature, transaction::TransactionError,
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::rpc_health::{EndpointHealth, RpcHealthProbe, RpcHealthTracker};
+
+/// Which endpoint a send finally succeeded on, and after how many attempts in total
+/// (including the primary endpoint).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SendReport {
+    pub endpoint: String,
+    pub attempts: usize,
+}
+
+/// Secondary RPC endpoints that the send helpers fail over to when the primary endpoint fails
+/// with a retryable error (blockhash not found, node behind). Empty by default.
+///
+/// Endpoints are tried healthiest first, as ranked by `health` from the calls made through
+/// the failover and, if started with `spawn_health_probe`, periodic slot probes.
+pub struct RpcFailover {
+    pub endpoints: Vec<RpcClient>,
+    /// Maximum number of secondary endpoints tried per send
+    pub max_attempts: usize,
+    pub health: Arc<RpcHealthTracker>,
+    last_send: Mutex<Option<SendReport>>,
+}
+
+impl Default for RpcFailover {
+    fn default() -> Self {
+        RpcFailover {
+            endpoints: vec![],
+            max_attempts: 3,
+            health: Arc::new(RpcHealthTracker::default()),
+            last_send: Mutex::new(None),
+        }
+    }
+}
+
+/// How often `send_and_confirm` checks whether the transaction it sent confirmed
+const CONFIRM_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Sends `transaction` through `rpc` and waits until it is confirmed at `commitment` or its
+/// blockhash expires. Unlike the RPC client's spinner methods it prints nothing, so it can be
+/// used from library code. A transaction that did not confirm fails with the client's
+/// `ForUser` error, like the spinner methods, see `is_resendable`.
+pub(crate) fn send_and_confirm(
+    rpc: &RpcClient,
+    transaction: &impl SerializableTransaction,
+    commitment: CommitmentConfig,
+    skip_preflight: bool,
+) -> anyhow::Result<Signature> {
+    let signature = rpc.send_transaction_with_config(
+        transaction,
+        RpcSendTransactionConfig {
+            skip_preflight,
+            ..RpcSendTransactionConfig::default()
+        },
+    )?;
+    let blockhash = *transaction.get_recent_blockhash();
+    let mut expired = false;
+    loop {
+        if let Some(result) = rpc.get_signature_status_with_commitment(&signature, commitment)? {
+            result.map_err(ClientError::from)?;
+            return Ok(signature);
+        }
+        // The status was read after the blockhash expired, so the transaction did not land
+        if expired {
+            return Err(ClientError::from(RpcError::ForUser(format!(
+                "unable to confirm transaction {}, its blockhash expired",
+                signature
+            )))
+            .into());
+        }
+        expired = !rpc.is_blockhash_valid(&blockhash, CommitmentConfig::processed())?;
+        if !expired {
+            std::thread::sleep(CONFIRM_POLL_INTERVAL);
+        }
+    }
+}
+
+/// The kind of `error`, if it is an RPC client error
+fn client_error_kind(error: &anyhow::Error) -> Option<&ClientErrorKind> {
+    error.downcast_ref::<ClientError>().map(|e| e.kind())
+}
+
+/// The transaction error of a send, reported by the node's preflight simulation or by the
+/// confirmation of the transaction
+fn transaction_error(kind: &ClientErrorKind) -> Option<&TransactionError> {
+    match kind {
+        ClientErrorKind::TransactionError(e) => Some(e),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+            ..
+        }) => result.err.as_ref(),
+        _ => None,
+    }
+}
+
+/// True if the node rejected the request in a way that is likely to go away when retried with
+/// a fresh blockhash or against a different node: the blockhash was not found, the node is
+/// behind, or it has not reached the requested minimum context slot. A transaction rejected
+/// this way was not forwarded by the node.
+pub fn is_retryable(error: &anyhow::Error) -> bool {
+    let kind = match client_error_kind(error) {
+        Some(kind) => kind,
+        None => return false,
+    };
+    if transaction_error(kind) == Some(&TransactionError::BlockhashNotFound) {
+        return true;
+    }
+    match kind {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, data, .. }) => {
+            *code == JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+                || *code == JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED
+                || matches!(data, RpcResponseErrorData::NodeUnhealthy { .. })
+        }
+        _ => false,
+    }
+}
+
+/// True if the transaction may not have landed and resending it could succeed: the error is
+/// retryable, the request timed out, or the client gave up confirming the transaction, e.g.
+/// because its blockhash expired. In the last two cases the transaction may still land, see
+/// `SDKClient::try_send_instructions_with_retries`.
+pub fn is_resendable(error: &anyhow::Error) -> bool {
+    if is_retryable(error) {
+        return true;
+    }
+    match client_error_kind(error) {
+        // Returned by the send and confirm methods when the transaction did not confirm
+        Some(ClientErrorKind::RpcError(RpcError::ForUser(_))) => true,
+        Some(ClientErrorKind::Reqwest(e)) => e.is_timeout(),
+        _ => false,
+    }
+}
+
+/// True if the node rejected the transaction because an identical one already landed.
+pub fn is_already_processed(error: &anyhow::Error) -> bool {
+    client_error_kind(error).and_then(transaction_error)
+        == Some(&TransactionError::AlreadyProcessed)
+}
+
+impl RpcFailover {
+    pub fn new(endpoints: Vec<RpcClient>) -> Self {
+        RpcFailover {
+            endpoints,
+            ..RpcFailover::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+
+    /// The endpoint and attempt count of the most recent successful send
+    pub fn last_send(&self) -> Option<SendReport> {
+        self.last_send.lock().unwrap().clone()
+    }
+
+    /// The health of each secondary endpoint that has been called or probed
+    pub fn health_report(&self) -> Vec<EndpointHealth> {
+        self.health.health_report()
+    }
+
+    /// Starts probing the slot of every secondary endpoint every `interval`, see
+    /// `RpcHealthTracker::spawn_probe`.
+    pub fn spawn_health_probe(&self, interval: Duration) -> RpcHealthProbe {
+        self.health.spawn_probe(
+            self.endpoints.iter().map(|rpc| rpc.url()).collect(),
+            interval,
+        )
+    }
+
+    /// The secondary endpoints, healthiest first, capped at `max_attempts`
+    fn ranked_endpoints(&self) -> impl Iterator<Item = &RpcClient> {
+        let urls = self
+            .endpoints
+            .iter()
+            .map(|rpc| rpc.url())
+            .collect::<Vec<_>>();
+        self.health
+            .rank(&urls)
+            .into_iter()
+            .take(self.max_attempts)
+            .map(move |i| &self.endpoints[i])
+    }
+
+    pub(crate) fn record_send(&self, endpoint: String, attempts: usize) {
+        *self.last_send.lock().unwrap() = Some(SendReport { endpoint, attempts });
+    }
+
+    /// Sends `transaction`, legacy or versioned and already signed, to each secondary endpoint
+    /// in turn, healthiest first, until one succeeds, a non-retryable error occurs, or
+    /// `max_attempts` endpoints were tried. Every endpoint gets the same signed transaction, so
+    /// it lands at most once however many of them forwarded it. `previous_attempts` is the
+    /// number of attempts already made on the primary endpoint, used for the `SendReport`.
+    pub fn send(
+        &self,
+        transaction: &impl SerializableTransaction,
+        skip_preflight: bool,
+        previous_attempts: usize,
+        last_error: anyhow::Error,
+    ) -> anyhow::Result<Signature> {
+        self.send_with(previous_attempts, last_error, |rpc| {
+            send_and_confirm(rpc, transaction, rpc.commitment(), skip_preflight)
+        })
+    }
+
+    /// Like `send`, but returns as soon as an endpoint accepts `transaction`, without waiting
+    /// for it to confirm. The caller watches the signature itself.
+    pub fn forward(
+        &self,
+        transaction: &impl SerializableTransaction,
+        skip_preflight: bool,
+        previous_attempts: usize,
+        last_error: anyhow::Error,
+    ) -> anyhow::Result<Signature> {
+        self.send_with(previous_attempts, last_error, |rpc| {
+            Ok(rpc.send_transaction_with_config(
+                transaction,
+                RpcSendTransactionConfig {
+                    skip_preflight,
+                    ..RpcSendTransactionConfig::default()
+                },
+            )?)
+        })
+    }
+
+    fn send_with(
+        &self,
+        previous_attempts: usize,
+        last_error: anyhow::Error,
+        send_on: impl Fn(&RpcClient) -> anyhow::Result<Signature>,
+    ) -> anyhow::Result<Signature> {
+        let mut last_error = last_error;
+        for (i, rpc) in self.ranked_endpoints().enumerate() {
+            match self.health.observe(&rpc.url(), || send_on(rpc)) {
+                Ok(signature) => {
+                    self.record_send(rpc.url(), previous_attempts + i + 1);
+                    return Ok(signature);
+                }
+                Err(e) if is_retryable(&e) => last_error = e,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Fetches the data of `key` from the secondary endpoints, healthiest first, returning
+    /// the first success or the last error.
+    pub fn get_account_data(&self, key: &Pubkey) -> anyhow::Result<Vec<u8>> {
+        let mut last_error = anyhow::anyhow!("No secondary RPC endpoints configured");
+        for rpc in self.ranked_endpoints() {
+            match self
+                .health
+                .observe(&rpc.url(), || Ok(rpc.get_account_data(key)?))
+            {
+                Ok(data) => return Ok(data),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+}
@@ -0,0 +1,274 @@
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::{
+    cmp::Ordering as CmpOrdering,
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{Builder, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Number of calls per endpoint kept for the rolling success rate and latency percentiles
+const DEFAULT_WINDOW: usize = 100;
+
+/// A snapshot of the health of one RPC endpoint, see `RpcHealthTracker::health_report`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EndpointHealth {
+    pub endpoint: String,
+    /// Number of calls in the rolling window
+    pub samples: usize,
+    /// Fraction of the calls in the window that succeeded, 1.0 if there are none
+    pub success_rate: f64,
+    pub p50_latency: Option<Duration>,
+    pub p95_latency: Option<Duration>,
+    pub last_error: Option<String>,
+    /// Slots behind the most advanced probed endpoint at processed commitment
+    pub processed_slot_lag: Option<u64>,
+    /// Slots behind the most advanced probed endpoint at finalized commitment
+    pub finalized_slot_lag: Option<u64>,
+}
+
+impl EndpointHealth {
+    fn untracked(endpoint: &str) -> Self {
+        EndpointHealth {
+            endpoint: endpoint.to_string(),
+            samples: 0,
+            success_rate: 1.0,
+            p50_latency: None,
+            p95_latency: None,
+            last_error: None,
+            processed_slot_lag: None,
+            finalized_slot_lag: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct EndpointStats {
+    endpoint: String,
+    /// Latency and outcome of the most recent calls, oldest first
+    calls: VecDeque<(Duration, bool)>,
+    last_error: Option<String>,
+    processed_slot: Option<u64>,
+    finalized_slot: Option<u64>,
+}
+
+/// Records the outcome and latency of the calls made to each RPC endpoint, and optionally the
+/// slot each endpoint is at, so the healthiest endpoint can be tried first.
+///
+/// Endpoints are ranked by rolling success rate, then by processed slot lag, then by p95
+/// latency. Endpoints without data rank as healthy, and ties keep the configured order.
+pub struct RpcHealthTracker {
+    window: usize,
+    stats: Mutex<Vec<EndpointStats>>,
+}
+
+impl Default for RpcHealthTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
+
+impl RpcHealthTracker {
+    /// `window` is the number of calls per endpoint the success rate and latencies cover.
+    pub fn new(window: usize) -> Self {
+        RpcHealthTracker {
+            window: window.max(1),
+            stats: Mutex::new(vec![]),
+        }
+    }
+
+    fn with_stats<R>(&self, endpoint: &str, f: impl FnOnce(&mut EndpointStats) -> R) -> R {
+        let mut stats = self.stats.lock().unwrap();
+        let index = match stats.iter().position(|s| s.endpoint == endpoint) {
+            Some(index) => index,
+            None => {
+                stats.push(EndpointStats {
+                    endpoint: endpoint.to_string(),
+                    ..EndpointStats::default()
+                });
+                stats.len() - 1
+            }
+        };
+        f(&mut stats[index])
+    }
+
+    fn record(&self, endpoint: &str, latency: Duration, succeeded: bool) {
+        let window = self.window;
+        self.with_stats(endpoint, |stats| {
+            stats.calls.push_back((latency, succeeded));
+            if stats.calls.len() > window {
+                stats.calls.pop_front();
+            }
+        });
+    }
+
+    pub fn record_success(&self, endpoint: &str, latency: Duration) {
+        self.record(endpoint, latency, true);
+    }
+
+    pub fn record_error(&self, endpoint: &str, latency: Duration, error: &anyhow::Error) {
+        self.record(endpoint, latency, false);
+        self.with_stats(endpoint, |stats| stats.last_error = Some(error.to_string()));
+    }
+
+    /// Records the slots `endpoint` reported at processed and finalized commitment.
+    pub fn record_slots(&self, endpoint: &str, processed_slot: u64, finalized_slot: u64) {
+        self.with_stats(endpoint, |stats| {
+            stats.processed_slot = Some(processed_slot);
+            stats.finalized_slot = Some(finalized_slot);
+        });
+    }
+
+    /// Times `f` and records its outcome for `endpoint`.
+    pub fn observe<T>(
+        &self,
+        endpoint: &str,
+        f: impl FnOnce() -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let start = Instant::now();
+        let result = f();
+        match &result {
+            Ok(_) => self.record_success(endpoint, start.elapsed()),
+            Err(e) => self.record_error(endpoint, start.elapsed(), e),
+        }
+        result
+    }
+
+    /// The health of every endpoint the tracker has data for, in the order they were first seen.
+    pub fn health_report(&self) -> Vec<EndpointHealth> {
+        let stats = self.stats.lock().unwrap();
+        let max_processed = stats.iter().filter_map(|s| s.processed_slot).max();
+        let max_finalized = stats.iter().filter_map(|s| s.finalized_slot).max();
+        stats
+            .iter()
+            .map(|s| {
+                let mut latencies = s
+                    .calls
+                    .iter()
+                    .map(|(latency, _)| *latency)
+                    .collect::<Vec<_>>();
+                latencies.sort();
+                let percentile = |p: usize| {
+                    (!latencies.is_empty()).then(|| latencies[(latencies.len() - 1) * p / 100])
+                };
+                let successes = s.calls.iter().filter(|(_, succeeded)| *succeeded).count();
+                EndpointHealth {
+                    endpoint: s.endpoint.clone(),
+                    samples: s.calls.len(),
+                    success_rate: if s.calls.is_empty() {
+                        1.0
+                    } else {
+                        successes as f64 / s.calls.len() as f64
+                    },
+                    p50_latency: percentile(50),
+                    p95_latency: percentile(95),
+                    last_error: s.last_error.clone(),
+                    processed_slot_lag: s
+                        .processed_slot
+                        .zip(max_processed)
+                        .map(|(slot, max)| max - slot),
+                    finalized_slot_lag: s
+                        .finalized_slot
+                        .zip(max_finalized)
+                        .map(|(slot, max)| max - slot),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the indices of `endpoints`, healthiest first.
+    pub fn rank(&self, endpoints: &[String]) -> Vec<usize> {
+        let report = self.health_report();
+        let health = endpoints
+            .iter()
+            .map(|endpoint| {
+                report
+                    .iter()
+                    .find(|h| &h.endpoint == endpoint)
+                    .cloned()
+                    .unwrap_or_else(|| EndpointHealth::untracked(endpoint))
+            })
+            .collect::<Vec<_>>();
+        let mut order = (0..endpoints.len()).collect::<Vec<_>>();
+        // Stable, so endpoints that compare equal keep their configured order
+        order.sort_by(|&a, &b| Self::compare(&health[a], &health[b]));
+        order
+    }
+
+    fn compare(a: &EndpointHealth, b: &EndpointHealth) -> CmpOrdering {
+        b.success_rate
+            .partial_cmp(&a.success_rate)
+            .unwrap_or(CmpOrdering::Equal)
+            .then_with(|| {
+                a.processed_slot_lag
+                    .unwrap_or(0)
+                    .cmp(&b.processed_slot_lag.unwrap_or(0))
+            })
+            .then_with(|| {
+                a.p95_latency
+                    .unwrap_or_default()
+                    .cmp(&b.p95_latency.unwrap_or_default())
+            })
+    }
+
+    /// Probes `endpoints` with `getSlot` at processed and finalized commitment every
+    /// `interval` on a background thread, recording the slots and the probe latencies. The
+    /// probe costs two requests per endpoint per interval and is not started unless this is
+    /// called.
+    pub fn spawn_probe(
+        self: &Arc<Self>,
+        endpoints: Vec<String>,
+        interval: Duration,
+    ) -> RpcHealthProbe {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let worker_shutdown = shutdown.clone();
+        let tracker = self.clone();
+        let worker = Builder::new()
+            .name("rpc-health-probe".to_string())
+            .spawn(move || {
+                let clients = endpoints
+                    .iter()
+                    .map(|endpoint| RpcClient::new(endpoint.clone()))
+                    .collect::<Vec<_>>();
+                while !worker_shutdown.load(Ordering::Relaxed) {
+                    for (endpoint, client) in endpoints.iter().zip(clients.iter()) {
+                        let slots = tracker.observe(endpoint, || {
+                            let processed =
+                                client.get_slot_with_commitment(CommitmentConfig::processed())?;
+                            let finalized =
+                                client.get_slot_with_commitment(CommitmentConfig::finalized())?;
+                            Ok((processed, finalized))
+                        });
+                        if let Ok((processed, finalized)) = slots {
+                            tracker.record_slots(endpoint, processed, finalized);
+                        }
+                    }
+                    std::thread::sleep(interval);
+                }
+            })
+            .unwrap();
+
+        RpcHealthProbe { worker, shutdown }
+    }
+}
+
+/// The background thread started by `RpcHealthTracker::spawn_probe`.
+pub struct RpcHealthProbe {
+    pub worker: JoinHandle<()>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl RpcHealthProbe {
+    /// Asks the probe to stop after the round in progress. Use `join` to wait for it.
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+    }
+
+    pub fn join(self) {
+        self.worker.join().unwrap()
+    }
+}
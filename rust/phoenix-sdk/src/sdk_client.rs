@@ -1,34 +1,769 @@
 use borsh::BorshDeserialize;
-use ellipsis_client::{transaction_utils::parse_transaction, EllipsisClient};
+use ellipsis_client::EllipsisClient;
+use futures::{future::Either, StreamExt};
+use phoenix_sdk_core::phoenix_instruction::KnownPhoenixInstruction;
 use phoenix_sdk_core::sdk_client_core::MarketState;
 pub use phoenix_sdk_core::{
+    evict_seat::{create_evict_seat_instruction, create_evict_seat_instruction_with_program_id},
+    ladder_utils::{LadderSimulator, MarketSimulator, SimulationSummaryInLots, SlippageMode},
     market_event::{Evict, Fill, FillSummary, MarketEventDetails, PhoenixEvent, Place, Reduce},
-    sdk_client_core::{get_decimal_string, MarketMetadata, PhoenixOrder, SDKClientCore},
+    market_fees::{create_collect_fees_instruction, MarketFeesInfo},
+    order_packet_template::{LimitOrderTemplate, PostOnlyOrderTemplate, ReplaceOrderTemplate},
+    sdk_client_core::{
+        get_decimal_string, get_transaction_size, get_v0_transaction_size, MarketMetadata,
+        MetadataConflict, PhoenixOrder, SDKClientCore, Snapshot,
+    },
 };
 use phoenix_types as phoenix;
 use phoenix_types::dispatch::*;
 use phoenix_types::enums::*;
-use phoenix_types::instructions::PhoenixInstruction;
 use phoenix_types::market::*;
 use rand::{rngs::StdRng, SeedableRng};
-use solana_client::{rpc_client::RpcClient, rpc_config::RpcTransactionConfig};
-use solana_program::instruction::Instruction;
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
+use solana_address_lookup_table_program::{
+    instruction::{create_lookup_table, extend_lookup_table},
+    state::{AddressLookupTable, LOOKUP_TABLE_META_SIZE},
+};
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    rpc_client::RpcClient,
+    rpc_config::{
+        RpcAccountInfoConfig, RpcProgramAccountsConfig, RpcSendTransactionConfig,
+        RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig, RpcTransactionConfig,
+    },
+    rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType},
+    rpc_request::{RpcError, RpcResponseErrorData},
+};
+use solana_program::{instruction::Instruction, keccak};
 use solana_sdk::{
+    account::Account,
+    address_lookup_table_account::AddressLookupTableAccount,
     commitment_config::CommitmentConfig,
+    compute_budget::ComputeBudgetInstruction,
+    message::{v0, VersionedMessage},
+    packet::PACKET_DATA_SIZE,
     program_pack::Pack,
     pubkey::Pubkey,
     signature::{Signature, Signer},
     signer::keypair::Keypair,
+    transaction::{Transaction, VersionedTransaction},
+};
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, UiInnerInstructions,
+    UiInstruction, UiLoadedAddresses, UiMessage, UiTransactionEncoding,
+};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    mem::size_of,
+    ops::DerefMut,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use std::{
+    future::Future,
+    ops::Deref,
+    sync::{Mutex, RwLock},
+};
+use tokio::runtime::{Handle, Runtime};
+
+#[cfg(feature = "serde")]
+use phoenix_sdk_core::debug_dump::DebugStateDump;
+#[cfg(feature = "serde")]
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::Path,
+};
+
+#[cfg(feature = "analytics")]
+use crate::analytics::locked_funds::LockedFundsAudit;
+use crate::{
+    error::PhoenixSdkError,
+    instruction_audit::ExpectedAccounts,
+    kill_switch::KillSwitch,
+    market_lookup::MarketLookupCache,
+    multiple_order_packet::{
+        create_place_multiple_post_only_orders_instruction_with_program_id,
+        create_place_multiple_post_only_orders_with_free_funds_instruction_with_program_id,
+        CondensedOrder, MultipleOrderPacket, MAX_POST_ONLY_ORDERS_PER_SIDE,
+    },
+    order_audit::{order_audit_records, OrderAuditLog},
+    rpc_failover::{
+        is_already_processed, is_resendable, is_retryable, send_and_confirm, RpcFailover,
+    },
+    sdk_client_builder::SDKClientBuilder,
 };
-use solana_transaction_status::UiTransactionEncoding;
-use std::{collections::BTreeMap, mem::size_of, ops::DerefMut, sync::Arc};
-use std::{ops::Deref, sync::Mutex};
 
 use crate::orderbook::Orderbook;
 
+/// Per-transaction send options. The default attaches no compute budget instructions and runs
+/// preflight checks, which matches `sign_send_instructions`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TransactionOptions {
+    /// If set, a `SetComputeUnitLimit` instruction is prepended to the transaction
+    pub compute_unit_limit: Option<u32>,
+    /// If set, a `SetComputeUnitPrice` instruction is prepended to the transaction
+    pub priority_fee_micro_lamports: Option<u64>,
+    /// Skip the RPC node's preflight simulation when sending
+    pub skip_preflight: bool,
+    /// The deadline of `SDKClient::try_send_instructions_with_deadline`. A `TransactionExecutor`
+    /// with a deadline sends every batch that way; the other send methods ignore it.
+    pub send_deadline: Option<SendDeadline>,
+}
+
+/// How long an order transaction may take to confirm before its quotes are considered stale,
+/// see `SDKClient::try_send_instructions_with_deadline`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SendDeadline {
+    /// Wall clock time since the transaction was sent
+    Within(Duration),
+    /// Slots since the slot the transaction was sent at
+    WithinSlots(u64),
+}
+
+/// What happened to a transaction sent with a `SendDeadline`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeadlineOutcome {
+    /// The transaction landed before the deadline, in a slot up to the deadline slot
+    Confirmed,
+    /// The transaction missed the deadline and its blockhash expired before it landed, so none
+    /// of its orders rest on the book
+    Expired,
+    /// The transaction missed the deadline and landed before its blockhash expired. Holds the
+    /// signature of the cancel sent for the orders it placed, None if it placed none, or the
+    /// error sending the cancel.
+    LandedLate {
+        protective_cancel: Result<Option<Signature>, PhoenixSdkError>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeadlineSendResult {
+    pub signature: Signature,
+    pub outcome: DeadlineOutcome,
+}
+
+impl DeadlineSendResult {
+    /// The signature of the protective cancel, if one was sent
+    pub fn protective_cancel(&self) -> Option<Signature> {
+        match &self.outcome {
+            DeadlineOutcome::LandedLate {
+                protective_cancel: Ok(signature),
+            } => *signature,
+            _ => None,
+        }
+    }
+
+    pub fn protective_cancel_issued(&self) -> bool {
+        self.protective_cancel().is_some()
+    }
+}
+
+impl TransactionOptions {
+    /// Returns the compute budget instructions to prepend to a transaction
+    pub fn get_compute_budget_ixs(&self) -> Vec<Instruction> {
+        let mut ixs = vec![];
+        if let Some(units) = self.compute_unit_limit {
+            ixs.push(ComputeBudgetInstruction::set_compute_unit_limit(units));
+        }
+        if let Some(micro_lamports) = self.priority_fee_micro_lamports {
+            ixs.push(ComputeBudgetInstruction::set_compute_unit_price(
+                micro_lamports,
+            ));
+        }
+        ixs
+    }
+}
+
+/// How often a sent transaction's status is checked while waiting for it to land or expire
+const SIGNATURE_STATUS_POLL_INTERVAL: Duration = Duration::from_millis(400);
+
+/// How long to wait for a sent transaction to land or for its blockhash to expire before its
+/// fate is treated as unknown. A blockhash is valid for 150 blocks, about a minute.
+const BLOCKHASH_EXPIRY_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// What became of a sent transaction, see `SDKClient::await_signature_outcome`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SignatureOutcome {
+    /// Confirmed in `slot`
+    Landed { slot: u64 },
+    /// Confirmed in a block, but the transaction failed
+    Failed,
+    /// The blockhash expired before the transaction landed, so it can no longer land
+    Expired,
+}
+
+/// Whether a transaction that landed in `slot` missed the deadline of a deadline send. It met
+/// the deadline if it landed up to `deadline_slot`, or if there is none, because it landed
+/// before a wall clock deadline passed.
+fn missed_deadline(slot: u64, deadline_slot: Option<u64>) -> bool {
+    deadline_slot.map_or(false, |deadline_slot| slot > deadline_slot)
+}
+
+/// The ids of the orders `trader` placed in `events`, by market, in event order
+fn placed_order_ids_by_market(
+    events: &[PhoenixEvent],
+    trader: &Pubkey,
+) -> BTreeMap<Pubkey, Vec<FIFOOrderId>> {
+    let mut orders_by_market: BTreeMap<Pubkey, Vec<FIFOOrderId>> = BTreeMap::new();
+    for event in events.iter() {
+        if let MarketEventDetails::Place(Place {
+            order_sequence_number,
+            price_in_ticks,
+            maker,
+            ..
+        }) = event.details
+        {
+            if maker == *trader {
+                orders_by_market
+                    .entry(event.market)
+                    .or_default()
+                    .push(FIFOOrderId {
+                        price_in_ticks,
+                        order_sequence_number,
+                    });
+            }
+        }
+    }
+    orders_by_market
+}
+
+/// How long `get_or_create_market_lookup_table` waits for a new table to become usable
+const LOOKUP_TABLE_ACTIVATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Times `send_instructions_v0_with_options` re-signs and resends a transaction that did not
+/// land, the default `max_retries` of a `TransactionExecutor`
+const V0_SEND_MAX_RETRIES: usize = 3;
+
+/// Times `simulate_order` simulates again when its two simulations ran at different slots
+const SIMULATE_ORDER_MAX_ATTEMPTS: usize = 3;
+
+/// Number of addresses added per extend lookup table instruction, small enough that each
+/// instruction fits in a transaction on its own
+pub const MAX_ADDRESSES_PER_EXTEND: usize = 20;
+
+/// The result of a deprecated `send_*` method. A transaction whose events could not be parsed
+/// still landed, so its signature is returned with no events instead of None.
+fn landed(
+    result: Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError>,
+) -> Option<(Signature, Vec<PhoenixEvent>)> {
+    match result {
+        Err(PhoenixSdkError::EventParseFailed { signature }) => Some((signature, vec![])),
+        result => result.ok(),
+    }
+}
+
+/// The runtime shared by the `_sync` methods, created on first use
+static SYNC_RUNTIME: Mutex<Option<Arc<Runtime>>> = Mutex::new(None);
+
+/// Runs `future` to completion on the shared runtime. Blocking inside a tokio runtime would
+/// panic, so that returns an error pointing to the async method instead.
+fn block_on_sync<F: Future>(future: F) -> anyhow::Result<F::Output> {
+    if Handle::try_current().is_ok() {
+        return Err(anyhow::anyhow!(
+            "_sync methods cannot be called within a tokio runtime, use the async methods"
+        ));
+    }
+    let rt = {
+        let mut shared = SYNC_RUNTIME.lock().unwrap();
+        match shared.as_ref() {
+            Some(rt) => rt.clone(),
+            None => {
+                let rt = Arc::new(Runtime::new()?);
+                *shared = Some(rt.clone());
+                rt
+            }
+        }
+    };
+    Ok(rt.block_on(future))
+}
+
+/// Options for `SDKClient::add_markets`
+#[derive(Clone, Debug)]
+pub struct AddMarketsOptions {
+    /// Maximum number of markets fetched at the same time
+    pub max_concurrency: usize,
+    /// Return an error if any market fails to load
+    pub require_all: bool,
+    /// Set to true from another task or thread to stop loading
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// A market that takes longer than this to load is counted as failed
+    pub timeout: Duration,
+}
+
+impl Default for AddMarketsOptions {
+    fn default() -> Self {
+        AddMarketsOptions {
+            max_concurrency: 8,
+            require_all: false,
+            cancel: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl AddMarketsOptions {
+    fn is_cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .map(|cancel| cancel.load(Ordering::Relaxed))
+            .unwrap_or(false)
+    }
+}
+
+/// Progress of `SDKClient::add_markets`
+#[derive(Debug)]
+pub struct MarketsLoaded {
+    /// Number of markets that finished loading, successfully or not
+    pub done: usize,
+    pub total: usize,
+    pub failed: Vec<(Pubkey, anyhow::Error)>,
+}
+
+/// Maximum number of accounts the RPC returns per `getMultipleAccounts` request
+pub const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Times a `getMultipleAccounts` request is retried after a retryable error, see `is_retryable`
+const FETCH_ACCOUNTS_MAX_RETRIES: usize = 2;
+const FETCH_ACCOUNTS_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Options for the program account scans, `SDKClient::scan_market_headers` and
+/// `SDKClient::scan_seat_traders`, and the follow-up `SDKClient::fetch_accounts_chunked`.
+#[derive(Clone, Debug)]
+pub struct ScanOptions {
+    /// Accounts per `getMultipleAccounts` request, at most `MAX_MULTIPLE_ACCOUNTS`
+    pub chunk_size: usize,
+    /// Minimum delay between two requests, to stay under the RPC provider's rate limit
+    pub request_interval: Duration,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            chunk_size: MAX_MULTIPLE_ACCOUNTS,
+            request_interval: Duration::from_millis(0),
+        }
+    }
+}
+
+/// Progress of a scan, passed to the scan's progress callback after each request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScanProgress {
+    pub fetched: usize,
+    pub total: usize,
+}
+
+/// The chunking of `SDKClient::fetch_accounts_chunked`: `keys` are passed to `fetch_chunk`
+/// `options.chunk_size` at a time, clamped to `MAX_MULTIPLE_ACCOUNTS`, and the accounts it
+/// returns are paired with their keys in the order of `keys`.
+async fn fetch_in_chunks<'a, F, Fut>(
+    keys: &'a [Pubkey],
+    options: &ScanOptions,
+    mut fetch_chunk: F,
+    on_progress: &mut impl FnMut(ScanProgress),
+) -> anyhow::Result<Vec<(Pubkey, Option<Account>)>>
+where
+    F: FnMut(&'a [Pubkey]) -> Fut,
+    Fut: Future<Output = anyhow::Result<Vec<Option<Account>>>>,
+{
+    let chunk_size = options.chunk_size.clamp(1, MAX_MULTIPLE_ACCOUNTS);
+    let mut accounts = Vec::with_capacity(keys.len());
+    for (i, chunk) in keys.chunks(chunk_size).enumerate() {
+        if i > 0 {
+            tokio::time::sleep(options.request_interval).await;
+        }
+        let fetched = fetch_chunk(chunk).await?;
+        accounts.extend(chunk.iter().copied().zip(fetched));
+        on_progress(ScanProgress {
+            fetched: accounts.len(),
+            total: keys.len(),
+        });
+    }
+    Ok(accounts)
+}
+
+/// Returns the 8 byte discriminant that prefixes Phoenix accounts of type `type_name`, e.g.
+/// `phoenix::program::accounts::MarketHeader`.
+pub fn get_account_discriminant(program_id: &Pubkey, type_name: &str) -> u64 {
+    let hash = keccak::hashv(&[program_id.as_ref(), type_name.as_bytes()]);
+    u64::from_le_bytes(hash.as_ref()[..8].try_into().unwrap())
+}
+
+/// Eviction exposure of a seat, see `SDKClient::get_seat_eviction_risk`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EvictionRisk {
+    /// True if every seat in the market's trader state is taken, in which case a new trader
+    /// can only register by evicting an evictable seat
+    pub trader_state_full: bool,
+    pub num_seats: u64,
+    pub num_registered_traders: u64,
+    /// Seats with no locked base or quote lots
+    pub num_evictable_seats: u64,
+    /// False if the trader has no seat on the market
+    pub is_registered: bool,
+    /// True if the trader's seat has no locked lots. The seat is only at risk if the trader
+    /// state is also full.
+    pub is_evictable: bool,
+    pub base_lots_locked: u64,
+    pub quote_lots_locked: u64,
+}
+
+/// A trader's seat balances on a market in base and quote units, see
+/// `SDKClient::get_trader_balances_in_units`. Locked funds back resting orders; free funds can
+/// be withdrawn or used by orders placed with `use_only_deposited_funds`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TraderBalances {
+    pub base_units_free: f64,
+    pub base_units_locked: f64,
+    pub quote_units_free: f64,
+    pub quote_units_locked: f64,
+}
+
+impl TraderBalances {
+    #[allow(clippy::useless_conversion)]
+    pub fn from_trader_state(metadata: &MarketMetadata, state: &TraderState) -> Self {
+        let base_units = |lots: u64| {
+            metadata.base_lots_to_base_amount(lots) as f64 / metadata.base_multiplier as f64
+        };
+        let quote_units = |lots: u64| {
+            metadata.quote_lots_to_quote_amount(lots) as f64 / metadata.quote_multiplier as f64
+        };
+        TraderBalances {
+            base_units_free: base_units(state.base_lots_free.into()),
+            base_units_locked: base_units(state.base_lots_locked.into()),
+            quote_units_free: quote_units(state.quote_lots_free.into()),
+            quote_units_locked: quote_units(state.quote_lots_locked.into()),
+        }
+    }
+}
+
+/// A trader's wallet and seat balances on one market, see
+/// `SDKClient::get_trader_market_balances`. Wallet amounts are in token atoms; a missing
+/// associated token account counts as a zero balance.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TraderMarketBalances {
+    pub base_wallet_amount: u64,
+    pub quote_wallet_amount: u64,
+    pub base_wallet_units: f64,
+    pub quote_wallet_units: f64,
+    /// False if the trader's base associated token account does not exist
+    pub base_token_account_exists: bool,
+    /// False if the trader's quote associated token account does not exist
+    pub quote_token_account_exists: bool,
+    /// False if the trader has no seat on the market, in which case the seat balances are zero
+    pub is_registered: bool,
+    pub base_lots_free: u64,
+    pub base_lots_locked: u64,
+    pub quote_lots_free: u64,
+    pub quote_lots_locked: u64,
+    /// The seat balances in units
+    pub seat: TraderBalances,
+}
+
+/// Size of a Phoenix seat account: discriminant, market, trader, approval status, and padding
+const SEAT_ACCOUNT_SIZE: usize = 128;
+
+/// An account that trading on a market requires, see `OnboardingCostItem`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnboardingAccount {
+    BaseTokenAccount,
+    QuoteTokenAccount,
+    Seat,
+    /// The address lookup table of `get_or_create_market_lookup_table`, only needed for the
+    /// v0 batch order helpers
+    LookupTable,
+}
+
+/// The rent of one account that does not exist yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OnboardingCostItem {
+    pub account: OnboardingAccount,
+    /// None for the lookup table, whose address depends on the slot it is created in
+    pub address: Option<Pubkey>,
+    pub size: usize,
+    pub lamports: u64,
+    /// True if the rent can be recovered by closing the account: token accounts can be
+    /// closed and lookup tables deactivated and closed, seats cannot be closed by the trader
+    pub is_refundable: bool,
+}
+
+/// The rent-exempt balances needed to start trading on a market, see
+/// `SDKClient::estimate_onboarding_cost`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OnboardingCost {
+    pub market: Pubkey,
+    pub trader: Pubkey,
+    /// The accounts that are missing, in the order they are created
+    pub items: Vec<OnboardingCostItem>,
+    pub total_lamports: u64,
+}
+
+impl OnboardingCost {
+    /// The part of the total that can be recovered by closing the accounts later
+    pub fn refundable_lamports(&self) -> u64 {
+        self.items
+            .iter()
+            .filter(|item| item.is_refundable)
+            .map(|item| item.lamports)
+            .sum()
+    }
+}
+
+/// The state of `trader`'s seat in a market account, or None if the trader is not registered
+fn registered_trader_state(
+    market_account_data: &mut [u8],
+    market_key: &Pubkey,
+    trader: &Pubkey,
+) -> anyhow::Result<Option<TraderState>> {
+    if market_account_data.len() < size_of::<MarketHeader>() {
+        return Err(anyhow::anyhow!(
+            "Market {} account data is too short",
+            market_key
+        ));
+    }
+    let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
+    let header = MarketHeader::try_from_slice(header_bytes)?;
+    let market = load_with_dispatch_mut(&header.market_size_params, bytes)
+        .ok_or_else(|| anyhow::anyhow!("Failed to load market {}", market_key))?
+        .inner;
+    Ok(market
+        .get_registered_traders()
+        .iter()
+        .find(|(k, _)| **k == *trader)
+        .map(|(_, state)| *state))
+}
+
+/// The simulated outcome of swapping `amount_in` atoms of one mint for another on a single
+/// Phoenix market. The expected output is net of the market's taker fee: a buy pays the fee
+/// out of `amount_in`, and a sell pays it out of the proceeds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapQuote {
+    pub market: Pubkey,
+    /// `Side::Bid` if the swap buys the base token, `Side::Ask` if it sells the base token
+    pub side: Side,
+    /// Input amount, in atoms of the input mint
+    pub amount_in: u64,
+    /// Output after the market's taker fee, in atoms of the output mint
+    pub expected_out_atoms: u64,
+    pub expected_out_units: f64,
+    /// The market's taker fee, charged in the quote token on top of a buy and out of the
+    /// proceeds of a sell
+    pub taker_fee_bps: u64,
+    /// How much worse the average execution price, before fees, is than the top of book, in
+    /// basis points
+    pub price_impact_bps: f64,
+    /// Accounts referenced by the IOC instruction that executes this swap
+    pub route_accounts: Vec<Pubkey>,
+    /// True if the book does not have enough depth to absorb the whole input
+    pub is_partial_fill: bool,
+}
+
+/// The quotes for a swap across all loaded markets, best first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwapRoutes {
+    pub best: SwapQuote,
+    pub runner_ups: Vec<SwapQuote>,
+    /// Markets for the pair that could not be quoted, with the error
+    pub failed: Vec<(Pubkey, String)>,
+}
+
+/// The outcome of simulating an order for the client's trader. Fills are derived from the
+/// change in the trader's token accounts and seat balances, and fees from the change in the
+/// market's uncollected fees.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SimulatedFillSummary {
+    /// `Side::Bid` if the order would buy base, `Side::Ask` if it would sell base, None if it
+    /// would not fill
+    pub side: Option<Side>,
+    pub base_lots_filled: u64,
+    /// Quote lots exchanged for the base lots, excluding fees
+    pub quote_lots_filled: u64,
+    pub fee_in_quote_lots: u64,
+    pub base_units_filled: f64,
+    pub quote_units_filled: f64,
+    pub fee_in_quote_units: f64,
+    /// Program logs of the simulation
+    pub logs: Vec<String>,
+}
+
+/// The result of `SDKClient::send_replace_order`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplacedOrder {
+    pub signature: Signature,
+    /// The `Reduce` event of the cancelled order, empty if it had already left the book
+    pub cancels: Vec<PhoenixEvent>,
+    /// The `Place` event of the new order, empty if it was filled or cancelled on arrival
+    pub places: Vec<PhoenixEvent>,
+    /// The fills of the new order if it crossed the book
+    pub fills: Vec<PhoenixEvent>,
+}
+
+/// The result of `SDKClient::try_send_post_only_from_template`
+#[derive(Clone, Debug, PartialEq)]
+pub struct PostOnlyPlacement {
+    pub signature: Signature,
+    /// The template's price, in ticks
+    pub requested_price_in_ticks: u64,
+    /// The price the order rests at, None if no order was placed
+    pub price_in_ticks: Option<u64>,
+    /// Whether the program slid the order to a price that does not cross the book
+    pub was_amended: bool,
+    /// `price_in_ticks` in quote units per base unit
+    pub price_as_float: Option<f64>,
+    /// The `Place` events of the transaction
+    pub events: Vec<PhoenixEvent>,
+}
+
+impl SimulatedFillSummary {
+    pub fn would_fill(&self) -> bool {
+        self.base_lots_filled > 0
+    }
+}
+
+/// Total base lots, total quote lots, and uncollected fees in quote lots of a market account,
+/// where the totals are the trader's free and locked seat balances.
+#[allow(clippy::useless_conversion)]
+fn get_trader_lots_and_fees(
+    market_account_data: &mut [u8],
+    trader: &Pubkey,
+) -> Option<(u64, u64, u64)> {
+    let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
+    let header = MarketHeader::try_from_slice(header_bytes).ok()?;
+    let market = load_with_dispatch_mut(&header.market_size_params, bytes)?.inner;
+    let uncollected_fees: u64 = market.get_uncollected_fee_amount().into();
+    let (base_lots, quote_lots) = market
+        .get_registered_traders()
+        .iter()
+        .find(|(k, _)| **k == *trader)
+        .map(|(_, state)| {
+            let base_lots_free: u64 = state.base_lots_free.into();
+            let base_lots_locked: u64 = state.base_lots_locked.into();
+            let quote_lots_free: u64 = state.quote_lots_free.into();
+            let quote_lots_locked: u64 = state.quote_lots_locked.into();
+            (
+                base_lots_free + base_lots_locked,
+                quote_lots_free + quote_lots_locked,
+            )
+        })
+        .unwrap_or_default();
+    Some((base_lots, quote_lots, uncollected_fees))
+}
+
+fn get_token_amount(account_data: Option<&[u8]>) -> u64 {
+    account_data
+        .and_then(|data| spl_token::state::Account::unpack(data).ok())
+        .map(|account| account.amount)
+        .unwrap_or(0)
+}
+
+/// Returns the event payload if `data` is a Phoenix Log instruction
+fn get_log_payload(data: &[u8]) -> Option<&[u8]> {
+    let (tag, payload) = data.split_first()?;
+    match KnownPhoenixInstruction::from(*tag) {
+        KnownPhoenixInstruction::Log => Some(payload),
+        _ => None,
+    }
+}
+
+/// Collects the payloads of the Phoenix Log instructions of a JSON encoded transaction.
+///
+/// Instruction program ids are indices into the transaction's full account list: the static
+/// account keys followed by the writable and then the readonly addresses loaded from address
+/// lookup tables. The list is resolved before matching against `program_id`, so v0 transactions
+/// whose Phoenix accounts come from a lookup table parse the same as legacy transactions.
+pub fn get_phoenix_log_payloads(
+    raw_tx: &EncodedConfirmedTransactionWithStatusMeta,
+    program_id: &Pubkey,
+) -> Option<Vec<Vec<u8>>> {
+    let meta = raw_tx.transaction.meta.as_ref()?;
+    if meta.err.is_some() {
+        return None;
+    }
+    let mut account_keys = match &raw_tx.transaction.transaction {
+        EncodedTransaction::Json(ui_tx) => match &ui_tx.message {
+            UiMessage::Raw(message) => message.account_keys.clone(),
+            UiMessage::Parsed(message) => message
+                .account_keys
+                .iter()
+                .map(|account| account.pubkey.clone())
+                .collect(),
+        },
+        _ => return None,
+    };
+    let loaded_addresses: Option<UiLoadedAddresses> = meta.loaded_addresses.clone().into();
+    if let Some(loaded_addresses) = loaded_addresses {
+        account_keys.extend(loaded_addresses.writable);
+        account_keys.extend(loaded_addresses.readonly);
+    }
+
+    let program_id = program_id.to_string();
+    let inner_instructions: Option<Vec<UiInnerInstructions>> =
+        meta.inner_instructions.clone().into();
+    let mut event_list = vec![];
+    for inner_ixs in inner_instructions.unwrap_or_default().iter() {
+        for inner_ix in inner_ixs.instructions.iter() {
+            let (program_id_index, data) = match inner_ix {
+                UiInstruction::Compiled(ix) => (ix.program_id_index, &ix.data),
+                _ => continue,
+            };
+            if account_keys.get(program_id_index as usize) != Some(&program_id) {
+                continue;
+            }
+            let data = bs58::decode(data).into_vec().ok()?;
+            if let Some(payload) = get_log_payload(&data) {
+                event_list.push(payload.to_vec());
+            }
+        }
+    }
+    Some(event_list)
+}
+
+/// Maps an error from sending a transaction to a `PhoenixSdkError`, keeping the preflight
+/// simulation logs if the RPC node returned them.
+pub(crate) fn classify_send_error(error: &anyhow::Error) -> PhoenixSdkError {
+    if let Some(e) = error.downcast_ref::<PhoenixSdkError>() {
+        return e.clone();
+    }
+    match error.downcast_ref::<ClientError>().map(|e| e.kind()) {
+        Some(ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            data: RpcResponseErrorData::SendTransactionPreflightFailure(result),
+            ..
+        })) => PhoenixSdkError::TransactionFailed {
+            logs: result.logs.clone().unwrap_or_default(),
+        },
+        Some(ClientErrorKind::TransactionError(_)) => {
+            PhoenixSdkError::TransactionFailed { logs: vec![] }
+        }
+        _ => PhoenixSdkError::RpcError(error.to_string()),
+    }
+}
+
 pub struct SDKClient {
     pub client: EllipsisClient,
     pub core: SDKClientCore,
+    /// Checked before sending any instruction, see `KillSwitch`
+    pub kill_switch: KillSwitch,
+    /// Secondary endpoints used when the primary endpoint fails with a retryable error
+    pub failover: RpcFailover,
+    /// If set, the send methods run `audit_phoenix_instructions` on the instructions they are
+    /// given and refuse to send any that reference unexpected accounts. Off by default.
+    pub audit_instructions: bool,
+    /// Negative cache and fetch coalescing for the markets loaded by the event parsers, see
+    /// `MarketLookupCache`
+    pub market_lookup: MarketLookupCache,
+    /// Address lookup table used by the batch order helpers of each market, see
+    /// `set_market_lookup_table`
+    lookup_tables: Mutex<HashMap<Pubkey, AddressLookupTableAccount>>,
+    /// Set by the read-only constructors: the payer is a throwaway keypair that must not sign
+    read_only: bool,
+    /// Component dumps collected by `debug_dump_to_file`, see `DebugStateDump`
+    #[cfg(feature = "serde")]
+    pub debug_dump: DebugStateDump,
 }
 
 impl Deref for SDKClient {
@@ -60,41 +795,92 @@ impl SDKClient {
         client: EllipsisClient,
         program_id: &Pubkey,
     ) -> Self {
-        let market_metadata = Self::get_market_metadata(&client, market_key).await;
-        let mut markets = BTreeMap::new();
+        SDKClientBuilder::new()
+            .ellipsis_client(client)
+            .program_id(*program_id)
+            .markets(&[*market_key])
+            .build()
+            .await
+            .unwrap()
+    }
 
-        markets.insert(*market_key, market_metadata);
+    /// A client without markets. The trader is the payer, or `Pubkey::default()` if
+    /// `read_only` is set.
+    pub(crate) fn from_parts(client: EllipsisClient, program_id: Pubkey, read_only: bool) -> Self {
         let core = SDKClientCore {
-            markets,
+            markets: RwLock::new(BTreeMap::new()),
             rng: Arc::new(Mutex::new(StdRng::from_entropy())),
-            active_market_key: *market_key,
-            trader: client.payer.pubkey(),
-            program_id: *program_id,
+            active_market_key: Pubkey::default(),
+            trader: if read_only {
+                Pubkey::default()
+            } else {
+                client.payer.pubkey()
+            },
+            program_id,
+            order_audit_log: None,
         };
-        SDKClient { client, core }
+        SDKClient {
+            client,
+            core,
+            kill_switch: KillSwitch::default(),
+            failover: RpcFailover::default(),
+            audit_instructions: false,
+            market_lookup: MarketLookupCache::default(),
+            lookup_tables: Mutex::new(HashMap::new()),
+            read_only,
+            #[cfg(feature = "serde")]
+            debug_dump: DebugStateDump::default(),
+        }
+    }
+
+    /// A client for reading and parsing market data that holds no key material. No market is
+    /// loaded: use `add_market` and `change_active_market` before calling methods that act on
+    /// the active market, which otherwise return `MarketNotLoaded`. The trader is
+    /// `Pubkey::default()`, and the order instruction builders and every method that signs or
+    /// sends a transaction return `NoSignerConfigured`.
+    pub fn new_read_only(url: &str) -> anyhow::Result<Self> {
+        Self::from_rpc_read_only(RpcClient::new_with_commitment(
+            url,
+            CommitmentConfig::confirmed(),
+        ))
+    }
+
+    /// Same as `new_read_only`, with an existing `RpcClient`.
+    pub fn from_rpc_read_only(rpc: RpcClient) -> anyhow::Result<Self> {
+        // EllipsisClient requires a payer. This one is generated on the spot and never signs.
+        let client = EllipsisClient::from_rpc(rpc, &Keypair::new())?;
+        Ok(Self::from_parts(client, phoenix::id(), true))
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
     }
 
-    pub fn new_from_ellipsis_client_sync(market_key: &Pubkey, client: EllipsisClient) -> Self {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(Self::new_from_ellipsis_client(market_key, client))
+    pub fn new_from_ellipsis_client_sync(
+        market_key: &Pubkey,
+        client: EllipsisClient,
+    ) -> anyhow::Result<Self> {
+        block_on_sync(Self::new_from_ellipsis_client(market_key, client))
     }
 
     pub fn new_from_ellipsis_client_with_custom_program_id_sync(
         market_key: &Pubkey,
         client: EllipsisClient,
         program_id: &Pubkey,
-    ) -> Self {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(Self::new_from_ellipsis_client_with_custom_program_id(
+    ) -> anyhow::Result<Self> {
+        block_on_sync(Self::new_from_ellipsis_client_with_custom_program_id(
             market_key, client, program_id,
         ))
     }
 
     pub async fn new(market_key: &Pubkey, payer: &Keypair, url: &str) -> Self {
-        let rpc = RpcClient::new_with_commitment(url, CommitmentConfig::confirmed());
-        let client = EllipsisClient::from_rpc(rpc, payer).unwrap(); //fix error handling instead of panic
-
-        SDKClient::new_from_ellipsis_client(market_key, client).await
+        SDKClientBuilder::new()
+            .url(url)
+            .payer(payer)
+            .markets(&[*market_key])
+            .build()
+            .await
+            .unwrap()
     }
 
     pub async fn new_with_custom_program_id(
@@ -103,16 +889,18 @@ impl SDKClient {
         url: &str,
         program_id: &Pubkey,
     ) -> Self {
-        let rpc = RpcClient::new_with_commitment(url, CommitmentConfig::confirmed());
-        let client = EllipsisClient::from_rpc(rpc, payer).unwrap(); //fix error handling instead of panic
-
-        SDKClient::new_from_ellipsis_client_with_custom_program_id(market_key, client, program_id)
+        SDKClientBuilder::new()
+            .url(url)
+            .payer(payer)
+            .program_id(*program_id)
+            .markets(&[*market_key])
+            .build()
             .await
+            .unwrap()
     }
 
-    pub fn new_sync(market_key: &Pubkey, payer: &Keypair, url: &str) -> Self {
-        let rt = tokio::runtime::Runtime::new().unwrap(); //fix error handling instead of panic
-        rt.block_on(Self::new(market_key, payer, url))
+    pub fn new_sync(market_key: &Pubkey, payer: &Keypair, url: &str) -> anyhow::Result<Self> {
+        block_on_sync(Self::new(market_key, payer, url))
     }
 
     pub fn new_with_custom_program_id_sync(
@@ -120,9 +908,8 @@ impl SDKClient {
         payer: &Keypair,
         url: &str,
         program_id: &Pubkey,
-    ) -> Self {
-        let rt = tokio::runtime::Runtime::new().unwrap(); //fix error handling instead of panic
-        rt.block_on(Self::new_with_custom_program_id(
+    ) -> anyhow::Result<Self> {
+        block_on_sync(Self::new_with_custom_program_id(
             market_key, payer, url, program_id,
         ))
     }
@@ -130,436 +917,3882 @@ impl SDKClient {
     pub fn set_payer(&mut self, payer: Keypair) {
         self.trader = payer.pubkey();
         self.client.payer = payer;
+        self.read_only = false;
     }
 
     pub fn get_trader(&self) -> Pubkey {
         self.trader
     }
 
-    pub fn change_active_market(&mut self, market: &Pubkey) -> anyhow::Result<()> {
-        if self.markets.get(market).is_some() {
-            self.active_market_key = *market;
-            Ok(())
+    pub fn change_active_market(&mut self, market: &Pubkey) -> Result<(), PhoenixSdkError> {
+        self.core.change_active_market(market)
+    }
+
+    /// Replaces the client's kill switch, e.g. with a handle shared across clients
+    pub fn set_kill_switch(&mut self, kill_switch: KillSwitch) {
+        self.kill_switch = kill_switch;
+    }
+
+    /// Records every order and cancel instruction built by this client, and every Phoenix
+    /// instruction it sends, to `order_audit_log`
+    pub fn set_order_audit_log(&mut self, order_audit_log: Arc<dyn OrderAuditLog>) {
+        self.core.order_audit_log = Some(order_audit_log);
+    }
+
+    /// Runs the instruction audit on `instructions` if `audit_instructions` is set
+    fn audit_send_instructions(&self, instructions: &[Instruction]) -> Result<(), PhoenixSdkError> {
+        if self.audit_instructions {
+            self.audit_phoenix_instructions(instructions)
         } else {
-            Err(anyhow::Error::msg("Market not found"))
+            Ok(())
+        }
+    }
+
+    /// Passes a `Sending` record of each Phoenix instruction in `instructions` to the order
+    /// audit log, if one is set. Called by every send helper before sending; call it yourself
+    /// for instructions you send without the client. The builders record a `Built` record
+    /// themselves. Failures are logged and otherwise ignored.
+    pub fn record_order_instructions(&self, instructions: &[Instruction]) {
+        let order_audit_log = match self.order_audit_log.as_ref() {
+            Some(order_audit_log) => order_audit_log,
+            None => return,
+        };
+        for record in order_audit_records(instructions, &self.program_id) {
+            if let Err(e) = order_audit_log.record(&record) {
+                println!("Failed to record order audit record {:?}: {}", record, e);
+            }
+        }
+    }
+
+    /// Sets the market used by the instruction builders and send methods that do not take a
+    /// market key. Equivalent to `change_active_market`.
+    pub fn set_default_market(&mut self, market: Pubkey) -> Result<(), PhoenixSdkError> {
+        self.change_active_market(&market)
+    }
+
+    /// Returns a handle whose methods act on `market_key` regardless of the active market. The
+    /// handle only stores the key, so it picks up refreshed metadata on every call.
+    pub fn market(&self, market_key: Pubkey) -> DefaultMarketClient<'_> {
+        DefaultMarketClient {
+            sdk: self,
+            market_key,
         }
     }
 
-    pub async fn add_market(&mut self, market_key: &Pubkey) -> anyhow::Result<()> {
-        let market_metadata = Self::get_market_metadata(&self.client, market_key).await;
+    /// A copy of the core with `market_key` as the active market, for building instructions
+    /// against a market other than the active one.
+    fn get_core_for_market(&self, market_key: &Pubkey) -> Result<SDKClientCore, PhoenixSdkError> {
+        let metadata = self.market_metadata(market_key)?;
+        Ok(SDKClientCore {
+            markets: RwLock::new(BTreeMap::from([(*market_key, metadata)])),
+            rng: self.rng.clone(),
+            active_market_key: *market_key,
+            trader: self.trader,
+            program_id: self.program_id,
+            order_audit_log: self.order_audit_log.clone(),
+        })
+    }
+
+    /// Fetches and caches the metadata of a market. If the market is already cached and the
+    /// fetched metadata differs, the cache is left untouched and a `MetadataConflict` error is
+    /// returned. Use `force_refresh_market` to overwrite the cached entry.
+    pub async fn add_market(&self, market_key: &Pubkey) -> anyhow::Result<()> {
+        let market_metadata = Self::try_get_market_metadata(&self.client, market_key).await?;
 
-        self.markets.insert(*market_key, market_metadata);
+        let mut markets = self.markets.write().unwrap();
+        if let Some(cached) = markets.get(market_key) {
+            if *cached != market_metadata {
+                return Err(MetadataConflict {
+                    market: *market_key,
+                    cached: *cached,
+                    fetched: market_metadata,
+                }
+                .into());
+            }
+        }
+        markets.insert(*market_key, market_metadata);
 
         Ok(())
     }
 
-    pub async fn get_market_ladder(&self, levels: u64) -> Ladder {
-        let mut market_account_data = (self.client.get_account_data(&self.active_market_key))
+    /// Adds many markets, fetching up to `options.max_concurrency` of them at a time. Markets
+    /// that fail to load (or whose metadata conflicts with the cache, see `add_market`) are
+    /// collected in `MarketsLoaded::failed` instead of aborting, unless `options.require_all`
+    /// is set. `on_progress` is called after each market finishes. If `options.cancel` is set
+    /// to true, loading stops and an error is returned; markets loaded so far stay cached.
+    pub async fn add_markets(
+        &self,
+        market_keys: &[Pubkey],
+        options: &AddMarketsOptions,
+        mut on_progress: impl FnMut(&MarketsLoaded),
+    ) -> anyhow::Result<MarketsLoaded> {
+        let client = &self.client;
+        let markets = &self.core.markets;
+        let mut progress = MarketsLoaded {
+            done: 0,
+            total: market_keys.len(),
+            failed: vec![],
+        };
+        let mut fetches = futures::stream::iter(market_keys.iter().map(|market_key| async move {
+            let result = tokio::time::timeout(
+                options.timeout,
+                Self::try_get_market_metadata(client, market_key),
+            )
             .await
-            .unwrap();
-        let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
-        let header = MarketHeader::try_from_slice(header_bytes).unwrap();
-        let market = load_with_dispatch_mut(&header.market_size_params, bytes)
-            .unwrap()
-            .inner;
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("Timed out loading market {}", market_key)));
+            (*market_key, result)
+        }))
+        .buffer_unordered(options.max_concurrency.max(1));
+
+        while let Some((market_key, result)) = fetches.next().await {
+            if options.is_cancelled() {
+                return Err(anyhow::anyhow!(
+                    "Loading markets was cancelled after {} of {} markets",
+                    progress.done,
+                    progress.total
+                ));
+            }
+            match result {
+                Ok(fetched) => {
+                    let mut markets = markets.write().unwrap();
+                    match markets.get(&market_key) {
+                        Some(cached) if *cached != fetched => progress.failed.push((
+                            market_key,
+                            MetadataConflict {
+                                market: market_key,
+                                cached: *cached,
+                                fetched,
+                            }
+                            .into(),
+                        )),
+                        _ => {
+                            markets.insert(market_key, fetched);
+                        }
+                    }
+                }
+                Err(e) => progress.failed.push((market_key, e)),
+            }
+            progress.done += 1;
+            on_progress(&progress);
+        }
 
-        market.get_ladder(levels)
+        if options.require_all && !progress.failed.is_empty() {
+            return Err(anyhow::anyhow!(
+                "{} of {} markets failed to load: {}",
+                progress.failed.len(),
+                progress.total,
+                progress
+                    .failed
+                    .iter()
+                    .map(|(market_key, e)| format!("{}: {}", market_key, e))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        Ok(progress)
     }
 
-    pub fn get_market_ladder_sync(&self, levels: u64) -> Ladder {
-        let rt = tokio::runtime::Runtime::new().unwrap(); //fix error handling instead of panic
-        rt.block_on(self.get_market_ladder(levels))
+    /// Runs `getProgramAccounts` for the Phoenix accounts with the discriminant of
+    /// `type_name`, further filtered by `filters`, returning only `data_slice` of each account.
+    #[allow(deprecated)]
+    fn get_program_accounts_sliced(
+        &self,
+        type_name: &str,
+        filters: Vec<RpcFilterType>,
+        data_slice: UiDataSliceConfig,
+    ) -> anyhow::Result<Vec<(Pubkey, Account)>> {
+        let discriminant = get_account_discriminant(&self.program_id, type_name);
+        let mut all_filters = vec![RpcFilterType::Memcmp(Memcmp {
+            offset: 0,
+            bytes: MemcmpEncodedBytes::Bytes(discriminant.to_le_bytes().to_vec()),
+            encoding: None,
+        })];
+        all_filters.extend(filters);
+        Ok(self.client.get_program_accounts_with_config(
+            &self.program_id,
+            RpcProgramAccountsConfig {
+                filters: Some(all_filters),
+                account_config: RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    data_slice: Some(data_slice),
+                    commitment: Some(self.client.commitment()),
+                    ..RpcAccountInfoConfig::default()
+                },
+                ..RpcProgramAccountsConfig::default()
+            },
+        )?)
     }
 
-    pub async fn get_market_orderbook(&self) -> Orderbook<FIFOOrderId, PhoenixOrder> {
-        let mut market_account_data = (self.client.get_account_data(&self.active_market_key))
-            .await
-            .unwrap_or_default();
-        let default = Orderbook::<FIFOOrderId, PhoenixOrder> {
-            size_mult: 0.0,
-            price_mult: 0.0,
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
-        };
-        if market_account_data.is_empty() {
-            return default;
+    /// The endpoint name calls through `client` are recorded under in `failover.health`
+    fn primary_endpoint(&self) -> String {
+        if self.client.is_bank_client {
+            "bank client".to_string()
+        } else {
+            self.client.url()
         }
-        let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
-        MarketHeader::try_from_slice(header_bytes)
-            .ok()
-            .map(|header| {
-                load_with_dispatch_mut(&header.market_size_params, bytes)
-                    .map(|market| {
-                        Orderbook::from_market(
-                            market.inner,
-                            self.base_lots_to_base_units_multiplier(),
-                            self.ticks_to_float_price_multiplier(),
-                        )
-                    })
-                    .unwrap_or_else(|| default.clone())
-            })
-            .unwrap_or(default)
     }
 
-    pub fn get_market_orderbook_sync(&self) -> Orderbook<FIFOOrderId, PhoenixOrder> {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(self.get_market_orderbook())
+    /// One `getMultipleAccounts` request for at most `MAX_MULTIPLE_ACCOUNTS` keys, retried
+    /// while the error is retryable. Each attempt is recorded in `failover.health`.
+    async fn fetch_accounts_chunk(&self, chunk: &[Pubkey]) -> anyhow::Result<Vec<Option<Account>>> {
+        let endpoint = self.primary_endpoint();
+        let mut attempt = 0;
+        loop {
+            let result = self.failover.health.observe(&endpoint, || {
+                Ok(self.client.get_multiple_accounts(chunk)?)
+            });
+            match result {
+                Err(e) if attempt < FETCH_ACCOUNTS_MAX_RETRIES && is_retryable(&e) => {
+                    attempt += 1;
+                    tokio::time::sleep(FETCH_ACCOUNTS_RETRY_DELAY).await;
+                }
+                result => return result,
+            }
+        }
     }
 
-    pub async fn get_traders(&self) -> BTreeMap<Pubkey, TraderState> {
-        let mut market_account_data = (self.client.get_account_data(&self.active_market_key))
-            .await
-            .unwrap();
-        let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
-        let header = MarketHeader::try_from_slice(header_bytes).unwrap();
-        let market = load_with_dispatch_mut(&header.market_size_params, bytes)
-            .unwrap()
-            .inner;
+    /// Fetches `keys` with `getMultipleAccounts`, split into as many requests as the RPC's
+    /// `MAX_MULTIPLE_ACCOUNTS` limit requires. The result is in the order of `keys`, with None
+    /// for accounts that do not exist. Each request is retried on retryable errors; the call
+    /// fails if any request still fails.
+    pub async fn fetch_accounts(&self, keys: &[Pubkey]) -> anyhow::Result<Vec<Option<Account>>> {
+        Ok(self
+            .fetch_accounts_chunked(keys, &ScanOptions::default(), |_| {})
+            .await?
+            .into_iter()
+            .map(|(_, account)| account)
+            .collect())
+    }
 
-        market
-            .get_registered_traders()
+    /// Like `fetch_accounts`, but fails with the list of missing keys if any account does not
+    /// exist.
+    pub async fn fetch_accounts_strict(&self, keys: &[Pubkey]) -> anyhow::Result<Vec<Account>> {
+        let accounts = self.fetch_accounts(keys).await?;
+        let missing = keys
             .iter()
-            .map(|(k, v)| (*k, *v))
-            .collect()
+            .zip(accounts.iter())
+            .filter(|(_, account)| account.is_none())
+            .map(|(key, _)| key.to_string())
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(anyhow::anyhow!(
+                "{} accounts not found: {}",
+                missing.len(),
+                missing.join(", ")
+            ));
+        }
+        Ok(accounts.into_iter().flatten().collect())
     }
 
-    pub fn get_traders_sync(&self) -> BTreeMap<Pubkey, TraderState> {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(self.get_traders())
+    /// Fetches `keys` with `getMultipleAccounts`, `options.chunk_size` accounts per request
+    /// and at most one request per `options.request_interval`. Accounts that do not exist are
+    /// returned as None. Requests are retried like those of `fetch_accounts`. `on_progress` is
+    /// called after each request.
+    pub async fn fetch_accounts_chunked(
+        &self,
+        keys: &[Pubkey],
+        options: &ScanOptions,
+        mut on_progress: impl FnMut(ScanProgress),
+    ) -> anyhow::Result<Vec<(Pubkey, Option<Account>)>> {
+        fetch_in_chunks(
+            keys,
+            options,
+            |chunk| self.fetch_accounts_chunk(chunk),
+            &mut on_progress,
+        )
+        .await
     }
 
-    pub async fn get_market_state(&self) -> MarketState {
-        let mut market_account_data = (self.client.get_account_data(&self.active_market_key))
-            .await
-            .unwrap();
-        let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
-        let header = MarketHeader::try_from_slice(header_bytes).unwrap();
-        let market = load_with_dispatch_mut(&header.market_size_params, bytes)
-            .unwrap()
-            .inner;
+    /// Lists the header of every Phoenix market. The scan asks `getProgramAccounts` for the
+    /// header bytes only, which keeps the response small; fetch the full accounts of the
+    /// markets you need with `fetch_accounts_chunked`.
+    ///
+    /// Some RPC providers reject `getProgramAccounts` for the Phoenix program. In that case a
+    /// warning is printed and the headers of the markets already loaded in the client are
+    /// fetched instead, so the result may be incomplete.
+    pub async fn scan_market_headers(
+        &self,
+        options: &ScanOptions,
+        mut on_progress: impl FnMut(ScanProgress),
+    ) -> anyhow::Result<Vec<(Pubkey, MarketHeader)>> {
+        let header_len = size_of::<MarketHeader>();
+        let accounts = match self.get_program_accounts_sliced(
+            "phoenix::program::accounts::MarketHeader",
+            vec![],
+            UiDataSliceConfig {
+                offset: 0,
+                length: header_len,
+            },
+        ) {
+            Ok(accounts) => {
+                on_progress(ScanProgress {
+                    fetched: accounts.len(),
+                    total: accounts.len(),
+                });
+                accounts
+            }
+            Err(e) => {
+                let keys = self.market_keys();
+                println!(
+                    "Warning: getProgramAccounts failed ({}), scanning the {} loaded markets",
+                    e,
+                    keys.len()
+                );
+                self.fetch_accounts_chunked(&keys, options, on_progress)
+                    .await?
+                    .into_iter()
+                    .filter_map(|(key, account)| account.map(|account| (key, account)))
+                    .collect()
+            }
+        };
+        Ok(accounts
+            .into_iter()
+            .filter_map(|(key, account)| {
+                let header_bytes = account.data.get(..header_len)?;
+                MarketHeader::try_from_slice(header_bytes)
+                    .ok()
+                    .map(|header| (key, header))
+            })
+            .collect())
+    }
 
-        let orderbook = Orderbook::from_market(
-            market,
-            self.base_lots_to_base_units_multiplier(),
-            self.ticks_to_float_price_multiplier(),
-        );
+    /// Finds every Phoenix market with `getProgramAccounts` and returns its metadata. The scan
+    /// downloads the market headers only; the markets are then fetched with `fetch_accounts`
+    /// and decoded with `MarketMetadata::from_market_account_data`, and the token decimals come
+    /// from one batched fetch of the mints. Markets whose data or mints cannot be decoded are
+    /// skipped with a warning.
+    ///
+    /// If `add` is set, the metadata of every market found is cached in the client, as
+    /// `add_market` does: a market whose cached metadata differs is left untouched, and once
+    /// the other markets are cached the first such conflict is returned as a
+    /// `MetadataConflict` error. Use `force_refresh_market` to overwrite the cached entry.
+    pub async fn discover_markets(
+        &self,
+        add: bool,
+    ) -> anyhow::Result<Vec<(Pubkey, MarketMetadata)>> {
+        let header_len = size_of::<MarketHeader>();
+        let headers = self
+            .get_program_accounts_sliced(
+                "phoenix::program::accounts::MarketHeader",
+                vec![],
+                UiDataSliceConfig {
+                    offset: 0,
+                    length: header_len,
+                },
+            )?
+            .into_iter()
+            .filter_map(|(key, account)| {
+                let header = MarketHeader::try_from_slice(account.data.get(..header_len)?).ok()?;
+                Some((key, header))
+            })
+            .collect::<Vec<_>>();
 
-        let traders = market
-            .get_registered_traders()
+        let mints = headers
             .iter()
-            .map(|(k, v)| (*k, *v))
-            .collect();
-
-        MarketState { orderbook, traders }
-    }
+            .flat_map(|(_, header)| [header.base_params.mint_key, header.quote_params.mint_key])
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let decimals = mints
+            .iter()
+            .zip(self.fetch_accounts(&mints).await?)
+            .filter_map(|(mint, account)| {
+                let mint_state = spl_token::state::Mint::unpack(&account?.data).ok()?;
+                Some((*mint, mint_state.decimals as u32))
+            })
+            .collect::<HashMap<_, _>>();
 
-    #[allow(clippy::useless_conversion)]
-    async fn get_market_metadata(client: &EllipsisClient, market_key: &Pubkey) -> MarketMetadata {
-        let mut market_account_data = (client.get_account_data(market_key)).await.unwrap();
-        let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
-        let header = MarketHeader::try_from_slice(header_bytes).unwrap();
-        let market = load_with_dispatch_mut(&header.market_size_params, bytes)
-            .unwrap()
-            .inner;
+        let keys = headers.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+        let market_accounts = self.fetch_accounts(&keys).await?;
+        let mut markets = Vec::with_capacity(headers.len());
+        for ((key, header), account) in headers.into_iter().zip(market_accounts) {
+            let metadata = match (
+                account,
+                decimals.get(&header.base_params.mint_key),
+                decimals.get(&header.quote_params.mint_key),
+            ) {
+                (Some(account), Some(&base_decimals), Some(&quote_decimals)) => {
+                    MarketMetadata::from_market_account_data(
+                        &account.data,
+                        base_decimals,
+                        quote_decimals,
+                    )
+                    .map_err(|e| e.to_string())
+                }
+                (None, _, _) => Err("market account not found".to_string()),
+                _ => Err("mint account not found".to_string()),
+            };
+            match metadata {
+                Ok(metadata) => markets.push((key, metadata)),
+                Err(e) => println!("Warning: skipping market {}: {}", key, e),
+            }
+        }
+        if add {
+            let mut conflict = None;
+            let mut cached_markets = self.markets.write().unwrap();
+            for &(key, metadata) in markets.iter() {
+                match cached_markets.get(&key) {
+                    Some(&cached) if cached != metadata => {
+                        conflict.get_or_insert(MetadataConflict {
+                            market: key,
+                            cached,
+                            fetched: metadata,
+                        });
+                    }
+                    _ => {
+                        cached_markets.insert(key, metadata);
+                    }
+                }
+            }
+            if let Some(conflict) = conflict {
+                return Err(conflict.into());
+            }
+        }
+        Ok(markets)
+    }
 
-        let base_mint_acct = spl_token::state::Mint::unpack(
-            &client
-                .get_account_data(&header.base_params.mint_key)
-                .await
-                .unwrap(),
-        )
-        .unwrap();
-        let quote_mint_acct = spl_token::state::Mint::unpack(
-            &client
-                .get_account_data(&header.quote_params.mint_key)
-                .await
-                .unwrap(),
+    /// Lists the traders that hold a seat account on `market_key`, approved or not. Only the
+    /// trader key of each seat is downloaded.
+    ///
+    /// If the RPC provider rejects `getProgramAccounts`, a warning is printed and the traders
+    /// registered in the market's trader state are returned instead, which omits seats that
+    /// were never approved.
+    pub async fn scan_seat_traders(&self, market_key: &Pubkey) -> anyhow::Result<Vec<Pubkey>> {
+        // A seat is laid out as the discriminant, the market, and then the trader
+        #[allow(deprecated)]
+        let market_filter = RpcFilterType::Memcmp(Memcmp {
+            offset: 8,
+            bytes: MemcmpEncodedBytes::Bytes(market_key.to_bytes().to_vec()),
+            encoding: None,
+        });
+        match self.get_program_accounts_sliced(
+            "phoenix::program::accounts::Seat",
+            vec![market_filter],
+            UiDataSliceConfig {
+                offset: 40,
+                length: 32,
+            },
+        ) {
+            Ok(seats) => Ok(seats
+                .iter()
+                .filter_map(|(_, account)| Pubkey::try_from(account.data.as_slice()).ok())
+                .collect()),
+            Err(e) => {
+                println!(
+                    "Warning: getProgramAccounts failed ({}), using the traders registered on {}",
+                    e, market_key
+                );
+                let mut data = self.client.get_account_data(market_key).await?;
+                let (header_bytes, bytes) = data.split_at_mut(size_of::<MarketHeader>());
+                let header = MarketHeader::try_from_slice(header_bytes)?;
+                let market = load_with_dispatch_mut(&header.market_size_params, bytes)
+                    .ok_or_else(|| anyhow::anyhow!("Failed to load market {}", market_key))?
+                    .inner;
+                Ok(market
+                    .get_registered_traders()
+                    .iter()
+                    .map(|(trader, _)| *trader)
+                    .collect())
+            }
+        }
+    }
+
+    /// The accounts that the SDK's order and cancel instructions for `market_key` and `trader`
+    /// reference and that a lookup table can hold: the log authority, market, seat, vaults,
+    /// the trader's token accounts, and the token and system programs, deduplicated. The trader
+    /// signs and the Phoenix program is invoked, and a v0 transaction must list both in its
+    /// static keys, so they are left out.
+    pub fn get_hot_accounts_for_market(
+        &self,
+        market_key: &Pubkey,
+        trader: &Pubkey,
+    ) -> Result<Vec<Pubkey>, PhoenixSdkError> {
+        let metadata = self.market_metadata(market_key)?;
+        Ok(ExpectedAccounts::for_market_and_trader_with_program_id(
+            &metadata,
+            market_key,
+            trader,
+            &self.program_id,
         )
-        .unwrap();
+        .accounts
+        .into_iter()
+        .filter(|account| account != trader && *account != self.program_id)
+        .collect())
+    }
 
-        let quote_lot_size = header.get_quote_lot_size().into();
-        let base_lot_size = header.get_base_lot_size().into();
-        let quote_multiplier = 10u64.pow(quote_mint_acct.decimals as u32);
-        let base_multiplier = 10u64.pow(base_mint_acct.decimals as u32);
-        let base_mint = header.base_params.mint_key;
-        let quote_mint = header.quote_params.mint_key;
-        let tick_size_in_quote_atoms_per_base_unit =
-            header.get_tick_size_in_quote_atoms_per_base_unit().into();
-        let num_base_lots_per_base_unit = market.get_base_lots_per_base_unit().into();
-
-        MarketMetadata {
-            base_mint,
-            quote_mint,
-            base_decimals: base_mint_acct.decimals as u32,
-            quote_decimals: quote_mint_acct.decimals as u32,
-            base_multiplier,
-            quote_multiplier,
-            tick_size_in_quote_atoms_per_base_unit,
-            quote_lot_size,
-            base_lot_size,
-            num_base_lots_per_base_unit,
+    /// Builds the instructions that create an address lookup table owned by `authority` and
+    /// fill it with the hot accounts of the client's trader on every market in `markets`.
+    /// Returns the address of the new table and the instructions, a create instruction followed
+    /// by extend instructions of at most `MAX_ADDRESSES_PER_EXTEND` addresses each. Send the
+    /// extend instructions in separate transactions if they do not fit in one.
+    pub fn create_lookup_table_ixs_for_markets(
+        &self,
+        markets: &[Pubkey],
+        authority: &Pubkey,
+        payer: &Pubkey,
+    ) -> anyhow::Result<(Pubkey, Vec<Instruction>)> {
+        let mut addresses = BTreeSet::new();
+        for market_key in markets {
+            addresses.extend(self.get_hot_accounts_for_market(market_key, &self.trader)?);
         }
+        let addresses = addresses.into_iter().collect::<Vec<_>>();
+        let recent_slot = self.client.get_slot()?;
+        let (create_ix, table) = create_lookup_table(*authority, *payer, recent_slot);
+        let mut ixs = vec![create_ix];
+        ixs.extend(
+            addresses
+                .chunks(MAX_ADDRESSES_PER_EXTEND)
+                .map(|chunk| extend_lookup_table(table, *authority, Some(*payer), chunk.to_vec())),
+        );
+        Ok((table, ixs))
     }
 
-    pub async fn parse_events_from_transaction(
+    /// Uses `table` for the v0 transactions of the batch order helpers on `market_key`, such as
+    /// `send_cancel_and_place`.
+    pub fn set_market_lookup_table(&self, market_key: &Pubkey, table: AddressLookupTableAccount) {
+        self.lookup_tables
+            .lock()
+            .unwrap()
+            .insert(*market_key, table);
+    }
+
+    pub fn get_market_lookup_table(
         &self,
-        sig: &Signature,
-    ) -> Option<Vec<PhoenixEvent>> {
-        let tx = if !self.client.is_bank_client {
-            let raw_tx = self
-                .client
-                .get_transaction_with_config(
-                    &sig,
-                    RpcTransactionConfig {
-                        encoding: Some(UiTransactionEncoding::Json),
-                        commitment: Some(CommitmentConfig::confirmed()),
-                        max_supported_transaction_version: None,
-                    },
-                )
-                .ok()?;
-            if raw_tx.transaction.meta.as_ref()?.err.is_some() {
-                return None;
-            }
+        market_key: &Pubkey,
+    ) -> Option<AddressLookupTableAccount> {
+        self.lookup_tables.lock().unwrap().get(market_key).cloned()
+    }
+
+    /// Fetches and decodes the address lookup table at `table_key`.
+    pub async fn fetch_lookup_table(
+        &self,
+        table_key: &Pubkey,
+    ) -> anyhow::Result<AddressLookupTableAccount> {
+        let data = self.client.get_account_data(table_key).await?;
+        let table = AddressLookupTable::deserialize(&data)
+            .map_err(|e| anyhow::anyhow!("Failed to decode lookup table {}: {}", table_key, e))?;
+        Ok(AddressLookupTableAccount {
+            key: *table_key,
+            addresses: table.addresses.to_vec(),
+        })
+    }
 
-            parse_transaction(raw_tx)
+    /// Returns the lookup table set for `market_key`, or creates one owned and paid for by the
+    /// payer that holds the hot accounts of the market and trader (see
+    /// `get_hot_accounts_for_market`) and sets it for the market. Creating a table sends one
+    /// transaction per `MAX_ADDRESSES_PER_EXTEND` addresses in addition to the create
+    /// transaction. A new table can only be used by transactions in later slots, so it is set
+    /// once it is active, see `await_lookup_table_active`.
+    pub async fn get_or_create_market_lookup_table(
+        &self,
+        market_key: &Pubkey,
+    ) -> anyhow::Result<AddressLookupTableAccount> {
+        if let Some(table) = self.get_market_lookup_table(market_key) {
+            return Ok(table);
+        }
+        self.ensure_signer()?;
+        let payer = self.client.payer.pubkey();
+        let (table_key, ixs) =
+            self.create_lookup_table_ixs_for_markets(&[*market_key], &payer, &payer)?;
+        for ix in ixs {
+            self.client.sign_send_instructions(vec![ix], vec![]).await?;
+        }
+        let addresses = self.get_hot_accounts_for_market(market_key, &self.trader)?;
+        let table = if self.client.is_bank_client {
+            AddressLookupTableAccount {
+                key: table_key,
+                addresses,
+            }
         } else {
-            self.client.get_transaction(&sig).await.ok()?
+            self.await_lookup_table_active(&table_key, addresses.len())
+                .await?
         };
-        let mut event_list = vec![];
-        for inner_ixs in tx.inner_instructions.iter() {
-            for inner_ix in inner_ixs.iter() {
-                let current_program_id = inner_ix.instruction.program_id.clone();
-                if current_program_id != self.program_id.to_string() {
-                    continue;
-                }
-                if inner_ix.instruction.data.is_empty() {
-                    continue;
-                }
-                let (tag, data) = inner_ix.instruction.data.split_first().unwrap();
-                let ix_enum = match PhoenixInstruction::try_from(*tag).ok() {
-                    Some(ix) => ix,
-                    None => continue,
-                };
-                if matches!(ix_enum, PhoenixInstruction::Log) {
-                    event_list.push(data.to_vec());
+        self.set_market_lookup_table(market_key, table.clone());
+        Ok(table)
+    }
+
+    /// Waits until the lookup table at `table_key` holds at least `num_addresses` addresses and
+    /// the current slot is past the slot it was last extended in, so transactions can use all
+    /// of them. Fails after `LOOKUP_TABLE_ACTIVATION_TIMEOUT`.
+    async fn await_lookup_table_active(
+        &self,
+        table_key: &Pubkey,
+        num_addresses: usize,
+    ) -> anyhow::Result<AddressLookupTableAccount> {
+        let started = Instant::now();
+        loop {
+            // The table may not be visible at confirmed commitment yet
+            if let Ok((_, data)) = self
+                .get_account_data_with_commitment(table_key, CommitmentConfig::confirmed())
+                .await
+            {
+                let table = AddressLookupTable::deserialize(&data).map_err(|e| {
+                    anyhow::anyhow!("Failed to decode lookup table {}: {}", table_key, e)
+                })?;
+                let slot = self
+                    .client
+                    .get_slot_with_commitment(CommitmentConfig::processed())?;
+                if table.addresses.len() >= num_addresses && slot > table.meta.last_extended_slot {
+                    return Ok(AddressLookupTableAccount {
+                        key: *table_key,
+                        addresses: table.addresses.to_vec(),
+                    });
                 }
             }
+            if started.elapsed() > LOOKUP_TABLE_ACTIVATION_TIMEOUT {
+                return Err(anyhow::anyhow!(
+                    "Lookup table {} did not become active within {:?}",
+                    table_key,
+                    LOOKUP_TABLE_ACTIVATION_TIMEOUT
+                ));
+            }
+            tokio::time::sleep(SIGNATURE_STATUS_POLL_INTERVAL).await;
         }
-        self.parse_phoenix_events(sig, event_list)
     }
 
-    pub async fn parse_places(&self, signature: &Signature) -> Vec<PhoenixEvent> {
-        let events = self
-            .parse_events_from_transaction(signature)
-            .await
-            .unwrap_or_default();
-        events
-            .iter()
-            .filter_map(|&event| match event.details {
-                MarketEventDetails::Place(..) => Some(event),
-                _ => None,
-            })
-            .collect::<Vec<PhoenixEvent>>()
+    /// Fetches the metadata of a market and overwrites any cached entry.
+    pub async fn force_refresh_market(&self, market_key: &Pubkey) -> anyhow::Result<()> {
+        let market_metadata = Self::try_get_market_metadata(&self.client, market_key).await?;
+
+        self.insert_market_metadata(*market_key, market_metadata);
+
+        Ok(())
     }
 
-    pub async fn parse_cancels(&self, signature: &Signature) -> Vec<PhoenixEvent> {
-        let events = self
-            .parse_events_from_transaction(signature)
+    /// The data of `key` read at `commitment`, and the slot it was read at. The bank client
+    /// has no commitment levels, so it reads the current account and reports slot 0.
+    async fn get_account_data_with_commitment(
+        &self,
+        key: &Pubkey,
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<(u64, Vec<u8>)> {
+        if self.client.is_bank_client {
+            return Ok((0, self.client.get_account_data(key).await?));
+        }
+        let response = self.client.get_account_with_commitment(key, commitment)?;
+        let account = response
+            .value
+            .ok_or_else(|| anyhow::anyhow!("Account {} does not exist", key))?;
+        Ok((response.context.slot, account.data))
+    }
+
+    /// The ladder of the active market, up to `levels` levels per side. Fails with
+    /// `MarketNotLoaded` if no active market is loaded, e.g. on a read-only client.
+    pub async fn get_market_ladder(&self, levels: u64) -> anyhow::Result<Ladder> {
+        self.ensure_active_market_loaded()?;
+        self.get_ladder_for_market(&self.active_market_key, levels)
             .await
-            .unwrap_or_default();
-        events
-            .iter()
-            .filter_map(|&event| match event.details {
-                MarketEventDetails::Reduce(..) => Some(event),
-                _ => None,
-            })
-            .collect::<Vec<PhoenixEvent>>()
     }
 
-    pub async fn parse_fills(&self, signature: &Signature) -> Vec<PhoenixEvent> {
-        let events = self
-            .parse_events_from_transaction(signature)
+    /// Like `get_market_ladder`, but reads the market at `commitment` instead of the client's
+    /// commitment.
+    pub async fn get_market_ladder_with_commitment(
+        &self,
+        levels: u64,
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<Ladder> {
+        self.get_ladder_for_market_with_commitment(&self.active_market_key, levels, commitment)
             .await
-            .unwrap_or_default();
-        events
-            .iter()
-            .filter_map(|&event| match event.details {
-                MarketEventDetails::Fill(..) => Some(event),
-                _ => None,
-            })
-            .collect::<Vec<PhoenixEvent>>()
     }
 
-    pub async fn parse_fills_and_places(
+    /// Fetches the ladder of any market, without requiring it to be the active market.
+    pub async fn get_ladder_for_market(
         &self,
-        signature: &Signature,
-    ) -> (Vec<PhoenixEvent>, Vec<PhoenixEvent>) {
-        let events = self
-            .parse_events_from_transaction(signature)
+        market_key: &Pubkey,
+        levels: u64,
+    ) -> anyhow::Result<Ladder> {
+        self.get_ladder_for_market_with_commitment(market_key, levels, self.client.commitment())
             .await
-            .unwrap_or_default();
-        let fills = events
-            .iter()
-            .filter_map(|&event| match event.details {
-                MarketEventDetails::Fill(..) => Some(event),
-                _ => None,
-            })
-            .collect::<Vec<PhoenixEvent>>();
-        let places = events
-            .iter()
-            .filter_map(|&event| match event.details {
-                MarketEventDetails::Place(..) => Some(event),
-                _ => None,
-            })
-            .collect::<Vec<PhoenixEvent>>();
+    }
 
-        (fills, places)
+    /// Like `get_ladder_for_market`, but reads the market at `commitment`.
+    pub async fn get_ladder_for_market_with_commitment(
+        &self,
+        market_key: &Pubkey,
+        levels: u64,
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<Ladder> {
+        Ok(self
+            .get_ladder_for_market_with_context(market_key, levels, commitment)
+            .await?
+            .value)
     }
 
-    pub async fn send_ioc(
+    /// Like `get_ladder_for_market_with_commitment`, with the slot the market was read at.
+    pub async fn get_ladder_for_market_with_context(
         &self,
-        price: u64,
+        market_key: &Pubkey,
+        levels: u64,
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<Snapshot<Ladder>> {
+        let (slot, mut market_account_data) = self
+            .get_account_data_with_commitment(market_key, commitment)
+            .await?;
+        let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
+        let header = MarketHeader::try_from_slice(header_bytes)?;
+        let market = load_with_dispatch_mut(&header.market_size_params, bytes)
+            .ok_or_else(|| anyhow::anyhow!("Failed to load market {}", market_key))?
+            .inner;
+
+        Ok(Snapshot {
+            slot,
+            value: market.get_ladder(levels),
+        })
+    }
+
+    /// Builds an IOC order on `market_key` with slippage protection from the current state of
+    /// the book. For a buy (`Side::Bid`) `size` is the number of quote units to spend, for a
+    /// sell (`Side::Ask`) it is the number of base units to sell. The expected output is
+    /// simulated against the live ladder and `min_lots_out` is set to the expected output
+    /// less `max_slippage_bps`.
+    pub async fn get_swap_ix_with_slippage(
+        &self,
+        market_key: &Pubkey,
         side: Side,
-        size: u64,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let new_order_ix = self.get_ioc_ix(price, side, size);
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
-            .await
-            .ok()?;
-        let fills = self.parse_fills(&signature).await;
-        Some((signature, fills))
+        size: f64,
+        max_slippage_bps: u64,
+    ) -> anyhow::Result<Instruction> {
+        let core = self.get_core_for_market(market_key)?;
+        let metadata = core.active_market_metadata()?;
+        let ladder = self.get_ladder_for_market(market_key, u64::MAX).await?;
+        let lots_in = match side {
+            Side::Bid => metadata.quote_units_to_quote_lots(size),
+            Side::Ask => metadata.base_units_to_base_lots(size),
+        };
+        let min_lots_out = SlippageMode::Relative {
+            max_bps: max_slippage_bps,
+        }
+        .min_lots_out(&ladder, &metadata, side, lots_in)?;
+        Ok(core.get_ioc_with_slippage_ix(lots_in, min_lots_out, side)?)
     }
 
-    pub async fn send_fok_buy(
+    pub fn get_market_ladder_sync(&self, levels: u64) -> anyhow::Result<Ladder> {
+        block_on_sync(self.get_market_ladder(levels))?
+    }
+
+    /// The orderbook of the active market. Fails if the market account cannot be fetched or
+    /// parsed, so an empty book always means the market has no resting orders.
+    pub async fn get_market_orderbook(
         &self,
-        price: u64,
-        size_in_quote_lots: u64,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let new_order_ix = self.get_fok_buy_ix(price, size_in_quote_lots);
+    ) -> anyhow::Result<Orderbook<FIFOOrderId, PhoenixOrder>> {
+        self.get_market_orderbook_with_commitment(self.client.commitment())
+            .await
+    }
 
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
+    /// Like `get_market_orderbook`, but returns an empty book on any error. This is how
+    /// `get_market_orderbook` used to behave; prefer it unless an empty book is a safe default.
+    pub async fn get_market_orderbook_or_default(&self) -> Orderbook<FIFOOrderId, PhoenixOrder> {
+        self.get_market_orderbook()
             .await
-            .ok()?;
-        let fills = self.parse_fills(&signature).await;
-        Some((signature, fills))
+            .unwrap_or_else(|_| Orderbook::new(0.0, 0.0))
     }
 
-    pub async fn send_fok_sell(
+    /// Like `get_market_orderbook`, but reads the market at `commitment` instead of the
+    /// client's commitment.
+    pub async fn get_market_orderbook_with_commitment(
         &self,
-        price: u64,
-        size_in_base_lots: u64,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let new_order_ix = self.get_fok_sell_ix(price, size_in_base_lots);
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<Orderbook<FIFOOrderId, PhoenixOrder>> {
+        self.get_orderbook_for_market_with_commitment(&self.active_market_key, commitment)
+            .await
+    }
 
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
+    /// Fetches the orderbook of any loaded market, without requiring it to be the active market.
+    pub async fn get_orderbook_for_market(
+        &self,
+        market_key: &Pubkey,
+    ) -> anyhow::Result<Orderbook<FIFOOrderId, PhoenixOrder>> {
+        self.get_orderbook_for_market_with_commitment(market_key, self.client.commitment())
             .await
-            .ok()?;
-        let fills = self.parse_fills(&signature).await;
-        Some((signature, fills))
     }
 
-    pub fn get_fok_buy_ix(&self, price: u64, size_in_quote_lots: u64) -> Instruction {
-        self.get_fok_generic_ix(price, Side::Bid, size_in_quote_lots, None, None, None, None)
+    /// Like `get_orderbook_for_market`, but reads the market at `commitment`.
+    pub async fn get_orderbook_for_market_with_commitment(
+        &self,
+        market_key: &Pubkey,
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<Orderbook<FIFOOrderId, PhoenixOrder>> {
+        Ok(self
+            .get_orderbook_for_market_with_context(market_key, commitment)
+            .await?
+            .value)
     }
 
-    pub fn get_fok_sell_ix(&self, price: u64, size_in_base_lots: u64) -> Instruction {
-        self.get_fok_generic_ix(price, Side::Ask, size_in_base_lots, None, None, None, None)
+    /// Like `get_orderbook_for_market_with_commitment`, with the slot the market was read at.
+    pub async fn get_orderbook_for_market_with_context(
+        &self,
+        market_key: &Pubkey,
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<Snapshot<Orderbook<FIFOOrderId, PhoenixOrder>>> {
+        let core = self.get_core_for_market(market_key)?;
+        let (slot, mut market_account_data) = self
+            .get_account_data_with_commitment(market_key, commitment)
+            .await?;
+        let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
+        let header = MarketHeader::try_from_slice(header_bytes)?;
+        let market = load_with_dispatch_mut(&header.market_size_params, bytes)
+            .ok_or_else(|| anyhow::anyhow!("Failed to load market {}", market_key))?
+            .inner;
+
+        Ok(Snapshot {
+            slot,
+            value: Orderbook::from_market_and_header(
+                &header,
+                market,
+                core.base_lots_to_base_units_multiplier()?,
+                core.ticks_to_float_price_multiplier()?,
+            ),
+        })
     }
 
-    pub async fn send_ioc_with_slippage(
+    /// The orderbook of the active market read at `commitment`, with the slot it was read at
+    pub async fn get_market_orderbook_with_context(
         &self,
-        lots_in: u64,
-        min_lots_out: u64,
-        side: Side,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let new_order_ix = self.get_ioc_with_slippage_ix(lots_in, min_lots_out, side);
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<Snapshot<Orderbook<FIFOOrderId, PhoenixOrder>>> {
+        self.get_orderbook_for_market_with_context(&self.active_market_key, commitment)
             .await
-            .ok()?;
-        let fills = self.parse_fills(&signature).await;
-        Some((signature, fills))
     }
 
-    pub async fn send_post_only(
+    pub fn get_market_orderbook_sync(
         &self,
-        price: u64,
-        side: Side,
-        size: u64,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let new_order_ix = self.get_post_only_ix(price, side, size);
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
+    ) -> anyhow::Result<Orderbook<FIFOOrderId, PhoenixOrder>> {
+        block_on_sync(self.get_market_orderbook())?
+    }
+
+    pub async fn get_traders(&self) -> BTreeMap<Pubkey, TraderState> {
+        self.get_traders_with_commitment(self.client.commitment())
             .await
-            .ok()?;
-        let fills = self.parse_fills(&signature).await;
-        Some((signature, fills))
+            .unwrap()
     }
 
-    pub async fn send_limit_order(
+    /// The registered traders of the active market, read at `commitment`
+    pub async fn get_traders_with_commitment(
         &self,
-        price: u64,
-        side: Side,
-        size: u64,
-    ) -> Option<(Signature, Vec<PhoenixEvent>, Vec<PhoenixEvent>)> {
-        let new_order_ix = self.get_limit_order_ix(price, side, size);
-        let signature = self
-            .client
-            .sign_send_instructions(vec![new_order_ix], vec![])
-            .await
-            .ok()?;
-        let (fills, places) = self.parse_fills_and_places(&signature).await;
-        Some((signature, places, fills))
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<BTreeMap<Pubkey, TraderState>> {
+        self.ensure_active_market_loaded()?;
+        let market_key = self.active_market_key;
+        let (_, mut market_account_data) = self
+            .get_account_data_with_commitment(&market_key, commitment)
+            .await?;
+        let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
+        let header = MarketHeader::try_from_slice(header_bytes)?;
+        let market = load_with_dispatch_mut(&header.market_size_params, bytes)
+            .ok_or_else(|| anyhow::anyhow!("Failed to load market {}", market_key))?
+            .inner;
+
+        Ok(market
+            .get_registered_traders()
+            .iter()
+            .map(|(k, v)| (*k, *v))
+            .collect())
     }
 
-    pub async fn send_cancel_ids(
+    /// The state of `trader`'s seat on `market_key`, or None if the trader is not registered
+    /// on the market. Only that trader's state is copied out of the market account.
+    pub async fn get_trader_state(
         &self,
-        ids: Vec<FIFOOrderId>,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let cancel_ix = self.get_cancel_ids_ix(ids);
-        let signature = self
-            .client
-            .sign_send_instructions(vec![cancel_ix], vec![])
-            .await
-            .ok()?;
+        market_key: &Pubkey,
+        trader: &Pubkey,
+    ) -> anyhow::Result<Option<TraderState>> {
+        let mut market_account_data = self.client.get_account_data(market_key).await?;
+        registered_trader_state(&mut market_account_data, market_key, trader)
+    }
 
-        let cancels = self.parse_cancels(&signature).await;
-        Some((signature, cancels))
+    /// `trader`'s free and locked seat balances on `market_key` in base and quote units, or
+    /// None if the trader is not registered on the market. The market must be loaded in the
+    /// client.
+    pub async fn get_trader_balances_in_units(
+        &self,
+        market_key: &Pubkey,
+        trader: &Pubkey,
+    ) -> anyhow::Result<Option<TraderBalances>> {
+        let metadata = self.market_metadata(market_key)?;
+        Ok(self
+            .get_trader_state(market_key, trader)
+            .await?
+            .map(|state| TraderBalances::from_trader_state(&metadata, &state)))
     }
 
-    pub async fn send_cancel_up_to(
+    /// `trader`'s wallet balances of the market's tokens and free and locked seat balances,
+    /// in lots or atoms and in units, read with one `getMultipleAccounts` request for the
+    /// market and the trader's associated token accounts. The market must be loaded in the
+    /// client. Missing token accounts and a missing seat are reported as zero balances with a
+    /// flag, not as errors.
+    #[allow(clippy::useless_conversion)]
+    pub async fn get_trader_market_balances(
         &self,
-        tick_limit: Option<u64>,
-        side: Side,
-    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let cancel_ix = self.get_cancel_up_to_ix(tick_limit, side);
-        let signature = self
-            .client
-            .sign_send_instructions(vec![cancel_ix], vec![])
-            .await
-            .ok()?;
+        market_key: &Pubkey,
+        trader: &Pubkey,
+    ) -> anyhow::Result<TraderMarketBalances> {
+        let metadata = self.market_metadata(market_key)?;
+        let mut accounts = self
+            .fetch_accounts(&[
+                *market_key,
+                get_associated_token_address(trader, &metadata.base_mint),
+                get_associated_token_address(trader, &metadata.quote_mint),
+            ])
+            .await?;
+        let quote_account = accounts.pop().flatten();
+        let base_account = accounts.pop().flatten();
+        let mut market_account_data = accounts
+            .pop()
+            .flatten()
+            .ok_or_else(|| anyhow::anyhow!("Market {} does not exist", market_key))?
+            .data;
+        let state = registered_trader_state(&mut market_account_data, market_key, trader)?;
 
-        let cancels = self.parse_cancels(&signature).await;
-        Some((signature, cancels))
+        let base_wallet_amount = get_token_amount(base_account.as_ref().map(|a| &a.data[..]));
+        let quote_wallet_amount = get_token_amount(quote_account.as_ref().map(|a| &a.data[..]));
+        let mut balances = TraderMarketBalances {
+            base_wallet_amount,
+            quote_wallet_amount,
+            base_wallet_units: base_wallet_amount as f64 / metadata.base_multiplier as f64,
+            quote_wallet_units: quote_wallet_amount as f64 / metadata.quote_multiplier as f64,
+            base_token_account_exists: base_account.is_some(),
+            quote_token_account_exists: quote_account.is_some(),
+            ..TraderMarketBalances::default()
+        };
+        if let Some(state) = state {
+            balances.is_registered = true;
+            balances.base_lots_free = state.base_lots_free.into();
+            balances.base_lots_locked = state.base_lots_locked.into();
+            balances.quote_lots_free = state.quote_lots_free.into();
+            balances.quote_lots_locked = state.quote_lots_locked.into();
+            balances.seat = TraderBalances::from_trader_state(&metadata, &state);
+        }
+        Ok(balances)
     }
 
-    pub async fn send_cancel_all(&self) -> Option<(Signature, Vec<PhoenixEvent>)> {
-        let cancel_all_ix = self.get_cancel_all_ix();
-        let signature = self
-            .client
-            .sign_send_instructions(vec![cancel_all_ix], vec![])
-            .await
-            .ok()?;
+    /// The rent of the accounts `trader` still needs to trade on `market_key`: the base and
+    /// quote associated token accounts and the seat if they do not exist, read with one
+    /// `getMultipleAccounts` request, and the lookup table if none is set for the market with
+    /// `set_market_lookup_table`. Rent is queried once per account size. Fees of the creating
+    /// transactions are not included.
+    pub async fn estimate_onboarding_cost(
+        &self,
+        market_key: &Pubkey,
+        trader: &Pubkey,
+    ) -> anyhow::Result<OnboardingCost> {
+        let metadata = self.market_metadata(market_key)?;
+        let (seat, _) = Pubkey::find_program_address(
+            &[b"seat", market_key.as_ref(), trader.as_ref()],
+            &self.program_id,
+        );
+        let base_account = get_associated_token_address(trader, &metadata.base_mint);
+        let quote_account = get_associated_token_address(trader, &metadata.quote_mint);
+        let accounts = self
+            .fetch_accounts(&[base_account, quote_account, seat])
+            .await?;
 
-        let cancels = self.parse_cancels(&signature).await;
-        Some((signature, cancels))
+        let mut missing = vec![];
+        for ((account, address, size, is_refundable), existing) in [
+            (
+                OnboardingAccount::BaseTokenAccount,
+                base_account,
+                spl_token::state::Account::LEN,
+                true,
+            ),
+            (
+                OnboardingAccount::QuoteTokenAccount,
+                quote_account,
+                spl_token::state::Account::LEN,
+                true,
+            ),
+            (OnboardingAccount::Seat, seat, SEAT_ACCOUNT_SIZE, false),
+        ]
+        .into_iter()
+        .zip(accounts.iter())
+        {
+            if existing.is_none() {
+                missing.push((account, Some(address), size, is_refundable));
+            }
+        }
+        if self.get_market_lookup_table(market_key).is_none() {
+            let num_addresses = self.get_hot_accounts_for_market(market_key, trader)?.len();
+            missing.push((
+                OnboardingAccount::LookupTable,
+                None,
+                LOOKUP_TABLE_META_SIZE + num_addresses * 32,
+                true,
+            ));
+        }
+
+        let mut rent_by_size: HashMap<usize, u64> = HashMap::new();
+        let mut items = vec![];
+        for (account, address, size, is_refundable) in missing {
+            let lamports = match rent_by_size.get(&size) {
+                Some(lamports) => *lamports,
+                None => {
+                    let lamports = self.client.get_minimum_balance_for_rent_exemption(size)?;
+                    rent_by_size.insert(size, lamports);
+                    lamports
+                }
+            };
+            items.push(OnboardingCostItem {
+                account,
+                address,
+                size,
+                lamports,
+                is_refundable,
+            });
+        }
+        Ok(OnboardingCost {
+            market: *market_key,
+            trader: *trader,
+            total_lamports: items.iter().map(|item| item.lamports).sum(),
+            items,
+        })
+    }
+
+    /// The instructions a taker needs before swapping on `market_key`: an idempotent create of
+    /// each of `trader`'s associated token accounts for the market's mints that does not exist
+    /// yet, funded by `trader`. With `wrap_sol_lamports`, also moves that many lamports from
+    /// `trader` into its wrapped SOL account and syncs it; fails if neither mint is wrapped SOL.
+    ///
+    /// Swaps do not need a seat, so no seat instruction is ever included. Only makers, whose
+    /// orders rest on the book, need to request one.
+    pub async fn get_taker_setup_instructions_for_market(
+        &self,
+        market_key: &Pubkey,
+        trader: &Pubkey,
+        wrap_sol_lamports: Option<u64>,
+    ) -> anyhow::Result<Vec<Instruction>> {
+        let metadata = self.market_metadata(market_key)?;
+        let mints = [metadata.base_mint, metadata.quote_mint];
+        let token_accounts = mints
+            .iter()
+            .map(|mint| get_associated_token_address(trader, mint))
+            .collect::<Vec<_>>();
+        let existing = self.fetch_accounts(&token_accounts).await?;
+
+        let mut ixs = vec![];
+        for (mint, existing) in mints.iter().zip(existing.iter()) {
+            if existing.is_none() {
+                ixs.push(create_associated_token_account_idempotent(
+                    trader,
+                    trader,
+                    mint,
+                    &spl_token::id(),
+                ));
+            }
+        }
+        if let Some(lamports) = wrap_sol_lamports {
+            let wsol_account = mints
+                .iter()
+                .zip(token_accounts.iter())
+                .find(|(mint, _)| **mint == spl_token::native_mint::id())
+                .map(|(_, account)| *account)
+                .ok_or_else(|| {
+                    PhoenixSdkError::InvalidArgument(format!(
+                        "Market {} does not trade wrapped SOL",
+                        market_key
+                    ))
+                })?;
+            ixs.push(solana_program::system_instruction::transfer(
+                trader,
+                &wsol_account,
+                lamports,
+            ));
+            ixs.push(spl_token::instruction::sync_native(
+                &spl_token::id(),
+                &wsol_account,
+            )?);
+        }
+        Ok(ixs)
+    }
+
+    /// `get_swap_ix_with_slippage` on `market_key`, preceded by the taker setup of the
+    /// client's trader, see `get_taker_setup_instructions_for_market`.
+    pub async fn get_swap_ixs_with_taker_setup(
+        &self,
+        market_key: &Pubkey,
+        side: Side,
+        size: f64,
+        max_slippage_bps: u64,
+        wrap_sol_lamports: Option<u64>,
+    ) -> anyhow::Result<Vec<Instruction>> {
+        let mut ixs = self
+            .get_taker_setup_instructions_for_market(market_key, &self.trader, wrap_sol_lamports)
+            .await?;
+        ixs.push(
+            self.get_swap_ix_with_slippage(market_key, side, size, max_slippage_bps)
+                .await?,
+        );
+        Ok(ixs)
+    }
+
+    /// Reports how exposed `trader`'s seat on `market_key` is to eviction. A seat can be evicted
+    /// when the market's trader state is full and the seat has no locked lots.
+    #[allow(clippy::useless_conversion)]
+    pub async fn get_seat_eviction_risk(
+        &self,
+        market_key: &Pubkey,
+        trader: &Pubkey,
+    ) -> anyhow::Result<EvictionRisk> {
+        let mut market_account_data = self.client.get_account_data(market_key).await?;
+        let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
+        let header = MarketHeader::try_from_slice(header_bytes)?;
+        let market = load_with_dispatch_mut(&header.market_size_params, bytes)
+            .ok_or_else(|| anyhow::anyhow!("Failed to load market {}", market_key))?
+            .inner;
+
+        let num_seats: u64 = header.market_size_params.num_seats.into();
+        let mut risk = EvictionRisk {
+            num_seats,
+            ..EvictionRisk::default()
+        };
+        for (registered_trader, state) in market.get_registered_traders().iter() {
+            let base_lots_locked: u64 = state.base_lots_locked.into();
+            let quote_lots_locked: u64 = state.quote_lots_locked.into();
+            let evictable = base_lots_locked == 0 && quote_lots_locked == 0;
+            risk.num_registered_traders += 1;
+            if evictable {
+                risk.num_evictable_seats += 1;
+            }
+            if registered_trader == trader {
+                risk.is_registered = true;
+                risk.is_evictable = evictable;
+                risk.base_lots_locked = base_lots_locked;
+                risk.quote_lots_locked = quote_lots_locked;
+            }
+        }
+        risk.trader_state_full = risk.num_registered_traders >= num_seats;
+        Ok(risk)
+    }
+
+    /// Compares `trader`'s locked lots on `market_key` against the lots their resting orders
+    /// lock, from a single snapshot of the market account. A discrepancy is reported as
+    /// `Unconfirmed`, since a transaction that is still settling can cause one; pass the audit
+    /// to `recheck_locked_funds` to classify it.
+    #[cfg(feature = "analytics")]
+    pub async fn audit_locked_funds(
+        &self,
+        market_key: &Pubkey,
+        trader: &Pubkey,
+    ) -> anyhow::Result<LockedFundsAudit> {
+        let metadata = self.market_metadata(market_key)?;
+        let response = self
+            .client
+            .get_account_with_commitment(market_key, self.client.commitment())?;
+        let slot = response.context.slot;
+        let mut market_account_data = response
+            .value
+            .ok_or_else(|| anyhow::anyhow!("Market {} does not exist", market_key))?
+            .data;
+        let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
+        let header = MarketHeader::try_from_slice(header_bytes)?;
+        let market = load_with_dispatch_mut(&header.market_size_params, bytes)
+            .ok_or_else(|| anyhow::anyhow!("Failed to load market {}", market_key))?
+            .inner;
+        let trader_state = market
+            .get_registered_traders()
+            .iter()
+            .find(|(k, _)| **k == *trader)
+            .map(|(_, state)| *state)
+            .ok_or_else(|| anyhow::anyhow!("{} has no seat on market {}", trader, market_key))?;
+        let orderbook = Orderbook::from_market_and_header(
+            &header,
+            market,
+            metadata.base_lots_to_base_units_multiplier(),
+            metadata.ticks_to_float_price_multiplier(),
+        );
+        Ok(LockedFundsAudit::from_snapshot(
+            &metadata,
+            *market_key,
+            *trader,
+            slot,
+            &orderbook,
+            &trader_state,
+        ))
+    }
+
+    /// The second phase of `audit_locked_funds`: waits until the market can be read at least
+    /// `min_slots` slots after `previous` and audits it again. A discrepancy that is gone is
+    /// classified as `PendingSettlement` and one that remains as `Mismatch`.
+    #[cfg(feature = "analytics")]
+    pub async fn recheck_locked_funds(
+        &self,
+        previous: &LockedFundsAudit,
+        min_slots: u64,
+    ) -> anyhow::Result<LockedFundsAudit> {
+        loop {
+            let audit = self
+                .audit_locked_funds(&previous.market, &previous.trader)
+                .await?;
+            if audit.slot >= previous.slot + min_slots {
+                return Ok(audit.reclassify(previous));
+            }
+            tokio::time::sleep(Duration::from_millis(400)).await;
+        }
+    }
+
+    /// The fee recipient and unclaimed fees of a loaded market.
+    pub async fn get_market_fees_info(
+        &self,
+        market_key: &Pubkey,
+    ) -> anyhow::Result<MarketFeesInfo> {
+        let metadata = self.market_metadata(market_key)?;
+        let market_account_data = self.client.get_account_data(market_key).await?;
+        Ok(MarketFeesInfo::from_market_account_data(
+            *market_key,
+            &market_account_data,
+            &metadata,
+        )?)
+    }
+
+    /// Builds the instruction that collects the unclaimed fees of `market_key` to the fee
+    /// recipient's quote token account. The fee recipient is read from the market first, and
+    /// the build fails with `PhoenixSdkError::NotFeeRecipient` if it is not the client's trader.
+    pub async fn get_collect_fees_ix(&self, market_key: &Pubkey) -> anyhow::Result<Instruction> {
+        let metadata = self.market_metadata(market_key)?;
+        let fees_info = self.get_market_fees_info(market_key).await?;
+        Ok(fees_info.collect_fees_instruction(&self.trader, &metadata.quote_mint)?)
+    }
+
+    /// Builds the instruction that evicts `trader_to_evict` from `market_key`, canceling their
+    /// orders and returning their free funds to their token accounts. The client's trader
+    /// signs and must be the market authority or its successor. Fails if the market is not
+    /// cached.
+    pub fn get_evict_seat_ix(
+        &self,
+        market_key: &Pubkey,
+        trader_to_evict: &Pubkey,
+    ) -> anyhow::Result<Instruction> {
+        let metadata = self.market_metadata(market_key)?;
+        Ok(create_evict_seat_instruction_with_program_id(
+            market_key,
+            &self.trader,
+            trader_to_evict,
+            &metadata.base_mint,
+            &metadata.quote_mint,
+            &self.program_id,
+        ))
+    }
+
+    pub fn get_traders_sync(&self) -> anyhow::Result<BTreeMap<Pubkey, TraderState>> {
+        block_on_sync(self.get_traders())
+    }
+
+    pub async fn get_market_state(&self) -> MarketState {
+        self.get_market_state_with_commitment(self.client.commitment())
+            .await
+            .unwrap()
+    }
+
+    /// The orderbook and registered traders of the active market, read at `commitment`
+    pub async fn get_market_state_with_commitment(
+        &self,
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<MarketState> {
+        Ok(self.get_market_state_with_context(commitment).await?.value)
+    }
+
+    /// Like `get_market_state_with_commitment`, with the slot the market was read at.
+    pub async fn get_market_state_with_context(
+        &self,
+        commitment: CommitmentConfig,
+    ) -> anyhow::Result<Snapshot<MarketState>> {
+        self.ensure_active_market_loaded()?;
+        let market_key = self.active_market_key;
+        let (slot, mut market_account_data) = self
+            .get_account_data_with_commitment(&market_key, commitment)
+            .await?;
+        let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
+        let header = MarketHeader::try_from_slice(header_bytes)?;
+        let market = load_with_dispatch_mut(&header.market_size_params, bytes)
+            .ok_or_else(|| anyhow::anyhow!("Failed to load market {}", market_key))?
+            .inner;
+
+        let orderbook = Orderbook::from_market_and_header(
+            &header,
+            market,
+            self.base_lots_to_base_units_multiplier()?,
+            self.ticks_to_float_price_multiplier()?,
+        );
+
+        let traders = market
+            .get_registered_traders()
+            .iter()
+            .map(|(k, v)| (*k, *v))
+            .collect();
+
+        Ok(Snapshot {
+            slot,
+            value: MarketState { orderbook, traders },
+        })
+    }
+
+    async fn try_get_market_metadata(
+        client: &EllipsisClient,
+        market_key: &Pubkey,
+    ) -> anyhow::Result<MarketMetadata> {
+        let market_account_data = client.get_account_data(market_key).await?;
+        let header =
+            MarketHeader::try_from_slice(&market_account_data[..size_of::<MarketHeader>()])?;
+
+        let base_mint_acct = spl_token::state::Mint::unpack(
+            &client
+                .get_account_data(&header.base_params.mint_key)
+                .await?,
+        )?;
+        let quote_mint_acct = spl_token::state::Mint::unpack(
+            &client
+                .get_account_data(&header.quote_params.mint_key)
+                .await?,
+        )?;
+
+        MarketMetadata::from_market_account_data(
+            &market_account_data,
+            base_mint_acct.decimals as u32,
+            quote_mint_acct.decimals as u32,
+        )
+        .map_err(|e| anyhow::anyhow!("Failed to load market {}: {}", market_key, e))
+    }
+
+    pub async fn parse_events_from_transaction(
+        &self,
+        sig: &Signature,
+    ) -> Option<Vec<PhoenixEvent>> {
+        self.parse_events_from_transaction_with_commitment(sig, CommitmentConfig::confirmed())
+            .await
+    }
+
+    /// Like `parse_events_from_transaction`, but fetches the transaction at `commitment`. RPC
+    /// nodes only serve transactions at confirmed or finalized commitment. The bank client
+    /// ignores the commitment.
+    pub async fn parse_events_from_transaction_with_commitment(
+        &self,
+        sig: &Signature,
+        commitment: CommitmentConfig,
+    ) -> Option<Vec<PhoenixEvent>> {
+        if !self.client.is_bank_client {
+            let raw_tx = self
+                .client
+                .get_transaction_with_config(
+                    sig,
+                    RpcTransactionConfig {
+                        encoding: Some(UiTransactionEncoding::Json),
+                        commitment: Some(commitment),
+                        max_supported_transaction_version: Some(0),
+                    },
+                )
+                .ok()?;
+            let event_list = get_phoenix_log_payloads(&raw_tx, &self.program_id)?;
+            self.load_event_markets(&event_list).await;
+            return self.parse_phoenix_events(sig, event_list);
+        }
+        let tx = self.client.get_transaction(sig).await.ok()?;
+        let mut event_list = vec![];
+        for inner_ixs in tx.inner_instructions.iter() {
+            for inner_ix in inner_ixs.iter() {
+                let current_program_id = inner_ix.instruction.program_id.clone();
+                if current_program_id != self.program_id.to_string() {
+                    continue;
+                }
+                if let Some(data) = get_log_payload(&inner_ix.instruction.data) {
+                    event_list.push(data.to_vec());
+                }
+            }
+        }
+        self.load_event_markets(&event_list).await;
+        self.parse_phoenix_events(sig, event_list)
+    }
+
+    /// Fetches and caches the markets that emitted `events` and are not cached yet, so their
+    /// events are converted with their own metadata. Lookups go through `market_lookup`: each
+    /// market is fetched once, and a market that fails to load is not fetched again until its
+    /// retry-after has passed.
+    async fn load_event_markets(&self, events: &[Vec<u8>]) {
+        for market_key in SDKClientCore::event_markets(events) {
+            self.market_lookup
+                .load(
+                    &market_key,
+                    |key| self.market_metadata(key).is_ok(),
+                    || self.add_market(&market_key),
+                )
+                .await;
+        }
+    }
+
+    /// Parses the Phoenix events of a JSON encoded transaction, e.g. one fetched from an RPC node
+    /// other than the client's. Legacy and v0 transactions are supported. Returns None if the
+    /// transaction failed or could not be decoded.
+    ///
+    /// Unlike `parse_events_from_transaction`, this does not fetch the markets of the events:
+    /// the events of a market that is not cached are skipped with a warning. Load the markets
+    /// first, e.g. with `add_market`, to get every event.
+    pub fn parse_events_from_encoded_transaction(
+        &self,
+        sig: &Signature,
+        raw_tx: &EncodedConfirmedTransactionWithStatusMeta,
+    ) -> Option<Vec<PhoenixEvent>> {
+        let event_list = get_phoenix_log_payloads(raw_tx, &self.program_id)?;
+        self.parse_phoenix_events(sig, event_list)
+    }
+
+    pub async fn parse_places(&self, signature: &Signature) -> Vec<PhoenixEvent> {
+        let events = self
+            .parse_events_from_transaction(signature)
+            .await
+            .unwrap_or_default();
+        events
+            .iter()
+            .filter_map(|&event| match event.details {
+                MarketEventDetails::Place(..) => Some(event),
+                _ => None,
+            })
+            .collect::<Vec<PhoenixEvent>>()
+    }
+
+    pub async fn parse_cancels(&self, signature: &Signature) -> Vec<PhoenixEvent> {
+        let events = self
+            .parse_events_from_transaction(signature)
+            .await
+            .unwrap_or_default();
+        events
+            .iter()
+            .filter_map(|&event| match event.details {
+                MarketEventDetails::Reduce(..) => Some(event),
+                _ => None,
+            })
+            .collect::<Vec<PhoenixEvent>>()
+    }
+
+    pub async fn parse_fills(&self, signature: &Signature) -> Vec<PhoenixEvent> {
+        let events = self
+            .parse_events_from_transaction(signature)
+            .await
+            .unwrap_or_default();
+        events
+            .iter()
+            .filter_map(|&event| match event.details {
+                MarketEventDetails::Fill(..) => Some(event),
+                _ => None,
+            })
+            .collect::<Vec<PhoenixEvent>>()
+    }
+
+    pub async fn parse_fills_and_places(
+        &self,
+        signature: &Signature,
+    ) -> (Vec<PhoenixEvent>, Vec<PhoenixEvent>) {
+        let events = self
+            .parse_events_from_transaction(signature)
+            .await
+            .unwrap_or_default();
+        let fills = events
+            .iter()
+            .filter_map(|&event| match event.details {
+                MarketEventDetails::Fill(..) => Some(event),
+                _ => None,
+            })
+            .collect::<Vec<PhoenixEvent>>();
+        let places = events
+            .iter()
+            .filter_map(|&event| match event.details {
+                MarketEventDetails::Place(..) => Some(event),
+                _ => None,
+            })
+            .collect::<Vec<PhoenixEvent>>();
+
+        (fills, places)
+    }
+
+    /// Signs and sends `instructions` with the payer, prepending the compute budget instructions
+    /// described by `options`. If the primary endpoint fails with a retryable error and
+    /// secondary endpoints are configured, the same signed transaction is sent to them in turn,
+    /// see `RpcFailover`.
+    pub async fn send_instructions_with_options(
+        &self,
+        instructions: Vec<Instruction>,
+        options: &TransactionOptions,
+    ) -> anyhow::Result<Signature> {
+        self.ensure_signer()?;
+        self.kill_switch
+            .check_instructions(&instructions, &self.program_id)?;
+        self.audit_send_instructions(&instructions)?;
+        self.record_order_instructions(&instructions);
+        let mut ixs = options.get_compute_budget_ixs();
+        ixs.extend(instructions);
+        if self.client.is_bank_client {
+            return Ok(self.client.sign_send_instructions(ixs, vec![]).await?);
+        }
+        let blockhash = self.client.get_latest_blockhash()?;
+        let transaction = Transaction::new_signed_with_payer(
+            &ixs,
+            Some(&self.client.payer.pubkey()),
+            &[&self.client.payer],
+            blockhash,
+        );
+        match self.send_on_primary(&transaction, options) {
+            Ok(signature) => {
+                self.failover.record_send(self.primary_endpoint(), 1);
+                Ok(signature)
+            }
+            Err(e) if is_retryable(&e) && !self.failover.is_empty() => {
+                self.failover
+                    .send(&transaction, options.skip_preflight, 1, e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Signs and sends `instructions`, re-signing with a fresh blockhash and resending up to
+    /// `max_retries` times while the failure is resendable, see `is_resendable`. A resend
+    /// rejected because the transaction already landed counts as a success. If `fee_payer` is
+    /// set it pays the fee and the client's payer signs as the trader.
+    ///
+    /// A transaction that was accepted but not confirmed in time may still land, so it is only
+    /// re-signed once its blockhash expired without it landing; otherwise the orders could be
+    /// placed twice. If it lands while waiting, its signature is returned.
+    pub async fn try_send_instructions_with_retries(
+        &self,
+        instructions: Vec<Instruction>,
+        fee_payer: Option<&Keypair>,
+        options: &TransactionOptions,
+        max_retries: usize,
+    ) -> Result<Signature, PhoenixSdkError> {
+        if self.client.is_bank_client && fee_payer.is_none() {
+            return self
+                .try_send_instructions_with_options(instructions, options)
+                .await;
+        }
+        self.ensure_signer()?;
+        self.kill_switch
+            .check_instructions(&instructions, &self.program_id)?;
+        self.audit_send_instructions(&instructions)?;
+        self.record_order_instructions(&instructions);
+        let mut ixs = options.get_compute_budget_ixs();
+        ixs.extend(instructions);
+        let fee_payer = fee_payer.unwrap_or(&self.client.payer);
+        let mut last_error = anyhow::anyhow!("No send attempted");
+        for _ in 0..=max_retries {
+            let (blockhash, last_valid_block_height) = match self
+                .client
+                .get_latest_blockhash_with_commitment(CommitmentConfig::confirmed())
+            {
+                Ok(blockhash) => blockhash,
+                Err(e) => {
+                    last_error = e.into();
+                    continue;
+                }
+            };
+            let transaction = Transaction::new_signed_with_payer(
+                &ixs,
+                Some(&fee_payer.pubkey()),
+                &[fee_payer, &self.client.payer],
+                blockhash,
+            );
+            let signature = transaction.signatures[0];
+            match send_and_confirm(
+                &self.client,
+                &transaction,
+                CommitmentConfig::confirmed(),
+                options.skip_preflight,
+            ) {
+                Ok(signature) => return Ok(signature),
+                Err(e) => {
+                    if is_already_processed(&e) {
+                        return Ok(signature);
+                    }
+                    if !is_resendable(&e) {
+                        return Err(classify_send_error(&e));
+                    }
+                    // Retryable errors reject the transaction before it is forwarded, the
+                    // others leave it in flight
+                    if !is_retryable(&e) {
+                        match self
+                            .await_signature_outcome(&signature, last_valid_block_height)
+                            .await?
+                        {
+                            SignatureOutcome::Landed { .. } => return Ok(signature),
+                            SignatureOutcome::Failed => {
+                                return Err(PhoenixSdkError::TransactionFailed { logs: vec![] })
+                            }
+                            SignatureOutcome::Expired => {}
+                        }
+                    }
+                    last_error = e;
+                }
+            }
+        }
+        Err(classify_send_error(&last_error))
+    }
+
+    /// Waits until `signature` is confirmed, or until the confirmed block height passes
+    /// `last_valid_block_height`, after which it can no longer land. Fails with `RpcError` if
+    /// neither is observed within `BLOCKHASH_EXPIRY_TIMEOUT`, e.g. while the RPC node is
+    /// unreachable, since the transaction may then still land.
+    async fn await_signature_outcome(
+        &self,
+        signature: &Signature,
+        last_valid_block_height: u64,
+    ) -> Result<SignatureOutcome, PhoenixSdkError> {
+        let started = Instant::now();
+        let mut expired = false;
+        loop {
+            match self.client.get_signature_statuses(&[*signature]) {
+                Ok(response) => match response.value.first() {
+                    Some(Some(status))
+                        if status.satisfies_commitment(CommitmentConfig::confirmed()) =>
+                    {
+                        return Ok(match status.err {
+                            None => SignatureOutcome::Landed { slot: status.slot },
+                            Some(_) => SignatureOutcome::Failed,
+                        });
+                    }
+                    // The status was read after the block height passed the last valid one, so
+                    // the transaction did not land in any block it could land in
+                    _ if expired => return Ok(SignatureOutcome::Expired),
+                    _ => {}
+                },
+                Err(e) => println!("Error fetching the status of {}: {}", signature, e),
+            }
+            if started.elapsed() > BLOCKHASH_EXPIRY_TIMEOUT {
+                return Err(PhoenixSdkError::RpcError(format!(
+                    "Could not determine whether transaction {} landed",
+                    signature
+                )));
+            }
+            match self
+                .client
+                .get_block_height_with_commitment(CommitmentConfig::confirmed())
+            {
+                // Read the status once more before reporting the expiry
+                Ok(block_height) if block_height > last_valid_block_height => {
+                    expired = true;
+                    continue;
+                }
+                Ok(_) => {}
+                Err(e) => println!("Error fetching the block height: {}", e),
+            }
+            tokio::time::sleep(SIGNATURE_STATUS_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Signs and sends `instructions` once and waits for it to land until
+    /// `options.send_deadline`. Whether it met the deadline is decided by the slot it landed
+    /// in, so a transaction that landed in time but whose confirmation is observed late still
+    /// counts as confirmed. A transaction that misses the deadline can still land until its
+    /// blockhash expires, so the send keeps watching it: if it lands, every order it placed is
+    /// cancelled right away with one protective cancel, identified by the Place events of the
+    /// transaction. If the blockhash expires first, nothing rests and nothing is cancelled.
+    ///
+    /// The transaction is never re-signed, a late quote is better dropped than retried, but a
+    /// retryable send error forwards it to the secondary endpoints like
+    /// `send_instructions_with_options`. If `fee_payer` is set it pays the fee and the client's
+    /// payer signs as the trader. Fails with `InvalidArgument` if `options.send_deadline` is
+    /// not set, and with `RpcError` if neither the landing nor the expiry of the transaction
+    /// is observed within `BLOCKHASH_EXPIRY_TIMEOUT`.
+    pub async fn try_send_instructions_with_deadline(
+        &self,
+        instructions: Vec<Instruction>,
+        fee_payer: Option<&Keypair>,
+        options: &TransactionOptions,
+    ) -> Result<DeadlineSendResult, PhoenixSdkError> {
+        let deadline = options.send_deadline.ok_or_else(|| {
+            PhoenixSdkError::InvalidArgument("No send deadline is set".to_string())
+        })?;
+        if self.client.is_bank_client && fee_payer.is_none() {
+            // Bank clients confirm synchronously, so the deadline cannot be missed
+            let signature = self
+                .try_send_instructions_with_options(instructions, options)
+                .await?;
+            return Ok(DeadlineSendResult {
+                signature,
+                outcome: DeadlineOutcome::Confirmed,
+            });
+        }
+        self.ensure_signer()?;
+        self.kill_switch
+            .check_instructions(&instructions, &self.program_id)?;
+        self.audit_send_instructions(&instructions)?;
+        self.record_order_instructions(&instructions);
+        let rpc_error = |e: ClientError| classify_send_error(&anyhow::Error::from(e));
+        let mut ixs = options.get_compute_budget_ixs();
+        ixs.extend(instructions);
+        let fee_payer = fee_payer.unwrap_or(&self.client.payer);
+        let (blockhash, last_valid_block_height) = self
+            .client
+            .get_latest_blockhash_with_commitment(CommitmentConfig::confirmed())
+            .map_err(rpc_error)?;
+        let transaction = Transaction::new_signed_with_payer(
+            &ixs,
+            Some(&fee_payer.pubkey()),
+            &[fee_payer, &self.client.payer],
+            blockhash,
+        );
+        let sent_slot = self
+            .client
+            .get_slot_with_commitment(CommitmentConfig::processed())
+            .map_err(rpc_error)?;
+        let sent_at = Instant::now();
+        let send_config = RpcSendTransactionConfig {
+            skip_preflight: options.skip_preflight,
+            ..RpcSendTransactionConfig::default()
+        };
+        let signature = match self
+            .client
+            .send_transaction_with_config(&transaction, send_config)
+        {
+            Ok(signature) => signature,
+            Err(e) => {
+                let e = anyhow::Error::from(e);
+                if self.failover.is_empty() || !is_retryable(&e) {
+                    return Err(classify_send_error(&e));
+                }
+                self.failover
+                    .forward(&transaction, options.skip_preflight, 1, e)
+                    .map_err(|e| classify_send_error(&e))?
+            }
+        };
+
+        // The transaction met the deadline if it landed in a slot up to `deadline_slot`, however
+        // late its confirmation is observed. For a wall clock deadline that is the slot when the
+        // deadline passes, or the slot it was sent at if that cannot be read.
+        let outcome = self.await_signature_outcome(&signature, last_valid_block_height);
+        futures::pin_mut!(outcome);
+        let deadline_slot = match deadline {
+            SendDeadline::WithinSlots(slots) => sent_slot + slots,
+            SendDeadline::Within(duration) => {
+                let timer = tokio::time::sleep(duration.saturating_sub(sent_at.elapsed()));
+                futures::pin_mut!(timer);
+                match futures::future::select(outcome.as_mut(), timer).await {
+                    Either::Left((outcome, _)) => {
+                        return self
+                            .deadline_send_result(signature, outcome?, None, options)
+                            .await;
+                    }
+                    Either::Right(_) => self
+                        .client
+                        .get_slot_with_commitment(CommitmentConfig::processed())
+                        .unwrap_or(sent_slot),
+                }
+            }
+        };
+        let outcome = outcome.await?;
+        self.deadline_send_result(signature, outcome, Some(deadline_slot), options)
+            .await
+    }
+
+    /// The result of a deadline send whose transaction `signature` had `outcome`, cancelling
+    /// the orders it placed if it landed after `deadline_slot`. Without a deadline slot it
+    /// landed before the deadline passed.
+    async fn deadline_send_result(
+        &self,
+        signature: Signature,
+        outcome: SignatureOutcome,
+        deadline_slot: Option<u64>,
+        options: &TransactionOptions,
+    ) -> Result<DeadlineSendResult, PhoenixSdkError> {
+        let slot = match outcome {
+            SignatureOutcome::Landed { slot } => slot,
+            SignatureOutcome::Failed => {
+                return Err(PhoenixSdkError::TransactionFailed { logs: vec![] })
+            }
+            SignatureOutcome::Expired => {
+                return Ok(DeadlineSendResult {
+                    signature,
+                    outcome: DeadlineOutcome::Expired,
+                })
+            }
+        };
+        if !missed_deadline(slot, deadline_slot) {
+            return Ok(DeadlineSendResult {
+                signature,
+                outcome: DeadlineOutcome::Confirmed,
+            });
+        }
+        println!(
+            "Warning: transaction {} landed after its send deadline, cancelling its orders",
+            signature
+        );
+        let protective_cancel = self.cancel_placed_orders(&signature, options).await;
+        if let Err(e) = protective_cancel.as_ref() {
+            println!("Warning: protective cancel for {} failed: {}", signature, e);
+        }
+        Ok(DeadlineSendResult {
+            signature,
+            outcome: DeadlineOutcome::LandedLate { protective_cancel },
+        })
+    }
+
+    /// Cancels the orders placed by the trader in the transaction `signature`, in one
+    /// transaction with a cancel instruction per market. Returns None if it placed none.
+    async fn cancel_placed_orders(
+        &self,
+        signature: &Signature,
+        options: &TransactionOptions,
+    ) -> Result<Option<Signature>, PhoenixSdkError> {
+        let events = self.parse_events_from_transaction(signature).await.ok_or(
+            PhoenixSdkError::EventParseFailed {
+                signature: *signature,
+            },
+        )?;
+        let orders_by_market = placed_order_ids_by_market(&events, &self.trader);
+        if orders_by_market.is_empty() {
+            return Ok(None);
+        }
+        let mut cancel_ixs = vec![];
+        for (market_key, order_ids) in orders_by_market {
+            cancel_ixs.push(
+                self.get_core_for_market(&market_key)?
+                    .get_cancel_ids_ix(order_ids)?,
+            );
+        }
+        let cancel_options = TransactionOptions {
+            send_deadline: None,
+            ..*options
+        };
+        self.try_send_instructions_with_options(cancel_ixs, &cancel_options)
+            .await
+            .map(Some)
+    }
+
+    /// Signs and sends `instructions` with the payer as a v0 transaction that loads accounts
+    /// from `lookup_tables`. Accounts found in a table take one byte of the transaction instead
+    /// of 32, so roughly twice as many order instructions fit as in a legacy transaction.
+    pub async fn send_instructions_v0(
+        &self,
+        instructions: Vec<Instruction>,
+        lookup_tables: &[AddressLookupTableAccount],
+    ) -> anyhow::Result<Signature> {
+        self.send_instructions_v0_with_options(
+            instructions,
+            lookup_tables,
+            &TransactionOptions::default(),
+        )
+        .await
+    }
+
+    /// Like `send_instructions_v0`, with the compute budget and preflight options of
+    /// `options`. Sends fail over to the secondary endpoints like
+    /// `send_instructions_with_options`, and are retried like
+    /// `try_send_instructions_with_retries`, up to `V0_SEND_MAX_RETRIES` times.
+    pub async fn send_instructions_v0_with_options(
+        &self,
+        instructions: Vec<Instruction>,
+        lookup_tables: &[AddressLookupTableAccount],
+        options: &TransactionOptions,
+    ) -> anyhow::Result<Signature> {
+        self.ensure_signer()?;
+        self.kill_switch
+            .check_instructions(&instructions, &self.program_id)?;
+        self.audit_send_instructions(&instructions)?;
+        self.record_order_instructions(&instructions);
+        let mut ixs = options.get_compute_budget_ixs();
+        ixs.extend(instructions);
+        let payer = &self.client.payer;
+        let mut last_error = anyhow::anyhow!("No send attempted");
+        for _ in 0..=V0_SEND_MAX_RETRIES {
+            let (blockhash, last_valid_block_height) = match self
+                .client
+                .get_latest_blockhash_with_commitment(CommitmentConfig::confirmed())
+            {
+                Ok(blockhash) => blockhash,
+                Err(e) => {
+                    last_error = e.into();
+                    continue;
+                }
+            };
+            let message = v0::Message::try_compile(&payer.pubkey(), &ixs, lookup_tables, blockhash)
+                .map_err(|e| anyhow::anyhow!("Failed to compile v0 message: {:?}", e))?;
+            let transaction =
+                VersionedTransaction::try_new(VersionedMessage::V0(message), &[payer])?;
+            let signature = transaction.signatures[0];
+            let sent = match send_and_confirm(
+                &self.client,
+                &transaction,
+                CommitmentConfig::confirmed(),
+                options.skip_preflight,
+            ) {
+                Ok(signature) => {
+                    self.failover.record_send(self.primary_endpoint(), 1);
+                    Ok(signature)
+                }
+                Err(e) if is_retryable(&e) && !self.failover.is_empty() => {
+                    self.failover
+                        .send(&transaction, options.skip_preflight, 1, e)
+                }
+                Err(e) => Err(e),
+            };
+            let e = match sent {
+                Ok(signature) => return Ok(signature),
+                Err(e) => e,
+            };
+            if is_already_processed(&e) {
+                return Ok(signature);
+            }
+            if !is_resendable(&e) {
+                return Err(e);
+            }
+            // Only re-sign once the transaction can no longer land, see
+            // `try_send_instructions_with_retries`
+            if !is_retryable(&e) {
+                match self
+                    .await_signature_outcome(&signature, last_valid_block_height)
+                    .await?
+                {
+                    SignatureOutcome::Landed { .. } => return Ok(signature),
+                    SignatureOutcome::Failed => {
+                        return Err(PhoenixSdkError::TransactionFailed { logs: vec![] }.into())
+                    }
+                    SignatureOutcome::Expired => {}
+                }
+            }
+            last_error = e;
+        }
+        Err(last_error)
+    }
+
+    fn send_on_primary(
+        &self,
+        transaction: &Transaction,
+        options: &TransactionOptions,
+    ) -> anyhow::Result<Signature> {
+        send_and_confirm(
+            &self.client,
+            transaction,
+            CommitmentConfig::confirmed(),
+            options.skip_preflight,
+        )
+    }
+
+    /// Fetches account data from the primary endpoint, falling back to the secondary
+    /// endpoints if it fails.
+    pub async fn get_account_data_with_failover(&self, key: &Pubkey) -> anyhow::Result<Vec<u8>> {
+        match self.client.get_account_data(key).await {
+            Ok(data) => Ok(data),
+            Err(_) if !self.failover.is_empty() => self.failover.get_account_data(key),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Adds secondary RPC endpoints for failover, using the primary client's commitment
+    pub fn set_fallback_endpoints(&mut self, urls: &[&str]) {
+        self.failover = RpcFailover::new(
+            urls.iter()
+                .map(|url| RpcClient::new_with_commitment(*url, self.client.commitment()))
+                .collect(),
+        );
+    }
+
+    /// Simulates `instructions` in a transaction signed by the payer and returns the slot the
+    /// simulation ran at, its logs, and the data of `addresses` after it. Fails with
+    /// `TransactionFailed` if the transaction would fail.
+    fn simulate_accounts(
+        &self,
+        instructions: &[Instruction],
+        addresses: &[Pubkey],
+        min_context_slot: Option<u64>,
+    ) -> Result<(u64, Vec<String>, Vec<Option<Vec<u8>>>), PhoenixSdkError> {
+        let rpc_error = |e: ClientError| PhoenixSdkError::RpcError(e.to_string());
+        let blockhash = self.client.get_latest_blockhash().map_err(rpc_error)?;
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.client.payer.pubkey()),
+            &[&self.client.payer],
+            blockhash,
+        );
+        let response = self
+            .client
+            .simulate_transaction_with_config(
+                &transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    accounts: Some(RpcSimulateTransactionAccountsConfig {
+                        encoding: Some(UiAccountEncoding::Base64),
+                        addresses: addresses.iter().map(|k| k.to_string()).collect(),
+                    }),
+                    min_context_slot,
+                    ..RpcSimulateTransactionConfig::default()
+                },
+            )
+            .map_err(rpc_error)?;
+        let result = response.value;
+        let logs = result.logs.unwrap_or_default();
+        if result.err.is_some() {
+            return Err(PhoenixSdkError::TransactionFailed { logs });
+        }
+        let accounts = result
+            .accounts
+            .unwrap_or_default()
+            .into_iter()
+            .map(|account| account.and_then(|a| a.decode::<Account>()).map(|a| a.data))
+            .collect::<Vec<_>>();
+        if accounts.len() != addresses.len() {
+            return Err(PhoenixSdkError::RpcError(
+                "Simulation did not return the requested accounts".to_string(),
+            ));
+        }
+        Ok((response.context.slot, logs, accounts))
+    }
+
+    /// Simulates `order_ix` as the only instruction of a transaction signed by the payer and
+    /// returns what the client's trader would fill on `market_key`. Works for any order type;
+    /// an order that would not match returns a summary with `would_fill() == false`. Returns
+    /// `TransactionFailed` with the simulation logs if the transaction would fail.
+    ///
+    /// The state before the order is taken from a first simulation without instructions, and
+    /// the order is simulated no earlier than that slot. If the two simulations still ran at
+    /// different slots, both are repeated, up to `SIMULATE_ORDER_MAX_ATTEMPTS` times, since
+    /// another transaction may have changed the accounts in between.
+    pub async fn simulate_order(
+        &self,
+        market_key: &Pubkey,
+        order_ix: &Instruction,
+    ) -> Result<SimulatedFillSummary, PhoenixSdkError> {
+        let metadata = self.market_metadata(market_key)?;
+        let addresses = [
+            *market_key,
+            get_associated_token_address(&self.trader, &metadata.base_mint),
+            get_associated_token_address(&self.trader, &metadata.quote_mint),
+        ];
+
+        let mut attempt = 0;
+        let (pre_accounts, logs, post_accounts) = loop {
+            attempt += 1;
+            let (pre_slot, _, pre_accounts) = self.simulate_accounts(&[], &addresses, None)?;
+            let (post_slot, logs, post_accounts) =
+                self.simulate_accounts(&[order_ix.clone()], &addresses, Some(pre_slot))?;
+            if post_slot == pre_slot {
+                break (pre_accounts, logs, post_accounts);
+            }
+            if attempt >= SIMULATE_ORDER_MAX_ATTEMPTS {
+                return Err(PhoenixSdkError::RpcError(format!(
+                    "Order simulation ran at slot {} but the state before it was read at slot {}",
+                    post_slot, pre_slot
+                )));
+            }
+        };
+
+        let mut pre_market = pre_accounts[0].clone().unwrap_or_default();
+        let mut post_market = post_accounts[0].clone().unwrap_or_default();
+        let (pre_base_lots, pre_quote_lots, pre_fees) =
+            get_trader_lots_and_fees(&mut pre_market, &self.trader).ok_or(
+                PhoenixSdkError::RpcError(format!("Failed to load market {}", market_key)),
+            )?;
+        let (post_base_lots, post_quote_lots, post_fees) =
+            get_trader_lots_and_fees(&mut post_market, &self.trader).ok_or(
+                PhoenixSdkError::RpcError(format!("Failed to load market {}", market_key)),
+            )?;
+
+        // Net change in the trader's holdings, in atoms, across the wallet and the seat
+        let delta = |pre_atoms: u64, post_atoms: u64, pre_lots: u64, post_lots: u64, lot_size| {
+            post_atoms as i128 - pre_atoms as i128
+                + (post_lots as i128 - pre_lots as i128) * lot_size as i128
+        };
+        let base_delta = delta(
+            get_token_amount(pre_accounts[1].as_deref()),
+            get_token_amount(post_accounts[1].as_deref()),
+            pre_base_lots,
+            post_base_lots,
+            metadata.base_lot_size,
+        );
+        let quote_delta = delta(
+            get_token_amount(pre_accounts[2].as_deref()),
+            get_token_amount(post_accounts[2].as_deref()),
+            pre_quote_lots,
+            post_quote_lots,
+            metadata.quote_lot_size,
+        );
+
+        let fee_in_quote_lots = post_fees.saturating_sub(pre_fees);
+        let base_lots_filled = (base_delta.unsigned_abs() / metadata.base_lot_size as u128) as u64;
+        let quote_lots_moved =
+            (quote_delta.unsigned_abs() / metadata.quote_lot_size as u128) as u64;
+        let (side, quote_lots_filled) = match base_delta.signum() {
+            // Buys pay the fee on top of the fill, sells receive the fill less the fee
+            1 => (
+                Some(Side::Bid),
+                quote_lots_moved.saturating_sub(fee_in_quote_lots),
+            ),
+            -1 => (Some(Side::Ask), quote_lots_moved + fee_in_quote_lots),
+            _ => (None, 0),
+        };
+        let to_units = |lots: u64, lot_size: u64, multiplier: u64| {
+            (lots as u128 * lot_size as u128) as f64 / multiplier as f64
+        };
+
+        Ok(SimulatedFillSummary {
+            side,
+            base_lots_filled,
+            quote_lots_filled,
+            fee_in_quote_lots,
+            base_units_filled: to_units(
+                base_lots_filled,
+                metadata.base_lot_size,
+                metadata.base_multiplier,
+            ),
+            quote_units_filled: to_units(
+                quote_lots_filled,
+                metadata.quote_lot_size,
+                metadata.quote_multiplier,
+            ),
+            fee_in_quote_units: to_units(
+                fee_in_quote_lots,
+                metadata.quote_lot_size,
+                metadata.quote_multiplier,
+            ),
+            logs,
+        })
+    }
+
+    /// Like `send_instructions_with_options`, but classifies failures. If the transaction was
+    /// rejected without logs (e.g. preflight was skipped), it is simulated to recover them.
+    pub async fn try_send_instructions_with_options(
+        &self,
+        instructions: Vec<Instruction>,
+        options: &TransactionOptions,
+    ) -> Result<Signature, PhoenixSdkError> {
+        match self
+            .send_instructions_with_options(instructions.clone(), options)
+            .await
+        {
+            Ok(signature) => Ok(signature),
+            Err(error) => match classify_send_error(&error) {
+                PhoenixSdkError::TransactionFailed { logs }
+                    if logs.is_empty() && !self.client.is_bank_client =>
+                {
+                    Err(PhoenixSdkError::TransactionFailed {
+                        logs: self.simulate_for_logs(instructions, options),
+                    })
+                }
+                e => Err(e),
+            },
+        }
+    }
+
+    fn simulate_for_logs(
+        &self,
+        instructions: Vec<Instruction>,
+        options: &TransactionOptions,
+    ) -> Vec<String> {
+        let mut ixs = options.get_compute_budget_ixs();
+        ixs.extend(instructions);
+        let blockhash = match self.client.get_latest_blockhash() {
+            Ok(blockhash) => blockhash,
+            Err(_) => return vec![],
+        };
+        let transaction = Transaction::new_signed_with_payer(
+            &ixs,
+            Some(&self.client.payer.pubkey()),
+            &[&self.client.payer],
+            blockhash,
+        );
+        self.client
+            .simulate_transaction(&transaction)
+            .ok()
+            .and_then(|response| response.value.logs)
+            .unwrap_or_default()
+    }
+
+    fn ensure_active_market_loaded(&self) -> Result<(), PhoenixSdkError> {
+        self.market_metadata(&self.active_market_key).map(|_| ())
+    }
+
+    /// Sends the instructions and returns the events of the transaction that match `filter`
+    async fn try_send_and_parse_events(
+        &self,
+        instructions: Vec<Instruction>,
+        options: &TransactionOptions,
+        filter: fn(&MarketEventDetails) -> bool,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        let signature = self
+            .try_send_instructions_with_options(instructions, options)
+            .await?;
+        let events = self
+            .parse_events_from_transaction(&signature)
+            .await
+            .ok_or(PhoenixSdkError::EventParseFailed { signature })?;
+        Ok((
+            signature,
+            events
+                .into_iter()
+                .filter(|event| filter(&event.details))
+                .collect(),
+        ))
+    }
+
+    pub async fn try_send_ioc(
+        &self,
+        price: u64,
+        side: Side,
+        size: u64,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.try_send_ioc_with_options(price, side, size, &TransactionOptions::default())
+            .await
+    }
+
+    pub async fn try_send_ioc_with_options(
+        &self,
+        price: u64,
+        side: Side,
+        size: u64,
+        options: &TransactionOptions,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.ensure_active_market_loaded()?;
+        let ix = self.get_ioc_ix(price, side, size)?;
+        self.try_send_and_parse_events(vec![ix], options, |details| {
+            matches!(details, MarketEventDetails::Fill(..))
+        })
+        .await
+    }
+
+    pub async fn try_send_fok_buy(
+        &self,
+        price: u64,
+        size_in_quote_lots: u64,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.try_send_fok_buy_with_options(
+            price,
+            size_in_quote_lots,
+            &TransactionOptions::default(),
+        )
+        .await
+    }
+
+    pub async fn try_send_fok_buy_with_options(
+        &self,
+        price: u64,
+        size_in_quote_lots: u64,
+        options: &TransactionOptions,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.ensure_active_market_loaded()?;
+        let ix = self.get_fok_buy_ix(price, size_in_quote_lots)?;
+        self.try_send_and_parse_events(vec![ix], options, |details| {
+            matches!(details, MarketEventDetails::Fill(..))
+        })
+        .await
+    }
+
+    pub async fn try_send_fok_sell(
+        &self,
+        price: u64,
+        size_in_base_lots: u64,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.try_send_fok_sell_with_options(
+            price,
+            size_in_base_lots,
+            &TransactionOptions::default(),
+        )
+        .await
+    }
+
+    pub async fn try_send_fok_sell_with_options(
+        &self,
+        price: u64,
+        size_in_base_lots: u64,
+        options: &TransactionOptions,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.ensure_active_market_loaded()?;
+        let ix = self.get_fok_sell_ix(price, size_in_base_lots)?;
+        self.try_send_and_parse_events(vec![ix], options, |details| {
+            matches!(details, MarketEventDetails::Fill(..))
+        })
+        .await
+    }
+
+    pub async fn try_send_ioc_with_slippage(
+        &self,
+        lots_in: u64,
+        min_lots_out: u64,
+        side: Side,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.try_send_ioc_with_slippage_with_options(
+            lots_in,
+            min_lots_out,
+            side,
+            &TransactionOptions::default(),
+        )
+        .await
+    }
+
+    pub async fn try_send_ioc_with_slippage_with_options(
+        &self,
+        lots_in: u64,
+        min_lots_out: u64,
+        side: Side,
+        options: &TransactionOptions,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.ensure_active_market_loaded()?;
+        let ix = self.get_ioc_with_slippage_ix(lots_in, min_lots_out, side)?;
+        self.try_send_and_parse_events(vec![ix], options, |details| {
+            matches!(details, MarketEventDetails::Fill(..))
+        })
+        .await
+    }
+
+    /// Sends an IOC order with slippage protection. With `SlippageMode::Relative`, the ladder
+    /// is fetched and `min_lots_out` derived from it right before the order is sent, see
+    /// `SlippageMode::min_lots_out` for the units of `lots_in`.
+    pub async fn try_send_ioc_with_slippage_mode(
+        &self,
+        lots_in: u64,
+        side: Side,
+        mode: SlippageMode,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.try_send_ioc_with_slippage_mode_with_options(
+            lots_in,
+            side,
+            mode,
+            &TransactionOptions::default(),
+        )
+        .await
+    }
+
+    pub async fn try_send_ioc_with_slippage_mode_with_options(
+        &self,
+        lots_in: u64,
+        side: Side,
+        mode: SlippageMode,
+        options: &TransactionOptions,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.ensure_active_market_loaded()?;
+        let min_lots_out = match mode {
+            SlippageMode::Static(min_lots_out) => min_lots_out,
+            SlippageMode::Relative { .. } => {
+                let ladder = self
+                    .get_ladder_for_market(&self.active_market_key, u64::MAX)
+                    .await
+                    .map_err(|e| PhoenixSdkError::RpcError(e.to_string()))?;
+                mode.min_lots_out(&ladder, &self.active_market_metadata()?, side, lots_in)?
+            }
+        };
+        self.try_send_ioc_with_slippage_with_options(lots_in, min_lots_out, side, options)
+            .await
+    }
+
+    pub async fn try_send_post_only(
+        &self,
+        price: u64,
+        side: Side,
+        size: u64,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.try_send_post_only_with_options(price, side, size, &TransactionOptions::default())
+            .await
+    }
+
+    pub async fn try_send_post_only_with_options(
+        &self,
+        price: u64,
+        side: Side,
+        size: u64,
+        options: &TransactionOptions,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.ensure_active_market_loaded()?;
+        let ix = self.get_post_only_ix(price, side, size)?;
+        self.try_send_and_parse_events(vec![ix], options, |details| {
+            matches!(details, MarketEventDetails::Fill(..))
+        })
+        .await
+    }
+
+    /// Sends `post_only_order_template` and reports the price the order was placed at, read
+    /// from its `Place` event. Use `predict_post_only_price_in_ticks` before sending to know
+    /// the price in advance.
+    pub async fn try_send_post_only_from_template(
+        &self,
+        post_only_order_template: &PostOnlyOrderTemplate,
+        options: &TransactionOptions,
+    ) -> Result<PostOnlyPlacement, PhoenixSdkError> {
+        self.ensure_active_market_loaded()?;
+        let requested_price_in_ticks =
+            self.float_price_to_ticks(post_only_order_template.price_as_float)?;
+        let ix = self.get_post_only_ix_from_template(post_only_order_template)?;
+        let (signature, events) = self
+            .try_send_and_parse_events(vec![ix], options, |details| {
+                matches!(details, MarketEventDetails::Place(..))
+            })
+            .await?;
+        let price_in_ticks = events.iter().find_map(|event| match &event.details {
+            MarketEventDetails::Place(place)
+                if place.client_order_id == post_only_order_template.client_order_id =>
+            {
+                Some(place.price_in_ticks)
+            }
+            _ => None,
+        });
+        Ok(PostOnlyPlacement {
+            signature,
+            requested_price_in_ticks,
+            price_in_ticks,
+            was_amended: price_in_ticks
+                .map(|ticks| ticks != requested_price_in_ticks)
+                .unwrap_or(false),
+            price_as_float: price_in_ticks
+                .map(|ticks| self.ticks_to_float_price(ticks))
+                .transpose()?,
+            events,
+        })
+    }
+
+    pub async fn try_send_limit_order(
+        &self,
+        price: u64,
+        side: Side,
+        size: u64,
+    ) -> Result<(Signature, Vec<PhoenixEvent>, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.try_send_limit_order_with_options(price, side, size, &TransactionOptions::default())
+            .await
+    }
+
+    pub async fn try_send_limit_order_with_options(
+        &self,
+        price: u64,
+        side: Side,
+        size: u64,
+        options: &TransactionOptions,
+    ) -> Result<(Signature, Vec<PhoenixEvent>, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.ensure_active_market_loaded()?;
+        let new_order_ix = self.get_limit_order_ix(price, side, size)?;
+        let (signature, events) = self
+            .try_send_and_parse_events(vec![new_order_ix], options, |details| {
+                matches!(
+                    details,
+                    MarketEventDetails::Fill(..) | MarketEventDetails::Place(..)
+                )
+            })
+            .await?;
+        let (fills, places): (Vec<PhoenixEvent>, Vec<PhoenixEvent>) = events
+            .into_iter()
+            .partition(|event| matches!(event.details, MarketEventDetails::Fill(..)));
+        Ok((signature, places, fills))
+    }
+
+    pub async fn try_send_cancel_ids(
+        &self,
+        ids: Vec<FIFOOrderId>,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.try_send_cancel_ids_with_options(ids, &TransactionOptions::default())
+            .await
+    }
+
+    pub async fn try_send_cancel_ids_with_options(
+        &self,
+        ids: Vec<FIFOOrderId>,
+        options: &TransactionOptions,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.ensure_active_market_loaded()?;
+        let ix = self.get_cancel_ids_ix(ids)?;
+        self.try_send_and_parse_events(vec![ix], options, |details| {
+            matches!(details, MarketEventDetails::Reduce(..))
+        })
+        .await
+    }
+
+    /// Cancels `ids` on `market_key` in as many transactions as needed, one cancel instruction
+    /// each, sized for `options` with `max_cancel_ids_per_transaction`. The transactions are
+    /// sent one after the other. Returns their signatures and the Reduce events of all of
+    /// them. Stops at the first transaction that fails, with an error saying how many were
+    /// sent.
+    pub async fn send_cancel_ids_chunked(
+        &self,
+        market_key: &Pubkey,
+        ids: Vec<FIFOOrderId>,
+        options: &TransactionOptions,
+    ) -> anyhow::Result<(Vec<Signature>, Vec<PhoenixEvent>)> {
+        let max_ids_per_ix = self.max_cancel_ids_per_transaction(market_key, options)?;
+        let ixs = self.get_cancel_ids_ixs_chunked(market_key, ids, Some(max_ids_per_ix))?;
+        let num_chunks = ixs.len();
+        let mut signatures = vec![];
+        let mut events = vec![];
+        for (i, ix) in ixs.into_iter().enumerate() {
+            match self
+                .try_send_and_parse_events(vec![ix], options, |details| {
+                    matches!(details, MarketEventDetails::Reduce(..))
+                })
+                .await
+            {
+                Ok((signature, chunk_events)) => {
+                    signatures.push(signature);
+                    events.extend(chunk_events);
+                }
+                Err(e) => {
+                    return Err(anyhow::anyhow!(
+                        "Cancel transaction {} of {} failed, {} were sent: {}",
+                        i + 1,
+                        num_chunks,
+                        signatures.len(),
+                        e
+                    ))
+                }
+            }
+        }
+        Ok((signatures, events))
+    }
+
+    /// Reduces `order_id` on `market_key` by `base_lots_to_remove`, see
+    /// `SDKClientCore::get_reduce_order_ix`, and returns the Reduce events of the transaction
+    pub async fn try_send_reduce_order(
+        &self,
+        market_key: &Pubkey,
+        order_id: FIFOOrderId,
+        base_lots_to_remove: u64,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.try_send_reduce_order_with_options(
+            market_key,
+            order_id,
+            base_lots_to_remove,
+            &TransactionOptions::default(),
+        )
+        .await
+    }
+
+    pub async fn try_send_reduce_order_with_options(
+        &self,
+        market_key: &Pubkey,
+        order_id: FIFOOrderId,
+        base_lots_to_remove: u64,
+        options: &TransactionOptions,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        let ix = self
+            .get_core_for_market(market_key)?
+            .get_reduce_order_ix(order_id, base_lots_to_remove)?;
+        self.try_send_and_parse_events(vec![ix], options, |details| {
+            matches!(details, MarketEventDetails::Reduce(..))
+        })
+        .await
+    }
+
+    pub async fn try_send_cancel_up_to(
+        &self,
+        tick_limit: Option<u64>,
+        side: Side,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.try_send_cancel_up_to_with_options(tick_limit, side, &TransactionOptions::default())
+            .await
+    }
+
+    pub async fn try_send_cancel_up_to_with_options(
+        &self,
+        tick_limit: Option<u64>,
+        side: Side,
+        options: &TransactionOptions,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.ensure_active_market_loaded()?;
+        let ix = self.get_cancel_up_to_ix(tick_limit, side)?;
+        self.try_send_and_parse_events(vec![ix], options, |details| {
+            matches!(details, MarketEventDetails::Reduce(..))
+        })
+        .await
+    }
+
+    /// Cancels the trader's orders on `side` of the active market up to `tick_limit`, at most
+    /// `orders_per_transaction` per transaction, so a side with hundreds of orders does not
+    /// exceed the compute budget of one transaction. Sends `get_cancel_up_to_generic_ix`
+    /// with `num_orders_to_cancel` set until a transaction cancels fewer than
+    /// `orders_per_transaction` orders, which means none are left. Returns the signatures and
+    /// the cancels (Reduce events) of every transaction sent. Fails once `max_transactions`
+    /// transactions each cancelled a full chunk, since orders may then remain, and on a failed
+    /// transaction; the error says how many transactions were sent.
+    pub async fn send_cancel_up_to_in_chunks(
+        &self,
+        side: Side,
+        tick_limit: Option<u64>,
+        orders_per_transaction: u32,
+        max_transactions: usize,
+        options: &TransactionOptions,
+    ) -> anyhow::Result<(Vec<Signature>, Vec<PhoenixEvent>)> {
+        self.ensure_active_market_loaded()?;
+        if orders_per_transaction == 0 {
+            return Err(PhoenixSdkError::InvalidArgument(
+                "Must cancel at least one order per transaction".to_string(),
+            )
+            .into());
+        }
+        if max_transactions == 0 {
+            return Err(PhoenixSdkError::InvalidArgument(
+                "Must send at least one cancel transaction".to_string(),
+            )
+            .into());
+        }
+        let mut signatures = vec![];
+        let mut events = vec![];
+        while signatures.len() < max_transactions {
+            let ix = self.get_cancel_up_to_generic_ix(
+                side,
+                tick_limit,
+                None,
+                Some(orders_per_transaction),
+            )?;
+            let (signature, cancels) = self
+                .try_send_and_parse_events(vec![ix], options, |details| {
+                    matches!(details, MarketEventDetails::Reduce(..))
+                })
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "Cancel transaction {} failed, {} were sent: {}",
+                        signatures.len() + 1,
+                        signatures.len(),
+                        e
+                    )
+                })?;
+            signatures.push(signature);
+            let is_last = cancels.len() < orders_per_transaction as usize;
+            events.extend(cancels);
+            if is_last {
+                return Ok((signatures, events));
+            }
+        }
+        Err(anyhow::anyhow!(
+            "Sent {} cancel transactions that each cancelled {} orders, orders may remain",
+            max_transactions,
+            orders_per_transaction
+        ))
+    }
+
+    pub async fn try_send_cancel_all(
+        &self,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.try_send_cancel_all_with_options(&TransactionOptions::default())
+            .await
+    }
+
+    pub async fn try_send_cancel_all_with_options(
+        &self,
+        options: &TransactionOptions,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        self.ensure_active_market_loaded()?;
+        let ix = self.get_cancel_all_ix()?;
+        self.try_send_and_parse_events(vec![ix], options, |details| {
+            matches!(details, MarketEventDetails::Reduce(..))
+        })
+        .await
+    }
+
+    /// Collects the unclaimed fees of `market_key`, see `get_collect_fees_ix`. Returns the
+    /// signature and the Fee events of the transaction.
+    pub async fn send_collect_fees(
+        &self,
+        market_key: &Pubkey,
+    ) -> anyhow::Result<(Signature, Vec<PhoenixEvent>)> {
+        self.send_collect_fees_with_options(market_key, &TransactionOptions::default())
+            .await
+    }
+
+    pub async fn send_collect_fees_with_options(
+        &self,
+        market_key: &Pubkey,
+        options: &TransactionOptions,
+    ) -> anyhow::Result<(Signature, Vec<PhoenixEvent>)> {
+        let ix = self.get_collect_fees_ix(market_key).await?;
+        Ok(self
+            .try_send_and_parse_events(vec![ix], options, |details| {
+                matches!(details, MarketEventDetails::Fee(..))
+            })
+            .await?)
+    }
+
+    #[deprecated(note = "Use try_send_ioc, which reports why the transaction failed")]
+    pub async fn send_ioc(
+        &self,
+        price: u64,
+        side: Side,
+        size: u64,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(self.try_send_ioc(price, side, size).await)
+    }
+
+    #[deprecated(note = "Use try_send_ioc_with_options, which reports why the transaction failed")]
+    pub async fn send_ioc_with_options(
+        &self,
+        price: u64,
+        side: Side,
+        size: u64,
+        options: &TransactionOptions,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(
+            self.try_send_ioc_with_options(price, side, size, options)
+                .await,
+        )
+    }
+
+    #[deprecated(note = "Use try_send_fok_buy, which reports why the transaction failed")]
+    pub async fn send_fok_buy(
+        &self,
+        price: u64,
+        size_in_quote_lots: u64,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(self.try_send_fok_buy(price, size_in_quote_lots).await)
+    }
+
+    #[deprecated(
+        note = "Use try_send_fok_buy_with_options, which reports why the transaction failed"
+    )]
+    pub async fn send_fok_buy_with_options(
+        &self,
+        price: u64,
+        size_in_quote_lots: u64,
+        options: &TransactionOptions,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(
+            self.try_send_fok_buy_with_options(price, size_in_quote_lots, options)
+                .await,
+        )
+    }
+
+    #[deprecated(note = "Use try_send_fok_sell, which reports why the transaction failed")]
+    pub async fn send_fok_sell(
+        &self,
+        price: u64,
+        size_in_base_lots: u64,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(self.try_send_fok_sell(price, size_in_base_lots).await)
+    }
+
+    #[deprecated(
+        note = "Use try_send_fok_sell_with_options, which reports why the transaction failed"
+    )]
+    pub async fn send_fok_sell_with_options(
+        &self,
+        price: u64,
+        size_in_base_lots: u64,
+        options: &TransactionOptions,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(
+            self.try_send_fok_sell_with_options(price, size_in_base_lots, options)
+                .await,
+        )
+    }
+
+    #[deprecated(note = "Use try_send_ioc_with_slippage, which reports why the transaction failed")]
+    pub async fn send_ioc_with_slippage(
+        &self,
+        lots_in: u64,
+        min_lots_out: u64,
+        side: Side,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(
+            self.try_send_ioc_with_slippage(lots_in, min_lots_out, side)
+                .await,
+        )
+    }
+
+    #[deprecated(
+        note = "Use try_send_ioc_with_slippage_with_options, which reports why the transaction failed"
+    )]
+    pub async fn send_ioc_with_slippage_with_options(
+        &self,
+        lots_in: u64,
+        min_lots_out: u64,
+        side: Side,
+        options: &TransactionOptions,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(
+            self.try_send_ioc_with_slippage_with_options(lots_in, min_lots_out, side, options)
+                .await,
+        )
+    }
+
+    #[deprecated(note = "Use try_send_post_only, which reports why the transaction failed")]
+    pub async fn send_post_only(
+        &self,
+        price: u64,
+        side: Side,
+        size: u64,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(self.try_send_post_only(price, side, size).await)
+    }
+
+    #[deprecated(
+        note = "Use try_send_post_only_with_options, which reports why the transaction failed"
+    )]
+    pub async fn send_post_only_with_options(
+        &self,
+        price: u64,
+        side: Side,
+        size: u64,
+        options: &TransactionOptions,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(
+            self.try_send_post_only_with_options(price, side, size, options)
+                .await,
+        )
+    }
+
+    #[deprecated(note = "Use try_send_limit_order, which reports why the transaction failed")]
+    pub async fn send_limit_order(
+        &self,
+        price: u64,
+        side: Side,
+        size: u64,
+    ) -> Option<(Signature, Vec<PhoenixEvent>, Vec<PhoenixEvent>)> {
+        match self.try_send_limit_order(price, side, size).await {
+            Err(PhoenixSdkError::EventParseFailed { signature }) => {
+                Some((signature, vec![], vec![]))
+            }
+            result => result.ok(),
+        }
+    }
+
+    #[deprecated(
+        note = "Use try_send_limit_order_with_options, which reports why the transaction failed"
+    )]
+    pub async fn send_limit_order_with_options(
+        &self,
+        price: u64,
+        side: Side,
+        size: u64,
+        options: &TransactionOptions,
+    ) -> Option<(Signature, Vec<PhoenixEvent>, Vec<PhoenixEvent>)> {
+        match self
+            .try_send_limit_order_with_options(price, side, size, options)
+            .await
+        {
+            Err(PhoenixSdkError::EventParseFailed { signature }) => {
+                Some((signature, vec![], vec![]))
+            }
+            result => result.ok(),
+        }
+    }
+
+    #[deprecated(note = "Use try_send_cancel_ids, which reports why the transaction failed")]
+    pub async fn send_cancel_ids(
+        &self,
+        ids: Vec<FIFOOrderId>,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(self.try_send_cancel_ids(ids).await)
+    }
+
+    #[deprecated(
+        note = "Use try_send_cancel_ids_with_options, which reports why the transaction failed"
+    )]
+    pub async fn send_cancel_ids_with_options(
+        &self,
+        ids: Vec<FIFOOrderId>,
+        options: &TransactionOptions,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(self.try_send_cancel_ids_with_options(ids, options).await)
+    }
+
+    #[deprecated(note = "Use try_send_reduce_order, which reports why the transaction failed")]
+    pub async fn send_reduce_order(
+        &self,
+        market_key: &Pubkey,
+        order_id: FIFOOrderId,
+        base_lots_to_remove: u64,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(
+            self.try_send_reduce_order(market_key, order_id, base_lots_to_remove)
+                .await,
+        )
+    }
+
+    #[deprecated(
+        note = "Use try_send_reduce_order_with_options, which reports why the transaction failed"
+    )]
+    pub async fn send_reduce_order_with_options(
+        &self,
+        market_key: &Pubkey,
+        order_id: FIFOOrderId,
+        base_lots_to_remove: u64,
+        options: &TransactionOptions,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(
+            self.try_send_reduce_order_with_options(
+                market_key,
+                order_id,
+                base_lots_to_remove,
+                options,
+            )
+            .await,
+        )
+    }
+
+    #[deprecated(note = "Use try_send_cancel_up_to, which reports why the transaction failed")]
+    pub async fn send_cancel_up_to(
+        &self,
+        tick_limit: Option<u64>,
+        side: Side,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(self.try_send_cancel_up_to(tick_limit, side).await)
+    }
+
+    #[deprecated(
+        note = "Use try_send_cancel_up_to_with_options, which reports why the transaction failed"
+    )]
+    pub async fn send_cancel_up_to_with_options(
+        &self,
+        tick_limit: Option<u64>,
+        side: Side,
+        options: &TransactionOptions,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(
+            self.try_send_cancel_up_to_with_options(tick_limit, side, options)
+                .await,
+        )
+    }
+
+    #[deprecated(note = "Use try_send_cancel_all, which reports why the transaction failed")]
+    pub async fn send_cancel_all(&self) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(self.try_send_cancel_all().await)
+    }
+
+    #[deprecated(
+        note = "Use try_send_cancel_all_with_options, which reports why the transaction failed"
+    )]
+    pub async fn send_cancel_all_with_options(
+        &self,
+        options: &TransactionOptions,
+    ) -> Option<(Signature, Vec<PhoenixEvent>)> {
+        landed(self.try_send_cancel_all_with_options(options).await)
+    }
+
+    pub fn get_fok_buy_ix(
+        &self,
+        price: u64,
+        size_in_quote_lots: u64,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        self.get_fok_generic_ix(price, Side::Bid, size_in_quote_lots, None, None, None, None)
+    }
+
+    pub fn get_fok_sell_ix(
+        &self,
+        price: u64,
+        size_in_base_lots: u64,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        self.get_fok_generic_ix(price, Side::Ask, size_in_base_lots, None, None, None, None)
+    }
+
+    /// Returns a cancel all instruction on `market_key` followed by one limit order
+    /// instruction per template. Returns an error with the number of orders that fit if the
+    /// instructions, sent with the compute budget instructions of `options`, do not fit in a
+    /// single transaction.
+    pub fn get_cancel_and_place_ixs(
+        &self,
+        market_key: &Pubkey,
+        orders: &[LimitOrderTemplate],
+        options: &TransactionOptions,
+    ) -> anyhow::Result<Vec<Instruction>> {
+        let core = self.get_core_for_market(market_key)?;
+        let mut ixs = vec![core.get_cancel_all_ix()?];
+        for order in orders {
+            ixs.push(core.get_limit_order_ix_from_template(order)?);
+        }
+        let compute_budget_ixs = options.get_compute_budget_ixs();
+        let payer = self.client.payer.pubkey();
+        // Measured as a v0 transaction if the market has a lookup table
+        let lookup_tables = self
+            .get_market_lookup_table(market_key)
+            .into_iter()
+            .collect::<Vec<_>>();
+        let size = |ixs: &[Instruction]| -> anyhow::Result<usize> {
+            let ixs = compute_budget_ixs
+                .iter()
+                .chain(ixs)
+                .cloned()
+                .collect::<Vec<_>>();
+            if lookup_tables.is_empty() {
+                Ok(get_transaction_size(&ixs, &payer))
+            } else {
+                get_v0_transaction_size(&ixs, &payer, &lookup_tables)
+            }
+        };
+        if size(&ixs)? <= PACKET_DATA_SIZE {
+            return Ok(ixs);
+        }
+        let mut num_orders_that_fit = 0;
+        while num_orders_that_fit < orders.len()
+            && size(&ixs[..num_orders_that_fit + 2])? <= PACKET_DATA_SIZE
+        {
+            num_orders_that_fit += 1;
+        }
+        Err(anyhow::anyhow!(
+            "Cancel and place for {} orders does not fit in a transaction, at most {} orders fit",
+            orders.len(),
+            num_orders_that_fit
+        ))
+    }
+
+    /// A cancel of `replace_order_template.order_to_cancel` on `market_key` followed by its
+    /// new limit order, to be sent in one transaction. Cancelling an order that has already
+    /// been filled or cancelled is a no-op, so the new order is placed either way.
+    pub fn get_replace_order_ixs(
+        &self,
+        market_key: &Pubkey,
+        replace_order_template: &ReplaceOrderTemplate,
+    ) -> Result<Vec<Instruction>, PhoenixSdkError> {
+        let core = self.get_core_for_market(market_key)?;
+        Ok(vec![
+            core.get_cancel_ids_ix(vec![replace_order_template.order_to_cancel])?,
+            core.get_limit_order_ix_from_template(&replace_order_template.new_order)?,
+        ])
+    }
+
+    /// Sends the instructions of `get_replace_order_ixs` in one transaction and returns its
+    /// cancel, place, and fill events.
+    pub async fn send_replace_order(
+        &self,
+        market_key: &Pubkey,
+        replace_order_template: &ReplaceOrderTemplate,
+        options: &TransactionOptions,
+    ) -> Result<ReplacedOrder, PhoenixSdkError> {
+        let ixs = self.get_replace_order_ixs(market_key, replace_order_template)?;
+        let (signature, events) = self
+            .try_send_and_parse_events(ixs, options, |details| {
+                matches!(
+                    details,
+                    MarketEventDetails::Reduce(..)
+                        | MarketEventDetails::Place(..)
+                        | MarketEventDetails::Fill(..)
+                )
+            })
+            .await?;
+        let mut replaced = ReplacedOrder {
+            signature,
+            cancels: vec![],
+            places: vec![],
+            fills: vec![],
+        };
+        for event in events
+            .into_iter()
+            .filter(|event| event.market == *market_key)
+        {
+            match event.details {
+                MarketEventDetails::Reduce(..) => replaced.cancels.push(event),
+                MarketEventDetails::Place(..) => replaced.places.push(event),
+                _ => replaced.fills.push(event),
+            }
+        }
+        Ok(replaced)
+    }
+
+    /// The most order ids a cancel instruction for `market_key` can carry while the
+    /// instruction, with the compute budget instructions of `options`, fits in a legacy
+    /// transaction. Measured by serializing the transaction, so it tracks the instruction
+    /// layout.
+    pub fn max_cancel_ids_per_transaction(
+        &self,
+        market_key: &Pubkey,
+        options: &TransactionOptions,
+    ) -> anyhow::Result<usize> {
+        let core = self.get_core_for_market(market_key)?;
+        let payer = self.client.payer.pubkey();
+        // Ids serialize to a fixed size, so their values do not matter
+        let size = |num_ids: usize| {
+            let mut ixs = options.get_compute_budget_ixs();
+            ixs.push(core.get_cancel_ids_ix(vec![
+                FIFOOrderId {
+                    price_in_ticks: 1,
+                    order_sequence_number: 1,
+                };
+                num_ids
+            ])?);
+            Ok::<_, PhoenixSdkError>(get_transaction_size(&ixs, &payer))
+        };
+        let (one_id, two_ids) = (size(1)?, size(2)?);
+        if one_id > PACKET_DATA_SIZE {
+            return Err(anyhow::anyhow!(
+                "A cancel instruction with one order id does not fit in a transaction"
+            ));
+        }
+        let id_size = two_ids.saturating_sub(one_id).max(1);
+        let mut max_ids = 1 + (PACKET_DATA_SIZE - one_id) / id_size;
+        // The length prefix of the id list can grow by a byte, so check the estimate
+        while max_ids > 1 && size(max_ids)? > PACKET_DATA_SIZE {
+            max_ids -= 1;
+        }
+        Ok(max_ids)
+    }
+
+    /// The most post-only orders, on both sides together, a `PlaceMultiplePostOnlyOrders`
+    /// instruction for `market_key` can carry while the instruction, with the compute budget
+    /// instructions of `options`, fits in a legacy transaction paid by `payer`. A payer other
+    /// than the trader adds a signature and an account. Measured by serializing the
+    /// transaction, like `max_cancel_ids_per_transaction`, and at most
+    /// `MAX_POST_ONLY_ORDERS_PER_SIDE` per side.
+    pub fn max_post_only_orders_per_transaction(
+        &self,
+        market_key: &Pubkey,
+        options: &TransactionOptions,
+        payer: &Pubkey,
+        use_only_deposited_funds: bool,
+    ) -> anyhow::Result<usize> {
+        let core = self.get_core_for_market(market_key)?;
+        // Orders serialize to a fixed size, so their values only need to pass validation
+        let size = |num_orders: usize| {
+            let order = |price_in_ticks: u64| CondensedOrder {
+                price_in_ticks,
+                size_in_base_lots: 1,
+            };
+            let packet = MultipleOrderPacket {
+                bids: vec![order(1); num_orders / 2],
+                asks: vec![order(2); num_orders - num_orders / 2],
+                client_order_id: None,
+                reject_post_only: false,
+            };
+            let mut ixs = options.get_compute_budget_ixs();
+            ixs.push(if use_only_deposited_funds {
+                create_place_multiple_post_only_orders_with_free_funds_instruction_with_program_id(
+                    market_key,
+                    &core.trader,
+                    &packet,
+                    &core.program_id,
+                )
+            } else {
+                let metadata = core.active_market_metadata()?;
+                create_place_multiple_post_only_orders_instruction_with_program_id(
+                    market_key,
+                    &core.trader,
+                    &metadata.base_mint,
+                    &metadata.quote_mint,
+                    &packet,
+                    &core.program_id,
+                )
+            });
+            Ok::<_, PhoenixSdkError>(get_transaction_size(&ixs, payer))
+        };
+        let max_orders = 2 * MAX_POST_ONLY_ORDERS_PER_SIDE;
+        let (one_order, two_orders) = (size(1)?, size(2)?);
+        if one_order > PACKET_DATA_SIZE {
+            return Err(anyhow::anyhow!(
+                "An instruction with one post-only order does not fit in a transaction"
+            ));
+        }
+        let order_size = two_orders.saturating_sub(one_order).max(1);
+        let mut num_orders = (1 + (PACKET_DATA_SIZE - one_order) / order_size).min(max_orders);
+        // The length prefix of the instruction data can grow by a byte, so check the estimate
+        while num_orders > 1 && size(num_orders)? > PACKET_DATA_SIZE {
+            num_orders -= 1;
+        }
+        Ok(num_orders)
+    }
+
+    /// Splits a cancel of `ids` on `market_key` into instructions of at most `max_ids_per_ix`
+    /// ids each, in order. Without `max_ids_per_ix`, each instruction carries as many ids as
+    /// fit in a transaction on its own, see `max_cancel_ids_per_transaction`.
+    pub fn get_cancel_ids_ixs_chunked(
+        &self,
+        market_key: &Pubkey,
+        ids: Vec<FIFOOrderId>,
+        max_ids_per_ix: Option<usize>,
+    ) -> anyhow::Result<Vec<Instruction>> {
+        let max_ids_per_ix = match max_ids_per_ix {
+            Some(max_ids) => max_ids.max(1),
+            None => {
+                self.max_cancel_ids_per_transaction(market_key, &TransactionOptions::default())?
+            }
+        };
+        let core = self.get_core_for_market(market_key)?;
+        Ok(ids
+            .chunks(max_ids_per_ix)
+            .map(|chunk| core.get_cancel_ids_ix(chunk.to_vec()))
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Cancels all orders on `market_key` and places the given limit orders in a single
+    /// transaction. Returns the signature along with the cancel (Reduce) and Place events of
+    /// the transaction. If a lookup table is set for the market, a v0 transaction using it is
+    /// sent.
+    pub async fn send_cancel_and_place(
+        &self,
+        market_key: &Pubkey,
+        orders: &[LimitOrderTemplate],
+    ) -> anyhow::Result<(Signature, Vec<PhoenixEvent>)> {
+        self.send_cancel_and_place_with_options(market_key, orders, &TransactionOptions::default())
+            .await
+    }
+
+    pub async fn send_cancel_and_place_with_options(
+        &self,
+        market_key: &Pubkey,
+        orders: &[LimitOrderTemplate],
+        options: &TransactionOptions,
+    ) -> anyhow::Result<(Signature, Vec<PhoenixEvent>)> {
+        let ixs = self.get_cancel_and_place_ixs(market_key, orders, options)?;
+        let signature = match self.get_market_lookup_table(market_key) {
+            Some(table) if !self.client.is_bank_client => {
+                self.send_instructions_v0_with_options(ixs, &[table], options)
+                    .await?
+            }
+            _ => self.send_instructions_with_options(ixs, options).await?,
+        };
+        let events = self
+            .parse_events_from_transaction(&signature)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|event| {
+                matches!(
+                    event.details,
+                    MarketEventDetails::Reduce(..) | MarketEventDetails::Place(..)
+                )
+            })
+            .collect::<Vec<PhoenixEvent>>();
+        Ok((signature, events))
+    }
+
+    /// The full ladder of `market_key` and its taker fee in basis points, from one read of the
+    /// market
+    async fn get_ladder_and_taker_fee(&self, market_key: &Pubkey) -> anyhow::Result<(Ladder, u64)> {
+        let (_, mut market_account_data) = self
+            .get_account_data_with_commitment(market_key, self.client.commitment())
+            .await?;
+        if market_account_data.len() < size_of::<MarketHeader>() {
+            return Err(anyhow::anyhow!(
+                "Market {} account data is too short",
+                market_key
+            ));
+        }
+        let (header_bytes, bytes) = market_account_data.split_at_mut(size_of::<MarketHeader>());
+        let header = MarketHeader::try_from_slice(header_bytes)?;
+        let market = load_with_dispatch_mut(&header.market_size_params, bytes)
+            .ok_or_else(|| anyhow::anyhow!("Failed to load market {}", market_key))?
+            .inner;
+        Ok((market.get_ladder(u64::MAX), market.get_taker_fee_bps()))
+    }
+
+    /// Quotes a swap of `amount_in_atoms` of `input_mint` for `output_mint` on every loaded
+    /// market for the pair, in either orientation, net of each market's taker fee. Routes that
+    /// fill the whole input are ranked ahead of partial fills, then by expected output.
+    /// Markets that cannot be quoted, e.g. because their account failed to load, are skipped
+    /// and listed in `SwapRoutes::failed`; an error is returned only if no market was quoted.
+    pub async fn quote_swap(
+        &self,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+        amount_in_atoms: u64,
+    ) -> anyhow::Result<SwapRoutes> {
+        let candidates = self
+            .markets
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|(market_key, metadata)| {
+                if metadata.base_mint == *input_mint && metadata.quote_mint == *output_mint {
+                    Some((*market_key, *metadata, Side::Ask))
+                } else if metadata.quote_mint == *input_mint && metadata.base_mint == *output_mint {
+                    Some((*market_key, *metadata, Side::Bid))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No loaded market trades {} against {}",
+                input_mint,
+                output_mint
+            ));
+        }
+
+        let markets = futures::future::join_all(
+            candidates
+                .iter()
+                .map(|(market_key, _, _)| self.get_ladder_and_taker_fee(market_key)),
+        )
+        .await;
+
+        let mut quotes = vec![];
+        let mut failed = vec![];
+        for ((market_key, metadata, side), market) in candidates.into_iter().zip(markets) {
+            let (ladder, taker_fee_bps) = match market {
+                Ok(market) => market,
+                Err(e) => {
+                    failed.push((market_key, e.to_string()));
+                    continue;
+                }
+            };
+            let simulator = LadderSimulator::new(&ladder, &metadata);
+            // The program rounds the taker fee up to a whole quote lot
+            let fee = |quote_lots: u64| {
+                ((quote_lots as u128 * taker_fee_bps as u128 + 9_999) / 10_000) as u64
+            };
+            let (lots_in, lots_to_match, summary, max_fillable, top_of_book) = match side {
+                Side::Ask => {
+                    let lots_in = amount_in_atoms / metadata.base_lot_size;
+                    (
+                        lots_in,
+                        lots_in,
+                        simulator.sell_base(lots_in),
+                        simulator.sell_base(u64::MAX).base_lots_filled,
+                        ladder.bids.first(),
+                    )
+                }
+                Side::Bid => {
+                    let lots_in = amount_in_atoms / metadata.quote_lot_size;
+                    // A buy pays the fee out of the same budget, so less of it is matched
+                    let lots_to_match =
+                        (lots_in as u128 * 10_000 / (10_000 + taker_fee_bps as u128)) as u64;
+                    (
+                        lots_in,
+                        lots_to_match,
+                        simulator.sell_quote(lots_to_match),
+                        simulator.sell_quote(u64::MAX).quote_lots_filled,
+                        ladder.asks.first(),
+                    )
+                }
+            };
+            let (lots_used, lots_out) = match side {
+                Side::Ask => (summary.base_lots_filled, summary.quote_lots_filled),
+                Side::Bid => (summary.quote_lots_filled, summary.base_lots_filled),
+            };
+            let (expected_out_atoms, out_decimals) = match side {
+                Side::Ask => (
+                    lots_out.saturating_sub(fee(lots_out)) * metadata.quote_lot_size,
+                    metadata.quote_decimals,
+                ),
+                Side::Bid => (lots_out * metadata.base_lot_size, metadata.base_decimals),
+            };
+
+            // Output had every lot used been filled at the top of book price
+            let price_impact_bps = top_of_book
+                .map(|order| {
+                    let quote_lots_per_base_lot = order.price_in_ticks as f64
+                        * metadata.tick_size_in_quote_atoms_per_base_unit as f64
+                        / (metadata.num_base_lots_per_base_unit as f64
+                            * metadata.quote_lot_size as f64);
+                    let lots_out_at_top = match side {
+                        Side::Ask => lots_used as f64 * quote_lots_per_base_lot,
+                        Side::Bid => lots_used as f64 / quote_lots_per_base_lot,
+                    };
+                    if lots_out_at_top > 0.0 {
+                        (1.0 - lots_out as f64 / lots_out_at_top) * 10_000.0
+                    } else {
+                        0.0
+                    }
+                })
+                .unwrap_or(0.0);
+
+            let route_ix = phoenix::instructions::create_new_order_instruction(
+                &market_key,
+                &self.trader,
+                &metadata.base_mint,
+                &metadata.quote_mint,
+                &match side {
+                    Side::Bid => {
+                        phoenix::order_packet::OrderPacket::new_ioc_buy_with_slippage(lots_in, 0)
+                    }
+                    Side::Ask => {
+                        phoenix::order_packet::OrderPacket::new_ioc_sell_with_slippage(lots_in, 0)
+                    }
+                },
+            );
+
+            quotes.push(SwapQuote {
+                market: market_key,
+                side,
+                amount_in: amount_in_atoms,
+                expected_out_atoms,
+                expected_out_units: expected_out_atoms as f64 / 10u64.pow(out_decimals) as f64,
+                taker_fee_bps,
+                price_impact_bps,
+                route_accounts: route_ix.accounts.iter().map(|meta| meta.pubkey).collect(),
+                is_partial_fill: lots_to_match > max_fillable,
+            });
+        }
+
+        quotes.sort_by(|a, b| {
+            a.is_partial_fill
+                .cmp(&b.is_partial_fill)
+                .then(b.expected_out_atoms.cmp(&a.expected_out_atoms))
+        });
+        let mut quotes = quotes.into_iter();
+        let best = match quotes.next() {
+            Some(best) => best,
+            None => {
+                return Err(anyhow::anyhow!(
+                    "No market trading {} against {} could be quoted: {}",
+                    input_mint,
+                    output_mint,
+                    failed
+                        .iter()
+                        .map(|(market_key, e)| format!("{}: {}", market_key, e))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            }
+        };
+        Ok(SwapRoutes {
+            best,
+            runner_ups: quotes.collect(),
+            failed,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SDKClient {
+    /// The client's own state as JSON: the active market, the cached market metadata, the kill
+    /// switch, and the RPC failover health. The market cache is reported as busy instead of
+    /// waiting if it is being written.
+    #[allow(clippy::useless_conversion)]
+    pub fn debug_state(&self) -> serde_json::Value {
+        let markets = match self.markets.try_read() {
+            Ok(markets) => serde_json::Value::Object(
+                markets
+                    .iter()
+                    .map(|(key, metadata)| {
+                        (
+                            key.to_string(),
+                            serde_json::json!({
+                                "base_mint": metadata.base_mint.to_string(),
+                                "quote_mint": metadata.quote_mint.to_string(),
+                                "base_decimals": metadata.base_decimals,
+                                "quote_decimals": metadata.quote_decimals,
+                                "base_lot_size": metadata.base_lot_size,
+                                "quote_lot_size": metadata.quote_lot_size,
+                                "tick_size_in_quote_atoms_per_base_unit":
+                                    metadata.tick_size_in_quote_atoms_per_base_unit,
+                                "num_base_lots_per_base_unit":
+                                    metadata.num_base_lots_per_base_unit,
+                            }),
+                        )
+                    })
+                    .collect(),
+            ),
+            Err(_) => serde_json::json!("busy"),
+        };
+        let rpc_health = self
+            .failover
+            .health_report()
+            .into_iter()
+            .map(|health| {
+                serde_json::json!({
+                    "endpoint": health.endpoint,
+                    "samples": health.samples,
+                    "success_rate": health.success_rate,
+                    "p50_latency_ms": health.p50_latency.map(|d| d.as_millis() as u64),
+                    "p95_latency_ms": health.p95_latency.map(|d| d.as_millis() as u64),
+                    "last_error": health.last_error,
+                    "processed_slot_lag": health.processed_slot_lag,
+                    "finalized_slot_lag": health.finalized_slot_lag,
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::json!({
+            "active_market": self.active_market_key.to_string(),
+            "trader": self.trader.to_string(),
+            "read_only": self.read_only,
+            "kill_switch": {
+                "tripped": self.kill_switch.is_tripped(),
+                "reason": self.kill_switch.reason(),
+            },
+            "markets": markets,
+            "rpc_health": rpc_health,
+            "last_send": self.failover.last_send().map(|report| serde_json::json!({
+                "endpoint": report.endpoint,
+                "attempts": report.attempts,
+            })),
+        })
+    }
+
+    /// Writes the client's state and every dump registered in `debug_dump` to `path` as one
+    /// JSON document.
+    pub fn debug_dump_to_file(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let state = catch_unwind(AssertUnwindSafe(|| self.debug_state()))
+            .unwrap_or_else(|_| serde_json::json!({ "error": "dump panicked" }));
+        let document = self
+            .debug_dump
+            .collect(None, vec![("sdk_client".to_string(), state)]);
+        DebugStateDump::write_to_file(&document, path.as_ref())
+    }
+
+    /// Registers the client's state in `debug_dump` as the `sdk_client` component, so dumps
+    /// written without the client at hand, like those of `DebugStateDump::dump_on_kill_switch`,
+    /// include it. The registration holds a weak reference and reports nothing once the client
+    /// is dropped.
+    pub fn register_debug_state(self: &Arc<Self>) {
+        let client = Arc::downgrade(self);
+        self.debug_dump.register("sdk_client", move || {
+            client
+                .upgrade()
+                .map(|client| client.debug_state())
+                .unwrap_or(serde_json::Value::Null)
+        });
+    }
+}
+
+/// A view of an `SDKClient` bound to a single market, returned by `SDKClient::market`.
+#[derive(Clone, Copy)]
+pub struct DefaultMarketClient<'a> {
+    sdk: &'a SDKClient,
+    market_key: Pubkey,
+}
+
+impl DefaultMarketClient<'_> {
+    pub fn market_key(&self) -> Pubkey {
+        self.market_key
+    }
+
+    /// The currently cached metadata of the market
+    pub fn metadata(&self) -> Result<MarketMetadata, PhoenixSdkError> {
+        self.sdk.market_metadata(&self.market_key)
+    }
+
+    pub async fn ladder(&self, levels: u64) -> anyhow::Result<Ladder> {
+        self.sdk
+            .get_ladder_for_market(&self.market_key, levels)
+            .await
+    }
+
+    pub async fn orderbook(&self) -> anyhow::Result<Orderbook<FIFOOrderId, PhoenixOrder>> {
+        self.sdk.get_orderbook_for_market(&self.market_key).await
+    }
+
+    pub fn limit_order_ix(
+        &self,
+        template: &LimitOrderTemplate,
+    ) -> Result<Instruction, PhoenixSdkError> {
+        self.sdk
+            .get_core_for_market(&self.market_key)?
+            .get_limit_order_ix_from_template(template)
+    }
+
+    pub fn cancel_all_ix(&self) -> Result<Instruction, PhoenixSdkError> {
+        self.sdk
+            .get_core_for_market(&self.market_key)?
+            .get_cancel_all_ix()
+    }
+
+    /// The client's trader's resting orders on the market
+    pub async fn open_orders(&self) -> anyhow::Result<Vec<(Side, FIFOOrderId, PhoenixOrder)>> {
+        let orderbook = self.orderbook().await?;
+        let trader = self.sdk.trader;
+        Ok(orderbook
+            .bids
+            .iter()
+            .map(|(id, order)| (Side::Bid, *id, *order))
+            .chain(
+                orderbook
+                    .asks
+                    .iter()
+                    .map(|(id, order)| (Side::Ask, *id, *order)),
+            )
+            .filter(|(_, _, order)| order.maker_id == trader)
+            .collect())
+    }
+
+    pub async fn send_ioc(
+        &self,
+        price: u64,
+        side: Side,
+        size: u64,
+    ) -> Result<(Signature, Vec<PhoenixEvent>), PhoenixSdkError> {
+        let ix = self
+            .sdk
+            .get_core_for_market(&self.market_key)?
+            .get_ioc_ix(price, side, size)?;
+        self.sdk
+            .try_send_and_parse_events(vec![ix], &TransactionOptions::default(), |details| {
+                matches!(details, MarketEventDetails::Fill(..))
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A client that never connects, with one cached market
+    fn offline_client() -> (SDKClient, Pubkey) {
+        let rpc = RpcClient::new("http://127.0.0.1:8899".to_string());
+        let client = EllipsisClient::from_rpc(rpc, &Keypair::new()).unwrap();
+        let sdk = SDKClient::from_parts(client, phoenix::id(), false);
+        let market = Pubkey::new_unique();
+        sdk.insert_market_metadata(
+            market,
+            MarketMetadata {
+                base_mint: Pubkey::new_unique(),
+                quote_mint: Pubkey::new_unique(),
+                base_decimals: 9,
+                quote_decimals: 6,
+                base_multiplier: 1_000_000_000,
+                quote_multiplier: 1_000_000,
+                quote_lot_size: 1,
+                base_lot_size: 1_000_000,
+                tick_size_in_quote_atoms_per_base_unit: 1_000,
+                num_base_lots_per_base_unit: 1_000,
+            },
+        );
+        (sdk, market)
+    }
+
+    fn cancel_size(
+        sdk: &SDKClient,
+        market: &Pubkey,
+        num_ids: u64,
+        options: &TransactionOptions,
+    ) -> usize {
+        let ids = (1..=num_ids).map(order_id).collect::<Vec<_>>();
+        let mut ixs = options.get_compute_budget_ixs();
+        ixs.push(
+            sdk.get_core_for_market(market)
+                .unwrap()
+                .get_cancel_ids_ix(ids)
+                .unwrap(),
+        );
+        get_transaction_size(&ixs, &sdk.client.payer.pubkey())
+    }
+
+    #[test]
+    fn cancel_ids_limit_is_the_most_that_fit() {
+        let (sdk, market) = offline_client();
+        let budgeted = TransactionOptions {
+            compute_unit_limit: Some(200_000),
+            priority_fee_micro_lamports: Some(1_000),
+            ..TransactionOptions::default()
+        };
+        let mut limits = vec![];
+        for options in [TransactionOptions::default(), budgeted] {
+            let max_ids = sdk
+                .max_cancel_ids_per_transaction(&market, &options)
+                .unwrap();
+            assert!(cancel_size(&sdk, &market, max_ids as u64, &options) <= PACKET_DATA_SIZE);
+            assert!(cancel_size(&sdk, &market, max_ids as u64 + 1, &options) > PACKET_DATA_SIZE);
+            limits.push(max_ids);
+        }
+        assert!(limits[1] < limits[0], "{:?}", limits);
+    }
+
+    #[test]
+    fn cancel_ids_are_chunked_in_order() {
+        let (sdk, market) = offline_client();
+        let core = sdk.get_core_for_market(&market).unwrap();
+        let ids = (1..=5).map(order_id).collect::<Vec<_>>();
+        let chunked = |max_ids_per_ix| {
+            sdk.get_cancel_ids_ixs_chunked(&market, ids.clone(), max_ids_per_ix)
+                .unwrap()
+        };
+        let expected = |chunks: &[&[FIFOOrderId]]| {
+            chunks
+                .iter()
+                .map(|chunk| core.get_cancel_ids_ix(chunk.to_vec()).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            chunked(Some(2)),
+            expected(&[&ids[..2], &ids[2..4], &ids[4..]])
+        );
+        // A limit of 0 is treated as 1
+        assert_eq!(chunked(Some(0)).len(), 5);
+        assert_eq!(chunked(None), expected(&[&ids[..]]));
+
+        let max_ids = sdk
+            .max_cancel_ids_per_transaction(&market, &TransactionOptions::default())
+            .unwrap();
+        let many = (1..=2 * max_ids as u64 + 1)
+            .map(order_id)
+            .collect::<Vec<_>>();
+        let ixs = sdk.get_cancel_ids_ixs_chunked(&market, many, None).unwrap();
+        assert_eq!(ixs.len(), 3);
+        for ix in ixs {
+            assert!(get_transaction_size(&[ix], &sdk.client.payer.pubkey()) <= PACKET_DATA_SIZE);
+        }
+    }
+
+    fn place(market: Pubkey, maker: Pubkey, order_sequence_number: u64) -> PhoenixEvent {
+        PhoenixEvent {
+            market,
+            sequence_number: 0,
+            slot: 0,
+            timestamp: 0,
+            signature: Signature::default(),
+            signer: maker,
+            event_index: order_sequence_number,
+            details: MarketEventDetails::Place(Place {
+                order_sequence_number,
+                client_order_id: 0,
+                maker,
+                price_in_ticks: 100 + order_sequence_number,
+                base_lots_placed: 10,
+            }),
+        }
+    }
+
+    fn order_id(order_sequence_number: u64) -> FIFOOrderId {
+        FIFOOrderId {
+            price_in_ticks: 100 + order_sequence_number,
+            order_sequence_number,
+        }
+    }
+
+    #[test]
+    fn only_a_landing_after_the_deadline_slot_is_late() {
+        assert!(!missed_deadline(10, Some(11)));
+        assert!(!missed_deadline(11, Some(11)));
+        assert!(missed_deadline(12, Some(11)));
+        // Observed before a wall clock deadline passed
+        assert!(!missed_deadline(u64::MAX, None));
+    }
+
+    #[test]
+    fn protective_cancel_covers_each_placed_order_once() {
+        let trader = Pubkey::new_unique();
+        let (sol, eth) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut fee = place(sol, trader, 9);
+        fee.details = MarketEventDetails::Fee(1);
+        let events = vec![
+            place(sol, trader, 1),
+            place(eth, trader, 2),
+            place(sol, Pubkey::new_unique(), 3),
+            fee,
+            place(sol, trader, 4),
+        ];
+        let orders = placed_order_ids_by_market(&events, &trader);
+        assert_eq!(
+            orders,
+            BTreeMap::from([
+                (sol, vec![order_id(1), order_id(4)]),
+                (eth, vec![order_id(2)]),
+            ])
+        );
+        assert!(placed_order_ids_by_market(&events[2..4], &trader).is_empty());
+    }
+
+    #[test]
+    fn reports_a_protective_cancel_only_when_one_was_sent() {
+        let result = |outcome| DeadlineSendResult {
+            signature: Signature::default(),
+            outcome,
+        };
+        let cancel = Signature::new_unique();
+        let sent = result(DeadlineOutcome::LandedLate {
+            protective_cancel: Ok(Some(cancel)),
+        });
+        assert_eq!(sent.protective_cancel(), Some(cancel));
+        assert!(sent.protective_cancel_issued());
+        for outcome in [
+            DeadlineOutcome::Confirmed,
+            DeadlineOutcome::Expired,
+            DeadlineOutcome::LandedLate {
+                protective_cancel: Ok(None),
+            },
+            DeadlineOutcome::LandedLate {
+                protective_cancel: Err(PhoenixSdkError::NoSignerConfigured),
+            },
+        ] {
+            assert!(!result(outcome).protective_cancel_issued());
+        }
+    }
+
+    /// Accounts whose lamports are their index in `keys`. Every third account does not exist.
+    fn mock_accounts(keys: &[Pubkey], chunk: &[Pubkey]) -> Vec<Option<Account>> {
+        chunk
+            .iter()
+            .map(|key| {
+                let index = keys.iter().position(|k| k == key).unwrap();
+                (index % 3 != 0).then(|| Account {
+                    lamports: index as u64,
+                    ..Account::default()
+                })
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn fetch_in_chunks_splits_into_requests_of_at_most_100() {
+        let keys = (0..250).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let mut chunk_lens = vec![];
+        let mut progress = vec![];
+
+        let accounts = fetch_in_chunks(
+            &keys,
+            &ScanOptions::default(),
+            |chunk| {
+                chunk_lens.push(chunk.len());
+                futures::future::ready(Ok(mock_accounts(&keys, chunk)))
+            },
+            &mut |p| progress.push(p),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(chunk_lens, vec![100, 100, 50]);
+        assert_eq!(
+            progress,
+            [100, 200, 250]
+                .iter()
+                .map(|&fetched| ScanProgress {
+                    fetched,
+                    total: 250
+                })
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(accounts.len(), keys.len());
+        for (index, (key, account)) in accounts.iter().enumerate() {
+            assert_eq!(*key, keys[index]);
+            match account {
+                Some(account) => assert_eq!(account.lamports, index as u64),
+                None => assert_eq!(index % 3, 0),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_in_chunks_clamps_the_chunk_size() {
+        let keys = (0..250).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        for (chunk_size, expected_requests) in [(0, 250), (1, 250), (60, 5), (500, 3)] {
+            let options = ScanOptions {
+                chunk_size,
+                ..ScanOptions::default()
+            };
+            let mut requests = 0;
+            let accounts = fetch_in_chunks(
+                &keys,
+                &options,
+                |chunk| {
+                    requests += 1;
+                    assert!(!chunk.is_empty() && chunk.len() <= MAX_MULTIPLE_ACCOUNTS);
+                    futures::future::ready(Ok(mock_accounts(&keys, chunk)))
+                },
+                &mut |_| {},
+            )
+            .await
+            .unwrap();
+            assert_eq!(requests, expected_requests, "chunk_size {}", chunk_size);
+            assert_eq!(accounts.len(), keys.len());
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_in_chunks_fails_when_a_request_fails() {
+        let keys = (0..250).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let mut requests = 0;
+        let mut progress = vec![];
+
+        let result = fetch_in_chunks(
+            &keys,
+            &ScanOptions::default(),
+            |chunk| {
+                requests += 1;
+                futures::future::ready(if requests == 2 {
+                    Err(anyhow::anyhow!("rate limited"))
+                } else {
+                    Ok(mock_accounts(&keys, chunk))
+                })
+            },
+            &mut |p| progress.push(p.fetched),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(requests, 2);
+        assert_eq!(progress, vec![100]);
+    }
+
+    #[tokio::test]
+    async fn fetch_in_chunks_of_no_keys_makes_no_request() {
+        let mut requests = 0;
+        let accounts = fetch_in_chunks(
+            &[],
+            &ScanOptions::default(),
+            |_| {
+                requests += 1;
+                futures::future::ready(Ok(vec![]))
+            },
+            &mut |_| {},
+        )
+        .await
+        .unwrap();
+        assert!(accounts.is_empty());
+        assert_eq!(requests, 0);
     }
 }
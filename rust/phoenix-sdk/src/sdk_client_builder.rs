@@ -0,0 +1,198 @@
+use crate::sdk_client::{AddMarketsOptions, SDKClient, ScanOptions};
+use ellipsis_client::EllipsisClient;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signer::keypair::Keypair};
+use std::{path::PathBuf, str::FromStr, time::Duration};
+
+/// Where `SDKClientBuilder::load_all_markets` finds the list of markets.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// Scan the program's market accounts on chain, see `SDKClient::scan_market_headers`
+    #[default]
+    ProgramAccounts,
+    /// A file with one market key per line. Empty lines and lines starting with `#` are
+    /// skipped. Use it where the full program account scan is unavailable or too slow.
+    File(PathBuf),
+}
+
+impl ConfigSource {
+    async fn market_keys(&self, sdk: &SDKClient) -> anyhow::Result<Vec<Pubkey>> {
+        match self {
+            ConfigSource::ProgramAccounts => Ok(sdk
+                .scan_market_headers(&ScanOptions::default(), |_| {})
+                .await?
+                .into_iter()
+                .map(|(market_key, _)| market_key)
+                .collect()),
+            ConfigSource::File(path) => std::fs::read_to_string(path)?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| {
+                    Pubkey::from_str(line)
+                        .map_err(|e| anyhow::anyhow!("Invalid market key {}: {}", line, e))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Configures and creates an `SDKClient`.
+///
+/// The client connects to `url` with the given commitment and RPC timeout, or uses the
+/// `EllipsisClient` passed with `ellipsis_client`. Without a payer, the client is read-only,
+/// see `SDKClient::new_read_only`. The markets passed to `markets` are loaded, followed by
+/// every market of `market_config_source` if `load_all_markets` is set, and the first one
+/// becomes the active market. Any market that fails to load fails `build`.
+pub struct SDKClientBuilder {
+    url: Option<String>,
+    payer: Option<Keypair>,
+    ellipsis_client: Option<EllipsisClient>,
+    commitment: CommitmentConfig,
+    rpc_timeout: Option<Duration>,
+    program_id: Pubkey,
+    markets: Vec<Pubkey>,
+    load_all_markets: bool,
+    market_config_source: ConfigSource,
+    add_markets_options: AddMarketsOptions,
+    audit_instructions: bool,
+}
+
+impl Default for SDKClientBuilder {
+    fn default() -> Self {
+        SDKClientBuilder {
+            url: None,
+            payer: None,
+            ellipsis_client: None,
+            commitment: CommitmentConfig::confirmed(),
+            rpc_timeout: None,
+            program_id: phoenix_types::id(),
+            markets: vec![],
+            load_all_markets: false,
+            market_config_source: ConfigSource::default(),
+            add_markets_options: AddMarketsOptions {
+                require_all: true,
+                ..AddMarketsOptions::default()
+            },
+            audit_instructions: false,
+        }
+    }
+}
+
+impl SDKClientBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_string());
+        self
+    }
+
+    /// The keypair that signs and pays for transactions, and whose orders the client manages
+    pub fn payer(mut self, payer: &Keypair) -> Self {
+        // Keypair is not Clone
+        self.payer = Keypair::from_bytes(&payer.to_bytes()).ok();
+        self
+    }
+
+    /// Uses an existing client instead of `url`, `payer`, `commitment`, and `rpc_timeout`
+    pub fn ellipsis_client(mut self, client: EllipsisClient) -> Self {
+        self.ellipsis_client = Some(client);
+        self
+    }
+
+    /// Defaults to confirmed
+    pub fn commitment(mut self, commitment: CommitmentConfig) -> Self {
+        self.commitment = commitment;
+        self
+    }
+
+    /// Defaults to the RPC client's default timeout
+    pub fn rpc_timeout(mut self, timeout: Duration) -> Self {
+        self.rpc_timeout = Some(timeout);
+        self
+    }
+
+    pub fn program_id(mut self, program_id: Pubkey) -> Self {
+        self.program_id = program_id;
+        self
+    }
+
+    pub fn markets(mut self, market_keys: &[Pubkey]) -> Self {
+        self.markets.extend_from_slice(market_keys);
+        self
+    }
+
+    /// Also loads every market listed by `market_config_source`
+    pub fn load_all_markets(mut self, load_all_markets: bool) -> Self {
+        self.load_all_markets = load_all_markets;
+        self
+    }
+
+    pub fn market_config_source(mut self, source: ConfigSource) -> Self {
+        self.market_config_source = source;
+        self
+    }
+
+    /// Options for loading the markets. `require_all` defaults to true.
+    pub fn add_markets_options(mut self, options: AddMarketsOptions) -> Self {
+        self.add_markets_options = options;
+        self
+    }
+
+    /// Audits the instructions the client sends, see `SDKClient::audit_instructions`
+    pub fn audit_instructions(mut self, audit_instructions: bool) -> Self {
+        self.audit_instructions = audit_instructions;
+        self
+    }
+
+    pub async fn build(self) -> anyhow::Result<SDKClient> {
+        let mut sdk = match self.ellipsis_client {
+            Some(client) => SDKClient::from_parts(client, self.program_id, false),
+            None => {
+                let url = self
+                    .url
+                    .ok_or_else(|| anyhow::anyhow!("SDKClientBuilder needs a url"))?;
+                let rpc = match self.rpc_timeout {
+                    Some(timeout) => {
+                        RpcClient::new_with_timeout_and_commitment(url, timeout, self.commitment)
+                    }
+                    None => RpcClient::new_with_commitment(url, self.commitment),
+                };
+                match &self.payer {
+                    Some(payer) => SDKClient::from_parts(
+                        EllipsisClient::from_rpc(rpc, payer)?,
+                        self.program_id,
+                        false,
+                    ),
+                    None => {
+                        let mut sdk = SDKClient::from_rpc_read_only(rpc)?;
+                        sdk.program_id = self.program_id;
+                        sdk
+                    }
+                }
+            }
+        };
+
+        sdk.audit_instructions = self.audit_instructions;
+
+        let mut market_keys = self.markets;
+        if self.load_all_markets {
+            for market_key in self.market_config_source.market_keys(&sdk).await? {
+                if !market_keys.contains(&market_key) {
+                    market_keys.push(market_key);
+                }
+            }
+        }
+        sdk.add_markets(&market_keys, &self.add_markets_options, |_| {})
+            .await?;
+        if let Some(active_market_key) = market_keys
+            .iter()
+            .find(|market_key| sdk.market_metadata(*market_key).is_ok())
+        {
+            sdk.change_active_market(active_market_key)?;
+        }
+        Ok(sdk)
+    }
+}
@@ -0,0 +1,548 @@
+use crate::market_event_handler::SDKMarketEvent;
+use solana_program::instruction::Instruction;
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex, MutexGuard,
+    },
+    thread::{Builder, JoinHandle},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "executor")]
+use crate::transaction_executor::{
+    ExecutionResult, TransactionExecutor, TransactionExecutorConfig,
+};
+#[cfg(feature = "rpc-client")]
+use crate::{event_poller::EventPoller, sdk_client::SDKClient};
+#[cfg(feature = "rpc-client")]
+use solana_program::pubkey::Pubkey;
+
+/// How often a component waiting out its restart backoff checks for shutdown
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What a `StrategyRuntime` does when one of its components fails. A component fails when it
+/// returns an error, panics, or returns before shutdown was requested.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SupervisionPolicy {
+    /// Restarts the component after a backoff that starts at `initial_backoff` and doubles up
+    /// to `max_backoff` with each consecutive failure. A component that ran for longer than
+    /// `max_backoff` before failing is restarted after `initial_backoff` again. Once the
+    /// component has been restarted `max_restarts` times, the next failure escalates.
+    RestartWithBackoff {
+        initial_backoff: Duration,
+        max_backoff: Duration,
+        max_restarts: Option<u32>,
+    },
+    /// Shuts the whole runtime down. Use for components the others cannot run without, e.g.
+    /// the executor.
+    EscalateToShutdown,
+}
+
+impl SupervisionPolicy {
+    /// Restarts forever, backing off from 500ms to 30s.
+    pub fn restart_with_default_backoff() -> Self {
+        SupervisionPolicy::RestartWithBackoff {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            max_restarts: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComponentStatus {
+    Running,
+    /// The component failed and is waiting out its backoff before it is restarted
+    Restarting,
+    /// The component returned after shutdown was requested
+    Stopped,
+    /// The component failed and escalated, shutting the runtime down
+    Failed,
+}
+
+/// A snapshot of one component of a `StrategyRuntime`, see `StrategyRuntime::health`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ComponentHealth {
+    pub name: String,
+    pub status: ComponentStatus,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+}
+
+/// The receiving end of a channel, shared so that it outlives the component draining it. A
+/// restarted component locks the same receiver and picks up the messages its predecessor
+/// left in the channel.
+pub struct SharedReceiver<T>(Arc<Mutex<Receiver<T>>>);
+
+impl<T> Clone for SharedReceiver<T> {
+    fn clone(&self) -> Self {
+        SharedReceiver(self.0.clone())
+    }
+}
+
+impl<T> SharedReceiver<T> {
+    pub fn new(receiver: Receiver<T>) -> Self {
+        SharedReceiver(Arc::new(Mutex::new(receiver)))
+    }
+
+    /// Locks the receiver for the calling component. A component that panicked while holding
+    /// it leaves it usable.
+    pub fn lock(&self) -> MutexGuard<'_, Receiver<T>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The channels of a strategy: event sources send `SDKMarketEvent` batches to the handler,
+/// which sends instruction batches to the executor. See `StrategyRuntime::add_handler`.
+pub struct StrategyChannels {
+    pub event_sender: Sender<Vec<SDKMarketEvent>>,
+    pub events: SharedReceiver<Vec<SDKMarketEvent>>,
+    pub instruction_sender: Sender<Vec<Instruction>>,
+    pub instructions: SharedReceiver<Vec<Instruction>>,
+}
+
+impl Default for StrategyChannels {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StrategyChannels {
+    pub fn new() -> Self {
+        let (event_sender, events) = channel();
+        let (instruction_sender, instructions) = channel();
+        StrategyChannels {
+            event_sender,
+            events: SharedReceiver::new(events),
+            instruction_sender,
+            instructions: SharedReceiver::new(instructions),
+        }
+    }
+}
+
+/// Owns the threads of a strategy (event sources, handler, executor) and supervises them, so
+/// that a component that dies is restarted or takes the rest of the strategy down with it
+/// instead of leaving it running in a half-broken state.
+///
+/// Each component is a closure that runs the component on the calling thread until the shared
+/// shutdown flag it is given is set, like `EventPoller::run` and
+/// `TransactionExecutor::run_with_config`. The closure is called again on every restart, so it
+/// must create whatever the component consumes, or take it from state shared with the other
+/// components, e.g. a `SharedReceiver`. `add_event_poller`, `add_handler` and `add_executor`
+/// wire the usual components through a `StrategyChannels`.
+pub struct StrategyRuntime {
+    shutdown: Arc<AtomicBool>,
+    health: Arc<Mutex<Vec<ComponentHealth>>>,
+    /// The escalated component and its error, if any
+    escalation: Arc<Mutex<Option<(String, String)>>>,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Default for StrategyRuntime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StrategyRuntime {
+    pub fn new() -> Self {
+        StrategyRuntime {
+            shutdown: Arc::new(AtomicBool::new(false)),
+            health: Arc::new(Mutex::new(vec![])),
+            escalation: Arc::new(Mutex::new(None)),
+            workers: Mutex::new(vec![]),
+        }
+    }
+
+    /// Starts `component` on its own thread under `policy`. `component` is given the shutdown
+    /// flag of the runtime and should return soon after it is set.
+    pub fn add_component(
+        &self,
+        name: &str,
+        policy: SupervisionPolicy,
+        component: impl FnMut(Arc<AtomicBool>) -> anyhow::Result<()> + Send + 'static,
+    ) {
+        let index = {
+            let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+            health.push(ComponentHealth {
+                name: name.to_string(),
+                status: ComponentStatus::Running,
+                restarts: 0,
+                last_error: None,
+            });
+            health.len() - 1
+        };
+        let supervisor = Supervisor {
+            name: name.to_string(),
+            index,
+            policy,
+            shutdown: self.shutdown.clone(),
+            health: self.health.clone(),
+            escalation: self.escalation.clone(),
+        };
+        let worker = Builder::new()
+            .name(format!("strategy-{}", name))
+            .spawn(move || supervisor.run(component))
+            .unwrap();
+        self.workers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(worker);
+    }
+
+    /// Starts `handler` on the events of `channels` under `policy`. `handler` is called with
+    /// every event batch and sends the instruction batches it builds to the executor through
+    /// the sender it is given. It keeps its state across restarts, and the batch it failed on
+    /// is not handed to it again.
+    pub fn add_handler(
+        &self,
+        name: &str,
+        policy: SupervisionPolicy,
+        channels: &StrategyChannels,
+        mut handler: impl FnMut(Vec<SDKMarketEvent>, &Sender<Vec<Instruction>>) -> anyhow::Result<()>
+            + Send
+            + 'static,
+    ) {
+        let events = channels.events.clone();
+        let instruction_sender = channels.instruction_sender.clone();
+        self.add_component(name, policy, move |shutdown| {
+            let events = events.lock();
+            while !shutdown.load(Ordering::Relaxed) {
+                match events.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+                    Ok(batch) => handler(batch, &instruction_sender)?,
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => {
+                        return Err(anyhow::anyhow!("The event channel disconnected"))
+                    }
+                }
+            }
+            Ok(())
+        });
+    }
+
+    /// Starts an `EventPoller` for `market_keys` under `policy`, sending to the events of
+    /// `channels`. A restarted poller starts from the newest signatures again.
+    #[cfg(feature = "rpc-client")]
+    pub fn add_event_poller(
+        &self,
+        name: &str,
+        policy: SupervisionPolicy,
+        channels: &StrategyChannels,
+        sdk: Arc<SDKClient>,
+        market_keys: Vec<Pubkey>,
+        timeout_ms: u64,
+    ) {
+        let event_sender = channels.event_sender.clone();
+        self.add_component(name, policy, move |shutdown| {
+            EventPoller::run(
+                event_sender.clone(),
+                sdk.clone(),
+                market_keys.clone(),
+                timeout_ms,
+                None,
+                shutdown,
+            );
+            Ok(())
+        });
+    }
+
+    /// Starts a `TransactionExecutor` under `policy` that sends the instruction batches of
+    /// `channels`. A restarted executor sends the batches its predecessor left in the channel.
+    #[cfg(feature = "executor")]
+    pub fn add_executor(
+        &self,
+        name: &str,
+        policy: SupervisionPolicy,
+        channels: &StrategyChannels,
+        sdk: Arc<SDKClient>,
+        config: TransactionExecutorConfig,
+        results: Option<Sender<ExecutionResult>>,
+    ) {
+        let instructions = channels.instructions.clone();
+        self.add_component(name, policy, move |shutdown| {
+            TransactionExecutor::run_with_receiver(
+                sdk.clone(),
+                &instructions.lock(),
+                config,
+                results.clone(),
+                shutdown,
+            );
+            Ok(())
+        });
+    }
+
+    /// The flag every component is given. Setting it is the same as calling `shutdown`.
+    pub fn shutdown_flag(&self) -> Arc<AtomicBool> {
+        self.shutdown.clone()
+    }
+
+    /// Asks every component to stop. Use `run_until_shutdown` to wait for them.
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::Relaxed)
+    }
+
+    /// The status of every component, in the order they were added.
+    pub fn health(&self) -> Vec<ComponentHealth> {
+        self.health
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Blocks until shutdown is requested, or a component escalates, and every component has
+    /// returned. Returns an error naming the component if the runtime was shut down by an
+    /// escalation. Components added while this is running are not waited for.
+    pub fn run_until_shutdown(&self) -> anyhow::Result<()> {
+        let workers = std::mem::take(&mut *self.workers.lock().unwrap_or_else(|e| e.into_inner()));
+        for worker in workers {
+            if worker.join().is_err() {
+                println!("Warning: a strategy supervisor thread panicked");
+            }
+        }
+        match self
+            .escalation
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+        {
+            Some((name, error)) => Err(anyhow::anyhow!(
+                "Strategy component {} failed and shut the runtime down: {}",
+                name,
+                error
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+struct Supervisor {
+    name: String,
+    index: usize,
+    policy: SupervisionPolicy,
+    shutdown: Arc<AtomicBool>,
+    health: Arc<Mutex<Vec<ComponentHealth>>>,
+    escalation: Arc<Mutex<Option<(String, String)>>>,
+}
+
+impl Supervisor {
+    fn update(&self, f: impl FnOnce(&mut ComponentHealth)) {
+        let mut health = self.health.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut health[self.index]);
+    }
+
+    fn escalate(&self, error: String) {
+        println!(
+            "Strategy component {} failed, shutting down: {}",
+            self.name, error
+        );
+        self.update(|health| health.status = ComponentStatus::Failed);
+        let mut escalation = self.escalation.lock().unwrap_or_else(|e| e.into_inner());
+        // Keep the first escalation, later ones are usually fallout from the shutdown
+        if escalation.is_none() {
+            *escalation = Some((self.name.clone(), error));
+        }
+        drop(escalation);
+        self.shutdown.store(true, Ordering::Relaxed);
+    }
+
+    /// Sleeps for `backoff`, returning early with false if shutdown is requested.
+    fn wait_out(&self, backoff: Duration) -> bool {
+        let deadline = Instant::now() + backoff;
+        loop {
+            if self.shutdown.load(Ordering::Relaxed) {
+                return false;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return true;
+            }
+            std::thread::sleep(SHUTDOWN_POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    fn run(self, mut component: impl FnMut(Arc<AtomicBool>) -> anyhow::Result<()>) {
+        let mut backoff = None;
+        loop {
+            self.update(|health| health.status = ComponentStatus::Running);
+            let started = Instant::now();
+            let result = catch_unwind(AssertUnwindSafe(|| component(self.shutdown.clone())));
+            if self.shutdown.load(Ordering::Relaxed) {
+                if let Ok(Err(e)) = &result {
+                    let error = e.to_string();
+                    self.update(|health| health.last_error = Some(error));
+                }
+                self.update(|health| {
+                    if health.status != ComponentStatus::Failed {
+                        health.status = ComponentStatus::Stopped;
+                    }
+                });
+                return;
+            }
+            let error = match result {
+                Ok(Ok(())) => "returned before shutdown was requested".to_string(),
+                Ok(Err(e)) => e.to_string(),
+                Err(_) => "panicked".to_string(),
+            };
+            let error_for_health = error.clone();
+            self.update(|health| health.last_error = Some(error_for_health));
+
+            let (initial_backoff, max_backoff, max_restarts) = match self.policy {
+                SupervisionPolicy::EscalateToShutdown => return self.escalate(error),
+                SupervisionPolicy::RestartWithBackoff {
+                    initial_backoff,
+                    max_backoff,
+                    max_restarts,
+                } => (initial_backoff, max_backoff, max_restarts),
+            };
+            let restarts =
+                self.health.lock().unwrap_or_else(|e| e.into_inner())[self.index].restarts;
+            if max_restarts.map(|max| restarts >= max).unwrap_or(false) {
+                return self.escalate(format!("{} (after {} restarts)", error, restarts));
+            }
+            // A component that ran for a while is not failing in a tight loop
+            let next_backoff = match backoff {
+                Some(previous) if started.elapsed() <= max_backoff => {
+                    (previous * 2).min(max_backoff)
+                }
+                _ => initial_backoff.min(max_backoff),
+            };
+            backoff = Some(next_backoff);
+            println!(
+                "Warning: strategy component {} failed, restarting in {:?}: {}",
+                self.name, next_backoff, error
+            );
+            self.update(|health| health.status = ComponentStatus::Restarting);
+            if !self.wait_out(next_backoff) {
+                self.update(|health| health.status = ComponentStatus::Stopped);
+                return;
+            }
+            self.update(|health| health.restarts += 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::pubkey::Pubkey;
+
+    fn fast_restarts(max_restarts: Option<u32>) -> SupervisionPolicy {
+        SupervisionPolicy::RestartWithBackoff {
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+            max_restarts,
+        }
+    }
+
+    fn run_until(shutdown: Arc<AtomicBool>) -> anyhow::Result<()> {
+        while !shutdown.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        Ok(())
+    }
+
+    fn wait_for(condition: impl Fn() -> bool) {
+        let started = Instant::now();
+        while !condition() {
+            assert!(started.elapsed() < Duration::from_secs(5), "Timed out");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_restarts_failed_component() {
+        let runtime = StrategyRuntime::new();
+        let mut runs = 0;
+        runtime.add_component("flaky", fast_restarts(None), move |shutdown| {
+            runs += 1;
+            if runs <= 2 {
+                return Err(anyhow::anyhow!("run {} failed", runs));
+            }
+            run_until(shutdown)
+        });
+        wait_for(|| {
+            let health = &runtime.health()[0];
+            health.restarts == 2 && health.status == ComponentStatus::Running
+        });
+        runtime.shutdown();
+        assert!(runtime.run_until_shutdown().is_ok());
+        let health = &runtime.health()[0];
+        assert_eq!(health.status, ComponentStatus::Stopped);
+        assert_eq!(health.last_error.as_deref(), Some("run 2 failed"));
+    }
+
+    #[test]
+    fn test_escalates_after_max_restarts() {
+        let runtime = StrategyRuntime::new();
+        runtime.add_component("doomed", fast_restarts(Some(1)), |_| {
+            Err(anyhow::anyhow!("boom"))
+        });
+        runtime.add_component("bystander", fast_restarts(None), run_until);
+        let error = runtime.run_until_shutdown().unwrap_err().to_string();
+        assert!(error.contains("doomed"), "{}", error);
+        assert!(error.contains("boom (after 1 restarts)"), "{}", error);
+        assert!(runtime.is_shutdown());
+        let health = runtime.health();
+        assert_eq!(health[0].status, ComponentStatus::Failed);
+        assert_eq!(health[0].restarts, 1);
+        assert_eq!(health[1].status, ComponentStatus::Stopped);
+    }
+
+    #[test]
+    fn test_panic_escalates_to_shutdown() {
+        let runtime = StrategyRuntime::new();
+        runtime.add_component("executor", SupervisionPolicy::EscalateToShutdown, |_| {
+            panic!("lost the payer")
+        });
+        let error = runtime.run_until_shutdown().unwrap_err().to_string();
+        assert!(error.contains("executor failed"), "{}", error);
+        assert!(error.contains("panicked"), "{}", error);
+        assert_eq!(runtime.health()[0].status, ComponentStatus::Failed);
+    }
+
+    #[test]
+    fn test_restarted_handler_drains_same_channel() {
+        let runtime = StrategyRuntime::new();
+        let channels = StrategyChannels::new();
+        let mut failed = false;
+        runtime.add_handler(
+            "handler",
+            fast_restarts(None),
+            &channels,
+            move |events, sender| {
+                if !failed {
+                    failed = true;
+                    panic!("first batch");
+                }
+                sender.send(vec![Instruction::new_with_bytes(
+                    Pubkey::default(),
+                    &[events.len() as u8],
+                    vec![],
+                )])?;
+                Ok(())
+            },
+        );
+        for _ in 0..3 {
+            channels
+                .event_sender
+                .send(vec![SDKMarketEvent::RefreshEvent])
+                .unwrap();
+        }
+        let instructions = channels.instructions.lock();
+        for _ in 0..2 {
+            let batch = instructions.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert_eq!(batch[0].data, vec![1]);
+        }
+        drop(instructions);
+        runtime.shutdown();
+        assert!(runtime.run_until_shutdown().is_ok());
+        assert_eq!(runtime.health()[0].restarts, 1);
+    }
+}
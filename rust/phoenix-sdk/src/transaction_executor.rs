@@ -1,57 +1,451 @@
-use crate::sdk_client::SDKClient;
-use solana_program::instruction::Instruction;
+use crate::{
+    error::PhoenixSdkError,
+    instruction_queue::InstructionReceiver,
+    market_event_handler::{PhoenixEvent, SDKMarketEvent},
+    payer_pool::PayerPool,
+    sdk_client::{DeadlineOutcome, SDKClient, TransactionOptions},
+};
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_sdk::{
+    signature::{Signature, Signer},
+    signer::keypair::Keypair,
+};
 use std::{
-    sync::{mpsc::Receiver, Arc},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
     thread::{Builder, JoinHandle},
+    time::Duration,
 };
+use tokio::{runtime::Runtime, sync::Semaphore};
+
+/// How often a waiting executor checks whether it was asked to shut down
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The outcome of one instruction batch sent by a `TransactionExecutor`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecutionResult {
+    /// The signature of the transaction, None if it could not be sent
+    pub signature: Option<Signature>,
+    pub fills: Vec<PhoenixEvent>,
+    pub places: Vec<PhoenixEvent>,
+    pub error: Option<PhoenixSdkError>,
+    /// The cancel sent because the transaction landed after `TransactionOptions::send_deadline`
+    pub protective_cancel: Option<Signature>,
+    /// The fee payer picked from the executor's `PayerPool`, None if the SDK's payer paid
+    pub fee_payer: Option<Pubkey>,
+}
+
+/// Send behavior of a `TransactionExecutor`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransactionExecutorConfig {
+    pub options: TransactionOptions,
+    /// How many times a batch is re-signed with a fresh blockhash and resent after the RPC
+    /// drops it or its blockhash expires. A transaction reported as already processed counts
+    /// as sent.
+    pub max_retries: usize,
+    /// The most transactions the executor waits on at once. Further batches stay in the
+    /// channel until a slot frees up. Defaults to 1, so batches land in the order they were
+    /// received; with more, a later batch can land before an earlier one.
+    pub max_in_flight: usize,
+}
+
+impl Default for TransactionExecutorConfig {
+    fn default() -> Self {
+        TransactionExecutorConfig {
+            options: TransactionOptions::default(),
+            max_retries: 3,
+            max_in_flight: 1,
+        }
+    }
+}
+
+/// Where an executor receives its instruction batches from
+enum BatchSource<'a> {
+    Channel(&'a Receiver<Vec<Instruction>>),
+    Queue(&'a InstructionReceiver),
+}
+
+impl BatchSource<'_> {
+    fn try_recv(&self) -> Option<Vec<Instruction>> {
+        match self {
+            BatchSource::Channel(receiver) => receiver.try_recv().ok(),
+            BatchSource::Queue(receiver) => receiver.try_recv().ok(),
+        }
+    }
+
+    fn recv_timeout(&self, timeout: Duration) -> Result<Vec<Instruction>, RecvTimeoutError> {
+        match self {
+            BatchSource::Channel(receiver) => receiver.recv_timeout(timeout),
+            BatchSource::Queue(receiver) => receiver.recv_timeout(timeout),
+        }
+    }
+}
 
 pub struct TransactionExecutor {
     pub worker: JoinHandle<()>,
+    shutdown: Arc<AtomicBool>,
 }
 
 impl TransactionExecutor {
     pub fn new(client: Arc<SDKClient>, receiver: Receiver<Vec<Instruction>>) -> Self {
+        Self::new_with_options(client, receiver, TransactionOptions::default())
+    }
+
+    /// Sends every received instruction batch with the given compute budget and preflight options
+    pub fn new_with_options(
+        client: Arc<SDKClient>,
+        receiver: Receiver<Vec<Instruction>>,
+        options: TransactionOptions,
+    ) -> Self {
+        Self::new_with_results(client, receiver, options, None)
+    }
+
+    /// Sends every received instruction batch with the retry and concurrency limits of `config`.
+    /// If `results` is set, the outcome of every batch is sent to it.
+    pub fn new_with_config(
+        client: Arc<SDKClient>,
+        receiver: Receiver<Vec<Instruction>>,
+        config: TransactionExecutorConfig,
+        results: Option<Sender<ExecutionResult>>,
+    ) -> Self {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let worker_shutdown = shutdown.clone();
+        let worker = Builder::new()
+            .name("transaction-executor".to_string())
+            .spawn(move || {
+                Self::run_with_config(client, receiver, config, results, worker_shutdown)
+            })
+            .unwrap();
+
+        Self { worker, shutdown }
+    }
+
+    /// Like `new_with_options`, but the outcome of every batch is also sent to `results`
+    pub fn new_with_results(
+        client: Arc<SDKClient>,
+        receiver: Receiver<Vec<Instruction>>,
+        options: TransactionOptions,
+        results: Option<Sender<ExecutionResult>>,
+    ) -> Self {
+        let config = TransactionExecutorConfig {
+            options,
+            ..TransactionExecutorConfig::default()
+        };
+        Self::new_with_config(client, receiver, config, results)
+    }
+
+    /// Like `new_with_config`, but each transaction's fee is paid by a payer from `payer_pool`.
+    /// Payer balances are checked every `payer_pool.balance_check_interval`, and a
+    /// `LowPayerBalance` event is sent to `event_sender` for each payer that runs low.
+    pub fn new_with_payer_pool(
+        client: Arc<SDKClient>,
+        receiver: Receiver<Vec<Instruction>>,
+        config: TransactionExecutorConfig,
+        payer_pool: PayerPool,
+        event_sender: Option<Sender<Vec<SDKMarketEvent>>>,
+        results: Option<Sender<ExecutionResult>>,
+    ) -> Self {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let worker_shutdown = shutdown.clone();
+        let worker = Builder::new()
+            .name("transaction-executor".to_string())
+            .spawn(move || {
+                Self::run_with_payer_pool(
+                    client,
+                    receiver,
+                    config,
+                    payer_pool,
+                    event_sender,
+                    results,
+                    worker_shutdown,
+                )
+            })
+            .unwrap();
+
+        Self { worker, shutdown }
+    }
+
+    /// Like `new_with_config`, but batches are received from a bounded two-priority
+    /// `instruction_queue`: urgent batches are sent before normal ones, and a strategy that
+    /// outpaces the executor is slowed down instead of queueing stale batches without bound.
+    /// If `payer_pool` is set, fees are paid as with `new_with_payer_pool`.
+    pub fn new_with_queue(
+        client: Arc<SDKClient>,
+        receiver: InstructionReceiver,
+        config: TransactionExecutorConfig,
+        payer_pool: Option<PayerPool>,
+        event_sender: Option<Sender<Vec<SDKMarketEvent>>>,
+        results: Option<Sender<ExecutionResult>>,
+    ) -> Self {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let worker_shutdown = shutdown.clone();
         let worker = Builder::new()
             .name("transaction-executor".to_string())
-            .spawn(move || Self::run(client.clone(), receiver))
+            .spawn(move || {
+                Self::execute(
+                    client,
+                    BatchSource::Queue(&receiver),
+                    config,
+                    payer_pool,
+                    event_sender,
+                    results,
+                    worker_shutdown,
+                )
+            })
             .unwrap();
 
-        Self { worker }
+        Self { worker, shutdown }
+    }
+
+    /// Asks the executor to stop. Instruction batches already in the channel are still sent
+    /// before the executor exits. Use `join` to wait for it.
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::Relaxed);
     }
 
     pub fn join(self) {
         self.worker.join().unwrap()
     }
 
-    pub fn run(sdk: Arc<SDKClient>, receiver: Receiver<Vec<Instruction>>) {
-        let rt = tokio::runtime::Runtime::new().unwrap();
+    /// Returns the next instruction batch, or None once the channel is disconnected or, after
+    /// `shutdown` is set, once the batches already in the channel have been drained.
+    fn next_batch(receiver: &BatchSource, shutdown: &AtomicBool) -> Option<Vec<Instruction>> {
         loop {
-            let instructions = match receiver.recv() {
-                Ok(instructions) => instructions,
-                Err(_) => {
-                    continue;
+            if shutdown.load(Ordering::Relaxed) {
+                return receiver.try_recv();
+            }
+            match receiver.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+                Ok(instructions) => return Some(instructions),
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+
+    /// Sends every received instruction batch with the default config until the channel
+    /// disconnects.
+    #[deprecated(note = "Use run_with_config, which takes the send options and a shutdown flag")]
+    pub fn run(sdk: Arc<SDKClient>, receiver: Receiver<Vec<Instruction>>) {
+        Self::run_with_config(
+            sdk,
+            receiver,
+            TransactionExecutorConfig::default(),
+            None,
+            Arc::new(AtomicBool::new(false)),
+        )
+    }
+
+    /// Sends every received instruction batch until the channel disconnects or `shutdown` is
+    /// set and the channel is drained.
+    pub fn run_with_config(
+        sdk: Arc<SDKClient>,
+        receiver: Receiver<Vec<Instruction>>,
+        config: TransactionExecutorConfig,
+        results: Option<Sender<ExecutionResult>>,
+        shutdown: Arc<AtomicBool>,
+    ) {
+        Self::run_with_receiver(sdk, &receiver, config, results, shutdown)
+    }
+
+    /// Like `run_with_config`, but borrows `receiver`, so the batches left in the channel
+    /// when it returns can be sent by the next executor, e.g. one restarted by a
+    /// `StrategyRuntime`.
+    pub fn run_with_receiver(
+        sdk: Arc<SDKClient>,
+        receiver: &Receiver<Vec<Instruction>>,
+        config: TransactionExecutorConfig,
+        results: Option<Sender<ExecutionResult>>,
+        shutdown: Arc<AtomicBool>,
+    ) {
+        Self::execute(
+            sdk,
+            BatchSource::Channel(receiver),
+            config,
+            None,
+            None,
+            results,
+            shutdown,
+        )
+    }
+
+    pub fn run_with_payer_pool(
+        sdk: Arc<SDKClient>,
+        receiver: Receiver<Vec<Instruction>>,
+        config: TransactionExecutorConfig,
+        payer_pool: PayerPool,
+        event_sender: Option<Sender<Vec<SDKMarketEvent>>>,
+        results: Option<Sender<ExecutionResult>>,
+        shutdown: Arc<AtomicBool>,
+    ) {
+        Self::execute(
+            sdk,
+            BatchSource::Channel(&receiver),
+            config,
+            Some(payer_pool),
+            event_sender,
+            results,
+            shutdown,
+        )
+    }
+
+    /// Receives batches on the calling thread and sends each one as a task on the runtime, with
+    /// at most `config.max_in_flight` tasks running at once. Fee payers are selected in the
+    /// order the batches are received. Returns once every task has finished.
+    fn execute(
+        sdk: Arc<SDKClient>,
+        receiver: BatchSource<'_>,
+        config: TransactionExecutorConfig,
+        mut payer_pool: Option<PayerPool>,
+        event_sender: Option<Sender<Vec<SDKMarketEvent>>>,
+        results: Option<Sender<ExecutionResult>>,
+        shutdown: Arc<AtomicBool>,
+    ) {
+        let rt = Runtime::new().unwrap();
+        let max_in_flight = config.max_in_flight.max(1);
+        let in_flight = Arc::new(Semaphore::new(max_in_flight));
+        while let Some(instructions) = Self::next_batch(&receiver, &shutdown) {
+            let fee_payer = payer_pool.as_mut().map(|payer_pool| {
+                if payer_pool.balances_due() {
+                    Self::check_payer_balances(&rt, &sdk, payer_pool, event_sender.as_ref());
                 }
-            };
-            let signature = rt.block_on(sdk.client.sign_send_instructions(instructions, vec![]));
-            match signature {
-                Ok(s) => {
-                    let logs = rt.block_on(sdk.client.get_transaction(&s));
-                    println!("Transaction sent: {}", s);
-                    println!("Fills: {:?}", rt.block_on(sdk.parse_fills(&s)));
-
-                    match logs {
-                        Ok(logs) => {
-                            println!("Logs: {:?}", logs.logs);
-                        }
-                        Err(e) => {
-                            println!("Error getting logs: {:?}", e);
-                        }
+                let payer_index = payer_pool.select(&instructions);
+                payer_pool.payer(payer_index)
+            });
+            let permit = rt
+                .block_on(in_flight.clone().acquire_owned())
+                .expect("The in-flight semaphore is never closed");
+            let sdk = sdk.clone();
+            let results = results.clone();
+            rt.spawn(async move {
+                let (signature, protective_cancel) =
+                    Self::send_batch(&sdk, instructions, fee_payer.as_deref(), &config).await;
+                let fee_payer = fee_payer.map(|fee_payer| fee_payer.pubkey());
+                Self::report(
+                    &sdk,
+                    signature,
+                    protective_cancel,
+                    fee_payer,
+                    results.as_ref(),
+                )
+                .await;
+                drop(permit);
+            });
+        }
+        // Wait for the batches still in flight
+        let _ = rt.block_on(in_flight.acquire_many(max_in_flight as u32));
+    }
+
+    /// Sends a batch with retries, or once against the deadline if `config.options` has a
+    /// send deadline. Also returns the protective cancel sent for a batch that landed late.
+    async fn send_batch(
+        sdk: &SDKClient,
+        instructions: Vec<Instruction>,
+        fee_payer: Option<&Keypair>,
+        config: &TransactionExecutorConfig,
+    ) -> (Result<Signature, PhoenixSdkError>, Option<Signature>) {
+        if config.options.send_deadline.is_none() {
+            let signature = sdk
+                .try_send_instructions_with_retries(
+                    instructions,
+                    fee_payer,
+                    &config.options,
+                    config.max_retries,
+                )
+                .await;
+            return (signature, None);
+        }
+        match sdk
+            .try_send_instructions_with_deadline(instructions, fee_payer, &config.options)
+            .await
+        {
+            Ok(result) if result.outcome == DeadlineOutcome::Expired => (
+                Err(PhoenixSdkError::SendDeadlineExpired {
+                    signature: result.signature,
+                }),
+                None,
+            ),
+            Ok(result) => (Ok(result.signature), result.protective_cancel()),
+            Err(e) => (Err(e), None),
+        }
+    }
+
+    /// Logs the outcome of a send and, if `results` is set, sends it with the parsed fills and
+    /// places.
+    async fn report(
+        sdk: &SDKClient,
+        signature: Result<Signature, PhoenixSdkError>,
+        protective_cancel: Option<Signature>,
+        fee_payer: Option<Pubkey>,
+        results: Option<&Sender<ExecutionResult>>,
+    ) {
+        let result = match signature {
+            Ok(s) => {
+                println!("Transaction sent: {}", s);
+                let (fills, places) = sdk.parse_fills_and_places(&s).await;
+                println!("Fills: {:?}", fills);
+                match sdk.client.get_transaction(&s).await {
+                    Ok(logs) => {
+                        println!("Logs: {:?}", logs.logs);
+                    }
+                    Err(e) => {
+                        println!("Error getting logs: {:?}", e);
                     }
                 }
-                Err(e) => {
-                    println!("Transaction failed: {}", e);
+                ExecutionResult {
+                    signature: Some(s),
+                    fills,
+                    places,
+                    error: None,
+                    protective_cancel,
+                    fee_payer,
                 }
             }
+            Err(e) => {
+                println!("Error sending transaction: {}", e);
+                ExecutionResult {
+                    signature: None,
+                    fills: vec![],
+                    places: vec![],
+                    error: Some(e),
+                    protective_cancel,
+                    fee_payer,
+                }
+            }
+        };
+        if let Some(results) = results {
+            if results.send(result).is_err() {
+                println!("Execution result receiver disconnected, continuing");
+            }
+        }
+    }
+
+    fn check_payer_balances(
+        rt: &Runtime,
+        sdk: &SDKClient,
+        payer_pool: &mut PayerPool,
+        event_sender: Option<&Sender<Vec<SDKMarketEvent>>>,
+    ) {
+        let balances = match rt.block_on(sdk.fetch_accounts(&payer_pool.payer_keys())) {
+            Ok(accounts) => accounts
+                .iter()
+                .map(|account| account.as_ref().map(|a| a.lamports).unwrap_or(0))
+                .collect::<Vec<_>>(),
+            Err(e) => {
+                println!("Error fetching fee payer balances: {}", e);
+                return;
+            }
+        };
+        let events = payer_pool.update_balances(&balances);
+        for event in events.iter() {
+            println!("Warning: {:?}", event);
+        }
+        if let Some(sender) = event_sender {
+            if !events.is_empty() && sender.send(events).is_err() {
+                println!("Event receiver disconnected, continuing");
+            }
         }
     }
 }